@@ -0,0 +1,83 @@
+// Benchmarks the layout and the drawing of the tray window over the fake connection, so that no
+// X server is needed.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use geektray::fake_connection::FakeConnection;
+use geektray::{
+    Atoms, ClickConfig, GroupsConfig, PhysicalSize, RenderContext, TrayEmbedder, UIConfig,
+    WindowConfig, XEmbedInfo,
+};
+use std::rc::Rc;
+
+const ITEM_COUNTS: [usize; 3] = [5, 50, 500];
+
+fn create_tray_embedder(
+    connection: &Rc<FakeConnection>,
+    item_count: usize,
+) -> TrayEmbedder<FakeConnection> {
+    let atoms = Rc::new(Atoms::new(&**connection).unwrap().reply().unwrap());
+    let mut tray_embedder = TrayEmbedder::new(
+        connection.clone(),
+        0,
+        atoms,
+        &WindowConfig::default(),
+        GroupsConfig::default(),
+        ClickConfig::default(),
+        PhysicalSize {
+            width: 160,
+            height: 100,
+        },
+    )
+    .unwrap();
+    for i in 0..item_count {
+        tray_embedder.add_icon(
+            1000 + i as u32,
+            format!("Item {}", i),
+            Some(format!("Class{}", i % 7)),
+            false,
+            XEmbedInfo::default(),
+        );
+    }
+    tray_embedder.select_item(item_count / 2);
+    tray_embedder
+}
+
+fn bench_layout(c: &mut Criterion) {
+    let ui_config = UIConfig::default();
+    let mut group = c.benchmark_group("layout");
+    for item_count in ITEM_COUNTS {
+        let connection = Rc::new(FakeConnection::new());
+        let mut tray_embedder = create_tray_embedder(&connection, item_count);
+        group.bench_function(BenchmarkId::from_parameter(item_count), |b| {
+            b.iter(|| {
+                tray_embedder.request_layout();
+                let size = tray_embedder.layout(&ui_config).unwrap();
+                // The fake connection would keep every request otherwise.
+                connection.take_requests();
+                size
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_draw(c: &mut Criterion) {
+    let ui_config = UIConfig::default();
+    let mut group = c.benchmark_group("draw");
+    for item_count in ITEM_COUNTS {
+        let connection = Rc::new(FakeConnection::new());
+        let mut tray_embedder = create_tray_embedder(&connection, item_count);
+        let size = tray_embedder.layout(&ui_config).unwrap();
+        let context = RenderContext::new_image(size).unwrap();
+        group.bench_function(BenchmarkId::from_parameter(item_count), |b| {
+            b.iter(|| {
+                tray_embedder.draw(true, &ui_config, &context).unwrap();
+                connection.take_requests();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_layout, bench_draw);
+criterion_main!(benches);
@@ -6,6 +6,10 @@ fn main() {
     probe_library("xkbcommon").unwrap();
     probe_library("xkbcommon-x11").unwrap();
 
+    if env::var_os("CARGO_FEATURE_SVG").is_some() {
+        probe_library("librsvg-2.0").unwrap();
+    }
+
     let bindings = bindgen::Builder::default()
         .header_contents(
             "wrapper.h",
@@ -0,0 +1,193 @@
+use anyhow::Context as _;
+use std::rc::Rc;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto;
+use x11rb::protocol::xproto::ConnectionExt as _;
+
+use crate::config::ScreenEdge;
+use crate::geometrics::PhysicalRect;
+
+// The thickness of the window along the edge.
+const EDGE_SIZE: u32 = 1;
+
+/// An invisible input-only window along an edge of the primary monitor, which stays mapped to
+/// show the tray window when the pointer enters it.
+#[derive(Debug)]
+pub struct ActivationEdge<C: Connection> {
+    connection: Rc<C>,
+    screen_num: usize,
+    window: xproto::Window,
+    edge: ScreenEdge,
+}
+
+impl<C: Connection> ActivationEdge<C> {
+    pub fn new(connection: Rc<C>, screen_num: usize, edge: ScreenEdge) -> anyhow::Result<Self> {
+        let screen = &connection.setup().roots[screen_num];
+        let window = connection.generate_id()?;
+        let values = xproto::CreateWindowAux::new()
+            .event_mask(xproto::EventMask::ENTER_WINDOW | xproto::EventMask::LEAVE_WINDOW)
+            .override_redirect(1);
+        connection
+            .create_window(
+                0,
+                window,
+                screen.root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                xproto::WindowClass::INPUT_ONLY,
+                x11rb::COPY_FROM_PARENT,
+                &values,
+            )?
+            .check()
+            .context("create activation edge window")?;
+
+        // The window is placed again when the monitors are changed.
+        connection
+            .randr_select_input(screen.root, randr::NotifyMask::SCREEN_CHANGE)?
+            .check()
+            .context("select screen change events")?;
+
+        let activation_edge = Self {
+            connection,
+            screen_num,
+            window,
+            edge,
+        };
+        activation_edge.place()?;
+        activation_edge
+            .connection
+            .map_window(window)?
+            .check()
+            .context("map activation edge window")?;
+
+        Ok(activation_edge)
+    }
+
+    pub fn window(&self) -> xproto::Window {
+        self.window
+    }
+
+    // Also raises the window, since other override-redirect windows may have covered it.
+    pub fn place(&self) -> anyhow::Result<()> {
+        let monitor = primary_monitor(&*self.connection, self.screen_num);
+        let bounds = edge_bounds(self.edge, monitor);
+        let values = xproto::ConfigureWindowAux::new()
+            .x(bounds.x)
+            .y(bounds.y)
+            .width(bounds.width)
+            .height(bounds.height)
+            .stack_mode(xproto::StackMode::ABOVE);
+        self.connection
+            .configure_window(self.window, &values)?
+            .check()
+            .context("place activation edge window")?;
+        Ok(())
+    }
+}
+
+impl<C: Connection> Drop for ActivationEdge<C> {
+    fn drop(&mut self) {
+        self.connection.destroy_window(self.window).ok();
+    }
+}
+
+// The whole screen is used if RandR is not available or no monitor is primary.
+fn primary_monitor(connection: &impl Connection, screen_num: usize) -> PhysicalRect {
+    let screen = &connection.setup().roots[screen_num];
+    connection
+        .randr_get_monitors(screen.root, true)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .and_then(|reply| reply.monitors.into_iter().find(|monitor| monitor.primary))
+        .map(|monitor| PhysicalRect {
+            x: monitor.x as i32,
+            y: monitor.y as i32,
+            width: monitor.width as u32,
+            height: monitor.height as u32,
+        })
+        .unwrap_or(PhysicalRect {
+            x: 0,
+            y: 0,
+            width: screen.width_in_pixels as u32,
+            height: screen.height_in_pixels as u32,
+        })
+}
+
+fn edge_bounds(edge: ScreenEdge, monitor: PhysicalRect) -> PhysicalRect {
+    match edge {
+        ScreenEdge::Top => PhysicalRect {
+            height: EDGE_SIZE,
+            ..monitor
+        },
+        ScreenEdge::Bottom => PhysicalRect {
+            y: monitor.y + monitor.height as i32 - EDGE_SIZE as i32,
+            height: EDGE_SIZE,
+            ..monitor
+        },
+        ScreenEdge::Left => PhysicalRect {
+            width: EDGE_SIZE,
+            ..monitor
+        },
+        ScreenEdge::Right => PhysicalRect {
+            x: monitor.x + monitor.width as i32 - EDGE_SIZE as i32,
+            width: EDGE_SIZE,
+            ..monitor
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edge_bounds() {
+        let monitor = PhysicalRect {
+            x: 1920,
+            y: 0,
+            width: 1280,
+            height: 1024,
+        };
+        assert_eq!(
+            edge_bounds(ScreenEdge::Top, monitor),
+            PhysicalRect {
+                x: 1920,
+                y: 0,
+                width: 1280,
+                height: 1,
+            }
+        );
+        assert_eq!(
+            edge_bounds(ScreenEdge::Bottom, monitor),
+            PhysicalRect {
+                x: 1920,
+                y: 1023,
+                width: 1280,
+                height: 1,
+            }
+        );
+        assert_eq!(
+            edge_bounds(ScreenEdge::Left, monitor),
+            PhysicalRect {
+                x: 1920,
+                y: 0,
+                width: 1,
+                height: 1024,
+            }
+        );
+        assert_eq!(
+            edge_bounds(ScreenEdge::Right, monitor),
+            PhysicalRect {
+                x: 3199,
+                y: 0,
+                width: 1,
+                height: 1024,
+            }
+        );
+    }
+}
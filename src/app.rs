@@ -2,166 +2,628 @@ use anyhow::{anyhow, Context as _};
 use nix::sys::epoll;
 use nix::sys::signal::{SigSet, Signal};
 use nix::sys::signalfd::{siginfo, SignalFd};
+use nix::unistd;
 use std::collections::HashMap;
+use std::env;
+use std::error;
+use std::ffi::CString;
+use std::fmt;
 use std::ops::ControlFlow;
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::process;
 use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
 use x11rb::connection::Connection;
 use x11rb::protocol;
+use x11rb::protocol::screensaver;
+use x11rb::protocol::screensaver::ConnectionExt as _;
+use x11rb::protocol::sync;
 use x11rb::protocol::xkb;
 use x11rb::protocol::xkb::ConnectionExt as _;
 use x11rb::protocol::xproto;
 use x11rb::protocol::xproto::ConnectionExt as _;
 use x11rb::xcb_ffi::XCBConnection;
 
+use crate::activation_edge::ActivationEdge;
 use crate::atoms::Atoms;
-use crate::config::{Action, Config, KeyBinding};
+use crate::command::{self, CommandOutput};
+use crate::config::{
+    Action, AutoShow, Config, HotCornerPosition, ItemHotkeyConfig, KeyBinding, RemoteIconPolicy,
+    TrayOrientation,
+};
+use crate::daemon;
+use crate::desktop_entry::{self, DesktopEntryIndex};
+use crate::dock_history::{DockEvent, DockHistory};
 use crate::event::{KeyState, Keysym, Modifiers};
-use crate::geometrics::Size;
-use crate::render_context::RenderContext;
+use crate::geometrics::{PhysicalPoint, PhysicalSize, Size};
+use crate::help_overlay;
+use crate::hot_corner::HotCorner;
+use crate::icon_theme::IconTheme;
+use crate::key_grabber::KeyGrabber;
+use crate::metrics::Metrics;
+use crate::plugin::{self, Plugins};
+use crate::protocol_trace;
+use crate::render_context::{self, RenderContext};
+use crate::renderer::Renderer as _;
+#[cfg(feature = "scripting")]
+use crate::script::Script;
+use crate::state_file;
+use crate::systemd::{self, Notifier};
+use crate::timer::TimerQueue;
 use crate::tray_embedder::TrayEmbedder;
-use crate::tray_manager::{SystemTrayColors, SystemTrayOrientation, TrayEvent, TrayManager};
+use crate::tray_manager::{
+    SelectionOwner, SystemTrayColors, SystemTrayOrientation, TrayEvent, TrayManager,
+};
+use crate::wm_quirks::Quirks;
 use crate::xkbcommon;
+use crate::xkbcommon_sys;
 
 type ActionTable = HashMap<(Keysym, Modifiers), usize>;
 
 const EVENT_KIND_X11: u64 = 1;
 const EVENT_KIND_SIGNAL: u64 = 2;
+// The index of the custom item is added to this.
+const EVENT_KIND_CUSTOM_ITEM: u64 = 16;
+// Startup sequences still running after this are completed by geektray.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
 
+#[derive(Debug)]
+enum Timer {
+    RunCustomItem(usize),
+    HideWindow,
+    AutoHideWindow(usize),
+    RemoveStartupSequence(usize, String),
+    SelectionWaitTimeout(usize),
+    HideEmptyWindow(usize),
+    HandoverTimeout,
+    RestartItem(usize, Option<u32>),
+    RedrawDeferred(usize),
+    RenderFrame,
+    RepeatKey(xproto::Keycode),
+    ExpireMessages(usize),
+    ExitWhenEmpty,
+    PollPointer,
+    ActivateEdge(usize),
+    PingIcons,
+}
+
+/// Another tray owns the tray selection and does not give it up.
+#[derive(Debug)]
+pub struct SelectionOwnedError {
+    pub screen_num: usize,
+    pub owner: SelectionOwner,
+}
+
+impl fmt::Display for SelectionOwnedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "another tray owns the tray selection on screen {} ({})",
+            self.screen_num, self.owner
+        )
+    }
+}
+
+impl error::Error for SelectionOwnedError {}
+
+// The tray window and the tray selection for a screen.
+struct Tray {
+    screen_num: usize,
+    tray_embedder: TrayEmbedder<XCBConnection>,
+    tray_manager: TrayManager<XCBConnection>,
+    render_context: Option<RenderContext>,
+    // Whether the window is going to be hidden because it has no items.
+    is_empty_hide_scheduled: bool,
+    hot_corner: HotCorner,
+    activation_edge: Option<ActivationEdge<XCBConnection>>,
+    // Whether the pointer is on the activation edge.
+    is_edge_entered: bool,
+}
+
+/// The whole application: connects to the X server and runs the event loop until exit.
 pub struct App {
     config: Config,
     connection: Rc<XCBConnection>,
-    screen_num: usize,
     atoms: Rc<Atoms>,
     xkb_state: xkbcommon::State,
     signal_fd: SignalFd,
-    tray_embedder: TrayEmbedder<XCBConnection>,
-    tray_manager: TrayManager<XCBConnection>,
+    trays: Vec<Tray>,
+    // The tray that key bindings apply to.
+    active_tray: usize,
+    // The key bindings of the profile merged with the configured ones.
+    key_bindings: Vec<KeyBinding>,
     action_table: ActionTable,
-    render_context: Option<RenderContext>,
+    key_grabber: KeyGrabber,
+    // The indices of the global key bindings that another client has grabbed.
+    conflicted_key_bindings: Vec<usize>,
+    // The atoms of SendClientMessage actions by their names.
+    message_atoms: HashMap<String, xproto::Atom>,
+    timers: TimerQueue<Timer>,
+    custom_item_outputs: Vec<Option<CommandOutput>>,
+    hide_deadline: Option<Instant>,
+    // Drawing is suspended while the screen saver is active.
+    is_screen_saver_active: bool,
+    was_any_mapped: bool,
+    // The content last written to the state file.
+    last_state: Option<String>,
+    // While exiting, the trays keep the selection until this deadline for a successor.
+    handover_deadline: Option<Instant>,
+    // Icons are remote if their WM_CLIENT_MACHINE differs from this.
+    hostname: Option<String>,
+    // Icons whose process has been killed by the user, which are never restarted.
+    killed_icons: Vec<xproto::Window>,
+    metrics: Option<Metrics>,
+    plugins: Plugins,
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+    dock_history: Option<DockHistory>,
+    // Frames are rendered at most once per the frame interval.
+    next_frame_at: Option<Instant>,
+    is_frame_scheduled: bool,
+    // Whether to fork into the background once all trays own the tray selection.
+    daemonize: bool,
+    is_ready: bool,
+    // Whether the last icon has been removed with behavior.exit_when_empty.
+    is_exit_requested: bool,
+    pid_path: Option<PathBuf>,
+    // The key held down, which repeats its key binding by the own timer instead of the server.
+    held_key: Option<xproto::Keycode>,
+    // Whether the key binding of the held key has been repeated, so that it does not run again
+    // on the release.
+    has_key_repeated: bool,
+    // Notifies systemd of the readiness and the number of items when run as a service.
+    notifier: Option<Notifier>,
+    notified_item_count: Option<usize>,
 }
 
 impl App {
-    pub fn new(config: Config) -> anyhow::Result<Self> {
-        let (connection, screen_num) =
-            XCBConnection::connect(None).context("connect to X server")?;
+    pub fn new(config: Config, display: Option<&str>) -> anyhow::Result<Self> {
+        let display_name = display
+            .map(CString::new)
+            .transpose()
+            .context("invalid display name")?;
+        let (connection, screen_num) = XCBConnection::connect(display_name.as_deref())
+            .with_context(|| {
+                let display = display
+                    .map(str::to_owned)
+                    .or_else(|| env::var("DISPLAY").ok())
+                    .unwrap_or_default();
+                format!("cannot connect to display \"{}\"", display)
+            })?;
         let connection = Rc::new(connection);
 
-        setup_xkb_extension(&*connection)?;
+        let screen_num = config.window.screen.unwrap_or(screen_num);
+        let screen_count = connection.setup().roots.len();
+        if screen_num >= screen_count {
+            return Err(anyhow!(
+                "screen {} does not exist (the display has {} screens)",
+                screen_num,
+                screen_count
+            ));
+        }
 
-        let atoms: Rc<_> = Atoms::new(&*connection)?
-            .reply()
-            .context("intern app atoms")?
-            .into();
+        // All atoms are interned at once, and the reply is received after the other setup.
+        let atoms_cookie = Atoms::new(&*connection)?;
+
+        setup_xkb_extension(&*connection, config.behavior.key_repeat_delay_ms > 0)?;
+
+        let atoms: Rc<_> = atoms_cookie.reply().context("intern app atoms")?.into();
 
         let xkb_state = create_xkb_state(&connection)?;
 
         let signal_fd = create_signal_fd()?;
 
-        let action_table = build_action_table(&config.key_bindings);
+        let mut overrides = config.key_bindings.clone();
+        overrides.extend(
+            config
+                .items
+                .hotkeys
+                .iter()
+                .map(ItemHotkeyConfig::key_binding),
+        );
+        let key_bindings = config.profile.merge_key_bindings(&overrides);
+        let action_table = build_action_table(&key_bindings);
+        let message_atoms = intern_message_atoms(&*connection, &key_bindings)?;
 
-        let window_size = Size {
-            width: config.window.default_width,
-            height: config.ui.icon_size.max(config.ui.text_size) + config.ui.item_padding * 2.0,
-        }
-        .snap();
+        let screen_nums = if config.window.all_screens {
+            (0..screen_count).collect()
+        } else {
+            vec![screen_num]
+        };
+        let desktop_entries = if config.ui.show_application_name || config.ui.icon_theme.is_some() {
+            Rc::new(DesktopEntryIndex::load(&desktop_entry::data_dirs()))
+        } else {
+            Rc::default()
+        };
+        let mut trays = screen_nums
+            .into_iter()
+            .map(|screen_num| {
+                Tray::new(
+                    &connection,
+                    screen_num,
+                    &atoms,
+                    desktop_entries.clone(),
+                    &config,
+                )
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
 
-        let tray_embedder = TrayEmbedder::new(
-            connection.clone(),
-            screen_num,
-            &*atoms,
-            &config.window,
-            window_size,
-        )?;
+        setup_screensaver_extension(&*connection, trays.iter().map(|tray| tray.screen_num))?;
+
+        let active_tray = trays
+            .iter()
+            .position(|tray| tray.screen_num == screen_num)
+            .unwrap_or(0);
+
+        let hostname = unistd::gethostname()
+            .ok()
+            .and_then(|hostname| hostname.into_string().ok());
+        let command = env::args_os().collect::<Vec<_>>();
+
+        for tray in &trays {
+            tray.tray_embedder.set_command(&command)?;
+            if let Some(hostname) = &hostname {
+                tray.tray_embedder.set_client_machine(hostname)?;
+            }
+        }
 
-        let tray_manager = TrayManager::new(connection.clone(), screen_num, atoms.clone())?;
+        let custom_item_outputs = config.items.custom.iter().map(|_| None).collect();
+        let notifier = Notifier::from_env()?;
+        let metrics = config
+            .behavior
+            .metrics
+            .then(|| Metrics::new(Instant::now()));
+        let plugins = match plugin::plugin_dir() {
+            Some(plugin_dir) if config.behavior.load_plugins => Plugins::load(&plugin_dir),
+            _ => Plugins::default(),
+        };
+        #[cfg(feature = "scripting")]
+        let script = load_script(&config.behavior.script_file);
+        #[cfg(not(feature = "scripting"))]
+        if !config.behavior.script_file.is_empty() {
+            log::warn!(
+                "script_file is ignored, since geektray is built without the scripting feature"
+            );
+        }
+        let dock_history = if config.behavior.dock_history {
+            DockHistory::start(SystemTime::now()).unwrap_or_else(|error| {
+                log::error!("failed to start dock history: {:#}", error);
+                None
+            })
+        } else {
+            None
+        };
+        if let Some(dock_history) = &dock_history {
+            for tray in &mut trays {
+                tray.tray_manager
+                    .set_orphan_icon_classes(dock_history.docked_classes().clone());
+            }
+        }
 
         Ok(Self {
             config,
             connection,
-            screen_num,
             atoms,
             xkb_state,
             signal_fd,
-            tray_embedder,
-            tray_manager,
+            trays,
+            active_tray,
+            key_bindings,
             action_table,
-            render_context: None,
+            key_grabber: KeyGrabber::new(),
+            conflicted_key_bindings: Vec::new(),
+            message_atoms,
+            timers: TimerQueue::new(),
+            custom_item_outputs,
+            hide_deadline: None,
+            is_screen_saver_active: false,
+            was_any_mapped: false,
+            last_state: None,
+            handover_deadline: None,
+            killed_icons: Vec::new(),
+            metrics,
+            plugins,
+            #[cfg(feature = "scripting")]
+            script,
+            dock_history,
+            next_frame_at: None,
+            is_frame_scheduled: false,
+            daemonize: false,
+            is_ready: false,
+            is_exit_requested: false,
+            pid_path: None,
+            held_key: None,
+            has_key_repeated: false,
+            notifier,
+            notified_item_count: None,
+            hostname,
         })
     }
 
-    pub fn run(&mut self) -> anyhow::Result<()> {
+    /// Runs until the process is terminated. If `wait_for_selection` is false, fails immediately
+    /// when another tray owns the tray selection instead of taking it over. If `daemonize` is
+    /// true, forks into the background once the tray selection is acquired.
+    pub fn run(&mut self, wait_for_selection: bool, daemonize: bool) -> anyhow::Result<()> {
+        self.daemonize = daemonize;
         self.grab_global_keys()?;
 
-        self.tray_manager.acquire_tray_selection(
-            self.tray_embedder.window(),
-            SystemTrayOrientation::HORZONTAL,
-            SystemTrayColors::single(self.config.window.icon_theme_color),
-        )?;
+        for (index, tray) in self.trays.iter_mut().enumerate() {
+            if let Some(owner) = tray.tray_manager.current_owner()? {
+                if !wait_for_selection {
+                    return Err(SelectionOwnedError {
+                        screen_num: tray.screen_num,
+                        owner,
+                    }
+                    .into());
+                }
+                log::warn!(
+                    "another tray owns the tray selection on screen {} ({}), wait for it to exit",
+                    tray.screen_num,
+                    owner
+                );
+            }
 
-        self.run_event_loop()?;
+            tray.tray_manager.acquire_tray_selection(
+                tray.tray_embedder.window(),
+                system_tray_orientation(self.config.window.tray_orientation),
+                SystemTrayColors::single(self.config.window.icon_theme_color),
+            )?;
 
-        Ok(())
+            // The replies to the pings from the icons are sent to the root window.
+            if self.config.behavior.icon_ping_interval_ms > 0 {
+                let screen = &self.connection.setup().roots[tray.screen_num];
+                let values = xproto::ChangeWindowAttributesAux::new()
+                    .event_mask(xproto::EventMask::SUBSTRUCTURE_NOTIFY);
+                self.connection
+                    .change_window_attributes(screen.root, &values)?
+                    .check()
+                    .context("select ping replies")?;
+            }
+
+            let timeout_ms = self.config.behavior.selection_wait_timeout_ms;
+            if timeout_ms > 0 && tray.tray_manager.current_owner()?.is_some() {
+                self.timers.schedule(
+                    Instant::now() + Duration::from_millis(timeout_ms),
+                    Timer::SelectionWaitTimeout(index),
+                );
+            }
+        }
+
+        if self.config.window.preload {
+            for tray in &mut self.trays {
+                tray.tray_embedder.park()?;
+            }
+        }
+
+        let now = Instant::now();
+        if self.config.behavior.hot_corner != HotCornerPosition::None {
+            self.timers.schedule(now, Timer::PollPointer);
+        }
+        if self.config.behavior.icon_ping_interval_ms > 0 {
+            self.timers.schedule(now, Timer::PingIcons);
+        }
+        for index in 0..self.config.items.custom.len() {
+            self.timers.schedule(now, Timer::RunCustomItem(index));
+        }
+
+        let result = self.run_event_loop();
+
+        if let Some(pid_path) = &self.pid_path {
+            daemon::remove_pid_file(pid_path);
+        }
+
+        if let Some(metrics) = &self.metrics {
+            log::info!(
+                "metrics: {}",
+                metrics.summary(Instant::now(), render_context::resource_counts())
+            );
+        }
+
+        result
     }
 
-    fn grab_global_keys(&self) -> anyhow::Result<()> {
-        for key_binding in self
-            .config
-            .key_bindings
-            .iter()
-            .filter(|key_binding| key_binding.global())
-        {
+    fn grab_global_keys(&mut self) -> anyhow::Result<()> {
+        for (index, key_binding) in self.key_bindings.iter().enumerate() {
+            if !key_binding.global() {
+                continue;
+            }
             let keycode = self
                 .xkb_state
                 .lookup_keycode(key_binding.keysym())
                 .context("lookup keycode")?;
-            grab_key(
-                &*self.connection,
-                self.screen_num,
-                keycode,
-                key_binding.modifiers(),
-            )?;
+            for tray in &self.trays {
+                let grabbed = self.key_grabber.grab(
+                    &*self.connection,
+                    tray.screen_num,
+                    keycode as xproto::Keycode,
+                    key_binding.modifiers(),
+                )?;
+                // The other key bindings still work without it.
+                if !grabbed && !self.conflicted_key_bindings.contains(&index) {
+                    log::warn!(
+                        "{} is already grabbed by another client, the global key binding is disabled",
+                        help_overlay::key_label(key_binding.keysym(), key_binding.modifiers())
+                    );
+                    self.conflicted_key_bindings.push(index);
+                }
+            }
         }
         Ok(())
     }
 
+    fn write_state_file(&mut self) {
+        let items: Vec<_> = self
+            .trays
+            .iter()
+            .flat_map(|tray| tray.tray_embedder.item_states())
+            .collect();
+        let state = state_file::to_json(&items);
+        if self.last_state.as_ref() == Some(&state) {
+            return;
+        }
+        let path = Path::new(self.config.behavior.state_file.as_ref());
+        if let Err(error) = state_file::write(path, &state) {
+            log::error!("failed to write state file: {}", error);
+        }
+        self.last_state = Some(state);
+    }
+
     fn handle_key_binding(&mut self, index: usize) -> anyhow::Result<()> {
-        let key_binding = &self.config.key_bindings[index];
-        for action in key_binding.actions() {
+        let is_mapped = self.trays[self.active_tray].tray_embedder.is_mapped();
+        let actions = self.key_bindings[index].actions_for(is_mapped).to_vec();
+        self.run_actions(self.active_tray, &actions)
+    }
+
+    fn run_actions(&mut self, tray_index: usize, actions: &[Action]) -> anyhow::Result<()> {
+        let tray_embedder = &mut self.trays[tray_index].tray_embedder;
+        let show_when_empty = self.config.behavior.show_when_empty;
+        let mut should_reload_desktop_entries = false;
+        let mut script_commands = Vec::new();
+        for action in actions {
             match action {
                 Action::HideWindow => {
-                    self.tray_embedder.hide()?;
+                    tray_embedder.hide()?;
                 }
                 Action::ShowWindow => {
-                    self.tray_embedder.show()?;
+                    if show_when_empty || !tray_embedder.is_empty() {
+                        tray_embedder.show()?;
+                    }
                 }
                 Action::ToggleWindow => {
-                    if self.tray_embedder.is_mapped() {
-                        self.tray_embedder.hide()?;
-                    } else {
-                        self.tray_embedder.show()?;
+                    if tray_embedder.is_mapped() {
+                        tray_embedder.hide()?;
+                    } else if show_when_empty || !tray_embedder.is_empty() {
+                        tray_embedder.show()?;
                     }
                 }
                 Action::DeselectItem => {
-                    self.tray_embedder.deselect_item();
+                    tray_embedder.deselect_item();
                 }
                 Action::SelectItem { index } => {
-                    self.tray_embedder.select_item(*index);
+                    tray_embedder.select_item(*index);
                 }
                 Action::SelectNextItem => {
-                    self.tray_embedder.select_next_item();
+                    tray_embedder.select_next_item();
                 }
                 Action::SelectPreviousItem => {
-                    self.tray_embedder.select_previous_item();
+                    tray_embedder.select_previous_item();
+                }
+                Action::SelectNextUrgentItem => {
+                    tray_embedder.select_next_urgent_item();
                 }
-                Action::ClickSelectedItem { button } => {
-                    self.tray_embedder.click_selected_item(*button)?;
+                Action::SelectLastUsedItem => {
+                    tray_embedder.select_last_used_item();
+                }
+                Action::SelectMatchingItem { class, title } => {
+                    tray_embedder.select_matching_item(class.as_deref(), title.as_deref());
+                }
+                Action::ClickSelectedItem { button, modifiers } => {
+                    tray_embedder.click_selected_item(*button, *modifiers)?;
+                }
+                Action::FocusSelectedIcon => {
+                    tray_embedder.focus_selected_icon()?;
+                }
+                Action::ActivateSelectedApplication => {
+                    tray_embedder.activate_selected_application()?;
+                }
+                Action::KillSelectedItemProcess => {
+                    if let Some(icon) = tray_embedder.kill_selected_item_process()? {
+                        self.killed_icons.push(icon);
+                    }
+                }
+                Action::ScreenshotSelectedItem => {
+                    let dir = Path::new(self.config.behavior.screenshot_dir.as_ref());
+                    if let Some(path) = tray_embedder.screenshot_selected_item(dir)? {
+                        log::info!("icon screenshot saved to {}", path.display());
+                    }
+                }
+                Action::MoveWindow { dx, dy } => {
+                    tray_embedder.move_window_by(*dx, *dy)?;
+                }
+                Action::ResizeWindow { dw } => {
+                    tray_embedder.resize_window_by(*dw);
+                }
+                Action::SendClientMessage {
+                    atom,
+                    data,
+                    destination,
+                } => {
+                    let mut message_data = [0; 5];
+                    message_data[..data.len()].copy_from_slice(data);
+                    tray_embedder.send_client_message(
+                        self.message_atoms[atom],
+                        message_data,
+                        *destination,
+                    )?;
+                }
+                Action::ShowHelp => {
+                    tray_embedder.toggle_help(&self.key_bindings, &self.conflicted_key_bindings)?;
+                }
+                Action::ShowMessageHistory => {
+                    tray_embedder.toggle_message_history()?;
+                }
+                Action::ReloadDesktopEntries => {
+                    should_reload_desktop_entries = true;
+                }
+                Action::RunPluginCommand { name } => {
+                    if !self.plugins.run_command(name) {
+                        log::warn!("no plugin has handled the command: {}", name);
+                    }
+                }
+                Action::RunScriptCommand { name } => {
+                    script_commands.push(name);
                 }
             }
         }
+        // The desktop entries are shared by the trays of all screens.
+        if should_reload_desktop_entries {
+            let desktop_entries = Rc::new(DesktopEntryIndex::load(&desktop_entry::data_dirs()));
+            for tray in &mut self.trays {
+                tray.tray_embedder
+                    .set_desktop_entries(desktop_entries.clone());
+            }
+        }
+        for name in script_commands {
+            let actions = self.run_script_command(name);
+            self.run_actions(tray_index, &actions)?;
+        }
         Ok(())
     }
 
+    #[cfg(feature = "scripting")]
+    fn run_script_command(&mut self, name: &str) -> Vec<Action> {
+        let actions = self
+            .script
+            .as_mut()
+            .and_then(|script| script.run_command(name));
+        actions.unwrap_or_else(|| {
+            log::warn!("no script function for the command: {}", name);
+            Vec::new()
+        })
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn run_script_command(&mut self, name: &str) -> Vec<Action> {
+        log::warn!(
+            "the script command {} is ignored, since geektray is built without the scripting feature",
+            name
+        );
+        Vec::new()
+    }
+
+    #[cfg(feature = "scripting")]
+    fn dispatch_script_event(&mut self, event: &TrayEvent) -> Vec<Action> {
+        self.script
+            .as_mut()
+            .map_or(Vec::new(), |script| script.dispatch_tray_event(event))
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn dispatch_script_event(&mut self, _event: &TrayEvent) -> Vec<Action> {
+        Vec::new()
+    }
+
     fn handle_signal(
         &mut self,
         signal: siginfo,
@@ -170,7 +632,7 @@ impl App {
         log::info!("signal {:?} received", signal.ssi_signo);
 
         match Signal::try_from(signal.ssi_signo as i32) {
-            Ok(Signal::SIGINT) | Ok(Signal::SIGKILL) | Ok(Signal::SIGTERM) => {
+            Ok(Signal::SIGINT) | Ok(Signal::SIGTERM) | Ok(Signal::SIGHUP) => {
                 *control_flow = ControlFlow::Break(());
             }
             _ => {}
@@ -180,48 +642,391 @@ impl App {
     }
 
     fn handle_tick(&mut self) -> anyhow::Result<()> {
-        if self.tray_embedder.is_mapped() {
-            let should_layout = self.tray_embedder.should_layout() || self.render_context.is_none();
+        for index in 0..self.trays.len() {
+            for tray_event in self.trays[index].tray_manager.process_pending_requests()? {
+                self.handle_tray_event(index, tray_event)?;
+            }
+        }
 
-            if should_layout {
-                let new_size = self.tray_embedder.layout(&self.config.ui)?;
-                self.render_context = Some(RenderContext::new(
-                    self.connection.clone(),
-                    self.screen_num,
-                    self.tray_embedder.window(),
-                    new_size,
-                )?);
+        if !self.is_ready && self.trays.iter().all(|tray| tray.tray_manager.is_managed()) {
+            self.is_ready = true;
+            self.handle_ready()?;
+        }
+
+        if self.is_ready {
+            self.notify_item_count();
+        }
+
+        for (index, tray) in self.trays.iter_mut().enumerate() {
+            for startup_id in tray.tray_embedder.take_startup_ids() {
+                self.timers.schedule(
+                    Instant::now() + STARTUP_TIMEOUT,
+                    Timer::RemoveStartupSequence(index, startup_id),
+                );
+            }
+        }
+
+        // The window may be shown or hidden without mapping it when it is preloaded.
+        let is_any_mapped = self.trays.iter().any(|tray| tray.tray_embedder.is_mapped());
+        if is_any_mapped != self.was_any_mapped {
+            self.was_any_mapped = is_any_mapped;
+            if !is_any_mapped {
+                // Cancel the deadline when the last window is hidden.
+                self.hide_deadline = None;
+            } else if self.hide_deadline.is_none() {
+                // Keep the deadline if the window was shown automatically.
+                self.set_hide_deadline(self.config.window.auto_hide_timeout_ms);
+            }
+        }
+
+        let hide_when_empty_ms = self.config.behavior.hide_when_empty_ms;
+        if hide_when_empty_ms > 0 {
+            for (index, tray) in self.trays.iter_mut().enumerate() {
+                if tray.tray_embedder.is_mapped()
+                    && tray.tray_embedder.is_empty()
+                    && !tray.is_empty_hide_scheduled
+                {
+                    tray.is_empty_hide_scheduled = true;
+                    self.timers.schedule(
+                        Instant::now() + Duration::from_millis(hide_when_empty_ms),
+                        Timer::HideEmptyWindow(index),
+                    );
+                }
+            }
+        }
+
+        for (index, tray) in self.trays.iter_mut().enumerate() {
+            if let Some(deadline) = tray.tray_embedder.take_deferred_redraw() {
+                self.timers.schedule(deadline, Timer::RedrawDeferred(index));
+            }
+        }
+
+        for tray in &mut self.trays {
+            tray.tray_embedder.update_title()?;
+        }
+
+        if !self.config.behavior.state_file.is_empty() {
+            self.write_state_file();
+        }
+
+        let now = Instant::now();
+        let mut is_animating = false;
+        for tray in &mut self.trays {
+            is_animating |= tray.tray_embedder.animate(now);
+        }
+
+        if !self.is_screen_saver_active
+            && !self.is_frame_scheduled
+            && self.trays.iter().any(Tray::needs_redraw)
+        {
+            match self.next_frame_at {
+                Some(next_frame_at) if now < next_frame_at => {
+                    self.is_frame_scheduled = true;
+                    self.timers.schedule(next_frame_at, Timer::RenderFrame);
+                }
+                _ => self.render_frame(now)?,
             }
+        }
+
+        // Wake up for the next frame of the animations even if no events arrive.
+        if is_animating && !self.is_frame_scheduled {
+            let frame_interval_ms = self.config.behavior.frame_interval_ms.max(16);
+            self.is_frame_scheduled = true;
+            self.timers.schedule(
+                now + Duration::from_millis(frame_interval_ms),
+                Timer::RenderFrame,
+            );
+        }
+
+        Ok(())
+    }
 
-            if should_layout || self.tray_embedder.should_redraw() {
-                let render_context = self.render_context.as_ref().unwrap();
-                self.tray_embedder
-                    .draw(should_layout, &self.config.ui, render_context)?;
+    fn render_frame(&mut self, now: Instant) -> anyhow::Result<()> {
+        for tray in &mut self.trays {
+            tray.redraw(&self.connection, &self.config, self.metrics.as_mut())?;
+        }
+        let frame_interval_ms = self.config.behavior.frame_interval_ms;
+        self.next_frame_at =
+            (frame_interval_ms > 0).then(|| now + Duration::from_millis(frame_interval_ms));
+        Ok(())
+    }
+
+    // All trays own the tray selection for the first time.
+    fn handle_ready(&mut self) -> anyhow::Result<()> {
+        log::info!("tray selection acquired on all screens");
+        if self.daemonize {
+            self.pid_path = daemon::daemonize(&self.config.log_file)?;
+        }
+        if self.config.window.show_on_startup {
+            self.trays[self.active_tray].tray_embedder.show()?;
+        }
+        if let Some(notifier) = &self.notifier {
+            // The process ID has been changed if it has been daemonized.
+            let state = format!("READY=1\nMAINPID={}", process::id());
+            if let Err(error) = notifier.notify(&state) {
+                log::warn!("failed to notify readiness: {:#}", error);
             }
         }
+        Ok(())
+    }
 
+    // Shows the window on the screen where the pointer has stayed in the hot corner.
+    fn poll_pointer(&mut self) -> anyhow::Result<()> {
+        let now = Instant::now();
+        for index in 0..self.trays.len() {
+            let tray = &mut self.trays[index];
+            let screen = &self.connection.setup().roots[tray.screen_num];
+            let reply = self
+                .connection
+                .query_pointer(screen.root)?
+                .reply()
+                .context("query pointer")?;
+            let pointer = reply.same_screen.then_some(PhysicalPoint {
+                x: reply.root_x as i32,
+                y: reply.root_y as i32,
+            });
+            let screen_size = PhysicalSize {
+                width: screen.width_in_pixels as u32,
+                height: screen.height_in_pixels as u32,
+            };
+            if !tray.hot_corner.update(pointer, screen_size, now) {
+                continue;
+            }
+            let tray_embedder = &mut tray.tray_embedder;
+            if !tray_embedder.is_mapped()
+                && (self.config.behavior.show_when_empty || !tray_embedder.is_empty())
+            {
+                log::info!("show window by the hot corner");
+                tray_embedder.show()?;
+                self.active_tray = index;
+            }
+        }
         Ok(())
     }
 
-    fn handle_tray_event(&mut self, event: TrayEvent) {
+    fn has_icons(&self) -> bool {
+        self.trays
+            .iter()
+            .any(|tray| tray.tray_embedder.item_states().next().is_some())
+    }
+
+    fn notify_item_count(&mut self) {
+        let Some(notifier) = &self.notifier else {
+            return;
+        };
+        let item_count = self
+            .trays
+            .iter()
+            .map(|tray| tray.tray_embedder.item_states().count())
+            .sum();
+        if self.notified_item_count == Some(item_count) {
+            return;
+        }
+        if let Err(error) = notifier.notify(&systemd::status_message(item_count)) {
+            log::warn!("failed to notify status: {:#}", error);
+        }
+        self.notified_item_count = Some(item_count);
+    }
+
+    fn handle_tray_event(&mut self, index: usize, event: TrayEvent) -> anyhow::Result<()> {
+        self.plugins.dispatch_tray_event(&event);
+        let script_actions = self.dispatch_script_event(&event);
+        self.apply_tray_event(index, event)?;
+        // The actions of the script run after the event is applied, e.g. to click an added icon.
+        self.run_actions(index, &script_actions)
+    }
+
+    fn apply_tray_event(&mut self, index: usize, event: TrayEvent) -> anyhow::Result<()> {
+        let tray_embedder = &mut self.trays[index].tray_embedder;
         match event {
-            TrayEvent::IconAdded(icon, title, xembed_info) => {
-                self.tray_embedder.add_icon(icon, title, xembed_info);
+            TrayEvent::IconAdded(icon, title, class, client_machine, xembed_info) => {
+                let remote_machine =
+                    client_machine.filter(|machine| Some(machine) != self.hostname.as_ref());
+                if let Some(machine) = &remote_machine {
+                    log::info!("remote icon (icon: {}, machine: {})", icon, machine);
+                    if self.config.click.remote_icons == RemoteIconPolicy::Skip {
+                        log::info!("skip remote icon (icon: {})", icon);
+                        // The icon has been embedded already, so it is returned to the root window.
+                        return self.trays[index].tray_manager.release_icon(icon);
+                    }
+                }
+                let should_restart = self
+                    .config
+                    .items
+                    .restart
+                    .iter()
+                    .any(|restart_item| class.as_ref() == Some(&restart_item.class));
+                record_dock_event(
+                    self.dock_history.as_ref(),
+                    DockEvent::Docked,
+                    class.as_deref(),
+                    &title,
+                );
+                tray_embedder.add_icon(icon, title, class, remote_machine.is_some(), xembed_info);
+                if self.config.ui.show_process_name || should_restart {
+                    tray_embedder.fetch_process(icon)?;
+                }
+                if self.config.behavior.auto_show == AutoShow::OnNewIcon {
+                    self.auto_show_window(index)?;
+                }
             }
             TrayEvent::IconRemoved(icon) => {
-                self.tray_embedder.remove_icon(icon);
+                if let Some(i) = self.killed_icons.iter().position(|killed| *killed == icon) {
+                    self.killed_icons.swap_remove(i);
+                } else if let Some((Some(class), pid)) = tray_embedder.icon_process(icon) {
+                    if let Some(restart_index) = self
+                        .config
+                        .items
+                        .restart
+                        .iter()
+                        .position(|restart_item| restart_item.class == class)
+                    {
+                        let delay_ms = self.config.items.restart[restart_index].delay_ms;
+                        self.timers.schedule(
+                            Instant::now() + Duration::from_millis(delay_ms),
+                            Timer::RestartItem(restart_index, pid),
+                        );
+                    }
+                }
+                if let Some(item_state) = tray_embedder
+                    .item_states()
+                    .find(|item_state| item_state.icon == icon)
+                {
+                    record_dock_event(
+                        self.dock_history.as_ref(),
+                        DockEvent::Undocked,
+                        item_state.class,
+                        item_state.title,
+                    );
+                }
+                tray_embedder.remove_icon(icon);
+                if self.config.behavior.exit_when_empty && !self.has_icons() {
+                    self.timers.schedule(
+                        Instant::now()
+                            + Duration::from_millis(
+                                self.config.behavior.exit_when_empty_timeout_ms,
+                            ),
+                        Timer::ExitWhenEmpty,
+                    );
+                }
             }
             TrayEvent::TitleChanged(icon, title) => {
-                self.tray_embedder.change_title(icon, title);
+                tray_embedder.change_title(icon, title);
             }
             TrayEvent::XEmbedInfoChanged(icon, xembed_info) => {
-                self.tray_embedder.change_xembed_info(icon, xembed_info);
+                tray_embedder.change_xembed_info(icon, xembed_info);
+            }
+            TrayEvent::PingSupportChanged(icon, supports_ping) => {
+                tray_embedder.change_ping_support(icon, supports_ping);
+            }
+            TrayEvent::UrgencyChanged(icon, is_urgent) => {
+                tray_embedder.change_urgency(icon, is_urgent);
+                if is_urgent && self.config.behavior.auto_show == AutoShow::OnAttention {
+                    self.auto_show_window(index)?;
+                }
+            }
+            TrayEvent::MessageReceived(message) => {
+                tray_embedder.record_message(message.icon(), message.as_str());
+                if message.timeout_millis() > 0 {
+                    self.timers.schedule(
+                        Instant::now() + Duration::from_millis(message.timeout_millis() as u64),
+                        Timer::ExpireMessages(index),
+                    );
+                }
             }
-            TrayEvent::MessageReceived(_message) => {}
+            TrayEvent::MessageCancelled(..) | TrayEvent::MessageExpired(..) => {}
             TrayEvent::SelectionCleared => {
-                self.tray_embedder.clear_icons();
+                tray_embedder.clear_icons();
             }
         }
+        Ok(())
+    }
+
+    fn auto_show_window(&mut self, index: usize) -> anyhow::Result<()> {
+        let tray_embedder = &mut self.trays[index].tray_embedder;
+        if tray_embedder.is_mapped() {
+            return Ok(());
+        }
+
+        log::info!("show window automatically");
+        tray_embedder.show()?;
+        self.active_tray = index;
+
+        self.set_hide_deadline(self.config.behavior.auto_show_timeout_ms);
+
+        Ok(())
+    }
+
+    // Hiding is deferred while an icon may have a menu open for the click forwarded to it, since
+    // the menu would be orphaned or closed along with the window.
+    fn auto_hide_window(&mut self, index: usize) -> anyhow::Result<()> {
+        let tray_embedder = &mut self.trays[index].tray_embedder;
+        let grace_period = Duration::from_millis(self.config.behavior.auto_hide_grace_period_ms);
+        let now = Instant::now();
+        if let Some(deadline) = tray_embedder
+            .last_clicked_at()
+            .map(|clicked_at| clicked_at + grace_period)
+            .filter(|deadline| *deadline > now)
+        {
+            log::debug!("defer auto-hide until the grace period after click ends");
+            self.timers.schedule(deadline, Timer::AutoHideWindow(index));
+            return Ok(());
+        }
+        tray_embedder.hide()
+    }
+
+    fn tray_of_window(&self, window: xproto::Window) -> Option<usize> {
+        self.trays
+            .iter()
+            .position(|tray| tray.tray_embedder.window() == window)
+    }
+
+    fn tray_of_edge_window(&self, window: xproto::Window) -> Option<usize> {
+        self.trays.iter().position(|tray| {
+            tray.activation_edge
+                .as_ref()
+                .is_some_and(|activation_edge| activation_edge.window() == window)
+        })
+    }
+
+    // Returns the key binding of the key with the current modifiers if all of its actions can be
+    // repeated.
+    fn repeatable_key_binding(&self, keycode: xproto::Keycode) -> Option<usize> {
+        let keysym = self.xkb_state.get_keysym(keycode as u32);
+        let modifiers = self.xkb_state.get_modifiers();
+        let index = *self.action_table.get(&(keysym, modifiers))?;
+        let is_mapped = self.trays[self.active_tray].tray_embedder.is_mapped();
+        self.key_bindings[index]
+            .actions_for(is_mapped)
+            .iter()
+            .all(Action::is_repeatable)
+            .then_some(index)
+    }
+
+    // The repeat is cancelled here, not to wait for the next tick of the timer.
+    fn release_held_key(&mut self) {
+        if let Some(keycode) = self.held_key.take() {
+            self.timers
+                .cancel(|timer| matches!(timer, Timer::RepeatKey(other) if *other == keycode));
+        }
+        self.has_key_repeated = false;
+    }
+
+    fn tray_of_root(&self, root: xproto::Window) -> Option<usize> {
+        self.trays
+            .iter()
+            .position(|tray| self.connection.setup().roots[tray.screen_num].root == root)
+    }
+
+    // Schedules to hide the window after the timeout, or cancels it if the timeout is 0.
+    fn set_hide_deadline(&mut self, timeout_ms: u64) {
+        if timeout_ms > 0 {
+            let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+            self.hide_deadline = Some(deadline);
+            self.timers.schedule(deadline, Timer::HideWindow);
+        } else {
+            self.hide_deadline = None;
+        }
     }
 
     fn handle_x11_event(
@@ -231,44 +1036,153 @@ impl App {
     ) -> anyhow::Result<()> {
         use protocol::Event::*;
 
+        protocol_trace::trace_event(&self.atoms, event);
+
+        // The substructure of the root windows is selected only for the replies to the pings, so
+        // the events of the top-level windows are dropped before they go through every tray.
+        if substructure_event_parent(event)
+            .is_some_and(|parent| self.tray_of_root(parent).is_some())
+        {
+            return Ok(());
+        }
+
+        // Key bindings apply to the tray on the screen where the input happened.
+        let input_root = match event {
+            KeyPress(event) | KeyRelease(event) => Some(event.root),
+            ButtonPress(event) | ButtonRelease(event) => Some(event.root),
+            _ => None,
+        };
+        if let Some(index) = input_root.and_then(|root| self.tray_of_root(root)) {
+            self.active_tray = index;
+        }
+
+        let is_any_mapped = self.trays.iter().any(|tray| tray.tray_embedder.is_mapped());
+
+        match event {
+            KeyPress(_) | KeyRelease(_) | ButtonPress(_) | ButtonRelease(_) if is_any_mapped => {
+                self.set_hide_deadline(self.config.window.auto_hide_timeout_ms);
+            }
+            _ => {}
+        }
+
         match event {
-            FocusOut(event) => {
+            EnterNotify(event) if self.tray_of_edge_window(event.event).is_some() => {
+                let index = self.tray_of_edge_window(event.event).unwrap();
+                self.trays[index].is_edge_entered = true;
+                self.timers.schedule(
+                    Instant::now()
+                        + Duration::from_millis(self.config.behavior.activation_edge_delay_ms),
+                    Timer::ActivateEdge(index),
+                );
+            }
+            LeaveNotify(event) if self.tray_of_edge_window(event.event).is_some() => {
+                let index = self.tray_of_edge_window(event.event).unwrap();
+                self.trays[index].is_edge_entered = false;
+            }
+            RandrScreenChangeNotify(event) => {
+                if let Some(index) = self.tray_of_root(event.root) {
+                    if let Some(activation_edge) = &self.trays[index].activation_edge {
+                        activation_edge.place()?;
+                    }
+                }
+            }
+            FocusOut(event)
                 if self.config.window.auto_hide
                     && event.mode == xproto::NotifyMode::NORMAL
-                    && event.detail == xproto::NotifyDetail::NONLINEAR
-                    && event.event == self.tray_embedder.window()
-                {
-                    self.tray_embedder.hide()?;
+                    && event.detail == xproto::NotifyDetail::NONLINEAR =>
+            {
+                if let Some(index) = self.tray_of_window(event.event) {
+                    self.auto_hide_window(index)?;
                 }
             }
+            // The auto-repeat of the server is ignored while the key is held.
+            KeyPress(event) if self.held_key == Some(event.detail) => {}
             KeyPress(event) => {
                 self.xkb_state
                     .update_key(event.detail as u32, KeyState::Down);
+                let repeat_delay_ms = self.config.behavior.key_repeat_delay_ms;
+                if repeat_delay_ms > 0 {
+                    self.held_key = Some(event.detail);
+                    self.has_key_repeated = false;
+                    if self.repeatable_key_binding(event.detail).is_some() {
+                        self.timers.schedule(
+                            Instant::now() + Duration::from_millis(repeat_delay_ms),
+                            Timer::RepeatKey(event.detail),
+                        );
+                    }
+                }
+                let keysym = self.xkb_state.get_keysym(event.detail as u32);
+                let tray_embedder = &self.trays[self.active_tray].tray_embedder;
+                if tray_embedder.focused_icon().is_some()
+                    && keysym.get() != xkbcommon_sys::XKB_KEY_Escape
+                {
+                    tray_embedder.forward_key_event(event)?;
+                }
+            }
+            // Key bindings are disabled while the key events are forwarded to the icon.
+            KeyRelease(event)
+                if self.trays[self.active_tray]
+                    .tray_embedder
+                    .focused_icon()
+                    .is_some() =>
+            {
+                self.release_held_key();
+                self.xkb_state.update_key(event.detail as u32, KeyState::Up);
+                let keysym = self.xkb_state.get_keysym(event.detail as u32);
+                let tray_embedder = &mut self.trays[self.active_tray].tray_embedder;
+                if keysym.get() == xkbcommon_sys::XKB_KEY_Escape {
+                    tray_embedder.unfocus_icon()?;
+                } else {
+                    tray_embedder.forward_key_event(event)?;
+                }
             }
             KeyRelease(event) => {
+                let has_repeated = self.held_key == Some(event.detail) && self.has_key_repeated;
+                if self.held_key == Some(event.detail) {
+                    self.release_held_key();
+                }
                 self.xkb_state.update_key(event.detail as u32, KeyState::Up);
                 let keysym = self.xkb_state.get_keysym(event.detail as u32);
                 let modifiers = self.xkb_state.get_modifiers();
                 if let Some(index) = self.action_table.get(&(keysym, modifiers)) {
-                    self.handle_key_binding(*index)?;
+                    if !has_repeated {
+                        self.handle_key_binding(*index)?;
+                    }
                 }
             }
-            LeaveNotify(event) => {
+            LeaveNotify(event)
                 if self.config.window.auto_hide
                     && event.mode == xproto::NotifyMode::NORMAL
-                    && event.detail == xproto::NotifyDetail::ANCESTOR
-                    && event.event == self.tray_embedder.window()
-                {
-                    self.tray_embedder.hide()?;
+                    && event.detail == xproto::NotifyDetail::ANCESTOR =>
+            {
+                if let Some(index) = self.tray_of_window(event.event) {
+                    self.auto_hide_window(index)?;
                 }
             }
             ClientMessage(event)
-                if event.type_ == self.atoms.WM_PROTOCOLS && event.format == 32 =>
+                if event.type_ == self.atoms.WM_PROTOCOLS
+                    && event.format == 32
+                    && event.data.as_data32()[0] == self.atoms._NET_WM_PING
+                    && self.tray_of_root(event.window).is_some() =>
             {
-                let [protocol, ..] = event.data.as_data32();
+                // Our own replies to the pings from the window manager also come here, but they
+                // match no icon.
+                let icon = event.data.as_data32()[2];
+                for tray in &mut self.trays {
+                    tray.tray_embedder.handle_ping_reply(icon);
+                }
+            }
+            ClientMessage(event)
+                if event.type_ == self.atoms.WM_PROTOCOLS
+                    && event.format == 32
+                    && self.tray_of_window(event.window).is_some() =>
+            {
+                let [protocol, _timestamp, value_lo, value_hi, _] = event.data.as_data32();
+                let index = self.tray_of_window(event.window).unwrap();
+                let tray = &mut self.trays[index];
                 if protocol == self.atoms._NET_WM_PING {
-                    let screen = &self.connection.setup().roots[self.screen_num];
-                    let mut reply_event = event.clone();
+                    let screen = &self.connection.setup().roots[tray.screen_num];
+                    let mut reply_event = *event;
                     reply_event.window = screen.root;
                     self.connection
                         .send_event(
@@ -280,19 +1194,38 @@ impl App {
                         )
                         .context("reply _NET_WM_PING")?;
                 } else if protocol == self.atoms._NET_WM_SYNC_REQUEST {
-                    self.tray_embedder.request_redraw();
+                    tray.tray_embedder.request_sync(sync::Int64 {
+                        hi: value_hi as i32,
+                        lo: value_lo,
+                    });
                 } else if protocol == self.atoms.WM_DELETE_WINDOW {
-                    self.tray_embedder.hide()?;
+                    tray.tray_embedder.hide()?;
+                } else if protocol == self.atoms.WM_SAVE_YOURSELF {
+                    log::info!("save yourself requested by the session manager");
+                    let command = env::args_os().collect::<Vec<_>>();
+                    tray.tray_embedder.set_command(&command)?;
+                }
+            }
+            ScreensaverNotify(event) => {
+                log::info!("screen saver state changed: {:?}", event.state);
+                self.is_screen_saver_active = event.state != screensaver::State::OFF;
+                // Drawing has been suspended, so refresh the windows once.
+                if !self.is_screen_saver_active {
+                    for tray in &mut self.trays {
+                        tray.tray_embedder.request_redraw();
+                    }
                 }
             }
-            XkbStateNotify(event) => self.xkb_state.update_mask(&event),
+            XkbStateNotify(event) => self.xkb_state.update_mask(event),
             _ => {}
         }
 
-        self.tray_embedder.handle_x11_event(&event, control_flow)?;
-
-        if let Some(tray_event) = self.tray_manager.translate_event(&event)? {
-            self.handle_tray_event(tray_event);
+        for index in 0..self.trays.len() {
+            let tray = &mut self.trays[index];
+            tray.tray_embedder.handle_x11_event(event, control_flow)?;
+            if let Some(tray_event) = tray.tray_manager.translate_event(event)? {
+                self.handle_tray_event(index, tray_event)?;
+            }
         }
 
         Ok(())
@@ -304,71 +1237,491 @@ impl App {
         add_interest_entry(epoll_fd, &*self.connection, EVENT_KIND_X11)?;
         add_interest_entry(epoll_fd, &self.signal_fd, EVENT_KIND_SIGNAL)?;
 
-        let mut epoll_events = [epoll::EpollEvent::empty(); 2];
+        let mut epoll_events = [epoll::EpollEvent::empty(); 8];
         let mut control_flow = ControlFlow::Continue(());
 
         'outer: loop {
-            let available_fds = epoll::epoll_wait(epoll_fd, &mut epoll_events, -1).unwrap_or(0);
+            let timeout = self
+                .timers
+                .timeout(Instant::now())
+                .map_or(-1, |timeout| timeout.as_micros().div_ceil(1000) as isize);
+            let available_fds =
+                epoll::epoll_wait(epoll_fd, &mut epoll_events, timeout).unwrap_or(0);
 
             for epoll_event in &epoll_events[0..available_fds] {
-                if epoll_event.data() == EVENT_KIND_X11 {
-                    while let Some(event) = self.connection.poll_for_event()? {
-                        self.handle_x11_event(&event, &mut control_flow)?;
+                match epoll_event.data() {
+                    EVENT_KIND_X11 => {
+                        while let Some(event) = self.connection.poll_for_event()? {
+                            if let Some(metrics) = &mut self.metrics {
+                                metrics.record_event();
+                            }
+                            self.handle_x11_event(&event, &mut control_flow)?;
 
-                        if matches!(control_flow, ControlFlow::Break(())) {
-                            break 'outer;
+                            if matches!(control_flow, ControlFlow::Break(())) {
+                                if !self.begin_handover()? {
+                                    break 'outer;
+                                }
+                                control_flow = ControlFlow::Continue(());
+                            }
                         }
                     }
-                } else if epoll_event.data() == EVENT_KIND_SIGNAL {
-                    if let Some(signal) = self.signal_fd.read_signal()? {
-                        self.handle_signal(signal, &mut control_flow)?;
+                    EVENT_KIND_SIGNAL => {
+                        if let Some(signal) = self.signal_fd.read_signal()? {
+                            self.handle_signal(signal, &mut control_flow)?;
 
-                        if matches!(control_flow, ControlFlow::Break(())) {
-                            break 'outer;
+                            if matches!(control_flow, ControlFlow::Break(())) {
+                                if !self.begin_handover()? {
+                                    break 'outer;
+                                }
+                                control_flow = ControlFlow::Continue(());
+                            }
                         }
                     }
-                } else {
-                    unreachable!();
+                    kind if kind >= EVENT_KIND_CUSTOM_ITEM => {
+                        let index = (kind - EVENT_KIND_CUSTOM_ITEM) as usize;
+                        self.handle_custom_item_output(epoll_fd, index)?;
+                    }
+                    _ => unreachable!(),
+                }
+            }
+
+            for timer in self.timers.take_expired(Instant::now()) {
+                self.handle_timer(epoll_fd, timer)?;
+            }
+
+            self.handle_tick()?;
+
+            if self.is_exit_requested {
+                self.is_exit_requested = false;
+                if !self.begin_handover()? {
+                    break;
                 }
+            }
 
-                self.handle_tick()?;
+            if let Some(deadline) = self.handover_deadline {
+                if Instant::now() >= deadline
+                    || self
+                        .trays
+                        .iter()
+                        .all(|tray| !tray.tray_manager.is_managed())
+                {
+                    break;
+                }
             }
         }
 
         Ok(())
     }
 
-    fn ungrab_global_keys(&self) -> anyhow::Result<()> {
-        for key_binding in self
-            .config
-            .key_bindings
-            .iter()
-            .filter(|key_binding| key_binding.global())
+    // Starts waiting for a successor tray instead of exiting immediately. Returns false if the
+    // application should exit now.
+    fn begin_handover(&mut self) -> anyhow::Result<bool> {
+        let timeout_ms = self.config.behavior.handover_timeout_ms;
+        if timeout_ms == 0
+            || self.handover_deadline.is_some()
+            || self
+                .trays
+                .iter()
+                .all(|tray| !tray.tray_manager.is_managed())
         {
-            let keycode = self
-                .xkb_state
-                .lookup_keycode(key_binding.keysym())
-                .context("lookup keycode")?;
-            ungrab_key(
-                &*self.connection,
-                self.screen_num,
-                keycode,
-                key_binding.modifiers(),
-            )?;
+            return Ok(false);
+        }
+
+        log::info!("wait for a successor tray up to {}ms", timeout_ms);
+
+        for tray in &mut self.trays {
+            tray.tray_embedder.hide()?;
+        }
+        self.ungrab_global_keys()?;
+
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        self.handover_deadline = Some(deadline);
+        self.timers.schedule(deadline, Timer::HandoverTimeout);
+
+        Ok(true)
+    }
+
+    fn handle_timer(&mut self, epoll_fd: RawFd, timer: Timer) -> anyhow::Result<()> {
+        match timer {
+            Timer::RunCustomItem(index) => {
+                let command = &self.config.items.custom[index].command;
+                log::debug!("run custom item command: {}", command);
+                match CommandOutput::spawn(command) {
+                    Ok(output) => {
+                        add_interest_entry(
+                            epoll_fd,
+                            &output,
+                            EVENT_KIND_CUSTOM_ITEM + index as u64,
+                        )?;
+                        self.custom_item_outputs[index] = Some(output);
+                    }
+                    Err(error) => {
+                        log::error!("failed to run custom item command: {}", error);
+                        self.schedule_custom_item(index);
+                    }
+                }
+            }
+            Timer::AutoHideWindow(index) => {
+                // The window may have been focused again in the meantime.
+                let tray_embedder = &self.trays[index].tray_embedder;
+                if tray_embedder.is_mapped() {
+                    let focus = self
+                        .connection
+                        .get_input_focus()?
+                        .reply()
+                        .context("get input focus")?
+                        .focus;
+                    if focus != tray_embedder.window() {
+                        self.auto_hide_window(index)?;
+                    }
+                }
+            }
+            Timer::RemoveStartupSequence(index, startup_id) => {
+                if let Err(error) = self.trays[index]
+                    .tray_embedder
+                    .remove_startup_sequence(&startup_id)
+                {
+                    log::error!("failed to remove startup sequence: {}", error);
+                }
+            }
+            Timer::SelectionWaitTimeout(index) => {
+                let tray = &self.trays[index];
+                if let Some(owner) = tray.tray_manager.current_owner()? {
+                    return Err(SelectionOwnedError {
+                        screen_num: tray.screen_num,
+                        owner,
+                    }
+                    .into());
+                }
+            }
+            Timer::HideEmptyWindow(index) => {
+                let tray = &mut self.trays[index];
+                tray.is_empty_hide_scheduled = false;
+                if tray.tray_embedder.is_mapped() && tray.tray_embedder.is_empty() {
+                    tray.tray_embedder.hide()?;
+                }
+            }
+            Timer::RedrawDeferred(index) => {
+                self.trays[index].tray_embedder.redraw_deferred();
+            }
+            Timer::RepeatKey(keycode) => {
+                let is_any_mapped = self.trays.iter().any(|tray| tray.tray_embedder.is_mapped());
+                let tray_embedder = &self.trays[self.active_tray].tray_embedder;
+                if self.held_key == Some(keycode)
+                    && is_any_mapped
+                    && tray_embedder.focused_icon().is_none()
+                {
+                    // The modifiers may have changed to those of another key binding.
+                    if let Some(index) = self.repeatable_key_binding(keycode) {
+                        self.handle_key_binding(index)?;
+                        self.has_key_repeated = true;
+                        let repeat_interval_ms = self.config.behavior.key_repeat_interval_ms.max(1);
+                        self.timers.schedule(
+                            Instant::now() + Duration::from_millis(repeat_interval_ms),
+                            Timer::RepeatKey(keycode),
+                        );
+                    }
+                }
+            }
+            Timer::RenderFrame => {
+                // The frame is rendered in the next tick.
+                self.is_frame_scheduled = false;
+            }
+            Timer::RestartItem(index, pid) => {
+                let restart_item = &self.config.items.restart[index];
+                let is_running = pid
+                    .is_some_and(|pid| Path::new(&format!("/proc/{}", pid)).exists())
+                    || self
+                        .trays
+                        .iter()
+                        .any(|tray| tray.tray_embedder.has_class(&restart_item.class));
+                if is_running {
+                    log::info!("application is still running: {}", restart_item.class);
+                } else {
+                    log::info!("restart application: {}", restart_item.command);
+                    if let Err(error) = command::spawn_detached(&restart_item.command, &[]) {
+                        log::error!("failed to restart application: {}", error);
+                    }
+                }
+            }
+            Timer::ExitWhenEmpty => {
+                // Another icon may have been docked in the meantime.
+                if !self.has_icons() {
+                    log::info!("exit because the last tray icon has been removed");
+                    self.is_exit_requested = true;
+                }
+            }
+            Timer::PollPointer => {
+                self.poll_pointer()?;
+                let poll_interval_ms = self.config.behavior.hot_corner_poll_interval_ms.max(1);
+                self.timers.schedule(
+                    Instant::now() + Duration::from_millis(poll_interval_ms),
+                    Timer::PollPointer,
+                );
+            }
+            Timer::PingIcons => {
+                for tray in &mut self.trays {
+                    tray.tray_embedder.ping_icons()?;
+                }
+                self.timers.schedule(
+                    Instant::now()
+                        + Duration::from_millis(self.config.behavior.icon_ping_interval_ms),
+                    Timer::PingIcons,
+                );
+            }
+            Timer::ActivateEdge(index) => {
+                let tray = &mut self.trays[index];
+                let tray_embedder = &mut tray.tray_embedder;
+                if tray.is_edge_entered
+                    && !tray_embedder.is_mapped()
+                    && (self.config.behavior.show_when_empty || !tray_embedder.is_empty())
+                {
+                    log::info!("show window by the activation edge");
+                    tray_embedder.show()?;
+                    self.active_tray = index;
+                }
+            }
+            Timer::HandoverTimeout => {
+                log::info!("no successor tray has taken over the tray selection");
+            }
+            Timer::HideWindow => {
+                // The deadline may have been cleared or postponed since this was scheduled.
+                if self
+                    .hide_deadline
+                    .is_some_and(|deadline| deadline <= Instant::now())
+                {
+                    self.hide_deadline = None;
+                    for tray in &mut self.trays {
+                        if tray.tray_embedder.is_mapped() {
+                            tray.tray_embedder.hide()?;
+                        }
+                    }
+                }
+            }
+            Timer::ExpireMessages(index) => {
+                let tray_events = self.trays[index]
+                    .tray_manager
+                    .expire_messages(Instant::now());
+                for tray_event in tray_events {
+                    self.handle_tray_event(index, tray_event)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_custom_item_output(&mut self, epoll_fd: RawFd, index: usize) -> anyhow::Result<()> {
+        let Some(output) = &mut self.custom_item_outputs[index] else {
+            return Ok(());
+        };
+
+        let is_finished = output.read().unwrap_or_else(|error| {
+            log::error!("failed to read custom item output: {}", error);
+            true
+        });
+        if !is_finished {
+            return Ok(());
+        }
+
+        let output = self.custom_item_outputs[index].take().unwrap();
+        epoll::epoll_ctl(
+            epoll_fd,
+            epoll::EpollOp::EpollCtlDel,
+            output.as_raw_fd(),
+            None,
+        )
+        .context("remove an interest entry from epoll fd")?;
+
+        match output.finish() {
+            Ok(content) => {
+                let title = content.lines().next().unwrap_or_default().trim().to_owned();
+                for tray in &mut self.trays {
+                    tray.tray_embedder
+                        .change_custom_item_title(index, title.clone());
+                }
+            }
+            Err(error) => {
+                log::error!("failed to wait custom item command: {}", error);
+            }
         }
+
+        self.schedule_custom_item(index);
+
         Ok(())
     }
+
+    fn schedule_custom_item(&mut self, index: usize) {
+        let interval = Duration::from_secs_f64(self.config.items.custom[index].interval.max(0.0));
+        self.timers
+            .schedule(Instant::now() + interval, Timer::RunCustomItem(index));
+    }
+
+    fn ungrab_global_keys(&mut self) -> anyhow::Result<()> {
+        self.key_grabber.ungrab_all(&*self.connection)
+    }
 }
 
 impl Drop for App {
     fn drop(&mut self) {
         self.ungrab_global_keys().ok();
-        if let Err(error) = self.tray_manager.release_tray_selection() {
-            log::error!(
-                "an error occurred while releasing tray selection: {}",
-                error
+        for tray in &mut self.trays {
+            if let Err(error) = tray.tray_manager.release_tray_selection() {
+                log::error!(
+                    "an error occurred while releasing tray selection: {}",
+                    error
+                );
+            }
+        }
+    }
+}
+
+impl Tray {
+    fn new(
+        connection: &Rc<XCBConnection>,
+        screen_num: usize,
+        atoms: &Rc<Atoms>,
+        desktop_entries: Rc<DesktopEntryIndex>,
+        config: &Config,
+    ) -> anyhow::Result<Self> {
+        let window_size = Size {
+            width: config.window.default_width,
+            height: config.ui.icon_size.max(config.ui.text_size) + config.ui.item_padding * 2.0,
+        }
+        .snap();
+
+        let quirks = Quirks::resolve(&**connection, screen_num, atoms, &config.window.wm_quirks)?;
+        let mut window_config = config.window.clone();
+        quirks.apply(&mut window_config);
+
+        let mut tray_embedder = TrayEmbedder::new(
+            connection.clone(),
+            screen_num,
+            atoms.clone(),
+            &window_config,
+            config.groups.clone(),
+            config.click,
+            window_size,
+        )?;
+
+        tray_embedder.set_background(config.ui.window_background)?;
+        tray_embedder.set_opacity(config.ui.window_opacity)?;
+        if config.behavior.remember_usage {
+            tray_embedder.load_usage_stats();
+        }
+        tray_embedder.set_sort_order(config.ui.sort);
+        tray_embedder.set_message_history_size(config.ui.message_history_size);
+        tray_embedder.set_close_on_outside_click(config.behavior.close_on_outside_click);
+        tray_embedder.set_reverse(config.ui.reverse);
+        tray_embedder.set_flash_duration(
+            config
+                .ui
+                .flash_on_update
+                .then(|| Duration::from_millis(config.ui.flash_duration_ms)),
+        );
+        tray_embedder.set_count_pattern(config.ui.count_regex()?);
+        tray_embedder.set_redraw_rate(
+            config.behavior.icon_redraw_rate,
+            config.items.redraw_rate.clone(),
+        );
+        tray_embedder.set_item_styles(&config.items.styles, config.ui.icon_size);
+        tray_embedder.set_desktop_entries(desktop_entries);
+        if let Some(icon_theme) = &config.ui.icon_theme {
+            tray_embedder.set_icon_theme(Some(IconTheme::new(
+                icon_theme.clone(),
+                config.ui.icon_size,
+            )));
+        }
+
+        for pinned_item in &config.items.pinned {
+            tray_embedder.add_pinned_item(
+                pinned_item.class.clone(),
+                pinned_item.label.clone(),
+                pinned_item.command.clone(),
             );
         }
+
+        for custom_item in &config.items.custom {
+            tray_embedder.add_custom_item(custom_item.action.clone());
+        }
+
+        let activation_edge = config
+            .behavior
+            .activation_edge
+            .map(|edge| ActivationEdge::new(connection.clone(), screen_num, edge))
+            .transpose()?;
+
+        let mut tray_manager = TrayManager::new(connection.clone(), screen_num, atoms.clone())?;
+        tray_manager.set_redock_nudge(config.behavior.redock_nudge);
+        tray_manager.set_message_queue(
+            config.behavior.message_queue_size,
+            config.behavior.message_overflow,
+        );
+
+        Ok(Self {
+            screen_num,
+            tray_embedder,
+            tray_manager,
+            render_context: None,
+            is_empty_hide_scheduled: false,
+            hot_corner: HotCorner::new(
+                config.behavior.hot_corner,
+                Duration::from_millis(config.behavior.hot_corner_delay_ms),
+            ),
+            activation_edge,
+            is_edge_entered: false,
+        })
+    }
+
+    fn needs_redraw(&self) -> bool {
+        self.tray_embedder.is_viewable()
+            && (self.render_context.is_none()
+                || self.tray_embedder.should_layout()
+                || self.tray_embedder.should_redraw())
+    }
+
+    fn redraw(
+        &mut self,
+        connection: &Rc<XCBConnection>,
+        config: &Config,
+        metrics: Option<&mut Metrics>,
+    ) -> anyhow::Result<()> {
+        if !self.tray_embedder.is_viewable() {
+            return Ok(());
+        }
+
+        let started_at = Instant::now();
+
+        let should_layout = self.tray_embedder.should_layout() || self.render_context.is_none();
+
+        if should_layout {
+            let new_size = self.tray_embedder.layout(&config.ui)?;
+            // The pixmap is reused unless the window is resized.
+            if self
+                .render_context
+                .as_ref()
+                .is_none_or(|render_context| render_context.size() != new_size)
+            {
+                // Free the old pixmap before creating a new one.
+                self.render_context = None;
+                self.render_context = Some(RenderContext::new(
+                    connection.clone(),
+                    self.screen_num,
+                    self.tray_embedder.window(),
+                    new_size,
+                )?);
+            }
+        }
+
+        if should_layout || self.tray_embedder.should_redraw() {
+            let render_context = self.render_context.as_ref().unwrap();
+            self.tray_embedder
+                .draw(should_layout, &config.ui, render_context)?;
+            if let Some(metrics) = metrics {
+                metrics.record_frame(should_layout, started_at.elapsed());
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -399,52 +1752,84 @@ fn build_action_table(key_bindings: &[KeyBinding]) -> ActionTable {
     action_table
 }
 
+// Interns the atoms of SendClientMessage actions, and validates their data.
+fn intern_message_atoms(
+    connection: &impl Connection,
+    key_bindings: &[KeyBinding],
+) -> anyhow::Result<HashMap<String, xproto::Atom>> {
+    let mut cookies = HashMap::new();
+    for action in key_bindings
+        .iter()
+        .flat_map(|key_binding| key_binding.actions())
+    {
+        if let Action::SendClientMessage { atom, data, .. } = action {
+            if data.len() > 5 {
+                return Err(anyhow!(
+                    "SendClientMessage has more than 5 data items: {}",
+                    atom
+                ));
+            }
+            if !cookies.contains_key(atom) {
+                let cookie = connection.intern_atom(false, atom.as_bytes())?;
+                cookies.insert(atom.clone(), cookie);
+            }
+        }
+    }
+    cookies
+        .into_iter()
+        .map(|(name, cookie)| {
+            let atom = cookie
+                .reply()
+                .with_context(|| format!("intern atom: {}", name))?
+                .atom;
+            Ok((name, atom))
+        })
+        .collect()
+}
+
+// Returns the parent of the window that the event is about, if the event is reported by
+// SUBSTRUCTURE_NOTIFY on the parent rather than by STRUCTURE_NOTIFY on the window itself.
+fn substructure_event_parent(event: &protocol::Event) -> Option<xproto::Window> {
+    use protocol::Event::*;
+
+    let (event, window) = match event {
+        CreateNotify(event) => return Some(event.parent),
+        CirculateNotify(event) => (event.event, event.window),
+        ConfigureNotify(event) => (event.event, event.window),
+        DestroyNotify(event) => (event.event, event.window),
+        GravityNotify(event) => (event.event, event.window),
+        MapNotify(event) => (event.event, event.window),
+        ReparentNotify(event) => (event.event, event.window),
+        UnmapNotify(event) => (event.event, event.window),
+        _ => return None,
+    };
+    (event != window).then_some(event)
+}
+
 fn create_signal_fd() -> anyhow::Result<SignalFd> {
     let mut sigset = SigSet::empty();
+    // These are handled by the event loop to exit cleanly, e.g. releasing the selection and
+    // removing the pid file.
     sigset.add(Signal::SIGINT);
+    sigset.add(Signal::SIGTERM);
+    sigset.add(Signal::SIGHUP);
     sigset.thread_block().context("add set of signals")?;
-    Ok(SignalFd::new(&sigset).context("create signal fd")?)
+    SignalFd::new(&sigset).context("create signal fd")
 }
 
 fn create_xkb_state(connection: &XCBConnection) -> anyhow::Result<xkbcommon::State> {
     let context = xkbcommon::Context::new();
     let device_id =
         xkbcommon::DeviceId::core_keyboard(connection).context("get the core keyboard")?;
-    let keymap = xkbcommon::Keymap::from_device(context, &connection, device_id)
+    let keymap = xkbcommon::Keymap::from_device(context, connection, device_id)
         .context("create a keymap from the device")?;
     Ok(xkbcommon::State::from_keymap(keymap))
 }
 
-fn grab_key(
+fn setup_xkb_extension(
     connection: &impl Connection,
-    screen_num: usize,
-    keycode: u32,
-    modifiers: Modifiers,
+    detectable_auto_repeat: bool,
 ) -> anyhow::Result<()> {
-    let screen = &connection.setup().roots[screen_num];
-    let modifiers = modifiers.without_locks();
-    for modifiers in [
-        modifiers,
-        modifiers | Modifiers::CAPS_LOCK,
-        modifiers | Modifiers::NUM_LOCK,
-        modifiers | Modifiers::CAPS_LOCK | Modifiers::NUM_LOCK,
-    ] {
-        connection
-            .grab_key(
-                true,
-                screen.root,
-                u16::from(modifiers).into(),
-                keycode as u8,
-                xproto::GrabMode::ASYNC,
-                xproto::GrabMode::ASYNC,
-            )?
-            .check()
-            .context("grab key")?;
-    }
-    Ok(())
-}
-
-fn setup_xkb_extension(connection: &impl Connection) -> anyhow::Result<()> {
     let reply = connection
         .xkb_use_extension(1, 0)?
         .reply()
@@ -468,27 +1853,89 @@ fn setup_xkb_extension(connection: &impl Connection) -> anyhow::Result<()> {
             .context("select xkb events")?;
     }
 
+    // The auto-repeat of the server sends only KeyPress events, so that it can be ignored.
+    if detectable_auto_repeat {
+        let reply = connection
+            .xkb_per_client_flags(
+                xkb::ID::USE_CORE_KBD.into(),
+                xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT,
+                xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT,
+                xkb::BoolCtrl::from(0u32),
+                xkb::BoolCtrl::from(0u32),
+                xkb::BoolCtrl::from(0u32),
+            )?
+            .reply()
+            .context("set detectable auto-repeat")?;
+        if !reply
+            .supported
+            .contains(xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT)
+        {
+            log::warn!("detectable auto-repeat not supported.");
+        }
+    }
+
     Ok(())
 }
 
-fn ungrab_key(
+// The screen saver extension is optional.
+fn setup_screensaver_extension(
     connection: &impl Connection,
-    screen_num: usize,
-    keycode: u32,
-    modifiers: Modifiers,
+    screen_nums: impl Iterator<Item = usize>,
 ) -> anyhow::Result<()> {
-    let screen = &connection.setup().roots[screen_num];
-    let modifiers = modifiers.without_locks();
-    for modifiers in [
-        modifiers,
-        modifiers | Modifiers::CAPS_LOCK,
-        modifiers | Modifiers::NUM_LOCK,
-        modifiers | Modifiers::CAPS_LOCK | Modifiers::NUM_LOCK,
-    ] {
+    if connection
+        .extension_information(screensaver::X11_EXTENSION_NAME)?
+        .is_none()
+    {
+        log::warn!("screen saver extension not supported.");
+        return Ok(());
+    }
+
+    for screen_num in screen_nums {
+        let screen = &connection.setup().roots[screen_num];
         connection
-            .ungrab_key(keycode as u8, screen.root, u16::from(modifiers).into())?
+            .screensaver_select_input(screen.root, screensaver::Event::NOTIFY_MASK)?
             .check()
-            .context("ungrab key")?;
+            .context("select screen saver events")?;
     }
+
     Ok(())
 }
+
+// The history is only for troubleshooting, so the failures are just logged.
+fn record_dock_event(
+    dock_history: Option<&DockHistory>,
+    event: DockEvent,
+    class: Option<&str>,
+    title: &str,
+) {
+    if let Some(dock_history) = dock_history {
+        if let Err(error) = dock_history.record(event, class, title, SystemTime::now()) {
+            log::warn!("failed to record dock history: {:#}", error);
+        }
+    }
+}
+
+// A script that fails to load is skipped with the error logged, as well as the plugins.
+#[cfg(feature = "scripting")]
+fn load_script(path: &str) -> Option<Script> {
+    if path.is_empty() {
+        return None;
+    }
+    match Script::load(Path::new(path)) {
+        Ok(script) => {
+            log::info!("script loaded: {}", path);
+            Some(script)
+        }
+        Err(error) => {
+            log::error!("failed to load script {}: {:#}", path, error);
+            None
+        }
+    }
+}
+
+fn system_tray_orientation(orientation: TrayOrientation) -> SystemTrayOrientation {
+    match orientation {
+        TrayOrientation::Horizontal => SystemTrayOrientation::HORZONTAL,
+        TrayOrientation::Vertical => SystemTrayOrientation::VERTICAL,
+    }
+}
@@ -1,9 +1,16 @@
 x11rb::atom_manager! {
     pub Atoms: AtomsCookie {
+        COMPOUND_TEXT,
         MANAGER,
         UTF8_STRING,
         WM_DELETE_WINDOW,
+        WM_SAVE_YOURSELF,
         WM_PROTOCOLS,
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_STARTUP_INFO,
+        _NET_STARTUP_INFO_BEGIN,
+        _NET_SUPPORTING_WM_CHECK,
         _NET_SYSTEM_TRAY_COLORS,
         _NET_SYSTEM_TRAY_MESSAGE_DATA,
         _NET_SYSTEM_TRAY_OPCODE,
@@ -15,12 +22,19 @@ x11rb::atom_manager! {
         _NET_WM_PING,
         _NET_WM_STATE,
         _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_DEMANDS_ATTENTION,
         _NET_WM_STATE_STAYS_ON_TOP,
         _NET_WM_STATE_STICKY,
         _NET_WM_SYNC_REQUEST,
+        _NET_WM_SYNC_REQUEST_COUNTER,
+        _NET_WM_WINDOW_OPACITY,
         _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DIALOG,
+        _NET_WM_WINDOW_TYPE_DOCK,
         _NET_WM_WINDOW_TYPE_NORMAL,
+        _NET_WM_WINDOW_TYPE_NOTIFICATION,
         _NET_WM_WINDOW_TYPE_UTILITY,
+        _MOTIF_WM_HINTS,
         _XEMBED,
         _XEMBED_INFO,
     }
@@ -57,7 +57,31 @@ impl Color {
         }
     }
 
-    pub const fn to_u16_components(&self) -> [u16; 4] {
+    pub const fn to_rgb(self) -> u32 {
+        u32::from_be_bytes([0, self.red, self.green, self.blue])
+    }
+
+    // Composites the color over the background (source-over).
+    pub fn blend(self, background: Self) -> Self {
+        let [red, green, blue, alpha] = self.to_f64_components();
+        let [bg_red, bg_green, bg_blue, bg_alpha] = background.to_f64_components();
+        let out_alpha = alpha + bg_alpha * (1.0 - alpha);
+        if out_alpha <= 0.0 {
+            return Self::new(0, 0, 0, 0);
+        }
+        let component = |c: f64, bg_c: f64| {
+            ((c * alpha + bg_c * bg_alpha * (1.0 - alpha)) / out_alpha * u8::MAX as f64).round()
+                as u8
+        };
+        Self {
+            red: component(red, bg_red),
+            green: component(green, bg_green),
+            blue: component(blue, bg_blue),
+            alpha: (out_alpha * u8::MAX as f64).round() as u8,
+        }
+    }
+
+    pub const fn to_u16_components(self) -> [u16; 4] {
         let r = self.red as u16;
         let g = self.green as u16;
         let b = self.blue as u16;
@@ -65,7 +89,14 @@ impl Color {
         [r << 8 | r, g << 8 | g, b << 8 | b, a << 8 | a]
     }
 
-    pub fn to_f64_components(&self) -> [f64; 4] {
+    pub fn scale_alpha(self, factor: f64) -> Self {
+        Self {
+            alpha: (self.alpha as f64 * factor.clamp(0.0, 1.0)).round() as u8,
+            ..self
+        }
+    }
+
+    pub fn to_f64_components(self) -> [f64; 4] {
         [
             self.red as f64 / u8::MAX as f64,
             self.green as f64 / u8::MAX as f64,
@@ -192,6 +223,24 @@ impl Serialize for Color {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_blend() {
+        let background = Color::from_rgb(0x202020);
+        assert_eq!(
+            Color::from_rgb(0x808080).blend(background),
+            Color::from_rgb(0x808080)
+        );
+        assert_eq!(Color::from_rgba(0x80808000).blend(background), background);
+        assert_eq!(
+            Color::from_rgba(0xffffff80).blend(Color::BLACK),
+            Color::from_rgb(0x808080)
+        );
+        assert_eq!(
+            Color::from_rgba(0xff000080).blend(Color::from_rgba(0x00000000)),
+            Color::from_rgba(0xff000080)
+        );
+    }
+
     #[test]
     fn test_from_str() {
         assert_eq!(
@@ -327,4 +376,16 @@ mod tests {
             Err(ColorParseError::ParseIntError(_))
         ));
     }
+
+    #[test]
+    fn test_scale_alpha() {
+        assert_eq!(
+            Color::from_rgba(0x12345680).scale_alpha(0.5),
+            Color::from_rgba(0x12345640)
+        );
+        assert_eq!(
+            Color::from_rgb(0x123456).scale_alpha(2.0),
+            Color::from_rgb(0x123456)
+        );
+    }
 }
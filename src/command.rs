@@ -0,0 +1,103 @@
+use nix::fcntl::{self, FcntlArg, OFlag};
+use nix::sys::signal::SigSet;
+use std::io::{self, Read as _};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::process::CommandExt as _;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+// Runs the command in the background without waiting for it to exit.
+pub fn spawn_detached(command: &str, envs: &[(&str, &str)]) -> io::Result<()> {
+    // The intermediate shell exits immediately, so that the command is reparented to init and
+    // never becomes a zombie.
+    shell_command("sh -c \"$0\" &", Some(command))
+        .envs(envs.iter().copied())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status()?;
+    Ok(())
+}
+
+// The output of a command being run, which is read without blocking as it becomes available.
+#[derive(Debug)]
+pub struct CommandOutput {
+    child: Child,
+    stdout: ChildStdout,
+    buffer: Vec<u8>,
+}
+
+impl CommandOutput {
+    pub fn spawn(command: &str) -> io::Result<Self> {
+        let mut child = shell_command(command, None)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        fcntl::fcntl(stdout.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+        Ok(Self {
+            child,
+            stdout,
+            buffer: Vec::new(),
+        })
+    }
+
+    // Reads the available output, and returns true if the output has reached EOF.
+    pub fn read(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 1024];
+        loop {
+            match self.stdout.read(&mut chunk) {
+                Ok(0) => return Ok(true),
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<String> {
+        let status = self.child.wait()?;
+        if !status.success() {
+            log::warn!("command exited with {}", status);
+        }
+        Ok(String::from_utf8_lossy(&self.buffer).into_owned())
+    }
+}
+
+impl AsRawFd for CommandOutput {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stdout.as_raw_fd()
+    }
+}
+
+fn shell_command(script: &str, argument: Option<&str>) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(script);
+    if let Some(argument) = argument {
+        command.arg(argument);
+    }
+    unsafe {
+        // Signals blocked for the signal fd are inherited by child processes.
+        command.pre_exec(|| {
+            SigSet::all().thread_unblock()?;
+            Ok(())
+        });
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_command_output() {
+        let mut output = CommandOutput::spawn("echo foo; echo bar").unwrap();
+        while !output.read().unwrap() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(output.finish().unwrap(), "foo\nbar\n");
+    }
+}
@@ -1,6 +1,12 @@
+use anyhow::{bail, Context as _};
+use regex::Regex;
 use serde::de;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
 use std::str::FromStr as _;
 
 use crate::color::Color;
@@ -14,10 +20,74 @@ pub struct Config {
     pub window: WindowConfig,
     pub ui: UIConfig,
     pub key_bindings: Vec<KeyBinding>,
+    pub profile: KeyBindingProfile,
+    pub items: ItemsConfig,
+    pub groups: GroupsConfig,
+    pub behavior: BehaviorConfig,
+    pub click: ClickConfig,
     pub log_level: LogLevel,
+    pub log_file: Cow<'static, str>,
 }
 
+/// The config file, the files included by it or the overrides are invalid.
+#[derive(Debug)]
+pub struct ConfigError(anyhow::Error);
+
+impl ConfigError {
+    pub fn new(error: impl Into<anyhow::Error>) -> Self {
+        Self(error.into())
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl error::Error for ConfigError {}
+
 impl Config {
+    /// Loads the config file merged with the files listed in its "include".
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let table = load_table(path, 0).map_err(ConfigError)?;
+        toml::Value::Table(table)
+            .try_into()
+            .context("parse config file")
+            .map_err(ConfigError)
+    }
+
+    /// Sets the values of the dotted keys (e.g. "ui.icon_size"). A value is parsed as TOML, or
+    /// taken as a string if it is not valid TOML.
+    pub fn with_overrides(self, overrides: &[(String, String)]) -> Result<Self, ConfigError> {
+        if overrides.is_empty() {
+            return Ok(self);
+        }
+        self.apply_overrides(overrides).map_err(ConfigError)
+    }
+
+    fn apply_overrides(self, overrides: &[(String, String)]) -> anyhow::Result<Self> {
+        let mut table = to_table(&self)?;
+        for (key, value) in overrides {
+            set_value(&mut table, key, parse_value(value))?;
+        }
+        let config: Self = toml::Value::Table(table)
+            .try_into()
+            .context("apply config overrides")?;
+
+        // Unknown keys are ignored by the deserializer, so they are found by serializing it again.
+        let table = to_table(&config)?;
+        for (key, _) in overrides {
+            if get_value(&table, key).is_none() {
+                let error: toml::de::Error =
+                    de::Error::custom(format!("unknown config key: {}", key));
+                return Err(error.into());
+            }
+        }
+
+        Ok(config)
+    }
+
     pub fn to_toml(&self) -> String {
         use toml::ser::to_string;
         let mut s = String::new();
@@ -43,169 +113,47 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             log_level: LogLevel(log::LevelFilter::Error),
+            log_file: Cow::Borrowed(""),
             window: WindowConfig::default(),
             ui: UIConfig::default(),
-            key_bindings: vec![
-                KeyBinding::new(
-                    xkb::XKB_KEY_1,
-                    Modifiers::NONE,
-                    vec![Action::SelectItem { index: 0 }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_2,
-                    Modifiers::NONE,
-                    vec![Action::SelectItem { index: 1 }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_3,
-                    Modifiers::NONE,
-                    vec![Action::SelectItem { index: 2 }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_4,
-                    Modifiers::NONE,
-                    vec![Action::SelectItem { index: 3 }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_5,
-                    Modifiers::NONE,
-                    vec![Action::SelectItem { index: 4 }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_6,
-                    Modifiers::NONE,
-                    vec![Action::SelectItem { index: 5 }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_7,
-                    Modifiers::NONE,
-                    vec![Action::SelectItem { index: 6 }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_8,
-                    Modifiers::NONE,
-                    vec![Action::SelectItem { index: 7 }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_9,
-                    Modifiers::NONE,
-                    vec![Action::SelectItem { index: 8 }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_0,
-                    Modifiers::NONE,
-                    vec![Action::DeselectItem],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_j,
-                    Modifiers::NONE,
-                    vec![Action::SelectNextItem],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_Down,
-                    Modifiers::NONE,
-                    vec![Action::SelectNextItem],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_n,
-                    Modifiers::CONTROL,
-                    vec![Action::SelectNextItem],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_k,
-                    Modifiers::NONE,
-                    vec![Action::SelectPreviousItem],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_Up,
-                    Modifiers::NONE,
-                    vec![Action::SelectPreviousItem],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_p,
-                    Modifiers::CONTROL,
-                    vec![Action::SelectPreviousItem],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_l,
-                    Modifiers::NONE,
-                    vec![Action::ClickSelectedItem {
-                        button: MouseButton::Left,
-                    }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_Return,
-                    Modifiers::NONE,
-                    vec![Action::ClickSelectedItem {
-                        button: MouseButton::Left,
-                    }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_h,
-                    Modifiers::NONE,
-                    vec![Action::ClickSelectedItem {
-                        button: MouseButton::Right,
-                    }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_Return,
-                    Modifiers::SHIFT,
-                    vec![Action::ClickSelectedItem {
-                        button: MouseButton::Right,
-                    }],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_q,
-                    Modifiers::NONE,
-                    vec![Action::HideWindow],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_Escape,
-                    Modifiers::NONE,
-                    vec![Action::HideWindow],
-                    false,
-                ),
-                KeyBinding::new(
-                    xkb::XKB_KEY_grave,
-                    Modifiers::SUPER,
-                    vec![Action::ToggleWindow],
-                    true,
-                ),
-            ],
+            items: ItemsConfig::default(),
+            groups: GroupsConfig::default(),
+            behavior: BehaviorConfig::default(),
+            click: ClickConfig::default(),
+            key_bindings: Vec::new(),
+            profile: KeyBindingProfile::default(),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(default)]
 pub struct WindowConfig {
     pub title: Cow<'static, str>,
     pub instance_name: Cow<'static, str>,
     pub class_name: Cow<'static, str>,
     pub default_width: f64,
+    pub auto_width: bool,
+    pub min_width: f64,
+    pub max_width: f64,
+    pub min_visible_items: usize,
+    pub max_visible_items: usize,
     pub auto_hide: bool,
+    pub auto_hide_timeout_ms: u64,
+    pub override_redirect: bool,
+    pub decorations: bool,
+    pub placement: Placement,
+    pub remember_position: bool,
+    pub preload: bool,
+    pub show_on_startup: bool,
+    pub all_screens: bool,
+    pub screen: Option<usize>,
+    pub tray_orientation: TrayOrientation,
+    #[serde(rename = "type")]
+    pub window_type: WindowType,
+    pub states: Vec<WindowState>,
     pub icon_theme_color: Color,
+    pub wm_quirks: Cow<'static, str>,
 }
 
 impl Default for WindowConfig {
@@ -215,21 +163,86 @@ impl Default for WindowConfig {
             instance_name: Cow::Borrowed("GeekTray"),
             class_name: Cow::Borrowed("GeekTray"),
             default_width: 480.0,
+            auto_width: false,
+            min_width: 240.0,
+            max_width: 960.0,
+            min_visible_items: 0,
+            max_visible_items: 0,
             auto_hide: true,
+            auto_hide_timeout_ms: 0,
+            override_redirect: false,
+            decorations: true,
+            placement: Placement::default(),
+            remember_position: false,
+            preload: false,
+            show_on_startup: false,
+            all_screens: false,
+            screen: None,
+            tray_orientation: TrayOrientation::default(),
+            window_type: WindowType::default(),
+            states: vec![
+                WindowState::Above,
+                WindowState::StaysOnTop,
+                WindowState::Sticky,
+            ],
             icon_theme_color: Color::WHITE,
+            wm_quirks: Cow::Borrowed("auto"),
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Placement {
+    #[default]
+    Center,
+    Pointer,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowType {
+    #[default]
+    Normal,
+    Dialog,
+    Utility,
+    Dock,
+    Notification,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum WindowState {
+    Above,
+    StaysOnTop,
+    Sticky,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub enum TrayOrientation {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 #[serde(default)]
 pub struct UIConfig {
     pub show_number: bool,
+    pub show_process_name: bool,
+    pub show_application_name: bool,
+    pub max_title_chars: usize,
+    pub title_ellipsis: TitleEllipsis,
+    pub sort: SortOrder,
+    pub reverse: bool,
     pub icon_size: f64,
+    pub icon_theme: Option<String>,
     pub text_size: f64,
     pub window_padding: f64,
     pub window_background: Color,
     pub window_foreground: Color,
+    pub window_opacity: f64,
+    pub window_border_size: f64,
+    pub window_border_color: Color,
     pub item_padding: f64,
     pub item_gap: f64,
     pub item_corner_radius: f64,
@@ -239,23 +252,59 @@ pub struct UIConfig {
     pub selected_item_font: FontDescription,
     pub selected_item_background: Color,
     pub selected_item_foreground: Color,
+    pub selection_style: SelectionStyle,
+    pub dim_unselected: f64,
+    pub attention_badge_color: Color,
+    pub flash_on_update: bool,
+    pub flash_duration_ms: u64,
+    pub flash_color: Color,
+    pub count_pattern: Cow<'static, str>,
+    pub count_badge_background: Color,
+    pub count_badge_foreground: Color,
+    pub message_history_size: usize,
+    pub empty_text: Cow<'static, str>,
+    pub show_footer: bool,
+    pub footer_font: FontDescription,
+    pub footer_background: Color,
+    pub footer_foreground: Color,
 }
 
 impl UIConfig {
     pub fn item_height(&self) -> f64 {
         self.icon_size + self.item_padding * 2.0
     }
+
+    // Returns None if count_pattern is empty.
+    pub fn count_regex(&self) -> Result<Option<Regex>, ConfigError> {
+        if self.count_pattern.is_empty() {
+            return Ok(None);
+        }
+        Regex::new(&self.count_pattern)
+            .map(Some)
+            .context("invalid ui.count_pattern")
+            .map_err(ConfigError)
+    }
 }
 
 impl Default for UIConfig {
     fn default() -> Self {
         Self {
             show_number: true,
+            show_process_name: false,
+            show_application_name: false,
+            max_title_chars: 0,
+            title_ellipsis: TitleEllipsis::default(),
+            sort: SortOrder::default(),
+            reverse: false,
             icon_size: 24.0,
+            icon_theme: None,
             text_size: 12.0,
             window_padding: 8.0,
             window_background: Color::from_rgb(0x22262b),
             window_foreground: Color::from_rgb(0xd1dbe7),
+            window_opacity: 1.0,
+            window_border_size: 0.0,
+            window_border_color: Color::from_rgb(0x5686d7),
             item_padding: 8.0,
             item_gap: 8.0,
             item_corner_radius: 4.0,
@@ -275,17 +324,316 @@ impl Default for UIConfig {
             ),
             selected_item_background: Color::from_rgb(0x5686d7),
             selected_item_foreground: Color::from_rgb(0xd1dbe7),
+            selection_style: SelectionStyle::default(),
+            dim_unselected: 0.0,
+            attention_badge_color: Color::from_rgb(0xe5534b),
+            flash_on_update: false,
+            flash_duration_ms: 1000,
+            flash_color: Color::from_rgba(0xffffff40),
+            count_pattern: Cow::Borrowed(""),
+            count_badge_background: Color::from_rgb(0x5686d7),
+            count_badge_foreground: Color::from_rgb(0xffffff),
+            message_history_size: 20,
+            empty_text: Cow::Borrowed("No tray items found"),
+            show_footer: false,
+            footer_font: FontDescription::new(
+                &FontFamily::default(),
+                FontStyle::Normal,
+                FontWeight::NORMAL,
+                FontStretch::Normal,
+            ),
+            footer_background: Color::from_rgb(0x1b1f23),
+            footer_foreground: Color::from_rgb(0x8b98a5),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub enum SelectionStyle {
+    #[default]
+    Background,
+    LeftBar,
+    Underline,
+    Outline,
+    Arrow,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TitleEllipsis {
+    Start,
+    Middle,
+    #[default]
+    End,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    #[default]
+    DockOrder,
+    Title,
+    Class,
+    Mru,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GroupsConfig {
+    pub enabled: bool,
+    pub show_separators: bool,
+    pub separator_color: Color,
+    pub rules: Vec<GroupRule>,
+}
+
+impl Default for GroupsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            show_separators: true,
+            separator_color: Color::from_rgba(0xd1dbe740),
+            rules: Vec::new(),
         }
     }
 }
 
-#[derive(Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct GroupRule {
+    pub class: String,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 #[serde(default)]
-pub struct FontConfig {
-    pub family: FontFamily,
-    pub weight: FontWeight,
-    pub style: FontStyle,
-    pub stretch: FontStretch,
+pub struct BehaviorConfig {
+    pub auto_show: AutoShow,
+    pub auto_show_timeout_ms: u64,
+    pub hot_corner: HotCornerPosition,
+    pub hot_corner_delay_ms: u64,
+    pub hot_corner_poll_interval_ms: u64,
+    pub activation_edge: Option<ScreenEdge>,
+    pub activation_edge_delay_ms: u64,
+    pub auto_hide_grace_period_ms: u64,
+    pub close_on_outside_click: bool,
+    pub selection_wait_timeout_ms: u64,
+    pub show_when_empty: bool,
+    pub hide_when_empty_ms: u64,
+    pub exit_when_empty: bool,
+    pub exit_when_empty_timeout_ms: u64,
+    pub state_file: Cow<'static, str>,
+    pub handover_timeout_ms: u64,
+    pub redock_nudge: bool,
+    pub remember_usage: bool,
+    pub dock_history: bool,
+    pub message_queue_size: usize,
+    pub message_overflow: MessageOverflow,
+    pub icon_redraw_rate: f64,
+    pub icon_ping_interval_ms: u64,
+    pub key_repeat_delay_ms: u64,
+    pub key_repeat_interval_ms: u64,
+    pub frame_interval_ms: u64,
+    pub screenshot_dir: Cow<'static, str>,
+    pub metrics: bool,
+    pub load_plugins: bool,
+    pub script_file: Cow<'static, str>,
+}
+
+impl Default for BehaviorConfig {
+    fn default() -> Self {
+        Self {
+            auto_show: AutoShow::default(),
+            auto_show_timeout_ms: 0,
+            hot_corner: HotCornerPosition::default(),
+            hot_corner_delay_ms: 200,
+            hot_corner_poll_interval_ms: 100,
+            activation_edge: None,
+            activation_edge_delay_ms: 300,
+            auto_hide_grace_period_ms: 500,
+            close_on_outside_click: false,
+            selection_wait_timeout_ms: 0,
+            show_when_empty: true,
+            hide_when_empty_ms: 0,
+            exit_when_empty: false,
+            exit_when_empty_timeout_ms: 1000,
+            state_file: Cow::Borrowed(""),
+            handover_timeout_ms: 0,
+            redock_nudge: false,
+            remember_usage: false,
+            dock_history: false,
+            message_queue_size: 16,
+            message_overflow: MessageOverflow::default(),
+            icon_redraw_rate: 30.0,
+            icon_ping_interval_ms: 0,
+            key_repeat_delay_ms: 0,
+            key_repeat_interval_ms: 40,
+            frame_interval_ms: 16,
+            screenshot_dir: Cow::Borrowed("/tmp"),
+            metrics: false,
+            load_plugins: false,
+            script_file: Cow::Borrowed(""),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClickConfig {
+    pub warp_pointer: bool,
+    pub remote_icons: RemoteIconPolicy,
+    pub debounce_ms: u64,
+}
+
+impl Default for ClickConfig {
+    fn default() -> Self {
+        Self {
+            warp_pointer: true,
+            remote_icons: RemoteIconPolicy::default(),
+            debounce_ms: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MessageOverflow {
+    #[default]
+    DropOldest,
+    DropNewest,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RemoteIconPolicy {
+    #[default]
+    Click,
+    SendEvent,
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoShow {
+    #[default]
+    Never,
+    OnNewIcon,
+    OnAttention,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HotCornerPosition {
+    #[default]
+    None,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreenEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ItemsConfig {
+    pub pinned: Vec<PinnedItemConfig>,
+    pub custom: Vec<CustomItemConfig>,
+    pub restart: Vec<RestartItemConfig>,
+    pub redraw_rate: Vec<RedrawRateConfig>,
+    pub hotkeys: Vec<ItemHotkeyConfig>,
+    pub styles: Vec<ItemStyleConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PinnedItemConfig {
+    pub class: String,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub command: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CustomItemConfig {
+    pub command: String,
+    #[serde(default = "CustomItemConfig::default_interval")]
+    pub interval: f64,
+    #[serde(default)]
+    pub action: Option<String>,
+}
+
+impl CustomItemConfig {
+    fn default_interval() -> f64 {
+        60.0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RestartItemConfig {
+    pub class: String,
+    pub command: String,
+    #[serde(default = "RestartItemConfig::default_delay_ms")]
+    pub delay_ms: u64,
+}
+
+impl RestartItemConfig {
+    fn default_delay_ms() -> u64 {
+        3000
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RedrawRateConfig {
+    pub class: String,
+    pub rate: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ItemStyleConfig {
+    pub class: String,
+    #[serde(default)]
+    pub background: Option<Color>,
+    #[serde(default)]
+    pub foreground: Option<Color>,
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ItemHotkeyConfig {
+    pub keysym: Keysym,
+    #[serde(default)]
+    pub modifiers: Modifiers,
+    #[serde(default)]
+    pub class: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub click: bool,
+}
+
+impl ItemHotkeyConfig {
+    // The hotkey works as a local key binding that selects the item, and clicks it if specified.
+    pub fn key_binding(&self) -> KeyBinding {
+        let mut actions = vec![Action::SelectMatchingItem {
+            class: self.class.clone(),
+            title: self.title.clone(),
+        }];
+        if self.click {
+            actions.push(Action::ClickSelectedItem {
+                button: MouseButton::Left,
+                modifiers: Modifiers::NONE,
+            });
+        }
+        KeyBinding::new(self.keysym, self.modifiers, actions, false)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -325,6 +673,9 @@ pub struct KeyBinding {
     #[serde(default)]
     modifiers: Modifiers,
     actions: Vec<Action>,
+    // The actions instead of "actions" while the window is shown.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    shown_actions: Vec<Action>,
     #[serde(default)]
     global: bool,
 }
@@ -340,10 +691,16 @@ impl KeyBinding {
             keysym: keysym.into(),
             modifiers,
             actions,
+            shown_actions: Vec::new(),
             global,
         }
     }
 
+    pub fn with_shown_actions(mut self, shown_actions: Vec<Action>) -> Self {
+        self.shown_actions = shown_actions;
+        self
+    }
+
     pub fn keysym(&self) -> Keysym {
         self.keysym
     }
@@ -356,11 +713,146 @@ impl KeyBinding {
         &self.actions
     }
 
+    pub fn shown_actions(&self) -> &[Action] {
+        &self.shown_actions
+    }
+
+    // Returns the actions to run depending on whether the window is shown.
+    pub fn actions_for(&self, is_shown: bool) -> &[Action] {
+        if is_shown && !self.shown_actions.is_empty() {
+            &self.shown_actions
+        } else {
+            &self.actions
+        }
+    }
+
     pub fn global(&self) -> bool {
         self.global
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub enum MessageDestination {
+    #[default]
+    Icon,
+    Root,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyBindingProfile {
+    None,
+    #[default]
+    Default,
+    Vim,
+    Emacs,
+}
+
+impl KeyBindingProfile {
+    pub fn key_bindings(self) -> Vec<KeyBinding> {
+        let local =
+            |keysym, modifiers, action| KeyBinding::new(keysym, modifiers, vec![action], false);
+        let left_click = Action::ClickSelectedItem {
+            button: MouseButton::Left,
+            modifiers: Modifiers::NONE,
+        };
+        let right_click = Action::ClickSelectedItem {
+            button: MouseButton::Right,
+            modifiers: Modifiers::NONE,
+        };
+
+        let navigation = match self {
+            Self::None => return Vec::new(),
+            Self::Default => vec![
+                local(xkb::XKB_KEY_j, Modifiers::NONE, Action::SelectNextItem),
+                local(xkb::XKB_KEY_Down, Modifiers::NONE, Action::SelectNextItem),
+                local(xkb::XKB_KEY_n, Modifiers::CONTROL, Action::SelectNextItem),
+                local(xkb::XKB_KEY_k, Modifiers::NONE, Action::SelectPreviousItem),
+                local(xkb::XKB_KEY_Up, Modifiers::NONE, Action::SelectPreviousItem),
+                local(
+                    xkb::XKB_KEY_p,
+                    Modifiers::CONTROL,
+                    Action::SelectPreviousItem,
+                ),
+                local(xkb::XKB_KEY_l, Modifiers::NONE, left_click.clone()),
+                local(xkb::XKB_KEY_Return, Modifiers::NONE, left_click),
+                local(xkb::XKB_KEY_h, Modifiers::NONE, right_click.clone()),
+                local(xkb::XKB_KEY_Return, Modifiers::SHIFT, right_click),
+                local(xkb::XKB_KEY_q, Modifiers::NONE, Action::HideWindow),
+                local(xkb::XKB_KEY_Escape, Modifiers::NONE, Action::HideWindow),
+            ],
+            Self::Vim => vec![
+                local(xkb::XKB_KEY_j, Modifiers::NONE, Action::SelectNextItem),
+                local(xkb::XKB_KEY_k, Modifiers::NONE, Action::SelectPreviousItem),
+                local(xkb::XKB_KEY_l, Modifiers::NONE, left_click.clone()),
+                local(xkb::XKB_KEY_Return, Modifiers::NONE, left_click),
+                local(xkb::XKB_KEY_h, Modifiers::NONE, right_click),
+                local(xkb::XKB_KEY_q, Modifiers::NONE, Action::HideWindow),
+                local(xkb::XKB_KEY_Escape, Modifiers::NONE, Action::HideWindow),
+            ],
+            Self::Emacs => vec![
+                local(xkb::XKB_KEY_n, Modifiers::CONTROL, Action::SelectNextItem),
+                local(
+                    xkb::XKB_KEY_p,
+                    Modifiers::CONTROL,
+                    Action::SelectPreviousItem,
+                ),
+                local(xkb::XKB_KEY_Return, Modifiers::NONE, left_click.clone()),
+                local(xkb::XKB_KEY_m, Modifiers::CONTROL, left_click),
+                local(xkb::XKB_KEY_Return, Modifiers::SHIFT, right_click),
+                local(xkb::XKB_KEY_g, Modifiers::CONTROL, Action::HideWindow),
+                local(xkb::XKB_KEY_Escape, Modifiers::NONE, Action::HideWindow),
+            ],
+        };
+
+        let mut key_bindings: Vec<_> = [
+            xkb::XKB_KEY_1,
+            xkb::XKB_KEY_2,
+            xkb::XKB_KEY_3,
+            xkb::XKB_KEY_4,
+            xkb::XKB_KEY_5,
+            xkb::XKB_KEY_6,
+            xkb::XKB_KEY_7,
+            xkb::XKB_KEY_8,
+            xkb::XKB_KEY_9,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, keysym)| local(keysym, Modifiers::NONE, Action::SelectItem { index }))
+        .collect();
+        key_bindings.push(local(xkb::XKB_KEY_0, Modifiers::NONE, Action::DeselectItem));
+        key_bindings.extend(navigation);
+        key_bindings.push(local(
+            xkb::XKB_KEY_question,
+            Modifiers::SHIFT,
+            Action::ShowHelp,
+        ));
+        key_bindings.push(KeyBinding::new(
+            xkb::XKB_KEY_grave,
+            Modifiers::SUPER,
+            vec![Action::ToggleWindow],
+            true,
+        ));
+        key_bindings
+    }
+
+    // Returns the key bindings of the profile followed by the given ones. A given key binding
+    // replaces the one of the profile with the same key and modifiers.
+    pub fn merge_key_bindings(self, overrides: &[KeyBinding]) -> Vec<KeyBinding> {
+        let mut key_bindings: Vec<_> = self
+            .key_bindings()
+            .into_iter()
+            .filter(|key_binding| {
+                !overrides.iter().any(|other| {
+                    other.keysym == key_binding.keysym && other.modifiers == key_binding.modifiers
+                })
+            })
+            .collect();
+        key_bindings.extend(overrides.iter().cloned());
+        key_bindings
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum Action {
@@ -374,10 +866,167 @@ pub enum Action {
     },
     SelectNextItem,
     SelectPreviousItem,
+    SelectNextUrgentItem,
+    SelectLastUsedItem,
+    SelectMatchingItem {
+        #[serde(rename = "class", default)]
+        class: Option<String>,
+        #[serde(rename = "title", default)]
+        title: Option<String>,
+    },
     ClickSelectedItem {
         #[serde(rename = "button")]
         button: MouseButton,
+        #[serde(default)]
+        modifiers: Modifiers,
+    },
+    FocusSelectedIcon,
+    ActivateSelectedApplication,
+    KillSelectedItemProcess,
+    ScreenshotSelectedItem,
+    MoveWindow {
+        #[serde(rename = "dx", default)]
+        dx: i32,
+        #[serde(rename = "dy", default)]
+        dy: i32,
+    },
+    ResizeWindow {
+        #[serde(rename = "dw")]
+        dw: i32,
+    },
+    SendClientMessage {
+        #[serde(rename = "atom")]
+        atom: String,
+        #[serde(rename = "data", default)]
+        data: Vec<u32>,
+        #[serde(rename = "destination", default)]
+        destination: MessageDestination,
     },
+    ShowHelp,
+    ShowMessageHistory,
+    ReloadDesktopEntries,
+    RunPluginCommand {
+        #[serde(rename = "name")]
+        name: String,
+    },
+    RunScriptCommand {
+        #[serde(rename = "name")]
+        name: String,
+    },
+}
+
+impl Action {
+    // Only the actions moving the selection or the window are repeated while the key is held.
+    pub fn is_repeatable(&self) -> bool {
+        matches!(
+            self,
+            Self::DeselectItem
+                | Self::SelectItem { .. }
+                | Self::SelectNextItem
+                | Self::SelectPreviousItem
+                | Self::SelectNextUrgentItem
+                | Self::SelectMatchingItem { .. }
+                | Self::MoveWindow { .. }
+                | Self::ResizeWindow { .. }
+        )
+    }
+}
+
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+// Loads the file as a table, and merges the included files in front of it. Relative paths are
+// resolved from the directory of the including file.
+fn load_table(path: &Path, depth: usize) -> anyhow::Result<toml::Table> {
+    if depth > MAX_INCLUDE_DEPTH {
+        bail!("config files are included too deeply: {}", path.display());
+    }
+
+    let toml_string = fs::read_to_string(path)
+        .with_context(|| format!("read config file: {}", path.display()))?;
+    let mut table: toml::Table = toml::from_str(&toml_string)
+        .with_context(|| format!("parse config file: {}", path.display()))?;
+
+    let includes = match table.remove("include") {
+        Some(toml::Value::Array(includes)) => includes,
+        Some(_) => bail!("include must be an array of paths: {}", path.display()),
+        None => return Ok(table),
+    };
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let mut merged_table = toml::Table::new();
+    for include in includes {
+        let toml::Value::String(include) = include else {
+            bail!("include must be an array of paths: {}", path.display());
+        };
+        let included_table = load_table(&base_dir.join(include), depth + 1)?;
+        merge_table(&mut merged_table, included_table);
+    }
+    merge_table(&mut merged_table, table);
+
+    Ok(merged_table)
+}
+
+// Tables are merged recursively, arrays of tables (e.g. key_bindings) are concatenated, and the
+// other values are replaced.
+fn merge_table(table: &mut toml::Table, other: toml::Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(other)) => {
+                merge_table(table, other);
+            }
+            (Some(toml::Value::Array(array)), toml::Value::Array(other))
+                if is_array_of_tables(array) && is_array_of_tables(&other) =>
+            {
+                array.extend(other);
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+fn is_array_of_tables(array: &[toml::Value]) -> bool {
+    array.iter().all(toml::Value::is_table)
+}
+
+fn to_table(config: &Config) -> anyhow::Result<toml::Table> {
+    match toml::Value::try_from(config).context("serialize config")? {
+        toml::Value::Table(table) => Ok(table),
+        _ => bail!("config is not serialized as a table"),
+    }
+}
+
+fn parse_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_owned()))
+}
+
+fn get_value<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let (parent, name) = match key.rsplit_once('.') {
+        Some((parent, name)) => (get_value(table, parent)?.as_table()?, name),
+        None => (table, key),
+    };
+    parent.get(name)
+}
+
+fn set_value(table: &mut toml::Table, key: &str, value: toml::Value) -> anyhow::Result<()> {
+    match key.split_once('.') {
+        Some((name, rest)) => {
+            let child = table
+                .entry(name)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            let Some(child) = child.as_table_mut() else {
+                bail!("config key is not a table: {}", name);
+            };
+            set_value(child, rest, value)
+        }
+        None => {
+            table.insert(key.to_owned(), value);
+            Ok(())
+        }
+    }
 }
 
 #[cfg(test)]
@@ -387,7 +1036,177 @@ mod tests {
     #[test]
     fn test_default_config() {
         let toml_string = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/config.toml"));
-        let config: Config = toml::from_str(&toml_string).unwrap();
+        let config: Config = toml::from_str(toml_string).unwrap();
         pretty_assertions::assert_eq!(config, Config::default());
     }
+
+    #[test]
+    fn test_with_overrides() {
+        let overrides = [
+            ("ui.icon_size".to_owned(), "32".to_owned()),
+            ("ui.empty_text".to_owned(), "Nothing".to_owned()),
+            ("window.override_redirect".to_owned(), "true".to_owned()),
+            ("window.screen".to_owned(), "1".to_owned()),
+        ];
+        let config = Config::default().with_overrides(&overrides).unwrap();
+        assert_eq!(config.ui.icon_size, 32.0);
+        assert_eq!(config.ui.empty_text, "Nothing");
+        assert!(config.window.override_redirect);
+        assert_eq!(config.window.screen, Some(1));
+
+        let overrides = [("ui.icon_sise".to_owned(), "32".to_owned())];
+        assert!(Config::default().with_overrides(&overrides).is_err());
+
+        let overrides = [("ui.icon_size".to_owned(), "large".to_owned())];
+        assert!(Config::default().with_overrides(&overrides).is_err());
+    }
+
+    #[test]
+    fn test_shown_actions() {
+        let config: Config = toml::from_str(
+            r#"
+            [[key_bindings]]
+            keysym = "grave"
+            modifiers = { super = true }
+            actions = [{ type = "ShowWindow" }]
+            shown_actions = [{ type = "SelectNextItem" }]
+            global = true
+            "#,
+        )
+        .unwrap();
+        let key_binding = &config.key_bindings[0];
+        assert_eq!(
+            *key_binding,
+            KeyBinding::new(
+                xkb::XKB_KEY_grave,
+                Modifiers::SUPER,
+                vec![Action::ShowWindow],
+                true,
+            )
+            .with_shown_actions(vec![Action::SelectNextItem])
+        );
+        assert_eq!(key_binding.actions_for(false), [Action::ShowWindow]);
+        assert_eq!(key_binding.actions_for(true), [Action::SelectNextItem]);
+    }
+
+    #[test]
+    fn test_repeatable_actions() {
+        assert!(Action::SelectNextItem.is_repeatable());
+        assert!(Action::MoveWindow { dx: 0, dy: 8 }.is_repeatable());
+        assert!(!Action::ToggleWindow.is_repeatable());
+        assert!(!Action::HideWindow.is_repeatable());
+        assert!(!Action::ActivateSelectedApplication.is_repeatable());
+        assert!(!Action::KillSelectedItemProcess.is_repeatable());
+    }
+
+    #[test]
+    fn test_item_hotkeys() {
+        let config: Config = toml::from_str(
+            r#"
+            [[items.hotkeys]]
+            keysym = "t"
+            title = "Telegram"
+            click = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.items.hotkeys[0].key_binding(),
+            KeyBinding::new(
+                xkb::XKB_KEY_t,
+                Modifiers::NONE,
+                vec![
+                    Action::SelectMatchingItem {
+                        class: None,
+                        title: Some("Telegram".to_owned()),
+                    },
+                    Action::ClickSelectedItem {
+                        button: MouseButton::Left,
+                        modifiers: Modifiers::NONE,
+                    },
+                ],
+                false,
+            )
+        );
+    }
+
+    #[test]
+    fn test_merge_key_bindings() {
+        let overrides = [
+            KeyBinding::new(
+                xkb::XKB_KEY_l,
+                Modifiers::NONE,
+                vec![Action::ClickSelectedItem {
+                    button: MouseButton::Middle,
+                    modifiers: Modifiers::CONTROL,
+                }],
+                false,
+            ),
+            KeyBinding::new(
+                xkb::XKB_KEY_space,
+                Modifiers::NONE,
+                vec![Action::ToggleWindow],
+                false,
+            ),
+        ];
+
+        let key_bindings = KeyBindingProfile::Vim.merge_key_bindings(&overrides);
+
+        assert_eq!(
+            key_bindings.len(),
+            KeyBindingProfile::Vim.key_bindings().len() + 1
+        );
+        assert_eq!(key_bindings[key_bindings.len() - 2..], overrides);
+        assert_eq!(
+            KeyBindingProfile::None.merge_key_bindings(&overrides),
+            overrides
+        );
+    }
+
+    #[test]
+    fn test_merge_table() {
+        let mut table: toml::Table = toml::from_str(
+            r#"
+            log_level = "Error"
+            [window]
+            title = "foo"
+            default_width = 100.0
+            [[key_bindings]]
+            keysym = "a"
+            actions = []
+            "#,
+        )
+        .unwrap();
+        let other: toml::Table = toml::from_str(
+            r#"
+            [window]
+            title = "bar"
+            states = ["above"]
+            [[key_bindings]]
+            keysym = "b"
+            actions = []
+            "#,
+        )
+        .unwrap();
+        let expected: toml::Table = toml::from_str(
+            r#"
+            log_level = "Error"
+            [window]
+            title = "bar"
+            default_width = 100.0
+            states = ["above"]
+            [[key_bindings]]
+            keysym = "a"
+            actions = []
+            [[key_bindings]]
+            keysym = "b"
+            actions = []
+            "#,
+        )
+        .unwrap();
+
+        merge_table(&mut table, other);
+
+        assert_eq!(table, expected);
+    }
 }
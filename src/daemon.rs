@@ -0,0 +1,72 @@
+use anyhow::Context as _;
+use nix::fcntl::{self, OFlag};
+use nix::sys::stat::Mode;
+use nix::unistd::{self, ForkResult};
+use std::env;
+use std::fs;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// Forks into the background, and returns the pidfile written by the child. The parent exits
+// immediately. The logs written to the standard error are redirected to the log file, or
+// discarded if it is empty.
+pub fn daemonize(log_file: &str) -> anyhow::Result<Option<PathBuf>> {
+    // The process has no other threads, so it is safe to continue after fork.
+    match unsafe { unistd::fork() }.context("fork")? {
+        ForkResult::Parent { .. } => process::exit(0),
+        ForkResult::Child => {}
+    }
+
+    unistd::setsid().context("create new session")?;
+
+    let null_fd =
+        fcntl::open("/dev/null", OFlag::O_RDWR, Mode::empty()).context("open /dev/null")?;
+    let log_fd = if log_file.is_empty() {
+        null_fd
+    } else {
+        fcntl::open(
+            log_file,
+            OFlag::O_WRONLY | OFlag::O_APPEND | OFlag::O_CREAT,
+            Mode::from_bits_truncate(0o644),
+        )
+        .with_context(|| format!("open log file {}", log_file))?
+    };
+    redirect(null_fd, 0)?;
+    redirect(null_fd, 1)?;
+    redirect(log_fd, 2)?;
+    if null_fd > 2 {
+        unistd::close(null_fd).ok();
+    }
+    if log_fd != null_fd && log_fd > 2 {
+        unistd::close(log_fd).ok();
+    }
+
+    let Some(pid_path) = pid_path() else {
+        log::warn!("XDG_RUNTIME_DIR is not set, the pidfile is not written");
+        return Ok(None);
+    };
+    fs::write(&pid_path, format!("{}\n", process::id())).context("write pidfile")?;
+    log::info!("daemonized with pidfile {}", pid_path.display());
+
+    Ok(Some(pid_path))
+}
+
+pub fn remove_pid_file(pid_path: &Path) {
+    if let Err(error) = fs::remove_file(pid_path) {
+        log::warn!("failed to remove pidfile: {}", error);
+    }
+}
+
+fn redirect(fd: RawFd, target_fd: RawFd) -> anyhow::Result<()> {
+    if fd != target_fd {
+        unistd::dup2(fd, target_fd).context("redirect standard streams")?;
+    }
+    Ok(())
+}
+
+fn pid_path() -> Option<PathBuf> {
+    env::var("XDG_RUNTIME_DIR")
+        .map(|runtime_dir| Path::new(&runtime_dir).join("geektray.pid"))
+        .ok()
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The metadata of an application from its desktop entry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DesktopEntry {
+    pub name: String,
+    pub icon: Option<String>,
+    pub startup_wm_class: Option<String>,
+}
+
+/// The desktop entries of the applications, looked up by the class of WM_CLASS. The class is
+/// matched with StartupWMClass first, then with the desktop file ID, both case-insensitively.
+#[derive(Debug, Default)]
+pub struct DesktopEntryIndex {
+    entries: Vec<DesktopEntry>,
+    by_wm_class: HashMap<String, usize>,
+    by_id: HashMap<String, usize>,
+}
+
+impl DesktopEntryIndex {
+    // The entries in the former data directories take precedence, as the XDG spec requires.
+    pub fn load(data_dirs: &[PathBuf]) -> Self {
+        let locale_keys = locale_keys(&current_locale());
+        let mut index = Self::default();
+        for data_dir in data_dirs {
+            let applications_dir = data_dir.join("applications");
+            for (id, path) in desktop_files(&applications_dir) {
+                if index.by_id.contains_key(&id) {
+                    continue;
+                }
+                let Ok(contents) = fs::read_to_string(&path) else {
+                    continue;
+                };
+                if let Some(entry) = parse_desktop_entry(&contents, &locale_keys) {
+                    index.insert(id, entry);
+                }
+            }
+        }
+        log::debug!("{} desktop entries loaded", index.entries.len());
+        index
+    }
+
+    pub fn find(&self, class: &str) -> Option<&DesktopEntry> {
+        let key = class.to_lowercase();
+        self.by_wm_class
+            .get(&key)
+            .or_else(|| self.by_id.get(&key))
+            .map(|i| &self.entries[*i])
+    }
+
+    fn insert(&mut self, id: String, entry: DesktopEntry) {
+        let i = self.entries.len();
+        if let Some(startup_wm_class) = &entry.startup_wm_class {
+            self.by_wm_class
+                .entry(startup_wm_class.to_lowercase())
+                .or_insert(i);
+        }
+        self.by_id.insert(id, i);
+        self.entries.push(entry);
+    }
+}
+
+// $XDG_DATA_HOME followed by $XDG_DATA_DIRS, with the defaults of the base directory spec.
+pub fn data_dirs() -> Vec<PathBuf> {
+    let data_home = env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home_dir| Path::new(&home_dir).join(".local/share")));
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|data_dirs| !data_dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_owned());
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .collect()
+}
+
+// Returns the desktop files under the directory with their lowercased desktop file IDs without
+// ".desktop", where the subdirectories are joined with "-" (e.g. "kde4-foo").
+fn desktop_files(applications_dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut desktop_files = Vec::new();
+    let mut dirs = vec![(String::new(), applications_dir.to_path_buf())];
+    while let Some((prefix, dir)) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(file_name) = path.file_name().and_then(|file_name| file_name.to_str()) else {
+                continue;
+            };
+            if path.is_dir() {
+                dirs.push((format!("{}{}-", prefix, file_name), path));
+            } else if let Some(stem) = file_name.strip_suffix(".desktop") {
+                desktop_files.push((format!("{}{}", prefix, stem).to_lowercase(), path));
+            }
+        }
+    }
+    desktop_files
+}
+
+// Returns None for the entries that are not applications or have been deleted by Hidden.
+fn parse_desktop_entry(contents: &str, locale_keys: &[String]) -> Option<DesktopEntry> {
+    let mut in_desktop_entry = false;
+    let mut values = HashMap::new();
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_desktop_entry = line == "[Desktop Entry]";
+        } else if in_desktop_entry && !line.starts_with('#') {
+            if let Some((key, value)) = line.split_once('=') {
+                values.entry(key.trim_end()).or_insert(value.trim_start());
+            }
+        }
+    }
+
+    if values.get("Type") != Some(&"Application") || values.get("Hidden") == Some(&"true") {
+        return None;
+    }
+    let name = locale_keys
+        .iter()
+        .find_map(|locale| values.get(format!("Name[{}]", locale).as_str()))
+        .or_else(|| values.get("Name"))?;
+    let non_empty = |key| {
+        values
+            .get(key)
+            .filter(|value| !value.is_empty())
+            .map(|value| (*value).to_owned())
+    };
+    Some(DesktopEntry {
+        name: (*name).to_owned(),
+        icon: non_empty("Icon"),
+        startup_wm_class: non_empty("StartupWMClass"),
+    })
+}
+
+fn current_locale() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+}
+
+// Returns the locales to match the localized keys with in the order of preference, e.g.
+// "sr_YU.UTF-8@Latn" is matched with "sr_YU@Latn", "sr_YU", "sr@Latn" and "sr".
+fn locale_keys(locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return Vec::new();
+    }
+
+    let mut keys = Vec::new();
+    if let Some(country) = country {
+        if let Some(modifier) = modifier {
+            keys.push(format!("{}_{}@{}", lang, country, modifier));
+        }
+        keys.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        keys.push(format!("{}@{}", lang, modifier));
+    }
+    keys.push(lang.to_owned());
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_desktop_entry() {
+        let contents = "\
+[Desktop Entry]
+Type=Application
+Name=Signal
+Name[de]=Signal (de)
+# Icon=comment
+Icon = signal-desktop
+StartupWMClass=Signal
+
+[Desktop Action new-window]
+Icon=other
+";
+        assert_eq!(
+            parse_desktop_entry(contents, &[]),
+            Some(DesktopEntry {
+                name: "Signal".to_owned(),
+                icon: Some("signal-desktop".to_owned()),
+                startup_wm_class: Some("Signal".to_owned()),
+            })
+        );
+        assert_eq!(
+            parse_desktop_entry(contents, &locale_keys("de_DE.UTF-8")).map(|entry| entry.name),
+            Some("Signal (de)".to_owned())
+        );
+        assert_eq!(
+            parse_desktop_entry("[Desktop Entry]\nType=Link\nName=Foo\n", &[]),
+            None
+        );
+        assert_eq!(
+            parse_desktop_entry(
+                "[Desktop Entry]\nType=Application\nName=Foo\nHidden=true\n",
+                &[]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_locale_keys() {
+        assert_eq!(
+            locale_keys("sr_YU.UTF-8@Latn"),
+            ["sr_YU@Latn", "sr_YU", "sr@Latn", "sr"]
+        );
+        assert_eq!(locale_keys("ja_JP.UTF-8"), ["ja_JP", "ja"]);
+        assert_eq!(locale_keys("fr"), ["fr"]);
+        assert!(locale_keys("C.UTF-8").is_empty());
+        assert!(locale_keys("").is_empty());
+    }
+
+    #[test]
+    fn test_load() {
+        let base_dir = env::temp_dir().join(format!("geektray-desktop-{}", std::process::id()));
+        let data_dirs = [base_dir.join("home"), base_dir.join("system")];
+        for (path, name, startup_wm_class) in [
+            (
+                "home/applications/signal-desktop.desktop",
+                "Signal (home)",
+                "",
+            ),
+            (
+                "system/applications/signal-desktop.desktop",
+                "Signal",
+                "Signal",
+            ),
+            ("system/applications/kde4/foo.desktop", "Foo", ""),
+            ("system/applications/element.desktop", "Element", "electron"),
+        ] {
+            let path = base_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(
+                path,
+                format!(
+                    "[Desktop Entry]\nType=Application\nName={}\nStartupWMClass={}\n",
+                    name, startup_wm_class
+                ),
+            )
+            .unwrap();
+        }
+
+        let index = DesktopEntryIndex::load(&data_dirs);
+        // The entry in the home overrides the one of the same ID in the system.
+        assert_eq!(
+            index
+                .find("signal-desktop")
+                .map(|entry| entry.name.as_str()),
+            Some("Signal (home)")
+        );
+        assert_eq!(index.find("signal"), None);
+        assert_eq!(
+            index.find("KDE4-Foo").map(|entry| entry.name.as_str()),
+            Some("Foo")
+        );
+        assert_eq!(
+            index.find("Electron").map(|entry| entry.name.as_str()),
+            Some("Element")
+        );
+
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::Context as _;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::window_position;
+
+// The older lines are dropped when a session starts, so that the file does not grow forever.
+const MAX_LINES: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DockEvent {
+    Docked,
+    Undocked,
+}
+
+impl DockEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Docked => "docked",
+            Self::Undocked => "undocked",
+        }
+    }
+}
+
+// Appends when the icons are docked and undocked to $XDG_STATE_HOME/geektray/dock-history, which
+// is kept across sessions. Each line has the time in UTC, the event, the class and the title,
+// separated by tabs.
+#[derive(Debug)]
+pub struct DockHistory {
+    path: PathBuf,
+    docked_classes: HashSet<String>,
+}
+
+impl DockHistory {
+    // Returns None if there is no state directory.
+    pub fn start(now: SystemTime) -> anyhow::Result<Option<Self>> {
+        let Some(path) = dock_history_path() else {
+            return Ok(None);
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("create dock history dir")?;
+        }
+        let mut content = fs::read_to_string(&path).unwrap_or_default();
+        let line_count = content.lines().count();
+        if line_count >= MAX_LINES {
+            content = content
+                .lines()
+                .skip(line_count + 1 - MAX_LINES)
+                .flat_map(|line| [line, "\n"])
+                .collect();
+        }
+        let docked_classes = parse_docked_classes(&content);
+        content.push_str(&format_line(now, "session", None, ""));
+        fs::write(&path, content).context("write dock history")?;
+        Ok(Some(Self {
+            path,
+            docked_classes,
+        }))
+    }
+
+    // The classes of the icons that have been docked in the previous sessions.
+    pub fn docked_classes(&self) -> &HashSet<String> {
+        &self.docked_classes
+    }
+
+    pub fn record(
+        &self,
+        event: DockEvent,
+        class: Option<&str>,
+        title: &str,
+        now: SystemTime,
+    ) -> anyhow::Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .context("open dock history")?;
+        file.write_all(format_line(now, event.as_str(), class, title).as_bytes())
+            .context("append dock history")?;
+        Ok(())
+    }
+}
+
+pub fn dock_history_path() -> Option<PathBuf> {
+    window_position::state_dir().map(|state_dir| state_dir.join("dock-history"))
+}
+
+fn format_line(now: SystemTime, event: &str, class: Option<&str>, title: &str) -> String {
+    let sanitize = |value: &str| value.replace(['\t', '\n', '\r'], " ");
+    format!(
+        "{}\t{}\t{}\t{}\n",
+        format_timestamp(now),
+        event,
+        class.map_or("-".to_owned(), sanitize),
+        sanitize(title)
+    )
+}
+
+fn parse_docked_classes(content: &str) -> HashSet<String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t').skip(1);
+            match (fields.next(), fields.next()) {
+                (Some("docked"), Some(class)) if class != "-" => Some(class.to_owned()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// Formats the time as "YYYY-MM-DDThh:mm:ssZ".
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+
+    // The civil date from the days since the epoch, by Howard Hinnant's algorithm.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(951782400 + 3723)),
+            "2000-02-29T01:02:03Z"
+        );
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(1791590399)),
+            "2026-10-09T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn test_format_line() {
+        assert_eq!(
+            format_line(UNIX_EPOCH, "docked", Some("Foo"), "Foo\tBar\n"),
+            "1970-01-01T00:00:00Z\tdocked\tFoo\tFoo Bar \n"
+        );
+        assert_eq!(
+            format_line(UNIX_EPOCH, "undocked", None, "Baz"),
+            "1970-01-01T00:00:00Z\tundocked\t-\tBaz\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_docked_classes() {
+        let content = [
+            format_line(UNIX_EPOCH, "session", None, ""),
+            format_line(UNIX_EPOCH, "docked", Some("Foo"), "foo"),
+            format_line(UNIX_EPOCH, "undocked", Some("Bar"), "bar"),
+            format_line(UNIX_EPOCH, "docked", None, "baz"),
+            format_line(UNIX_EPOCH, "docked", Some("Foo"), "foo"),
+        ]
+        .concat();
+        assert_eq!(
+            parse_docked_classes(&content),
+            HashSet::from(["Foo".to_owned()])
+        );
+    }
+}
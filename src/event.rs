@@ -21,9 +21,8 @@ impl Keysym {
 impl fmt::Display for Keysym {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut buffer = [0u8; 256];
-        let length = unsafe {
-            ffi::xkb_keysym_get_name((self.0).into(), buffer.as_mut_ptr().cast(), buffer.len())
-        };
+        let length =
+            unsafe { ffi::xkb_keysym_get_name(self.0, buffer.as_mut_ptr().cast(), buffer.len()) };
         if length < 0 {
             f.write_str("<NO_SYMBOL>")?;
         } else {
@@ -54,9 +53,8 @@ impl Serialize for Keysym {
         S: Serializer,
     {
         let mut buffer = [0u8; 256];
-        let length = unsafe {
-            ffi::xkb_keysym_get_name((self.0).into(), buffer.as_mut_ptr().cast(), buffer.len())
-        };
+        let length =
+            unsafe { ffi::xkb_keysym_get_name(self.0, buffer.as_mut_ptr().cast(), buffer.len()) };
         if length < 0 {
             return Err(ser::Error::custom(format!(
                 "Keysym \"{}\" is not defined.",
@@ -64,7 +62,7 @@ impl Serialize for Keysym {
             )));
         }
         match str::from_utf8(&buffer[0..length as usize]) {
-            Ok(s) => serializer.serialize_str(&s),
+            Ok(s) => serializer.serialize_str(s),
             Err(error) => Err(ser::Error::custom(error)),
         }
     }
@@ -279,5 +277,5 @@ pub enum MouseButton {
 }
 
 fn is_false(value: &bool) -> bool {
-    *value == false
+    !*value
 }
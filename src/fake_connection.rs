@@ -0,0 +1,468 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::io::IoSlice;
+use x11rb::connection::{
+    BufWithFds, Connection, DiscardMode, ReplyOrError, RequestConnection, RequestKind,
+    SequenceNumber,
+};
+use x11rb::cookie::{Cookie, CookieWithFds, VoidCookie};
+use x11rb::errors::{ConnectionError, ParseError, ReplyOrIdError};
+use x11rb::protocol::xproto;
+use x11rb::protocol::{Event, Request};
+use x11rb::utils::RawFdContainer;
+use x11rb::x11_utils::{
+    parse_request_header, BigRequests, ExtInfoProvider, ExtensionInformation, Serialize as _,
+    TryParse, TryParseFd, X11Error,
+};
+
+pub const ROOT_WINDOW: xproto::Window = 1;
+
+const BAD_WINDOW: u8 = 3;
+const BAD_ACCESS: u8 = 10;
+
+// A connection that records every request instead of talking to an X server. It emulates just
+// enough of the server state (atoms, properties and selections) for the tray protocol.
+#[derive(Debug)]
+pub struct FakeConnection {
+    setup: xproto::Setup,
+    last_id: Cell<u32>,
+    last_sequence: Cell<SequenceNumber>,
+    requests: RefCell<Vec<Request<'static>>>,
+    responses: RefCell<HashMap<SequenceNumber, Response>>,
+    atoms: RefCell<HashMap<Vec<u8>, xproto::Atom>>,
+    properties: RefCell<HashMap<(xproto::Window, xproto::Atom), xproto::GetPropertyReply>>,
+    selection_owners: RefCell<HashMap<xproto::Atom, xproto::Window>>,
+    destroyed_windows: RefCell<HashSet<xproto::Window>>,
+    root_children: RefCell<Vec<xproto::Window>>,
+    // Keys grabbed by another client, which cannot be grabbed.
+    foreign_grabs: RefCell<HashSet<xproto::Keycode>>,
+}
+
+impl FakeConnection {
+    pub fn new() -> Self {
+        let screen = xproto::Screen {
+            root: ROOT_WINDOW,
+            root_visual: 1,
+            root_depth: 24,
+            width_in_pixels: 1920,
+            height_in_pixels: 1080,
+            ..Default::default()
+        };
+        Self {
+            setup: xproto::Setup {
+                roots: vec![screen],
+                ..Default::default()
+            },
+            last_id: Cell::new(0x100),
+            last_sequence: Cell::new(0),
+            requests: RefCell::new(Vec::new()),
+            responses: RefCell::new(HashMap::new()),
+            atoms: RefCell::new(HashMap::new()),
+            properties: RefCell::new(HashMap::new()),
+            selection_owners: RefCell::new(HashMap::new()),
+            destroyed_windows: RefCell::new(HashSet::new()),
+            root_children: RefCell::new(Vec::new()),
+            foreign_grabs: RefCell::new(HashSet::new()),
+        }
+    }
+
+    pub fn atom(&self, name: &str) -> xproto::Atom {
+        let mut atoms = self.atoms.borrow_mut();
+        let next_atom = atoms.len() as xproto::Atom + 1000;
+        *atoms.entry(name.as_bytes().to_vec()).or_insert(next_atom)
+    }
+
+    pub fn set_property32(
+        &self,
+        window: xproto::Window,
+        property: xproto::Atom,
+        type_: impl Into<xproto::Atom>,
+        data: &[u32],
+    ) {
+        self.properties.borrow_mut().insert(
+            (window, property),
+            property_reply(
+                type_.into(),
+                32,
+                data.len() as u32,
+                data.iter().flat_map(|n| n.to_ne_bytes()).collect(),
+            ),
+        );
+    }
+
+    pub fn set_property8(
+        &self,
+        window: xproto::Window,
+        property: impl Into<xproto::Atom>,
+        type_: impl Into<xproto::Atom>,
+        data: &[u8],
+    ) {
+        self.properties.borrow_mut().insert(
+            (window, property.into()),
+            property_reply(type_.into(), 8, data.len() as u32, data.to_vec()),
+        );
+    }
+
+    pub fn set_selection_owner(&self, selection: xproto::Atom, owner: xproto::Window) {
+        self.selection_owners.borrow_mut().insert(selection, owner);
+    }
+
+    pub fn destroy_window(&self, window: xproto::Window) {
+        self.destroyed_windows.borrow_mut().insert(window);
+    }
+
+    pub fn add_root_child(&self, window: xproto::Window) {
+        self.root_children.borrow_mut().push(window);
+    }
+
+    pub fn grab_key_by_other(&self, keycode: xproto::Keycode) {
+        self.foreign_grabs.borrow_mut().insert(keycode);
+    }
+
+    // The number of replies and errors that have been neither read nor discarded.
+    pub fn pending_responses(&self) -> usize {
+        self.responses.borrow().len()
+    }
+
+    pub fn take_requests(&self) -> Vec<Request<'static>> {
+        self.requests.take()
+    }
+
+    fn handle_request(&self, request: &Request) -> Option<Response> {
+        if let Some(window) = target_window(request) {
+            if self.destroyed_windows.borrow().contains(&window) {
+                return Some(Response::Error(window_error(
+                    self.last_sequence.get(),
+                    window,
+                )));
+            }
+        }
+
+        let response = match request {
+            Request::InternAtom(request) => {
+                let name = String::from_utf8_lossy(&request.name);
+                let reply = xproto::InternAtomReply {
+                    atom: self.atom(&name),
+                    ..Default::default()
+                };
+                Response::Reply(reply_bytes(&reply.serialize()))
+            }
+            Request::GetProperty(request) => {
+                let reply = self
+                    .properties
+                    .borrow()
+                    .get(&(request.window, request.property))
+                    .filter(|reply| {
+                        request.type_ == u32::from(xproto::AtomEnum::ANY)
+                            || request.type_ == reply.type_
+                    })
+                    .cloned()
+                    .unwrap_or_default();
+                Response::Reply(reply_bytes(&reply.serialize()))
+            }
+            Request::ChangeProperty(request) => {
+                self.properties.borrow_mut().insert(
+                    (request.window, request.property),
+                    property_reply(
+                        request.type_,
+                        request.format,
+                        request.data_len,
+                        request.data.to_vec(),
+                    ),
+                );
+                return None;
+            }
+            Request::GrabKey(request) if self.foreign_grabs.borrow().contains(&request.key) => {
+                Response::Error(access_error(self.last_sequence.get()))
+            }
+            Request::SetSelectionOwner(request) => {
+                self.set_selection_owner(request.selection, request.owner);
+                return None;
+            }
+            Request::GetSelectionOwner(request) => {
+                let reply = xproto::GetSelectionOwnerReply {
+                    owner: self
+                        .selection_owners
+                        .borrow()
+                        .get(&request.selection)
+                        .copied()
+                        .unwrap_or(x11rb::NONE),
+                    ..Default::default()
+                };
+                Response::Reply(reply_bytes(&reply.serialize()))
+            }
+            Request::GetWindowAttributes(_) => {
+                let reply = xproto::GetWindowAttributesReply {
+                    visual: self.setup.roots[0].root_visual,
+                    ..Default::default()
+                };
+                Response::Reply(reply_bytes(&reply.serialize()))
+            }
+            Request::QueryTree(request) => {
+                let children = if request.window == ROOT_WINDOW {
+                    self.root_children.borrow().clone()
+                } else {
+                    Vec::new()
+                };
+                let reply = xproto::QueryTreeReply {
+                    root: ROOT_WINDOW,
+                    children,
+                    ..Default::default()
+                };
+                Response::Reply(reply_bytes(&reply.serialize()))
+            }
+            Request::QueryPointer(_) => {
+                let reply = xproto::QueryPointerReply {
+                    same_screen: true,
+                    root: ROOT_WINDOW,
+                    ..Default::default()
+                };
+                Response::Reply(reply_bytes(&reply.serialize()))
+            }
+            Request::TranslateCoordinates(_) => {
+                let reply = xproto::TranslateCoordinatesReply {
+                    same_screen: true,
+                    ..Default::default()
+                };
+                Response::Reply(reply_bytes(&reply.serialize()))
+            }
+            _ => return None,
+        };
+
+        Some(response)
+    }
+
+    fn send_request(&self, bufs: &[IoSlice<'_>]) -> Result<SequenceNumber, ConnectionError> {
+        let bytes = bufs
+            .iter()
+            .flat_map(|buf| buf.iter().copied())
+            .collect::<Vec<_>>();
+        let (header, body) = parse_request_header(&bytes, BigRequests::NotEnabled)?;
+        let request = Request::parse(header, body, &mut Vec::new(), &NoExtensions)?.into_owned();
+
+        let sequence = self.last_sequence.get() + 1;
+        self.last_sequence.set(sequence);
+
+        if let Some(response) = self.handle_request(&request) {
+            self.responses.borrow_mut().insert(sequence, response);
+        }
+
+        self.requests.borrow_mut().push(request);
+
+        Ok(sequence)
+    }
+}
+
+impl Default for FakeConnection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestConnection for FakeConnection {
+    type Buf = Vec<u8>;
+
+    fn send_request_with_reply<R>(
+        &self,
+        bufs: &[IoSlice<'_>],
+        _fds: Vec<RawFdContainer>,
+    ) -> Result<Cookie<'_, Self, R>, ConnectionError>
+    where
+        R: TryParse,
+    {
+        Ok(Cookie::new(self, self.send_request(bufs)?))
+    }
+
+    fn send_request_with_reply_with_fds<R>(
+        &self,
+        bufs: &[IoSlice<'_>],
+        _fds: Vec<RawFdContainer>,
+    ) -> Result<CookieWithFds<'_, Self, R>, ConnectionError>
+    where
+        R: TryParseFd,
+    {
+        Ok(CookieWithFds::new(self, self.send_request(bufs)?))
+    }
+
+    fn send_request_without_reply(
+        &self,
+        bufs: &[IoSlice<'_>],
+        _fds: Vec<RawFdContainer>,
+    ) -> Result<VoidCookie<'_, Self>, ConnectionError> {
+        Ok(VoidCookie::new(self, self.send_request(bufs)?))
+    }
+
+    fn discard_reply(&self, sequence: SequenceNumber, _kind: RequestKind, _mode: DiscardMode) {
+        self.responses.borrow_mut().remove(&sequence);
+    }
+
+    fn prefetch_extension_information(
+        &self,
+        _extension_name: &'static str,
+    ) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    fn extension_information(
+        &self,
+        _extension_name: &'static str,
+    ) -> Result<Option<ExtensionInformation>, ConnectionError> {
+        Ok(None)
+    }
+
+    fn wait_for_reply_or_raw_error(
+        &self,
+        sequence: SequenceNumber,
+    ) -> Result<ReplyOrError<Vec<u8>>, ConnectionError> {
+        match self.responses.borrow_mut().remove(&sequence) {
+            Some(Response::Reply(reply)) => Ok(ReplyOrError::Reply(reply)),
+            Some(Response::Error(error)) => Ok(ReplyOrError::Error(error)),
+            None => Err(ConnectionError::UnknownError),
+        }
+    }
+
+    fn wait_for_reply(&self, sequence: SequenceNumber) -> Result<Option<Vec<u8>>, ConnectionError> {
+        match self.responses.borrow_mut().remove(&sequence) {
+            Some(Response::Reply(reply)) => Ok(Some(reply)),
+            _ => Ok(None),
+        }
+    }
+
+    fn wait_for_reply_with_fds_raw(
+        &self,
+        _sequence: SequenceNumber,
+    ) -> Result<ReplyOrError<BufWithFds<Vec<u8>>, Vec<u8>>, ConnectionError> {
+        Err(ConnectionError::FdPassingFailed)
+    }
+
+    fn check_for_raw_error(
+        &self,
+        sequence: SequenceNumber,
+    ) -> Result<Option<Vec<u8>>, ConnectionError> {
+        match self.responses.borrow_mut().remove(&sequence) {
+            Some(Response::Error(error)) => Ok(Some(error)),
+            _ => Ok(None),
+        }
+    }
+
+    fn prefetch_maximum_request_bytes(&self) {}
+
+    fn maximum_request_bytes(&self) -> usize {
+        u16::MAX as usize * 4
+    }
+
+    fn parse_error(&self, error: &[u8]) -> Result<X11Error, ParseError> {
+        X11Error::try_parse(error, &NoExtensions)
+    }
+
+    fn parse_event(&self, event: &[u8]) -> Result<Event, ParseError> {
+        Event::parse(event, &NoExtensions)
+    }
+}
+
+impl Connection for FakeConnection {
+    fn wait_for_raw_event_with_sequence(
+        &self,
+    ) -> Result<(Vec<u8>, SequenceNumber), ConnectionError> {
+        // No event ever arrives, so waiting for one would block forever.
+        Err(ConnectionError::UnknownError)
+    }
+
+    fn poll_for_raw_event_with_sequence(
+        &self,
+    ) -> Result<Option<(Vec<u8>, SequenceNumber)>, ConnectionError> {
+        Ok(None)
+    }
+
+    fn flush(&self) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    fn setup(&self) -> &xproto::Setup {
+        &self.setup
+    }
+
+    fn generate_id(&self) -> Result<u32, ReplyOrIdError> {
+        let id = self.last_id.get() + 1;
+        self.last_id.set(id);
+        Ok(id)
+    }
+}
+
+#[derive(Debug)]
+enum Response {
+    Reply(Vec<u8>),
+    Error(Vec<u8>),
+}
+
+struct NoExtensions;
+
+impl ExtInfoProvider for NoExtensions {
+    fn get_from_major_opcode(&self, _major_opcode: u8) -> Option<(&str, ExtensionInformation)> {
+        None
+    }
+
+    fn get_from_event_code(&self, _event_code: u8) -> Option<(&str, ExtensionInformation)> {
+        None
+    }
+
+    fn get_from_error_code(&self, _error_code: u8) -> Option<(&str, ExtensionInformation)> {
+        None
+    }
+}
+
+fn property_reply(
+    type_: xproto::Atom,
+    format: u8,
+    value_len: u32,
+    value: Vec<u8>,
+) -> xproto::GetPropertyReply {
+    xproto::GetPropertyReply {
+        format,
+        type_,
+        value_len,
+        value,
+        ..Default::default()
+    }
+}
+
+fn reply_bytes(serialized: &[u8]) -> Vec<u8> {
+    let mut reply = serialized.to_vec();
+    reply.resize(reply.len().max(32), 0);
+    reply.resize(reply.len().next_multiple_of(4), 0);
+    reply[0] = 1;
+    let length = (reply.len() as u32 - 32) / 4;
+    reply[4..8].copy_from_slice(&length.to_ne_bytes());
+    reply
+}
+
+fn target_window(request: &Request) -> Option<xproto::Window> {
+    match request {
+        Request::ChangeProperty(request) => Some(request.window),
+        Request::ChangeSaveSet(request) => Some(request.window),
+        Request::ChangeWindowAttributes(request) => Some(request.window),
+        Request::ConfigureWindow(request) => Some(request.window),
+        Request::GetProperty(request) => Some(request.window),
+        Request::GetWindowAttributes(request) => Some(request.window),
+        Request::MapWindow(request) => Some(request.window),
+        Request::ReparentWindow(request) => Some(request.window),
+        Request::SendEvent(request) => Some(request.destination),
+        Request::UnmapWindow(request) => Some(request.window),
+        _ => None,
+    }
+}
+
+fn access_error(sequence: SequenceNumber) -> Vec<u8> {
+    let mut error = vec![0; 32];
+    error[1] = BAD_ACCESS;
+    error[2..4].copy_from_slice(&(sequence as u16).to_ne_bytes());
+    error
+}
+
+fn window_error(sequence: SequenceNumber, window: xproto::Window) -> Vec<u8> {
+    let mut error = vec![0; 32];
+    error[1] = BAD_WINDOW;
+    error[2..4].copy_from_slice(&(sequence as u16).to_ne_bytes());
+    error[4..8].copy_from_slice(&window.to_ne_bytes());
+    error
+}
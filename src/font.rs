@@ -193,12 +193,13 @@ impl Default for FontFamily {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Deserialize, Serialize)]
 pub enum FontStretch {
     UltraCondensed,
     ExtraCondensed,
     Condensed,
     SemiCondensed,
+    #[default]
     Normal,
     SemiExpanded,
     Expanded,
@@ -206,25 +207,14 @@ pub enum FontStretch {
     UltraExpanded,
 }
 
-impl Default for FontStretch {
-    fn default() -> Self {
-        Self::Normal
-    }
-}
-
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Deserialize, Serialize)]
 pub enum FontStyle {
+    #[default]
     Normal,
     Italic,
     Oblique,
 }
 
-impl Default for FontStyle {
-    fn default() -> Self {
-        Self::Normal
-    }
-}
-
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
 pub struct FontWeight(u16);
 
@@ -244,7 +234,7 @@ impl TryFrom<u16> for FontWeight {
     type Error = InvalidWeight;
 
     fn try_from(weight: u16) -> Result<Self, Self::Error> {
-        if 1 <= weight && weight <= 1000 {
+        if (1..=1000).contains(&weight) {
             Ok(Self(weight))
         } else {
             Err(InvalidWeight)
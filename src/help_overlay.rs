@@ -0,0 +1,205 @@
+use crate::config::{Action, KeyBinding, UIConfig};
+use crate::event::{Keysym, Modifiers, MouseButton};
+use crate::geometrics::Rect;
+use crate::render_context::{HAlign, VAlign};
+use crate::renderer::Renderer;
+
+/// Lists the key bindings with their actions over the items of the tray window.
+#[derive(Debug)]
+pub struct HelpOverlay {
+    entries: Vec<(String, String)>,
+}
+
+impl HelpOverlay {
+    // The conflicted key bindings are the global ones that another client has grabbed.
+    pub fn new(key_bindings: &[KeyBinding], conflicted_key_bindings: &[usize]) -> Self {
+        let entries = key_bindings
+            .iter()
+            .enumerate()
+            .map(|(i, key_binding)| {
+                let key = key_label(key_binding.keysym(), key_binding.modifiers());
+                let mut actions = actions_label(key_binding.actions());
+                if !key_binding.shown_actions().is_empty() {
+                    actions = format!(
+                        "{} / {} when shown",
+                        actions,
+                        actions_label(key_binding.shown_actions())
+                    );
+                }
+                if conflicted_key_bindings.contains(&i) {
+                    (key, format!("{} (grabbed by another client)", actions))
+                } else if key_binding.global() {
+                    (key, format!("{} (global)", actions))
+                } else {
+                    (key, actions)
+                }
+            })
+            .collect();
+        Self { entries }
+    }
+
+    pub fn height(&self, ui_config: &UIConfig) -> f64 {
+        line_height(ui_config) * self.entries.len().max(1) as f64
+    }
+
+    pub fn draw(&self, bounds: Rect, ui_config: &UIConfig, context: &impl Renderer) {
+        let line_height = line_height(ui_config);
+        let key_width = (bounds.width * 0.4).floor();
+
+        for (i, (key, actions)) in self.entries.iter().enumerate() {
+            let y = bounds.y + line_height * i as f64;
+            context.draw_text(
+                key,
+                &ui_config.selected_item_font,
+                ui_config.text_size,
+                HAlign::Left,
+                VAlign::Middle,
+                Rect {
+                    x: bounds.x,
+                    y,
+                    width: key_width,
+                    height: line_height,
+                },
+                ui_config.window_foreground,
+            );
+            context.draw_text(
+                actions,
+                &ui_config.normal_item_font,
+                ui_config.text_size,
+                HAlign::Left,
+                VAlign::Middle,
+                Rect {
+                    x: bounds.x + key_width,
+                    y,
+                    width: bounds.width - key_width,
+                    height: line_height,
+                },
+                ui_config.window_foreground,
+            );
+        }
+    }
+}
+
+fn line_height(ui_config: &UIConfig) -> f64 {
+    ui_config.text_size * 2.0
+}
+
+pub fn key_label(keysym: Keysym, modifiers: Modifiers) -> String {
+    format!("{}{}", modifiers_label(modifiers), keysym)
+}
+
+fn modifiers_label(modifiers: Modifiers) -> String {
+    let mut label = String::new();
+    for (enabled, name) in [
+        (modifiers.control, "Ctrl+"),
+        (modifiers.shift, "Shift+"),
+        (modifiers.alt, "Alt+"),
+        (modifiers.super_, "Super+"),
+    ] {
+        if enabled {
+            label.push_str(name);
+        }
+    }
+    label
+}
+
+fn actions_label(actions: &[Action]) -> String {
+    actions
+        .iter()
+        .map(action_label)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn action_label(action: &Action) -> String {
+    match action {
+        Action::HideWindow => "Hide window".to_owned(),
+        Action::ShowWindow => "Show window".to_owned(),
+        Action::ToggleWindow => "Toggle window".to_owned(),
+        Action::DeselectItem => "Deselect item".to_owned(),
+        Action::SelectItem { index } => format!("Select item {}", index + 1),
+        Action::SelectNextItem => "Select next item".to_owned(),
+        Action::SelectPreviousItem => "Select previous item".to_owned(),
+        Action::SelectNextUrgentItem => "Select next urgent item".to_owned(),
+        Action::SelectLastUsedItem => "Select last used item".to_owned(),
+        Action::SelectMatchingItem { class, title } => {
+            let name = title.as_deref().or(class.as_deref()).unwrap_or("any item");
+            format!("Select {}", name)
+        }
+        Action::ClickSelectedItem { button, modifiers } => {
+            let button = match button {
+                MouseButton::Left => "Left",
+                MouseButton::Right => "Right",
+                MouseButton::Middle => "Middle",
+                MouseButton::X1 => "X1",
+                MouseButton::X2 => "X2",
+            };
+            format!("{}{} click item", modifiers_label(*modifiers), button)
+        }
+        Action::FocusSelectedIcon => "Focus icon".to_owned(),
+        Action::ActivateSelectedApplication => "Activate application".to_owned(),
+        Action::KillSelectedItemProcess => "Kill process".to_owned(),
+        Action::ScreenshotSelectedItem => "Screenshot icon".to_owned(),
+        Action::MoveWindow { dx, dy } => format!("Move window by ({}, {})", dx, dy),
+        Action::ResizeWindow { dw } => format!("Resize window by {}", dw),
+        Action::SendClientMessage { atom, .. } => format!("Send {}", atom),
+        Action::ShowHelp => "Toggle help".to_owned(),
+        Action::ShowMessageHistory => "Toggle message history".to_owned(),
+        Action::ReloadDesktopEntries => "Reload desktop entries".to_owned(),
+        Action::RunPluginCommand { name } => format!("Run {}", name),
+        Action::RunScriptCommand { name } => format!("Run {}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xkbcommon_sys as xkb;
+
+    #[test]
+    fn test_new() {
+        let help_overlay = HelpOverlay::new(
+            &[
+                KeyBinding::new(
+                    xkb::XKB_KEY_Return,
+                    Modifiers::SHIFT,
+                    vec![Action::ClickSelectedItem {
+                        button: MouseButton::Right,
+                        modifiers: Modifiers::CONTROL,
+                    }],
+                    false,
+                ),
+                KeyBinding::new(
+                    xkb::XKB_KEY_grave,
+                    Modifiers::SUPER,
+                    vec![Action::ToggleWindow, Action::SelectItem { index: 0 }],
+                    true,
+                ),
+                KeyBinding::new(
+                    xkb::XKB_KEY_space,
+                    Modifiers::SUPER,
+                    vec![Action::ShowWindow],
+                    true,
+                ),
+            ],
+            &[2],
+        );
+        assert_eq!(
+            help_overlay.entries,
+            [
+                (
+                    "Shift+Return".to_owned(),
+                    "Ctrl+Right click item".to_owned()
+                ),
+                (
+                    "Super+grave".to_owned(),
+                    "Toggle window, Select item 1 (global)".to_owned()
+                ),
+                (
+                    "Super+space".to_owned(),
+                    "Show window (grabbed by another client)".to_owned()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+use crate::config::HotCornerPosition;
+use crate::geometrics::{PhysicalPoint, PhysicalSize};
+
+// The pointer is in the corner if it is within this distance from both edges.
+const CORNER_SIZE: i32 = 2;
+
+// Watches the pointer positions polled from the root window, and triggers once the pointer has
+// stayed in the corner for the delay. It triggers again only after the pointer leaves the corner.
+#[derive(Debug)]
+pub struct HotCorner {
+    position: HotCornerPosition,
+    delay: Duration,
+    entered_at: Option<Instant>,
+    is_triggered: bool,
+}
+
+impl HotCorner {
+    pub fn new(position: HotCornerPosition, delay: Duration) -> Self {
+        Self {
+            position,
+            delay,
+            entered_at: None,
+            is_triggered: false,
+        }
+    }
+
+    // The pointer is None if it is on another screen.
+    pub fn update(
+        &mut self,
+        pointer: Option<PhysicalPoint>,
+        screen_size: PhysicalSize,
+        now: Instant,
+    ) -> bool {
+        let is_in_corner = pointer.is_some_and(|pointer| self.contains(pointer, screen_size));
+        if !is_in_corner {
+            self.entered_at = None;
+            self.is_triggered = false;
+            return false;
+        }
+        let entered_at = *self.entered_at.get_or_insert(now);
+        if self.is_triggered || now.saturating_duration_since(entered_at) < self.delay {
+            return false;
+        }
+        self.is_triggered = true;
+        true
+    }
+
+    fn contains(&self, pointer: PhysicalPoint, screen_size: PhysicalSize) -> bool {
+        let right = screen_size.width as i32 - 1;
+        let bottom = screen_size.height as i32 - 1;
+        let (corner_x, corner_y) = match self.position {
+            HotCornerPosition::None => return false,
+            HotCornerPosition::TopLeft => (0, 0),
+            HotCornerPosition::TopRight => (right, 0),
+            HotCornerPosition::BottomLeft => (0, bottom),
+            HotCornerPosition::BottomRight => (right, bottom),
+        };
+        (pointer.x - corner_x).abs() < CORNER_SIZE && (pointer.y - corner_y).abs() < CORNER_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN_SIZE: PhysicalSize = PhysicalSize {
+        width: 1920,
+        height: 1080,
+    };
+
+    #[test]
+    fn test_update() {
+        let now = Instant::now();
+        let mut hot_corner =
+            HotCorner::new(HotCornerPosition::BottomRight, Duration::from_millis(200));
+
+        assert!(!hot_corner.update(Some(PhysicalPoint { x: 1919, y: 1079 }), SCREEN_SIZE, now));
+        assert!(hot_corner.update(
+            Some(PhysicalPoint { x: 1918, y: 1078 }),
+            SCREEN_SIZE,
+            now + Duration::from_millis(200)
+        ));
+        // It does not trigger again until the pointer leaves the corner.
+        assert!(!hot_corner.update(
+            Some(PhysicalPoint { x: 1919, y: 1079 }),
+            SCREEN_SIZE,
+            now + Duration::from_millis(400)
+        ));
+        assert!(!hot_corner.update(
+            Some(PhysicalPoint { x: 1900, y: 1079 }),
+            SCREEN_SIZE,
+            now + Duration::from_millis(500)
+        ));
+        assert!(!hot_corner.update(
+            Some(PhysicalPoint { x: 1919, y: 1079 }),
+            SCREEN_SIZE,
+            now + Duration::from_millis(600)
+        ));
+        assert!(!hot_corner.update(None, SCREEN_SIZE, now + Duration::from_millis(700)));
+        assert!(!hot_corner.update(
+            Some(PhysicalPoint { x: 1919, y: 1079 }),
+            SCREEN_SIZE,
+            now + Duration::from_millis(800)
+        ));
+        assert!(hot_corner.update(
+            Some(PhysicalPoint { x: 1919, y: 1079 }),
+            SCREEN_SIZE,
+            now + Duration::from_millis(1000)
+        ));
+    }
+
+    #[test]
+    fn test_contains() {
+        let hot_corner = HotCorner::new(HotCornerPosition::TopLeft, Duration::ZERO);
+        assert!(hot_corner.contains(PhysicalPoint { x: 0, y: 0 }, SCREEN_SIZE));
+        assert!(hot_corner.contains(PhysicalPoint { x: 1, y: 1 }, SCREEN_SIZE));
+        assert!(!hot_corner.contains(PhysicalPoint { x: 2, y: 0 }, SCREEN_SIZE));
+
+        let hot_corner = HotCorner::new(HotCornerPosition::TopRight, Duration::ZERO);
+        assert!(hot_corner.contains(PhysicalPoint { x: 1919, y: 0 }, SCREEN_SIZE));
+        assert!(!hot_corner.contains(PhysicalPoint { x: 0, y: 0 }, SCREEN_SIZE));
+
+        let hot_corner = HotCorner::new(HotCornerPosition::None, Duration::ZERO);
+        assert!(!hot_corner.contains(PhysicalPoint { x: 0, y: 0 }, SCREEN_SIZE));
+    }
+}
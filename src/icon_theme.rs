@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::desktop_entry::{self, DesktopEntryIndex};
+use crate::geometrics::PhysicalSize;
+use crate::render_context::load_image;
+use crate::renderer::Image;
+
+// The sizes of the icon directories searched when the directory of the icon size has no icon,
+// larger first to be scaled down.
+const FALLBACK_SIZES: [u32; 10] = [512, 256, 128, 96, 64, 48, 32, 24, 22, 16];
+
+/// Looks up the icons of applications from an XDG icon theme, falling back to "hicolor". The icons
+/// are rasterized at the icon size once and cached.
+#[derive(Debug)]
+pub struct IconTheme {
+    themes: Vec<String>,
+    size: PhysicalSize,
+    data_dirs: Vec<PathBuf>,
+    cache: HashMap<String, Option<Rc<Image>>>,
+}
+
+impl IconTheme {
+    pub fn new(theme: String, icon_size: f64) -> Self {
+        let mut themes = vec![theme];
+        if themes[0] != "hicolor" {
+            themes.push("hicolor".to_owned());
+        }
+        Self {
+            themes,
+            size: PhysicalSize {
+                width: icon_size as u32,
+                height: icon_size as u32,
+            },
+            data_dirs: desktop_entry::data_dirs(),
+            cache: HashMap::new(),
+        }
+    }
+
+    // The icon is named by the Icon key of the desktop entry of the application, or by the
+    // lowercased class if there is no desktop entry.
+    pub fn find_class_icon(
+        &mut self,
+        class: &str,
+        desktop_entries: &DesktopEntryIndex,
+    ) -> Option<Rc<Image>> {
+        let icon_name = desktop_entries
+            .find(class)
+            .and_then(|entry| entry.icon.clone())
+            .unwrap_or_else(|| class.to_lowercase());
+        self.load_icon(&icon_name)
+    }
+
+    fn load_icon(&mut self, icon_name: &str) -> Option<Rc<Image>> {
+        if let Some(icon) = self.cache.get(icon_name) {
+            return icon.clone();
+        }
+        let icon = lookup_icon(&self.data_dirs, &self.themes, icon_name, self.size.width)
+            .and_then(|path| {
+                load_image(&path, self.size)
+                    .map_err(|error| {
+                        log::error!("failed to load icon {}: {:#}", path.display(), error)
+                    })
+                    .ok()
+            })
+            .map(Rc::new);
+        self.cache.insert(icon_name.to_owned(), icon.clone());
+        icon
+    }
+}
+
+// SVG icons are only looked up if they can be rendered.
+fn lookup_icon(
+    data_dirs: &[PathBuf],
+    themes: &[String],
+    icon_name: &str,
+    size: u32,
+) -> Option<PathBuf> {
+    let icon_path = Path::new(icon_name);
+    if icon_path.is_absolute() {
+        return icon_path.is_file().then(|| icon_path.to_path_buf());
+    }
+
+    let mut candidates = vec![format!("{0}x{0}/apps/{1}.png", size, icon_name)];
+    if cfg!(feature = "svg") {
+        candidates.push(format!("scalable/apps/{}.svg", icon_name));
+    }
+    candidates.extend(
+        FALLBACK_SIZES
+            .iter()
+            .filter(|fallback_size| **fallback_size != size)
+            .map(|fallback_size| format!("{0}x{0}/apps/{1}.png", fallback_size, icon_name)),
+    );
+
+    for theme in themes {
+        for data_dir in data_dirs {
+            let theme_dir = data_dir.join("icons").join(theme);
+            if let Some(path) = candidates
+                .iter()
+                .map(|candidate| theme_dir.join(candidate))
+                .find(|path| path.is_file())
+            {
+                return Some(path);
+            }
+        }
+    }
+
+    data_dirs
+        .iter()
+        .map(|data_dir| data_dir.join("pixmaps").join(format!("{}.png", icon_name)))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_lookup_icon() {
+        let data_dir = env::temp_dir().join(format!("geektray-icons-{}", std::process::id()));
+        let themes = ["Foo".to_owned(), "hicolor".to_owned()];
+        for path in [
+            "icons/hicolor/24x24/apps/foo.png",
+            "icons/hicolor/48x48/apps/foo.png",
+            "icons/Foo/48x48/apps/bar.png",
+            "icons/hicolor/24x24/apps/bar.png",
+            "pixmaps/baz.png",
+        ] {
+            let path = data_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let data_dirs = [data_dir.clone()];
+
+        assert_eq!(
+            lookup_icon(&data_dirs, &themes, "foo", 24),
+            Some(data_dir.join("icons/hicolor/24x24/apps/foo.png"))
+        );
+        assert_eq!(
+            lookup_icon(&data_dirs, &themes, "foo", 32),
+            Some(data_dir.join("icons/hicolor/48x48/apps/foo.png"))
+        );
+        // The icon of the theme is preferred to the one of hicolor in the exact size.
+        assert_eq!(
+            lookup_icon(&data_dirs, &themes, "bar", 24),
+            Some(data_dir.join("icons/Foo/48x48/apps/bar.png"))
+        );
+        assert_eq!(
+            lookup_icon(&data_dirs, &themes, "baz", 24),
+            Some(data_dir.join("pixmaps/baz.png"))
+        );
+        assert_eq!(lookup_icon(&data_dirs, &themes, "qux", 24), None);
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::Context as _;
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto;
+use x11rb::protocol::xproto::ConnectionExt as _;
+use x11rb::protocol::ErrorKind;
+
+use crate::event::Modifiers;
+
+// Records every key grabbed on the root windows, so that exactly those keys are ungrabbed even if
+// the keymap has been changed since they were grabbed.
+#[derive(Debug, Default)]
+pub struct KeyGrabber {
+    grabs: Vec<(xproto::Window, xproto::Keycode, Modifiers)>,
+}
+
+impl KeyGrabber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The key is also grabbed with the lock modifiers, which are ignored by key bindings. Returns
+    // false if another client has already grabbed the key.
+    pub fn grab(
+        &mut self,
+        connection: &impl Connection,
+        screen_num: usize,
+        keycode: xproto::Keycode,
+        modifiers: Modifiers,
+    ) -> anyhow::Result<bool> {
+        let root = connection.setup().roots[screen_num].root;
+        for modifiers in lock_variants(modifiers) {
+            if self.grabs.contains(&(root, keycode, modifiers)) {
+                continue;
+            }
+            let result = connection
+                .grab_key(
+                    true,
+                    root,
+                    u16::from(modifiers).into(),
+                    keycode,
+                    xproto::GrabMode::ASYNC,
+                    xproto::GrabMode::ASYNC,
+                )?
+                .check();
+            match result {
+                Err(ReplyError::X11Error(error)) if error.error_kind == ErrorKind::Access => {
+                    return Ok(false);
+                }
+                result => result.context("grab key")?,
+            }
+            self.grabs.push((root, keycode, modifiers));
+        }
+        Ok(true)
+    }
+
+    // The grabs are forgotten even if ungrabbing fails, since they cannot be retried anyway.
+    pub fn ungrab_all(&mut self, connection: &impl Connection) -> anyhow::Result<()> {
+        for (root, keycode, modifiers) in std::mem::take(&mut self.grabs) {
+            connection
+                .ungrab_key(keycode, root, u16::from(modifiers).into())?
+                .check()
+                .context("ungrab key")?;
+        }
+        Ok(())
+    }
+}
+
+fn lock_variants(modifiers: Modifiers) -> [Modifiers; 4] {
+    let modifiers = modifiers.without_locks();
+    [
+        modifiers,
+        modifiers | Modifiers::CAPS_LOCK,
+        modifiers | Modifiers::NUM_LOCK,
+        modifiers | Modifiers::CAPS_LOCK | Modifiers::NUM_LOCK,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x11rb::protocol::Request;
+
+    use crate::fake_connection::{FakeConnection, ROOT_WINDOW};
+
+    #[test]
+    fn test_grab_and_ungrab_all() {
+        let connection = FakeConnection::new();
+        let mut key_grabber = KeyGrabber::new();
+
+        assert!(key_grabber
+            .grab(&connection, 0, 38, Modifiers::CONTROL)
+            .unwrap());
+        // Grabbing the same key again does not record it twice.
+        assert!(key_grabber
+            .grab(&connection, 0, 38, Modifiers::CONTROL | Modifiers::NUM_LOCK)
+            .unwrap());
+        assert!(key_grabber
+            .grab(&connection, 0, 39, Modifiers::NONE)
+            .unwrap());
+        let grab_count = connection
+            .take_requests()
+            .iter()
+            .filter(|request| matches!(request, Request::GrabKey(_)))
+            .count();
+        assert_eq!(grab_count, 8);
+        assert_eq!(key_grabber.grabs.len(), 8);
+
+        key_grabber.ungrab_all(&connection).unwrap();
+        let ungrabs: Vec<_> = connection
+            .take_requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                Request::UngrabKey(request) => Some((request.grab_window, request.key)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ungrabs.len(), 8);
+        assert!(ungrabs.contains(&(ROOT_WINDOW, 38)));
+        assert!(ungrabs.contains(&(ROOT_WINDOW, 39)));
+        assert!(key_grabber.grabs.is_empty());
+
+        // Nothing is left to ungrab.
+        key_grabber.ungrab_all(&connection).unwrap();
+        assert!(connection.take_requests().is_empty());
+    }
+
+    #[test]
+    fn test_grab_conflict() {
+        let connection = FakeConnection::new();
+        connection.grab_key_by_other(38);
+        let mut key_grabber = KeyGrabber::new();
+
+        assert!(!key_grabber
+            .grab(&connection, 0, 38, Modifiers::SUPER)
+            .unwrap());
+        assert!(key_grabber.grabs.is_empty());
+        assert!(key_grabber
+            .grab(&connection, 0, 39, Modifiers::SUPER)
+            .unwrap());
+        assert_eq!(key_grabber.grabs.len(), 4);
+    }
+}
@@ -1,16 +1,99 @@
+//! A keyboard-oriented X11 system tray.
+//!
+//! The `geektray` binary is a thin wrapper around [`App`], which owns the X connection and the
+//! event loop. Applications that run their own event loop (e.g. status bars) can instead drive
+//! the tray protocol with [`TrayManager`], and optionally show the icons with [`TrayEmbedder`]:
+//!
+//! ```no_run
+//! use geektray::{Atoms, Color, SystemTrayColors, SystemTrayOrientation, TrayEvent, TrayManager};
+//! use std::rc::Rc;
+//! use x11rb::connection::Connection as _;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let (connection, screen_num) = x11rb::connect(None)?;
+//! let connection = Rc::new(connection);
+//! let atoms = Rc::new(Atoms::new(&*connection)?.reply()?);
+//! # let embedder = 0;
+//!
+//! let mut tray_manager = TrayManager::new(connection.clone(), screen_num, atoms)?;
+//! tray_manager.acquire_tray_selection(
+//!     embedder, // The window that icons are reparented into.
+//!     SystemTrayOrientation::HORZONTAL,
+//!     SystemTrayColors::single(Color::WHITE),
+//! )?;
+//!
+//! loop {
+//!     let event = connection.wait_for_event()?;
+//!     let mut tray_events: Vec<TrayEvent> =
+//!         tray_manager.translate_event(&event)?.into_iter().collect();
+//!     // Replies to the requests sent while translating events are resolved here.
+//!     tray_events.extend(tray_manager.process_pending_requests()?);
+//!     for tray_event in tray_events {
+//!         println!("{:?}", tray_event);
+//!     }
+//! }
+//! # }
+//! ```
+
+mod activation_edge;
 mod app;
 mod atoms;
 mod color;
+mod command;
 mod config;
+mod daemon;
+mod desktop_entry;
+mod dock_history;
 mod event;
+#[cfg(any(test, feature = "bench"))]
+#[doc(hidden)]
+pub mod fake_connection;
 mod font;
 mod geometrics;
+mod help_overlay;
+mod hot_corner;
+mod icon_theme;
+mod key_grabber;
+mod message_history;
+mod metrics;
+mod pending_reply;
+mod plugin;
+mod protocol_trace;
+mod rate_limiter;
 mod render_context;
+mod renderer;
+#[cfg(feature = "scripting")]
+mod script;
+mod startup_notification;
+mod state_file;
+#[cfg(feature = "svg")]
+mod svg;
+mod systemd;
+mod text_property;
+mod timer;
 mod tray_embedder;
 mod tray_manager;
+mod usage_stats;
+mod window_position;
+mod wm_quirks;
 mod xembed;
 mod xkbcommon;
 mod xkbcommon_sys;
 
-pub use app::App;
-pub use config::Config;
+pub use app::{App, SelectionOwnedError};
+pub use atoms::Atoms;
+pub use color::Color;
+pub use config::{
+    Action, ClickConfig, Config, ConfigError, GroupsConfig, KeyBinding, MessageOverflow, UIConfig,
+    WindowConfig,
+};
+pub use dock_history::dock_history_path;
+pub use event::MouseButton;
+pub use geometrics::{PhysicalSize, Size};
+pub use render_context::{HAlign, RenderContext, VAlign};
+pub use renderer::Renderer;
+pub use tray_embedder::TrayEmbedder;
+pub use tray_manager::{
+    BalloonMessage, SelectionOwner, SystemTrayColors, SystemTrayOrientation, TrayEvent, TrayManager,
+};
+pub use xembed::XEmbedInfo;
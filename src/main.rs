@@ -1,25 +1,78 @@
 extern crate geektray;
 
 use anyhow::Context as _;
-use geektray::{App, Config};
+use geektray::{App, Config, ConfigError, SelectionOwnedError};
+use log::LevelFilter;
 use simple_logger::SimpleLogger;
 use std::env;
+use std::error;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use x11rb::connection::Connection as _;
+use x11rb::errors::ConnectError;
 
-const HELP: &'static str = "\
+const HELP: &str = "\
 USAGE:
   geektray [OPTIONS]
 
 OPTIONS:
-  -c, --config <CONFIG>  a path to the alternative config file [Default: $XDG_CONFIG_HOME/geektray/config.yml]
-  -h, --help             Print help information
-  -V, --version          Print version information
+  -c, --config <CONFIG>    a path to the alternative config file [Default: $XDG_CONFIG_HOME/geektray/config.toml]
+  -d, --display <DISPLAY>  the X display to connect to [Default: $DISPLAY]
+  -s, --screen <SCREEN>    the screen to manage the system tray on [Default: the screen of the display]
+      --set <KEY=VALUE>    override a value of the config file, e.g. --set ui.icon_size=32 (repeatable)
+      --no-wait            exit immediately if another tray owns the tray selection
+      --daemonize          fork into the background once the tray selection is acquired
+      --show               show the window once the tray selection is acquired
+      --hidden             keep the window hidden until it is shown by a key binding [Default]
+      --trace-protocol     log the messages of the tray protocol regardless of the log level
+      --history            print the history of docked and undocked icons, and exit
+  -h, --help               Print help information
+  -V, --version            Print version information
+
+EXIT STATUS:
+  0  Exited normally
+  1  Failed for any other reason
+  2  Invalid arguments or config file
+  3  Cannot connect to the X display
+  4  Another tray owns the tray selection
 ";
 
+const EXIT_FAILURE: u8 = 1;
+const EXIT_CONFIG_ERROR: u8 = 2;
+const EXIT_DISPLAY_ERROR: u8 = 3;
+const EXIT_SELECTION_OWNED: u8 = 4;
+
+/// Only the config file of an older version, which is written in YAML, is found.
+#[derive(Debug)]
+struct LegacyConfigError {
+    path: PathBuf,
+}
+
+impl fmt::Display for LegacyConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} is no longer supported, rewrite it as config.toml in the same directory",
+            self.path.display()
+        )
+    }
+}
+
+impl error::Error for LegacyConfigError {}
+
 #[derive(Debug)]
 struct Args {
     config: Option<String>,
+    display: Option<String>,
+    screen: Option<usize>,
+    overrides: Vec<(String, String)>,
+    no_wait: bool,
+    daemonize: bool,
+    show_on_startup: Option<bool>,
+    trace_protocol: bool,
+    print_history: bool,
 }
 
 impl Args {
@@ -36,39 +89,118 @@ impl Args {
             std::process::exit(0);
         }
 
+        let show_on_startup = match (pargs.contains("--show"), pargs.contains("--hidden")) {
+            (true, true) => {
+                return Err(pico_args::Error::ArgumentParsingFailed {
+                    cause: "--show and --hidden cannot be used together".to_owned(),
+                });
+            }
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            (false, false) => None,
+        };
+
         Ok(Self {
             config: pargs.opt_value_from_str(["-c", "--config"])?,
+            display: pargs.opt_value_from_str(["-d", "--display"])?,
+            screen: pargs.opt_value_from_str(["-s", "--screen"])?,
+            overrides: pargs.values_from_fn("--set", parse_override)?,
+            no_wait: pargs.contains("--no-wait"),
+            daemonize: pargs.contains("--daemonize"),
+            show_on_startup,
+            trace_protocol: pargs.contains("--trace-protocol"),
+            print_history: pargs.contains("--history"),
         })
     }
 }
 
-fn main() -> anyhow::Result<()> {
+fn parse_override(s: &str) -> Result<(String, String), &'static str> {
+    s.split_once('=')
+        .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+        .ok_or("expected KEY=VALUE")
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("geektray: {:#}", error);
+            ExitCode::from(exit_status(&error))
+        }
+    }
+}
+
+fn run() -> anyhow::Result<()> {
     let args = Args::parse_from_env().context("parse args")?;
 
-    let config = match args.config.map(PathBuf::from).or_else(get_config_dir) {
+    if args.print_history {
+        return print_history();
+    }
+
+    let mut config = match args.config.map(PathBuf::from).or_else(get_config_dir) {
         Some(config_dir) => {
             let config_path = config_dir.join("config.toml");
+            let legacy_config_path = config_dir.join("config.yml");
             if config_path.exists() {
                 load_config(config_path)?
+            } else if legacy_config_path.exists() {
+                // Writing the default config here would silently discard the user's settings.
+                return Err(ConfigError::new(LegacyConfigError {
+                    path: legacy_config_path,
+                })
+                .into());
             } else {
                 if !config_dir.exists() {
                     fs::create_dir_all(config_dir).context("create config dir")?;
                 }
-                save_default_config(config_path)?;
-                Config::default()
+                let scale = probe_scale(args.display.as_deref(), args.screen);
+                save_default_config(&config_path, scale)?;
+                load_config(config_path)?
             }
         }
         _ => Config::default(),
     };
-    SimpleLogger::new()
-        .with_level(config.log_level.into())
-        .init()
-        .context("init logger")?;
-    let mut app = App::new(config)?;
-    app.run()?;
+    config = config.with_overrides(&args.overrides)?;
+    if args.screen.is_some() {
+        config.window.screen = args.screen;
+    }
+    if let Some(show_on_startup) = args.show_on_startup {
+        config.window.show_on_startup = show_on_startup;
+    }
+    let mut logger = SimpleLogger::new().with_level(config.log_level.into());
+    if args.trace_protocol {
+        logger = logger.with_module_level("geektray::protocol_trace", LevelFilter::Trace);
+    }
+    logger.init().context("init logger")?;
+    let mut app = App::new(config, args.display.as_deref())?;
+    app.run(!args.no_wait, args.daemonize)?;
+    Ok(())
+}
+
+fn print_history() -> anyhow::Result<()> {
+    let path = geektray::dock_history_path().context("no state directory")?;
+    let history = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "read {} (is behavior.dock_history enabled?)",
+            path.display()
+        )
+    })?;
+    print!("{}", history);
     Ok(())
 }
 
+fn exit_status(error: &anyhow::Error) -> u8 {
+    if error.is::<pico_args::Error>() || error.is::<ConfigError>() {
+        EXIT_CONFIG_ERROR
+    } else if error.is::<ConnectError>() {
+        EXIT_DISPLAY_ERROR
+    } else if error.is::<SelectionOwnedError>() {
+        EXIT_SELECTION_OWNED
+    } else {
+        EXIT_FAILURE
+    }
+}
+
 fn get_config_dir() -> Option<PathBuf> {
     env::var("XDG_CONFIG_HOME")
         .map(|config_dir| Path::new(&config_dir).to_path_buf())
@@ -78,13 +210,133 @@ fn get_config_dir() -> Option<PathBuf> {
 }
 
 fn load_config(path: impl AsRef<Path>) -> anyhow::Result<Config> {
-    let toml_string = fs::read_to_string(path).context("read config file")?;
-    let config: Config = toml::from_str(&toml_string).context("parse config file")?;
-    Ok(config)
+    Ok(Config::load(path.as_ref())?)
 }
 
-fn save_default_config(path: impl AsRef<Path>) -> anyhow::Result<()> {
+fn save_default_config(path: impl AsRef<Path>, scale: f64) -> anyhow::Result<()> {
     let default_string = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/config.toml"));
-    fs::write(path, default_string).context("write config file")?;
+    fs::write(path, scale_config_string(default_string, scale)).context("write config file")?;
     Ok(())
 }
+
+// Returns the scale of the sizes for the DPI of the screen. If the display is not available, the
+// error is left to be reported by the app.
+fn probe_scale(display: Option<&str>, screen_num: Option<usize>) -> f64 {
+    let Ok((connection, default_screen_num)) = x11rb::connect(display) else {
+        return 1.0;
+    };
+    let Some(screen) = connection
+        .setup()
+        .roots
+        .get(screen_num.unwrap_or(default_screen_num))
+    else {
+        return 1.0;
+    };
+    if screen.width_in_millimeters == 0 {
+        return 1.0;
+    }
+    let dpi = screen.width_in_pixels as f64 * 25.4 / screen.width_in_millimeters as f64;
+    scale_for_dpi(dpi)
+}
+
+// The scale is rounded to quarters, and the sizes are never scaled down.
+fn scale_for_dpi(dpi: f64) -> f64 {
+    ((dpi / 96.0) * 4.0).round().max(4.0) / 4.0
+}
+
+fn scale_config_string(config_string: &str, scale: f64) -> String {
+    if scale == 1.0 {
+        return config_string.to_owned();
+    }
+    let mut scaled_string = String::with_capacity(config_string.len());
+    for line in config_string.lines() {
+        let scaled_line = ["default_width", "icon_size", "text_size"]
+            .into_iter()
+            .find_map(|key| {
+                let value: f64 = line.strip_prefix(key)?.strip_prefix(" = ")?.parse().ok()?;
+                Some(format!("{} = {:.1}", key, (value * scale).round()))
+            });
+        scaled_string.push_str(scaled_line.as_deref().unwrap_or(line));
+        scaled_string.push('\n');
+    }
+    scaled_string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_for_dpi() {
+        assert_eq!(scale_for_dpi(96.0), 1.0);
+        assert_eq!(scale_for_dpi(72.0), 1.0);
+        assert_eq!(scale_for_dpi(144.0), 1.5);
+        assert_eq!(scale_for_dpi(163.0), 1.75);
+    }
+
+    #[test]
+    fn test_exit_status_of_config_errors() {
+        let config_dir = env::temp_dir().join(format!("geektray-exit-{}", std::process::id()));
+        fs::create_dir_all(&config_dir).unwrap();
+        let load = |name: &str, content: &str| {
+            let path = config_dir.join(name);
+            fs::write(&path, content).unwrap();
+            load_config(path).map(|_| ())
+        };
+        let status = |result: anyhow::Result<()>| exit_status(&result.unwrap_err());
+
+        // The included file cannot be read.
+        assert_eq!(
+            status(load("missing.toml", "include = [\"missing-include.toml\"]")),
+            EXIT_CONFIG_ERROR
+        );
+        // The included file cannot be parsed.
+        fs::write(config_dir.join("broken.toml"), "[ui").unwrap();
+        assert_eq!(
+            status(load("broken-include.toml", "include = [\"broken.toml\"]")),
+            EXIT_CONFIG_ERROR
+        );
+
+        let with_override = |key: &str, value: &str| {
+            Config::default()
+                .with_overrides(&[(key.to_owned(), value.to_owned())])
+                .map(|_| ())
+                .map_err(anyhow::Error::from)
+        };
+        assert_eq!(
+            status(with_override("ui.no_such_key", "1")),
+            EXIT_CONFIG_ERROR
+        );
+        assert_eq!(
+            status(with_override("ui.icon_size", "large")),
+            EXIT_CONFIG_ERROR
+        );
+        assert_eq!(
+            status(with_override("ui.icon_size.x", "1")),
+            EXIT_CONFIG_ERROR
+        );
+
+        let config = Config::default()
+            .with_overrides(&[("ui.count_pattern".to_owned(), "(".to_owned())])
+            .unwrap();
+        assert_eq!(
+            status(config.ui.count_regex().map(|_| ()).context("create tray")),
+            EXIT_CONFIG_ERROR
+        );
+
+        assert_eq!(status(Err(anyhow::anyhow!("other error"))), EXIT_FAILURE);
+
+        fs::remove_dir_all(&config_dir).unwrap();
+    }
+
+    #[test]
+    fn test_scale_config_string() {
+        let config_string =
+            "# icon_size = 24.0\nicon_size = 24.0\ntext_size = 12.0\nitem_padding = 8.0\n";
+        assert_eq!(scale_config_string(config_string, 1.0), config_string);
+        assert_eq!(
+            scale_config_string(config_string, 1.5),
+            "# icon_size = 24.0\nicon_size = 36.0\ntext_size = 18.0\nitem_padding = 8.0\n"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::config::UIConfig;
+use crate::geometrics::{Rect, Size};
+use crate::render_context::{HAlign, VAlign};
+use crate::renderer::Renderer;
+
+// A balloon message kept in the history with the title of the icon that sent it.
+#[derive(Debug)]
+pub struct ReceivedMessage {
+    pub source: String,
+    pub text: String,
+    pub received_at: Instant,
+}
+
+/// Lists the received balloon messages from the newest over the items of the tray window.
+#[derive(Debug)]
+pub struct MessageHistory {
+    entries: Vec<(String, String)>,
+    selected_index: Option<usize>,
+}
+
+impl MessageHistory {
+    // The ages of the messages are as of when the history is shown.
+    pub fn new(messages: &VecDeque<ReceivedMessage>, now: Instant) -> Self {
+        let entries = messages
+            .iter()
+            .rev()
+            .map(|message| {
+                let age = now.saturating_duration_since(message.received_at);
+                (
+                    format!("{} - {}", message.source, age_label(age)),
+                    message.text.clone(),
+                )
+            })
+            .collect();
+        Self {
+            entries,
+            selected_index: None,
+        }
+    }
+
+    pub fn height(&self, ui_config: &UIConfig) -> f64 {
+        entry_height(ui_config) * self.entries.len().max(1) as f64
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected_index = match self.selected_index {
+            Some(index) if index + 1 < self.entries.len() => Some(index + 1),
+            Some(_) => None,
+            None if !self.entries.is_empty() => Some(0),
+            None => None,
+        };
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected_index = match self.selected_index {
+            Some(index) if index > 0 => Some(index - 1),
+            Some(_) => None,
+            None => self.entries.len().checked_sub(1),
+        };
+    }
+
+    pub fn draw(&self, bounds: Rect, ui_config: &UIConfig, context: &impl Renderer) {
+        let line_height = ui_config.text_size * 2.0;
+
+        if self.entries.is_empty() {
+            context.draw_text(
+                "No messages received",
+                &ui_config.normal_item_font,
+                ui_config.text_size,
+                HAlign::Center,
+                VAlign::Middle,
+                Rect {
+                    height: line_height,
+                    ..bounds
+                },
+                ui_config.window_foreground,
+            );
+            return;
+        }
+
+        for (i, (header, text)) in self.entries.iter().enumerate() {
+            let entry_bounds = Rect {
+                x: bounds.x,
+                y: bounds.y + entry_height(ui_config) * i as f64,
+                width: bounds.width,
+                height: entry_height(ui_config),
+            };
+            let (foreground, font) = if self.selected_index == Some(i) {
+                context.draw_rounded_rect(
+                    entry_bounds,
+                    ui_config.selected_item_background,
+                    Size {
+                        width: ui_config.item_corner_radius,
+                        height: ui_config.item_corner_radius,
+                    },
+                );
+                (
+                    ui_config.selected_item_foreground,
+                    &ui_config.selected_item_font,
+                )
+            } else {
+                (ui_config.window_foreground, &ui_config.normal_item_font)
+            };
+            context.draw_text(
+                header,
+                font,
+                ui_config.text_size,
+                HAlign::Left,
+                VAlign::Middle,
+                Rect {
+                    x: entry_bounds.x + ui_config.item_padding,
+                    y: entry_bounds.y,
+                    width: entry_bounds.width - ui_config.item_padding * 2.0,
+                    height: line_height,
+                },
+                foreground,
+            );
+            context.draw_text(
+                text,
+                &ui_config.normal_item_font,
+                ui_config.text_size,
+                HAlign::Left,
+                VAlign::Middle,
+                Rect {
+                    x: entry_bounds.x + ui_config.item_padding,
+                    y: entry_bounds.y + line_height,
+                    width: entry_bounds.width - ui_config.item_padding * 2.0,
+                    height: line_height,
+                },
+                foreground,
+            );
+        }
+    }
+}
+
+fn entry_height(ui_config: &UIConfig) -> f64 {
+    ui_config.text_size * 4.0
+}
+
+fn age_label(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=59 => "just now".to_owned(),
+        60..=3599 => format!("{} min ago", seconds / 60),
+        3600..=86399 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} d ago", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let now = Instant::now();
+        let messages = VecDeque::from([
+            ReceivedMessage {
+                source: "Foo".to_owned(),
+                text: "Hello".to_owned(),
+                received_at: now,
+            },
+            ReceivedMessage {
+                source: "Bar".to_owned(),
+                text: "World".to_owned(),
+                received_at: now + Duration::from_secs(30),
+            },
+        ]);
+        let message_history = MessageHistory::new(&messages, now + Duration::from_secs(90));
+        assert_eq!(
+            message_history.entries,
+            [
+                ("Bar - 1 min ago".to_owned(), "World".to_owned()),
+                ("Foo - 1 min ago".to_owned(), "Hello".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        let now = Instant::now();
+        let messages = (0..3)
+            .map(|i| ReceivedMessage {
+                source: "Foo".to_owned(),
+                text: i.to_string(),
+                received_at: now,
+            })
+            .collect();
+        let mut message_history = MessageHistory::new(&messages, now);
+
+        message_history.select_next();
+        assert_eq!(message_history.selected_index, Some(0));
+        message_history.select_previous();
+        assert_eq!(message_history.selected_index, None);
+        message_history.select_previous();
+        assert_eq!(message_history.selected_index, Some(2));
+        message_history.select_next();
+        assert_eq!(message_history.selected_index, None);
+
+        let mut message_history = MessageHistory::new(&VecDeque::new(), now);
+        message_history.select_next();
+        assert_eq!(message_history.selected_index, None);
+    }
+
+    #[test]
+    fn test_age_label() {
+        assert_eq!(age_label(Duration::from_secs(59)), "just now");
+        assert_eq!(age_label(Duration::from_secs(60)), "1 min ago");
+        assert_eq!(age_label(Duration::from_secs(7200)), "2 h ago");
+        assert_eq!(age_label(Duration::from_secs(86400 * 3)), "3 d ago");
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Counters of the work done by the event loop, which are logged when the application exits.
+#[derive(Debug)]
+pub struct Metrics {
+    started_at: Instant,
+    events: u64,
+    layouts: u64,
+    redraws: u64,
+    total_render_time: Duration,
+    max_render_time: Duration,
+}
+
+impl Metrics {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            started_at: now,
+            events: 0,
+            layouts: 0,
+            redraws: 0,
+            total_render_time: Duration::ZERO,
+            max_render_time: Duration::ZERO,
+        }
+    }
+
+    pub fn record_event(&mut self) {
+        self.events += 1;
+    }
+
+    pub fn record_frame(&mut self, did_layout: bool, render_time: Duration) {
+        if did_layout {
+            self.layouts += 1;
+        }
+        self.redraws += 1;
+        self.total_render_time += render_time;
+        self.max_render_time = self.max_render_time.max(render_time);
+    }
+
+    // The resource counts are the numbers of the X resources created and freed.
+    pub fn summary(&self, now: Instant, resource_counts: (usize, usize)) -> Summary<'_> {
+        Summary {
+            metrics: self,
+            elapsed: now.saturating_duration_since(self.started_at),
+            resource_counts,
+        }
+    }
+}
+
+pub struct Summary<'a> {
+    metrics: &'a Metrics,
+    elapsed: Duration,
+    resource_counts: (usize, usize),
+}
+
+impl<'a> fmt::Display for Summary<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metrics = self.metrics;
+        let average_render_time = if metrics.redraws > 0 {
+            metrics.total_render_time / metrics.redraws as u32
+        } else {
+            Duration::ZERO
+        };
+        let (created_resources, freed_resources) = self.resource_counts;
+        write!(
+            f,
+            "{} events, {} redraws, {} layouts in {:.1}s, render time avg {:.3}ms / max {:.3}ms, \
+             {} X resources alive ({} created, {} freed)",
+            metrics.events,
+            metrics.redraws,
+            metrics.layouts,
+            self.elapsed.as_secs_f64(),
+            average_render_time.as_secs_f64() * 1000.0,
+            metrics.max_render_time.as_secs_f64() * 1000.0,
+            created_resources.saturating_sub(freed_resources),
+            created_resources,
+            freed_resources
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let now = Instant::now();
+        let mut metrics = Metrics::new(now);
+
+        assert_eq!(
+            metrics.summary(now, (0, 0)).to_string(),
+            "0 events, 0 redraws, 0 layouts in 0.0s, render time avg 0.000ms / max 0.000ms, \
+             0 X resources alive (0 created, 0 freed)"
+        );
+
+        metrics.record_event();
+        metrics.record_event();
+        metrics.record_event();
+        metrics.record_frame(true, Duration::from_millis(3));
+        metrics.record_frame(false, Duration::from_millis(1));
+
+        assert_eq!(
+            metrics
+                .summary(now + Duration::from_secs(2), (6, 4))
+                .to_string(),
+            "3 events, 2 redraws, 1 layouts in 2.0s, render time avg 2.000ms / max 3.000ms, \
+             2 X resources alive (6 created, 4 freed)"
+        );
+    }
+}
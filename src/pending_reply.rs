@@ -0,0 +1,42 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use x11rb::connection::{RequestConnection, SequenceNumber};
+use x11rb::cookie::Cookie;
+use x11rb::errors::ReplyError;
+use x11rb::x11_utils::TryParse;
+
+// A cookie detached from the connection's lifetime, so that a request can be sent while
+// handling an event and its reply can be resolved on a later tick of the event loop.
+pub struct PendingReply<R> {
+    sequence: SequenceNumber,
+    phantom: PhantomData<R>,
+}
+
+impl<R: TryParse> PendingReply<R> {
+    pub fn new<C: RequestConnection + ?Sized>(cookie: Cookie<'_, C, R>) -> Self {
+        let sequence = cookie.sequence_number();
+        // The reply must be kept by the connection until it is resolved.
+        mem::forget(cookie);
+        Self {
+            sequence,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn reply<C: RequestConnection + ?Sized>(self, connection: &C) -> Result<R, ReplyError> {
+        Cookie::<C, R>::new(connection, self.sequence).reply()
+    }
+
+    pub fn discard<C: RequestConnection + ?Sized>(self, connection: &C) {
+        Cookie::<C, R>::new(connection, self.sequence).discard_reply_and_errors();
+    }
+}
+
+impl<R> fmt::Debug for PendingReply<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PendingReply")
+            .field("sequence", &self.sequence)
+            .finish()
+    }
+}
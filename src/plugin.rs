@@ -0,0 +1,245 @@
+use anyhow::{anyhow, Context as _};
+use libloading::Library;
+use std::env;
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use crate::tray_manager::TrayEvent;
+
+// The version of the plugin interface. Plugins that return another version from
+// geektray_plugin_abi_version() are not loaded.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+pub const EVENT_ICON_ADDED: u32 = 1;
+pub const EVENT_ICON_REMOVED: u32 = 2;
+pub const EVENT_TITLE_CHANGED: u32 = 3;
+pub const EVENT_URGENCY_CHANGED: u32 = 4;
+pub const EVENT_MESSAGE_RECEIVED: u32 = 5;
+
+/// A tray event passed to geektray_plugin_on_tray_event(). The pointers are valid only during the
+/// call.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RawTrayEvent {
+    pub kind: u32,
+    pub icon: u32,
+    // The title for EVENT_ICON_ADDED and EVENT_TITLE_CHANGED, or the text for
+    // EVENT_MESSAGE_RECEIVED. Otherwise it is null.
+    pub text: *const c_char,
+    // 1 if the icon demands attention for EVENT_URGENCY_CHANGED, or the id of the message for
+    // EVENT_MESSAGE_RECEIVED. Otherwise it is 0.
+    pub value: u32,
+}
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+// Returns the state passed to the other functions, or null if the plugin fails to initialize.
+type InitFn = unsafe extern "C" fn() -> *mut c_void;
+type OnTrayEventFn = unsafe extern "C" fn(*mut c_void, *const RawTrayEvent);
+// Returns non-zero if the plugin has handled the command.
+type OnCommandFn = unsafe extern "C" fn(*mut c_void, *const c_char) -> c_int;
+
+/// The plugins loaded from the shared libraries in the plugin directory.
+#[derive(Debug, Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Debug)]
+struct Plugin {
+    path: PathBuf,
+    state: *mut c_void,
+    on_tray_event: OnTrayEventFn,
+    on_command: OnCommandFn,
+    // The library is unloaded after the functions above are no longer used.
+    _library: Library,
+}
+
+impl Plugins {
+    // The plugins that fail to load are skipped with the error logged.
+    pub fn load(plugin_dir: &Path) -> Self {
+        let mut plugins = Vec::new();
+        for path in plugin_paths(plugin_dir) {
+            match Plugin::load(&path) {
+                Ok(plugin) => {
+                    log::info!("plugin loaded: {}", path.display());
+                    plugins.push(plugin);
+                }
+                Err(error) => {
+                    log::error!("failed to load plugin {}: {:#}", path.display(), error);
+                }
+            }
+        }
+        Self { plugins }
+    }
+
+    pub fn dispatch_tray_event(&self, event: &TrayEvent) {
+        if self.plugins.is_empty() {
+            return;
+        }
+        let Some((kind, icon, text, value)) = raw_event_fields(event) else {
+            return;
+        };
+        let raw_event = RawTrayEvent {
+            kind,
+            icon,
+            text: text.as_ref().map_or(ptr::null(), |text| text.as_ptr()),
+            value,
+        };
+        for plugin in &self.plugins {
+            unsafe { (plugin.on_tray_event)(plugin.state, &raw_event) };
+        }
+    }
+
+    // Every plugin is given the command until one of them handles it. Returns false if no plugin
+    // has handled it.
+    pub fn run_command(&self, name: &str) -> bool {
+        let Ok(name) = CString::new(name) else {
+            return false;
+        };
+        self.plugins.iter().any(|plugin| {
+            let handled = unsafe { (plugin.on_command)(plugin.state, name.as_ptr()) } != 0;
+            if handled {
+                log::debug!(
+                    "plugin command {:?} handled by {}",
+                    name,
+                    plugin.path.display()
+                );
+            }
+            handled
+        })
+    }
+}
+
+impl Plugin {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let library = unsafe { Library::new(path) }.context("open library")?;
+        unsafe {
+            let abi_version = *library
+                .get::<AbiVersionFn>(b"geektray_plugin_abi_version\0")
+                .context("get geektray_plugin_abi_version")?;
+            let version = abi_version();
+            if version != PLUGIN_ABI_VERSION {
+                return Err(anyhow!(
+                    "unsupported plugin ABI version {} (expected {})",
+                    version,
+                    PLUGIN_ABI_VERSION
+                ));
+            }
+            let init = *library
+                .get::<InitFn>(b"geektray_plugin_init\0")
+                .context("get geektray_plugin_init")?;
+            let on_tray_event = *library
+                .get::<OnTrayEventFn>(b"geektray_plugin_on_tray_event\0")
+                .context("get geektray_plugin_on_tray_event")?;
+            let on_command = *library
+                .get::<OnCommandFn>(b"geektray_plugin_on_command\0")
+                .context("get geektray_plugin_on_command")?;
+            let state = init();
+            if state.is_null() {
+                return Err(anyhow!("geektray_plugin_init failed"));
+            }
+            Ok(Self {
+                path: path.to_owned(),
+                state,
+                on_tray_event,
+                on_command,
+                _library: library,
+            })
+        }
+    }
+}
+
+// The plugins are placed in $XDG_DATA_HOME/geektray/plugins.
+pub fn plugin_dir() -> Option<PathBuf> {
+    env::var("XDG_DATA_HOME")
+        .map(|data_dir| Path::new(&data_dir).to_path_buf())
+        .or_else(|_| env::var("HOME").map(|home_dir| Path::new(&home_dir).join(".local/share")))
+        .map(|data_dir| data_dir.join("geektray/plugins"))
+        .ok()
+}
+
+// The shared libraries are loaded in the order of their file names.
+fn plugin_paths(plugin_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(plugin_dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "so"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn raw_event_fields(event: &TrayEvent) -> Option<(u32, u32, Option<CString>, u32)> {
+    let text = |text: &str| CString::new(text.replace('\0', "")).ok();
+    match event {
+        TrayEvent::IconAdded(icon, title, ..) => Some((EVENT_ICON_ADDED, *icon, text(title), 0)),
+        TrayEvent::IconRemoved(icon) => Some((EVENT_ICON_REMOVED, *icon, None, 0)),
+        TrayEvent::TitleChanged(icon, title) => Some((EVENT_TITLE_CHANGED, *icon, text(title), 0)),
+        TrayEvent::UrgencyChanged(icon, is_urgent) => {
+            Some((EVENT_URGENCY_CHANGED, *icon, None, u32::from(*is_urgent)))
+        }
+        TrayEvent::MessageReceived(message) => Some((
+            EVENT_MESSAGE_RECEIVED,
+            message.icon(),
+            text(message.as_str()),
+            message.id(),
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xembed::XEmbedInfo;
+
+    #[test]
+    fn test_plugin_paths() {
+        let plugin_dir = env::temp_dir().join(format!("geektray-plugins-{}", std::process::id()));
+        fs::create_dir_all(plugin_dir.join("dir.so")).unwrap();
+        for name in ["b.so", "a.so", "README"] {
+            fs::write(plugin_dir.join(name), "").unwrap();
+        }
+        assert_eq!(
+            plugin_paths(&plugin_dir),
+            [plugin_dir.join("a.so"), plugin_dir.join("b.so")]
+        );
+        fs::remove_dir_all(&plugin_dir).unwrap();
+
+        assert!(plugin_paths(&plugin_dir).is_empty());
+    }
+
+    #[test]
+    fn test_load_invalid_plugin() {
+        let plugin_dir = env::temp_dir().join(format!("geektray-invalid-{}", std::process::id()));
+        fs::create_dir_all(&plugin_dir).unwrap();
+        fs::write(plugin_dir.join("invalid.so"), "").unwrap();
+        let plugins = Plugins::load(&plugin_dir);
+        assert!(plugins.plugins.is_empty());
+        assert!(!plugins.run_command("foo"));
+        fs::remove_dir_all(&plugin_dir).unwrap();
+    }
+
+    #[test]
+    fn test_raw_event_fields() {
+        assert_eq!(
+            raw_event_fields(&TrayEvent::IconAdded(
+                1,
+                "Foo".to_owned(),
+                None,
+                None,
+                XEmbedInfo::default()
+            )),
+            Some((EVENT_ICON_ADDED, 1, CString::new("Foo").ok(), 0))
+        );
+        assert_eq!(
+            raw_event_fields(&TrayEvent::UrgencyChanged(2, true)),
+            Some((EVENT_URGENCY_CHANGED, 2, None, 1))
+        );
+        assert_eq!(raw_event_fields(&TrayEvent::SelectionCleared), None);
+    }
+}
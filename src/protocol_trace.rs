@@ -0,0 +1,181 @@
+use x11rb::protocol;
+use x11rb::protocol::xproto;
+
+use crate::atoms::Atoms;
+use crate::tray_manager::{
+    SYSTEM_TRAY_BEGIN_MESSAGE, SYSTEM_TRAY_CANCEL_MESSAGE, SYSTEM_TRAY_REQUEST_DOCK,
+};
+
+// Logs the events related to the tray protocol with their decoded fields. They are logged at the
+// trace level for this module, which is enabled by "--trace-protocol".
+pub fn trace_event(atoms: &Atoms, event: &protocol::Event) {
+    use protocol::Event::*;
+
+    if !log::log_enabled!(log::Level::Trace) {
+        return;
+    }
+
+    match event {
+        ClientMessage(event) if event.type_ == atoms._NET_SYSTEM_TRAY_OPCODE => {
+            let [time, opcode, data1, data2, data3] = event.data.as_data32();
+            match opcode {
+                SYSTEM_TRAY_REQUEST_DOCK => log::trace!(
+                    "<- SYSTEM_TRAY_REQUEST_DOCK (window: 0x{:x}, icon: 0x{:x}, time: {})",
+                    event.window,
+                    data1,
+                    time
+                ),
+                SYSTEM_TRAY_BEGIN_MESSAGE => log::trace!(
+                    "<- SYSTEM_TRAY_BEGIN_MESSAGE (window: 0x{:x}, timeout: {}, length: {}, id: {})",
+                    event.window,
+                    data1,
+                    data2,
+                    data3
+                ),
+                SYSTEM_TRAY_CANCEL_MESSAGE => log::trace!(
+                    "<- SYSTEM_TRAY_CANCEL_MESSAGE (window: 0x{:x}, id: {})",
+                    event.window,
+                    data1
+                ),
+                _ => log::trace!(
+                    "<- _NET_SYSTEM_TRAY_OPCODE (window: 0x{:x}, opcode: {}, data: {:?})",
+                    event.window,
+                    opcode,
+                    [data1, data2, data3]
+                ),
+            }
+        }
+        ClientMessage(event) if event.type_ == atoms._NET_SYSTEM_TRAY_MESSAGE_DATA => {
+            log::trace!(
+                "<- _NET_SYSTEM_TRAY_MESSAGE_DATA (window: 0x{:x}, data: {:?})",
+                event.window,
+                String::from_utf8_lossy(&event.data.as_data8())
+            );
+        }
+        ClientMessage(event) if event.type_ == atoms._XEMBED => {
+            let [time, message, detail, data1, data2] = event.data.as_data32();
+            trace_xembed_message("<-", event.window, time, message, detail, [data1, data2]);
+        }
+        ClientMessage(event) if event.type_ == atoms.MANAGER => {
+            let [time, selection, owner, ..] = event.data.as_data32();
+            log::trace!(
+                "<- MANAGER (selection: {}, owner: 0x{:x}, time: {})",
+                selection,
+                owner,
+                time
+            );
+        }
+        PropertyNotify(event) => {
+            let name = if event.atom == atoms._XEMBED_INFO {
+                "_XEMBED_INFO"
+            } else if event.atom == atoms._NET_WM_NAME {
+                "_NET_WM_NAME"
+            } else if event.atom == atoms._NET_WM_STATE {
+                "_NET_WM_STATE"
+            } else if event.atom == u32::from(xproto::AtomEnum::WM_HINTS) {
+                "WM_HINTS"
+            } else if event.atom == u32::from(xproto::AtomEnum::WM_CLASS) {
+                "WM_CLASS"
+            } else {
+                return;
+            };
+            log::trace!(
+                "<- PropertyNotify (window: 0x{:x}, atom: {}, state: {:?})",
+                event.window,
+                name,
+                event.state
+            );
+        }
+        ReparentNotify(event) if event.event == event.window => {
+            log::trace!(
+                "<- ReparentNotify (window: 0x{:x}, parent: 0x{:x}, x: {}, y: {})",
+                event.window,
+                event.parent,
+                event.x,
+                event.y
+            );
+        }
+        MapNotify(event) if event.event == event.window => {
+            log::trace!("<- MapNotify (window: 0x{:x})", event.window);
+        }
+        UnmapNotify(event) if event.event == event.window => {
+            log::trace!("<- UnmapNotify (window: 0x{:x})", event.window);
+        }
+        DestroyNotify(event) if event.event == event.window => {
+            log::trace!("<- DestroyNotify (window: 0x{:x})", event.window);
+        }
+        SelectionClear(event) => {
+            log::trace!(
+                "<- SelectionClear (owner: 0x{:x}, selection: {}, time: {})",
+                event.owner,
+                event.selection,
+                event.time
+            );
+        }
+        _ => {}
+    }
+}
+
+// The direction is "<-" for received messages and "->" for sent ones.
+pub fn trace_xembed_message(
+    direction: &str,
+    window: xproto::Window,
+    time: xproto::Timestamp,
+    message: u32,
+    detail: u32,
+    data: [u32; 2],
+) {
+    log::trace!(
+        "{} XEMBED_{} (window: 0x{:x}, detail: {}, data: {:?}, time: {})",
+        direction,
+        xembed_message_name(message),
+        window,
+        detail,
+        data,
+        time
+    );
+}
+
+fn xembed_message_name(message: u32) -> &'static str {
+    match message {
+        0 => "EMBEDDED_NOTIFY",
+        1 => "WINDOW_ACTIVATE",
+        2 => "WINDOW_DEACTIVATE",
+        3 => "REQUEST_FOCUS",
+        4 => "FOCUS_IN",
+        5 => "FOCUS_OUT",
+        6 => "FOCUS_NEXT",
+        7 => "FOCUS_PREV",
+        8 => "GRAB_KEY",
+        9 => "UNGRAB_KEY",
+        10 => "MODALITY_ON",
+        11 => "MODALITY_OFF",
+        12 => "REGISTER_ACCELERATOR",
+        13 => "UNREGISTER_ACCELERATOR",
+        14 => "ACTIVATE_ACCELERATOR",
+        _ => "UNKNOWN",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xembed::XEmbedMessage;
+
+    #[test]
+    fn test_xembed_message_name() {
+        assert_eq!(
+            xembed_message_name(XEmbedMessage::EmbeddedNotify.into()),
+            "EMBEDDED_NOTIFY"
+        );
+        assert_eq!(
+            xembed_message_name(XEmbedMessage::FocusIn.into()),
+            "FOCUS_IN"
+        );
+        assert_eq!(
+            xembed_message_name(XEmbedMessage::ActivateAccelerator.into()),
+            "ACTIVATE_ACCELERATOR"
+        );
+        assert_eq!(xembed_message_name(15), "UNKNOWN");
+    }
+}
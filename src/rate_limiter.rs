@@ -0,0 +1,60 @@
+use std::time::{Duration, Instant};
+
+// A token bucket that allows the bursts of up to a second worth of the rate.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate.max(1.0),
+            updated_at: now,
+        }
+    }
+
+    // Takes a token, or returns the time when the next token is available.
+    pub fn acquire(&mut self, now: Instant) -> Result<(), Instant> {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate.max(1.0));
+        self.updated_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(now + Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire() {
+        let now = Instant::now();
+        let mut rate_limiter = RateLimiter::new(2.0, now);
+
+        assert_eq!(rate_limiter.acquire(now), Ok(()));
+        assert_eq!(rate_limiter.acquire(now), Ok(()));
+        assert_eq!(
+            rate_limiter.acquire(now),
+            Err(now + Duration::from_millis(500))
+        );
+
+        let now = now + Duration::from_millis(500);
+        assert_eq!(rate_limiter.acquire(now), Ok(()));
+        assert!(rate_limiter.acquire(now).is_err());
+
+        // The tokens are never accumulated more than the burst.
+        let now = now + Duration::from_secs(10);
+        assert_eq!(rate_limiter.acquire(now), Ok(()));
+        assert_eq!(rate_limiter.acquire(now), Ok(()));
+        assert!(rate_limiter.acquire(now).is_err());
+    }
+}
@@ -3,8 +3,11 @@ use cairo_sys as cairo;
 use gobject_sys as gobject;
 use pango_cairo_sys as pango_cairo;
 use pango_sys as pango;
+use std::fs;
 use std::os::raw::*;
+use std::path::Path;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto;
 use x11rb::protocol::xproto::ConnectionExt as _;
@@ -13,20 +16,34 @@ use x11rb::xcb_ffi::XCBConnection;
 
 use crate::color::Color;
 use crate::font::FontDescription;
-use crate::geometrics::{PhysicalSize, Rect, Size};
+use crate::geometrics::{PhysicalSize, Point, Rect, Size};
+use crate::renderer::{Image, Renderer};
+
+// The number of the X resources (pixmaps and GCs) created and freed by render contexts, which
+// should be balanced except for the contexts alive.
+static CREATED_RESOURCES: AtomicUsize = AtomicUsize::new(0);
+static FREED_RESOURCES: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Debug)]
 pub struct RenderContext {
-    connection: Rc<XCBConnection>,
-    window: xproto::Window,
+    target: RenderTarget,
     size: PhysicalSize,
-    pixmap: xproto::Pixmap,
-    gc: xproto::Gcontext,
     cairo: *mut cairo::cairo_t,
     cairo_surface: *mut cairo::cairo_surface_t,
     pango: *mut pango::PangoContext,
 }
 
+#[derive(Debug)]
+enum RenderTarget {
+    Window {
+        connection: Rc<XCBConnection>,
+        window: xproto::Window,
+        pixmap: xproto::Pixmap,
+        gc: xproto::Gcontext,
+    },
+    Image,
+}
+
 impl RenderContext {
     pub fn new(
         connection: Rc<XCBConnection>,
@@ -59,16 +76,17 @@ impl RenderContext {
             .create_pixmap(depth, pixmap, window, size.width as u16, size.height as u16)?
             .check()
             .context("create pixmap for render context")?;
+        CREATED_RESOURCES.fetch_add(1, Ordering::Relaxed);
 
-        let gc = connection.generate_id().context("genrate gc id")?;
-        {
-            let values =
-                xproto::CreateGCAux::new().subwindow_mode(xproto::SubwindowMode::INCLUDE_INFERIORS);
-            connection
-                .create_gc(gc, pixmap, &values)?
-                .check()
-                .context("create gc for render context")?;
-        }
+        let gc = match create_gc(&connection, pixmap) {
+            Ok(gc) => gc,
+            Err(error) => {
+                connection.free_pixmap(pixmap).ok();
+                FREED_RESOURCES.fetch_add(1, Ordering::Relaxed);
+                return Err(error);
+            }
+        };
+        CREATED_RESOURCES.fetch_add(1, Ordering::Relaxed);
 
         let cairo_surface = unsafe {
             let visual = visual.serialize();
@@ -80,49 +98,177 @@ impl RenderContext {
                 size.height as i32,
             )
         };
+
+        Ok(Self::from_surface(
+            RenderTarget::Window {
+                connection,
+                window,
+                pixmap,
+                gc,
+            },
+            cairo_surface,
+            size,
+        ))
+    }
+
+    // Creates a context that renders into an off-screen ARGB image instead of a window.
+    pub fn new_image(size: PhysicalSize) -> anyhow::Result<Self> {
+        let cairo_surface = unsafe {
+            cairo::cairo_image_surface_create(
+                cairo::FORMAT_A_RGB32,
+                size.width as i32,
+                size.height as i32,
+            )
+        };
+        let status = unsafe { cairo::cairo_surface_status(cairo_surface) };
+        if status != cairo::STATUS_SUCCESS {
+            unsafe {
+                cairo::cairo_surface_destroy(cairo_surface);
+            }
+            return Err(anyhow::anyhow!(
+                "create image surface for render context (status {})",
+                status
+            ));
+        }
+
+        Ok(Self::from_surface(RenderTarget::Image, cairo_surface, size))
+    }
+
+    fn from_surface(
+        target: RenderTarget,
+        cairo_surface: *mut cairo::cairo_surface_t,
+        size: PhysicalSize,
+    ) -> Self {
         let cairo = unsafe { cairo::cairo_create(cairo_surface) };
         let pango = unsafe { pango_cairo::pango_cairo_create_context(cairo) };
 
-        Ok(Self {
-            connection,
-            window,
-            pixmap,
-            gc,
-            cairo_surface,
+        Self {
+            target,
+            size,
             cairo,
+            cairo_surface,
             pango,
-            size,
-        })
+        }
+    }
+
+    // Returns the rendered pixels as premultiplied native-endian ARGB words, row by row.
+    #[cfg(test)]
+    pub fn image_data(&self) -> Option<Vec<u32>> {
+        if !matches!(self.target, RenderTarget::Image) {
+            return None;
+        }
+
+        unsafe {
+            cairo::cairo_surface_flush(self.cairo_surface);
+            Some(read_image_pixels(self.cairo_surface))
+        }
+    }
+
+    #[cfg(test)]
+    pub fn to_png(&self) -> anyhow::Result<Vec<u8>> {
+        unsafe {
+            cairo::cairo_surface_flush(self.cairo_surface);
+            write_png(self.cairo_surface)
+        }
     }
 
-    pub fn size(&self) -> PhysicalSize {
+    unsafe fn rounded_rect_path(&self, bounds: Rect, mut radius: Size) {
+        // Reference: https://www.cairographics.org/cookbook/roundedrectangles/ (Method B)
+        const ARC_TO_BEZIER: f64 = 0.55228475;
+
+        if radius.width > bounds.width - radius.width {
+            radius.width = bounds.width / 2.0;
+        }
+        if radius.height > bounds.height - radius.height {
+            radius.height = bounds.height / 2.0;
+        }
+
+        let curve_x = radius.width * ARC_TO_BEZIER;
+        let curve_y = radius.height * ARC_TO_BEZIER;
+
+        cairo::cairo_new_path(self.cairo);
+        cairo::cairo_move_to(self.cairo, bounds.x + radius.width, bounds.y);
+        cairo::cairo_rel_line_to(self.cairo, bounds.width - 2.0 * radius.width, 0.0);
+        cairo::cairo_rel_curve_to(
+            self.cairo,
+            curve_x,
+            0.0,
+            radius.width,
+            curve_y,
+            radius.width,
+            radius.height,
+        );
+        cairo::cairo_rel_line_to(self.cairo, 0.0, bounds.height - 2.0 * radius.height);
+        cairo::cairo_rel_curve_to(
+            self.cairo,
+            0.0,
+            curve_y,
+            curve_x - radius.width,
+            radius.height,
+            -radius.width,
+            radius.height,
+        );
+        cairo::cairo_rel_line_to(self.cairo, -bounds.width + 2.0 * radius.width, 0.0);
+        cairo::cairo_rel_curve_to(
+            self.cairo,
+            -curve_x,
+            0.0,
+            -radius.width,
+            -curve_y,
+            -radius.width,
+            -radius.height,
+        );
+        cairo::cairo_rel_line_to(self.cairo, 0.0, -bounds.height + 2.0 * radius.height);
+        cairo::cairo_rel_curve_to(
+            self.cairo,
+            0.0,
+            -curve_y,
+            radius.width - curve_x,
+            -radius.height,
+            radius.width,
+            -radius.height,
+        );
+        cairo::cairo_close_path(self.cairo);
+    }
+}
+
+impl Renderer for RenderContext {
+    fn size(&self) -> PhysicalSize {
         self.size
     }
 
-    pub fn flush(&self) -> anyhow::Result<()> {
+    fn flush(&self) -> anyhow::Result<()> {
         unsafe {
             cairo::cairo_surface_flush(self.cairo_surface);
         }
 
-        self.connection
-            .copy_area(
-                self.pixmap,
-                self.window,
-                self.gc,
-                0,
-                0,
-                0,
-                0,
-                self.size.width as u16,
-                self.size.height as u16,
-            )?
-            .check()
-            .context("copy rendered contents")?;
+        if let RenderTarget::Window {
+            connection,
+            window,
+            pixmap,
+            gc,
+        } = &self.target
+        {
+            connection
+                .copy_area(
+                    *pixmap,
+                    *window,
+                    *gc,
+                    0,
+                    0,
+                    0,
+                    0,
+                    self.size.width as u16,
+                    self.size.height as u16,
+                )?
+                .check()
+                .context("copy rendered contents")?;
+        }
 
         Ok(())
     }
 
-    pub fn draw_rect(&self, bounds: Rect, color: Color) {
+    fn draw_rect(&self, bounds: Rect, color: Color) {
         let [r, g, b, a] = color.to_f64_components();
 
         unsafe {
@@ -134,65 +280,67 @@ impl RenderContext {
         }
     }
 
-    pub fn draw_rounded_rect(&self, bounds: Rect, color: Color, mut radius: Size) {
-        // Reference: https://www.cairographics.org/cookbook/roundedrectangles/ (Method B)
-        const ARC_TO_BEZIER: f64 = 0.55228475;
+    fn stroke_rect(&self, bounds: Rect, color: Color, line_width: f64) {
+        let [r, g, b, a] = color.to_f64_components();
+        let half_width = line_width / 2.0;
 
-        if radius.width > bounds.width - radius.width {
-            radius.width = bounds.width / 2.0;
+        unsafe {
+            cairo::cairo_save(self.cairo);
+            cairo::cairo_rectangle(
+                self.cairo,
+                bounds.x + half_width,
+                bounds.y + half_width,
+                bounds.width - line_width,
+                bounds.height - line_width,
+            );
+            cairo::cairo_set_source_rgba(self.cairo, r, g, b, a);
+            cairo::cairo_set_line_width(self.cairo, line_width);
+            cairo::cairo_stroke(self.cairo);
+            cairo::cairo_restore(self.cairo);
         }
-        if radius.height > bounds.height - radius.height {
-            radius.height = bounds.height / 2.0;
+    }
+
+    fn draw_rounded_rect(&self, bounds: Rect, color: Color, radius: Size) {
+        let [r, g, b, a] = color.to_f64_components();
+
+        unsafe {
+            cairo::cairo_save(self.cairo);
+            self.rounded_rect_path(bounds, radius);
+            cairo::cairo_set_source_rgba(self.cairo, r, g, b, a);
+            cairo::cairo_fill(self.cairo);
+            cairo::cairo_restore(self.cairo);
         }
+    }
 
-        let curve_x = radius.width * ARC_TO_BEZIER;
-        let curve_y = radius.height * ARC_TO_BEZIER;
+    fn stroke_rounded_rect(&self, bounds: Rect, color: Color, radius: Size, line_width: f64) {
+        let [r, g, b, a] = color.to_f64_components();
+        let half_width = line_width / 2.0;
+        let bounds = Rect {
+            x: bounds.x + half_width,
+            y: bounds.y + half_width,
+            width: bounds.width - line_width,
+            height: bounds.height - line_width,
+        };
+
+        unsafe {
+            cairo::cairo_save(self.cairo);
+            self.rounded_rect_path(bounds, radius);
+            cairo::cairo_set_source_rgba(self.cairo, r, g, b, a);
+            cairo::cairo_set_line_width(self.cairo, line_width);
+            cairo::cairo_stroke(self.cairo);
+            cairo::cairo_restore(self.cairo);
+        }
+    }
+
+    fn draw_polygon(&self, points: &[Point], color: Color) {
         let [r, g, b, a] = color.to_f64_components();
 
         unsafe {
             cairo::cairo_save(self.cairo);
             cairo::cairo_new_path(self.cairo);
-            cairo::cairo_move_to(self.cairo, bounds.x + radius.width, bounds.y);
-            cairo::cairo_rel_line_to(self.cairo, bounds.width - 2.0 * radius.width, 0.0);
-            cairo::cairo_rel_curve_to(
-                self.cairo,
-                curve_x,
-                0.0,
-                radius.width,
-                curve_y,
-                radius.width,
-                radius.height,
-            );
-            cairo::cairo_rel_line_to(self.cairo, 0.0, bounds.height - 2.0 * radius.height);
-            cairo::cairo_rel_curve_to(
-                self.cairo,
-                0.0,
-                curve_y,
-                curve_x - radius.width,
-                radius.height,
-                -radius.width,
-                radius.height,
-            );
-            cairo::cairo_rel_line_to(self.cairo, -bounds.width + 2.0 * radius.width, 0.0);
-            cairo::cairo_rel_curve_to(
-                self.cairo,
-                -curve_x,
-                0.0,
-                -radius.width,
-                -curve_y,
-                -radius.width,
-                -radius.height,
-            );
-            cairo::cairo_rel_line_to(self.cairo, 0.0, -bounds.height + 2.0 * radius.height);
-            cairo::cairo_rel_curve_to(
-                self.cairo,
-                0.0,
-                -curve_y,
-                radius.width - curve_x,
-                -radius.height,
-                radius.width,
-                -radius.height,
-            );
+            for point in points {
+                cairo::cairo_line_to(self.cairo, point.x, point.y);
+            }
             cairo::cairo_close_path(self.cairo);
             cairo::cairo_set_source_rgba(self.cairo, r, g, b, a);
             cairo::cairo_fill(self.cairo);
@@ -200,7 +348,34 @@ impl RenderContext {
         }
     }
 
-    pub fn draw_text(
+    fn draw_image(&self, image: &Image, bounds: Rect) {
+        if image.size.width == 0 || image.size.height == 0 {
+            return;
+        }
+        unsafe {
+            // The surface only borrows the pixels, which are never written by painting it.
+            let surface = cairo::cairo_image_surface_create_for_data(
+                image.pixels.as_ptr() as *mut c_uchar,
+                cairo::FORMAT_A_RGB32,
+                image.size.width as i32,
+                image.size.height as i32,
+                image.size.width as i32 * 4,
+            );
+            cairo::cairo_save(self.cairo);
+            cairo::cairo_translate(self.cairo, bounds.x, bounds.y);
+            cairo::cairo_scale(
+                self.cairo,
+                bounds.width / image.size.width as f64,
+                bounds.height / image.size.height as f64,
+            );
+            cairo::cairo_set_source_surface(self.cairo, surface, 0.0, 0.0);
+            cairo::cairo_paint(self.cairo);
+            cairo::cairo_restore(self.cairo);
+            cairo::cairo_surface_destroy(surface);
+        }
+    }
+
+    fn draw_text(
         &self,
         content: &str,
         font: &FontDescription,
@@ -262,11 +437,262 @@ impl Drop for RenderContext {
             cairo::cairo_destroy(self.cairo);
             cairo::cairo_surface_destroy(self.cairo_surface);
         }
-        self.connection.free_gc(self.gc).ok();
-        self.connection.free_pixmap(self.pixmap).ok();
+        if let RenderTarget::Window {
+            connection,
+            pixmap,
+            gc,
+            ..
+        } = &self.target
+        {
+            connection.free_gc(*gc).ok();
+            connection.free_pixmap(*pixmap).ok();
+            FREED_RESOURCES.fetch_add(2, Ordering::Relaxed);
+        }
+    }
+}
+
+// Returns the number of the X resources created and freed so far.
+pub fn resource_counts() -> (usize, usize) {
+    (
+        CREATED_RESOURCES.load(Ordering::Relaxed),
+        FREED_RESOURCES.load(Ordering::Relaxed),
+    )
+}
+
+fn create_gc(
+    connection: &XCBConnection,
+    pixmap: xproto::Pixmap,
+) -> anyhow::Result<xproto::Gcontext> {
+    let gc = connection.generate_id().context("genrate gc id")?;
+    let values =
+        xproto::CreateGCAux::new().subwindow_mode(xproto::SubwindowMode::INCLUDE_INFERIORS);
+    connection
+        .create_gc(gc, pixmap, &values)?
+        .check()
+        .context("create gc for render context")?;
+    Ok(gc)
+}
+
+// Measures the text in a single line without a render context, e.g. to size the window before it is
+// rendered.
+pub fn measure_text(content: &str, font: &FontDescription, font_size: f64) -> Size {
+    let mut font = font.clone();
+    font.set_font_size(font_size * pango::PANGO_SCALE as f64);
+
+    let mut width = 0;
+    let mut height = 0;
+
+    unsafe {
+        let font_map = pango_cairo::pango_cairo_font_map_get_default();
+        let pango = pango::pango_font_map_create_context(font_map);
+        let layout = pango::pango_layout_new(pango);
+
+        pango::pango_layout_set_font_description(layout, font.as_mut_ptr());
+        pango::pango_layout_set_text(
+            layout,
+            content.as_ptr() as *const c_char,
+            content.len() as i32,
+        );
+        pango::pango_layout_get_pixel_size(layout, &mut width, &mut height);
+
+        gobject::g_object_unref(layout.cast());
+        gobject::g_object_unref(pango.cast());
+    }
+
+    Size {
+        width: width as f64,
+        height: height as f64,
     }
 }
 
+// Compares the rendered image with the golden image in "tests/snapshots". Set
+// UPDATE_SNAPSHOTS=1 to rewrite the golden image instead.
+#[cfg(test)]
+pub fn assert_snapshot(context: &RenderContext, name: &str) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{}.png", name));
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, context.to_png().unwrap()).unwrap();
+        return;
+    }
+
+    let bytes = std::fs::read(&path)
+        .unwrap_or_else(|error| panic!("read snapshot {}: {}", path.display(), error));
+    let (size, expected) = read_png(&bytes).unwrap();
+    assert_eq!(
+        size,
+        context.size(),
+        "snapshot {} has a different size",
+        name
+    );
+    assert!(
+        context.image_data().unwrap() == expected,
+        "snapshot {} does not match, rerun with UPDATE_SNAPSHOTS=1 to update it",
+        name
+    );
+}
+
+// Runs the test again in a child process that sees only the font in "tests/fonts", since
+// fontconfig reads its configuration once per process. Returns true in the child process, which
+// runs the rest of the test.
+#[cfg(test)]
+pub fn run_with_bundled_font(test_name: &str) -> bool {
+    let config_file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fonts/fonts.conf");
+    if std::env::var_os("FONTCONFIG_FILE").as_deref() == Some(config_file.as_os_str()) {
+        return true;
+    }
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", test_name])
+        .env("FONTCONFIG_FILE", &config_file)
+        .status()
+        .unwrap();
+    assert!(
+        status.success(),
+        "{} failed with the bundled font",
+        test_name
+    );
+    false
+}
+
+// SVG images are rendered at the size, while other images are loaded as PNG in their own size.
+pub fn load_image(path: &Path, size: PhysicalSize) -> anyhow::Result<Image> {
+    if path.extension().is_some_and(|ext| ext == "svg") {
+        load_svg(path, size)
+    } else {
+        load_png(path)
+    }
+}
+
+#[cfg(feature = "svg")]
+fn load_svg(path: &Path, size: PhysicalSize) -> anyhow::Result<Image> {
+    crate::svg::render_svg(path, size)
+}
+
+#[cfg(not(feature = "svg"))]
+fn load_svg(_path: &Path, _size: PhysicalSize) -> anyhow::Result<Image> {
+    Err(anyhow::anyhow!(
+        "SVG support is not enabled (build with the svg feature)"
+    ))
+}
+
+pub fn load_png(path: &Path) -> anyhow::Result<Image> {
+    let bytes = fs::read(path).context("read png")?;
+    let (size, pixels) = read_png(&bytes)?;
+    Ok(Image { size, pixels })
+}
+
+// Decodes a PNG image into pixels in the same layout as `RenderContext::image_data()`.
+fn read_png(mut bytes: &[u8]) -> anyhow::Result<(PhysicalSize, Vec<u32>)> {
+    unsafe extern "C" fn read(
+        closure: *mut c_void,
+        data: *mut c_uchar,
+        length: c_uint,
+    ) -> cairo::cairo_status_t {
+        let bytes = &mut *(closure as *mut &[u8]);
+        let length = length as usize;
+        if bytes.len() < length {
+            return cairo::STATUS_READ_ERROR;
+        }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, length);
+        *bytes = &bytes[length..];
+        cairo::STATUS_SUCCESS
+    }
+
+    unsafe {
+        let surface = cairo::cairo_image_surface_create_from_png_stream(
+            Some(read),
+            &mut bytes as *mut &[u8] as *mut c_void,
+        );
+        let status = cairo::cairo_surface_status(surface);
+        if status != cairo::STATUS_SUCCESS {
+            cairo::cairo_surface_destroy(surface);
+            return Err(anyhow::anyhow!("decode png (status {})", status));
+        }
+        let size = PhysicalSize {
+            width: cairo::cairo_image_surface_get_width(surface) as u32,
+            height: cairo::cairo_image_surface_get_height(surface) as u32,
+        };
+        let pixels = read_image_pixels(surface);
+        cairo::cairo_surface_destroy(surface);
+        Ok((size, pixels))
+    }
+}
+
+// Encodes the premultiplied native-endian ARGB words, row by row, as PNG.
+pub fn encode_png(size: PhysicalSize, pixels: &[u32]) -> anyhow::Result<Vec<u8>> {
+    assert_eq!(pixels.len(), (size.width * size.height) as usize);
+
+    unsafe {
+        let surface = cairo::cairo_image_surface_create(
+            cairo::FORMAT_A_RGB32,
+            size.width as i32,
+            size.height as i32,
+        );
+        let status = cairo::cairo_surface_status(surface);
+        if status != cairo::STATUS_SUCCESS {
+            cairo::cairo_surface_destroy(surface);
+            return Err(anyhow::anyhow!(
+                "create image surface for png (status {})",
+                status
+            ));
+        }
+
+        let data = cairo::cairo_image_surface_get_data(surface);
+        let stride = cairo::cairo_image_surface_get_stride(surface) as usize;
+        for (y, row) in pixels.chunks(size.width.max(1) as usize).enumerate() {
+            std::ptr::copy_nonoverlapping(
+                row.as_ptr(),
+                data.add(y * stride) as *mut u32,
+                row.len(),
+            );
+        }
+        cairo::cairo_surface_mark_dirty(surface);
+
+        let result = write_png(surface);
+        cairo::cairo_surface_destroy(surface);
+        result
+    }
+}
+
+unsafe fn write_png(surface: *mut cairo::cairo_surface_t) -> anyhow::Result<Vec<u8>> {
+    unsafe extern "C" fn write(
+        closure: *mut c_void,
+        data: *mut c_uchar,
+        length: c_uint,
+    ) -> cairo::cairo_status_t {
+        let buffer = &mut *(closure as *mut Vec<u8>);
+        buffer.extend_from_slice(std::slice::from_raw_parts(data, length as usize));
+        cairo::STATUS_SUCCESS
+    }
+
+    let mut buffer: Vec<u8> = Vec::new();
+    let status = cairo::cairo_surface_write_to_png_stream(
+        surface,
+        Some(write),
+        &mut buffer as *mut Vec<u8> as *mut c_void,
+    );
+    if status != cairo::STATUS_SUCCESS {
+        return Err(anyhow::anyhow!("encode png (status {})", status));
+    }
+
+    Ok(buffer)
+}
+
+pub unsafe fn read_image_pixels(surface: *mut cairo::cairo_surface_t) -> Vec<u32> {
+    let data = cairo::cairo_image_surface_get_data(surface);
+    let width = cairo::cairo_image_surface_get_width(surface) as usize;
+    let height = cairo::cairo_image_surface_get_height(surface) as usize;
+    let stride = cairo::cairo_image_surface_get_stride(surface) as usize;
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = std::slice::from_raw_parts(data.add(y * stride) as *const u32, width);
+        pixels.extend_from_slice(row);
+    }
+    pixels
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[allow(unused)]
 pub enum VAlign {
@@ -284,7 +710,7 @@ pub enum HAlign {
 }
 
 impl HAlign {
-    fn to_pango_align(&self) -> pango::PangoAlignment {
+    fn to_pango_align(self) -> pango::PangoAlignment {
         match self {
             Self::Left => pango::PANGO_ALIGN_LEFT,
             Self::Center => pango::PANGO_ALIGN_CENTER,
@@ -292,3 +718,72 @@ impl HAlign {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_png() {
+        let size = PhysicalSize {
+            width: 2,
+            height: 2,
+        };
+        let pixels = [0xffff0000, 0xff00ff00, 0xff0000ff, 0x00000000];
+        let bytes = encode_png(size, &pixels).unwrap();
+        assert_eq!(read_png(&bytes).unwrap(), (size, pixels.to_vec()));
+    }
+
+    #[test]
+    fn test_draw_image() {
+        let image = Image {
+            size: PhysicalSize {
+                width: 2,
+                height: 2,
+            },
+            pixels: vec![0xffff0000, 0xff00ff00, 0xff0000ff, 0x00000000],
+        };
+        let context = RenderContext::new_image(PhysicalSize {
+            width: 4,
+            height: 4,
+        })
+        .unwrap();
+        context.draw_image(
+            &image,
+            Rect {
+                x: 2.0,
+                y: 1.0,
+                width: 2.0,
+                height: 2.0,
+            },
+        );
+        assert_eq!(
+            context.image_data().unwrap(),
+            [0, 0, 0, 0, 0, 0, 0xffff0000, 0xff00ff00, 0, 0, 0xff0000ff, 0, 0, 0, 0, 0,]
+        );
+    }
+
+    #[test]
+    fn test_load_image() {
+        let size = PhysicalSize {
+            width: 2,
+            height: 1,
+        };
+        let pixels = [0xffff0000, 0xff00ff00];
+        let icon_size = PhysicalSize {
+            width: 24,
+            height: 24,
+        };
+        let path = std::env::temp_dir().join(format!("geektray-icon-{}.png", std::process::id()));
+        fs::write(&path, encode_png(size, &pixels).unwrap()).unwrap();
+        // PNG images are loaded in their own size.
+        let image = load_image(&path, icon_size).unwrap();
+        assert_eq!(image.size, size);
+        assert_eq!(image.pixels, pixels);
+        fs::remove_file(&path).unwrap();
+
+        assert!(load_image(&path, icon_size).is_err());
+        #[cfg(not(feature = "svg"))]
+        assert!(load_image(Path::new("icon.svg"), icon_size).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use crate::color::Color;
+use crate::font::FontDescription;
+use crate::geometrics::{PhysicalSize, Point, Rect, Size};
+use crate::render_context::{HAlign, VAlign};
+
+/// An image of premultiplied native-endian ARGB pixels, row by row.
+#[derive(Debug, PartialEq)]
+pub struct Image {
+    pub size: PhysicalSize,
+    pub pixels: Vec<u32>,
+}
+
+/// The drawing operations that the tray window is rendered with. [`RenderContext`] implements it
+/// with cairo and pango.
+///
+/// [`RenderContext`]: crate::RenderContext
+pub trait Renderer {
+    fn size(&self) -> PhysicalSize;
+
+    /// Presents the rendered contents, e.g. copies them to the window.
+    fn flush(&self) -> anyhow::Result<()>;
+
+    fn draw_rect(&self, bounds: Rect, color: Color);
+
+    fn stroke_rect(&self, bounds: Rect, color: Color, line_width: f64);
+
+    fn draw_rounded_rect(&self, bounds: Rect, color: Color, radius: Size);
+
+    fn stroke_rounded_rect(&self, bounds: Rect, color: Color, radius: Size, line_width: f64);
+
+    fn draw_polygon(&self, points: &[Point], color: Color);
+
+    /// Draws the image scaled to the bounds.
+    fn draw_image(&self, image: &Image, bounds: Rect);
+
+    /// Draws the text in a single line, which is ellipsized if it overflows the bounds.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_text(
+        &self,
+        content: &str,
+        font: &FontDescription,
+        font_size: f64,
+        halign: HAlign,
+        valign: VAlign,
+        bounds: Rect,
+        color: Color,
+    );
+}
@@ -0,0 +1,235 @@
+use anyhow::{anyhow, Context as _};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::config::Action;
+use crate::tray_manager::TrayEvent;
+
+const ON_TRAY_EVENT: &str = "on_tray_event";
+
+// A Rhai script that handles the tray events with on_tray_event(event), and defines the commands
+// run by "RunScriptCommand" as functions without parameters. Both run the actions of the key
+// bindings with run_action(action), after the script returns.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    actions: Rc<RefCell<Vec<Action>>>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let actions = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = Engine::new();
+        engine.on_print(|text| log::info!("script: {}", text));
+        {
+            let actions = actions.clone();
+            engine.register_fn(
+                "run_action",
+                move |action: Map| -> Result<(), Box<EvalAltResult>> {
+                    let action: Action = rhai::serde::from_dynamic(&Dynamic::from_map(action))?;
+                    // Commands are called as functions instead, so that they never recurse.
+                    if let Action::RunScriptCommand { name } = &action {
+                        return Err(format!("call {}() instead of RunScriptCommand", name).into());
+                    }
+                    actions.borrow_mut().push(action);
+                    Ok(())
+                },
+            );
+        }
+
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|error| anyhow!("{}", error))
+            .context("compile script")?;
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|error| anyhow!("{}", error))
+            .context("run script")?;
+        // The actions run on load are discarded, since there is no tray yet.
+        actions.borrow_mut().clear();
+
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            actions,
+        })
+    }
+
+    // Returns the actions that the script has run for the event.
+    pub fn dispatch_tray_event(&mut self, event: &TrayEvent) -> Vec<Action> {
+        if let Some(event) = event_map(event) {
+            if self.has_function(ON_TRAY_EVENT, 1) {
+                self.call_function(ON_TRAY_EVENT, (event,));
+            }
+        }
+        self.actions.take()
+    }
+
+    // Returns the actions that the command has run, or None if the script does not define it.
+    pub fn run_command(&mut self, name: &str) -> Option<Vec<Action>> {
+        if !self.has_function(name, 0) {
+            return None;
+        }
+        self.call_function(name, ());
+        Some(self.actions.take())
+    }
+
+    fn has_function(&self, name: &str, params: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|function| function.name == name && function.params.len() == params)
+    }
+
+    // Errors are logged, and the actions run before the error are kept.
+    fn call_function(&mut self, name: &str, args: impl rhai::FuncArgs) {
+        // The top-level statements have been run on load, and are not run again.
+        let options = CallFnOptions::new().eval_ast(false);
+        if let Err(error) = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            name,
+            args,
+        ) {
+            log::error!("script error in {}(): {}", name, error);
+        }
+    }
+}
+
+fn event_map(event: &TrayEvent) -> Option<Map> {
+    let mut map = Map::new();
+    let mut insert = |key: &str, value: Dynamic| {
+        map.insert(key.into(), value);
+    };
+    let icon = |icon: u32| Dynamic::from(icon as INT);
+    let text = |text: &str| Dynamic::from(text.to_owned());
+    let optional_text = |text: &Option<String>| {
+        text.as_ref()
+            .map_or(Dynamic::UNIT, |text| Dynamic::from(text.clone()))
+    };
+    match event {
+        TrayEvent::IconAdded(window, title, class, client_machine, _) => {
+            insert("kind", text("icon_added"));
+            insert("icon", icon(*window));
+            insert("title", text(title));
+            insert("class", optional_text(class));
+            insert("client_machine", optional_text(client_machine));
+        }
+        TrayEvent::IconRemoved(window) => {
+            insert("kind", text("icon_removed"));
+            insert("icon", icon(*window));
+        }
+        TrayEvent::TitleChanged(window, title) => {
+            insert("kind", text("title_changed"));
+            insert("icon", icon(*window));
+            insert("title", text(title));
+        }
+        TrayEvent::UrgencyChanged(window, is_urgent) => {
+            insert("kind", text("urgency_changed"));
+            insert("icon", icon(*window));
+            insert("is_urgent", Dynamic::from(*is_urgent));
+        }
+        TrayEvent::MessageReceived(message) => {
+            insert("kind", text("message_received"));
+            insert("icon", icon(message.icon()));
+            insert("id", Dynamic::from(message.id() as INT));
+            insert("text", text(message.as_str()));
+        }
+        _ => return None,
+    }
+    Some(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    use crate::event::MouseButton;
+    use crate::xembed::XEmbedInfo;
+
+    const SCRIPT: &str = r#"
+        fn on_tray_event(event) {
+            if event.kind == "icon_added" && event.class == "Foo" {
+                run_action(#{ "type": "SelectMatchingItem", "class": event.class });
+                run_action(#{ "type": "ClickSelectedItem", "button": "Left" });
+            }
+        }
+
+        fn toggle() {
+            run_action(#{ "type": "ToggleWindow" });
+        }
+
+        fn recurse() {
+            run_action(#{ "type": "HideWindow" });
+            run_action(#{ "type": "RunScriptCommand", "name": "recurse" });
+        }
+    "#;
+
+    fn load_script(name: &str, source: &str) -> anyhow::Result<Script> {
+        let path = env::temp_dir().join(format!("geektray-{}-{}.rhai", name, std::process::id()));
+        fs::write(&path, source).unwrap();
+        let script = Script::load(&path);
+        fs::remove_file(&path).unwrap();
+        script
+    }
+
+    #[test]
+    fn test_dispatch_tray_event() {
+        let mut script = load_script("dispatch", SCRIPT).unwrap();
+        let icon_added = |class: &str| {
+            TrayEvent::IconAdded(
+                1,
+                "foo".to_owned(),
+                Some(class.to_owned()),
+                None,
+                XEmbedInfo::default(),
+            )
+        };
+        assert_eq!(
+            script.dispatch_tray_event(&icon_added("Foo")),
+            [
+                Action::SelectMatchingItem {
+                    class: Some("Foo".to_owned()),
+                    title: None,
+                },
+                Action::ClickSelectedItem {
+                    button: MouseButton::Left,
+                    modifiers: Default::default(),
+                },
+            ]
+        );
+        assert!(script.dispatch_tray_event(&icon_added("Bar")).is_empty());
+        assert!(script
+            .dispatch_tray_event(&TrayEvent::SelectionCleared)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_run_command() {
+        let mut script = load_script("command", SCRIPT).unwrap();
+        assert_eq!(
+            script.run_command("toggle"),
+            Some(vec![Action::ToggleWindow])
+        );
+        // The actions before the error are kept.
+        assert_eq!(
+            script.run_command("recurse"),
+            Some(vec![Action::HideWindow])
+        );
+        assert_eq!(script.run_command("on_tray_event"), None);
+        assert_eq!(script.run_command("undefined"), None);
+    }
+
+    #[test]
+    fn test_load_invalid_script() {
+        assert!(load_script("invalid", "fn {").is_err());
+        assert!(load_script("throw", "throw \"error\";").is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::Context as _;
+use std::process;
+use std::sync::atomic::{AtomicU32, Ordering};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{self, ConnectionExt as _};
+
+use crate::atoms::Atoms;
+
+// Generates an ID for a startup sequence. The launched application takes it from
+// DESKTOP_STARTUP_ID, and completes the sequence when its window is mapped.
+pub fn generate_startup_id(timestamp: Option<xproto::Timestamp>) -> String {
+    static SEQUENCE: AtomicU32 = AtomicU32::new(0);
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    match timestamp {
+        Some(timestamp) => format!("geektray-{}-{}_TIME{}", process::id(), sequence, timestamp),
+        None => format!("geektray-{}-{}", process::id(), sequence),
+    }
+}
+
+pub fn send_new(
+    connection: &impl Connection,
+    atoms: &Atoms,
+    screen_num: usize,
+    window: xproto::Window,
+    startup_id: &str,
+    name: &str,
+) -> anyhow::Result<()> {
+    let message = format!(
+        "new: ID={} NAME={} SCREEN={}",
+        quote(startup_id),
+        quote(name),
+        screen_num
+    );
+    send_message(connection, atoms, screen_num, window, &message)
+}
+
+pub fn send_remove(
+    connection: &impl Connection,
+    atoms: &Atoms,
+    screen_num: usize,
+    window: xproto::Window,
+    startup_id: &str,
+) -> anyhow::Result<()> {
+    let message = format!("remove: ID={}", quote(startup_id));
+    send_message(connection, atoms, screen_num, window, &message)
+}
+
+// The message is NUL-terminated and split into chunks of 20 bytes. The first chunk is sent as
+// _NET_STARTUP_INFO_BEGIN and the rest as _NET_STARTUP_INFO.
+fn send_message(
+    connection: &impl Connection,
+    atoms: &Atoms,
+    screen_num: usize,
+    window: xproto::Window,
+    message: &str,
+) -> anyhow::Result<()> {
+    log::debug!("send startup notification message: {}", message);
+
+    let root = connection.setup().roots[screen_num].root;
+    let mut bytes = message.as_bytes().to_vec();
+    bytes.push(0);
+
+    for (i, chunk) in bytes.chunks(20).enumerate() {
+        let mut data = [0; 20];
+        data[..chunk.len()].copy_from_slice(chunk);
+        let type_ = if i == 0 {
+            atoms._NET_STARTUP_INFO_BEGIN
+        } else {
+            atoms._NET_STARTUP_INFO
+        };
+        let event = xproto::ClientMessageEvent::new(8, window, type_, data);
+        connection
+            .send_event(false, root, xproto::EventMask::PROPERTY_CHANGE, event)?
+            .check()
+            .context("send startup notification message")?;
+    }
+
+    connection
+        .flush()
+        .context("flush after send startup notification message")?;
+
+    Ok(())
+}
+
+fn quote(value: &str) -> String {
+    if !value.contains([' ', '"', '\\']) {
+        return value.to_owned();
+    }
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use x11rb::protocol::Request;
+    use x11rb::x11_utils::TryParse as _;
+
+    use super::*;
+    use crate::fake_connection::{FakeConnection, ROOT_WINDOW};
+
+    #[test]
+    fn test_send_new() {
+        let connection = FakeConnection::new();
+        let atoms = Atoms::new(&connection).unwrap().reply().unwrap();
+
+        send_new(
+            &connection,
+            &atoms,
+            0,
+            1,
+            "geektray-1-0",
+            "echo \"foo bar\"",
+        )
+        .unwrap();
+
+        let events: Vec<_> = connection
+            .take_requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                Request::SendEvent(request) if request.destination == ROOT_WINDOW => {
+                    xproto::ClientMessageEvent::try_parse(request.event.as_slice())
+                        .ok()
+                        .map(|(event, _)| event)
+                }
+                _ => None,
+            })
+            .collect();
+        let message: Vec<u8> = events
+            .iter()
+            .flat_map(|event| event.data.as_data8())
+            .take_while(|byte| *byte != 0)
+            .collect();
+
+        assert_eq!(events[0].type_, atoms._NET_STARTUP_INFO_BEGIN);
+        assert!(events[1..]
+            .iter()
+            .all(|event| event.type_ == atoms._NET_STARTUP_INFO));
+        assert_eq!(
+            String::from_utf8(message).unwrap(),
+            "new: ID=geektray-1-0 NAME=\"echo \\\"foo bar\\\"\" SCREEN=0"
+        );
+    }
+}
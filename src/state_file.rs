@@ -0,0 +1,99 @@
+use anyhow::Context as _;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use x11rb::protocol::xproto;
+
+#[derive(Debug)]
+pub struct ItemState<'a> {
+    pub screen_num: usize,
+    pub icon: xproto::Window,
+    pub title: &'a str,
+    pub class: Option<&'a str>,
+    pub is_urgent: bool,
+}
+
+// Serializes the items as a JSON array of objects.
+pub fn to_json(items: &[ItemState]) -> String {
+    let mut json = String::from("[");
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(
+            json,
+            "{{\"screen\":{},\"id\":{},\"title\":{},\"class\":{},\"urgent\":{}}}",
+            item.screen_num,
+            item.icon,
+            quote(item.title),
+            item.class.map_or("null".to_owned(), quote),
+            item.is_urgent
+        )
+        .unwrap();
+    }
+    json.push_str("]\n");
+    json
+}
+
+// Writes the content to a temporary file next to the path and renames it, so that readers never
+// see a partially written file.
+pub fn write(path: &Path, content: &str) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("create state file dir")?;
+    }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    fs::write(&temp_path, content).context("write temporary state file")?;
+    fs::rename(&temp_path, path).context("rename temporary state file")?;
+    Ok(())
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(to_json(&[]), "[]\n");
+        assert_eq!(
+            to_json(&[
+                ItemState {
+                    screen_num: 0,
+                    icon: 123,
+                    title: "foo \"bar\"\n",
+                    class: Some("Foo"),
+                    is_urgent: true,
+                },
+                ItemState {
+                    screen_num: 1,
+                    icon: 456,
+                    title: "\u{1}",
+                    class: None,
+                    is_urgent: false,
+                },
+            ]),
+            concat!(
+                "[{\"screen\":0,\"id\":123,\"title\":\"foo \\\"bar\\\"\\n\",\"class\":\"Foo\",\"urgent\":true},",
+                "{\"screen\":1,\"id\":456,\"title\":\"\\u0001\",\"class\":null,\"urgent\":false}]\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::{anyhow, Context as _};
+use cairo_sys as cairo;
+use glib_sys as glib;
+use gobject_sys as gobject;
+use std::ffi::{CStr, CString};
+use std::os::raw::*;
+use std::os::unix::ffi::OsStrExt as _;
+use std::path::Path;
+use std::ptr;
+
+use crate::geometrics::PhysicalSize;
+use crate::render_context::read_image_pixels;
+use crate::renderer::Image;
+
+#[repr(C)]
+struct RsvgHandle {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct RsvgRectangle {
+    x: c_double,
+    y: c_double,
+    width: c_double,
+    height: c_double,
+}
+
+extern "C" {
+    fn rsvg_handle_new_from_file(
+        filename: *const c_char,
+        error: *mut *mut glib::GError,
+    ) -> *mut RsvgHandle;
+
+    fn rsvg_handle_render_document(
+        handle: *mut RsvgHandle,
+        cr: *mut cairo::cairo_t,
+        viewport: *const RsvgRectangle,
+        error: *mut *mut glib::GError,
+    ) -> glib::gboolean;
+}
+
+// Renders the document into the viewport of the size, where it is centered with its aspect ratio
+// kept.
+pub fn render_svg(path: &Path, size: PhysicalSize) -> anyhow::Result<Image> {
+    let filename = CString::new(path.as_os_str().as_bytes()).context("svg path")?;
+
+    unsafe {
+        let mut error = ptr::null_mut();
+        let handle = rsvg_handle_new_from_file(filename.as_ptr(), &mut error);
+        if handle.is_null() {
+            return Err(take_error(error)).context("load svg");
+        }
+
+        let surface = cairo::cairo_image_surface_create(
+            cairo::FORMAT_A_RGB32,
+            size.width as i32,
+            size.height as i32,
+        );
+        let cairo = cairo::cairo_create(surface);
+        let viewport = RsvgRectangle {
+            x: 0.0,
+            y: 0.0,
+            width: size.width as f64,
+            height: size.height as f64,
+        };
+        let result = if rsvg_handle_render_document(handle, cairo, &viewport, &mut error) != 0 {
+            cairo::cairo_surface_flush(surface);
+            Ok(Image {
+                size,
+                pixels: read_image_pixels(surface),
+            })
+        } else {
+            Err(take_error(error)).context("render svg")
+        };
+
+        cairo::cairo_destroy(cairo);
+        cairo::cairo_surface_destroy(surface);
+        gobject::g_object_unref(handle.cast());
+
+        result
+    }
+}
+
+unsafe fn take_error(error: *mut glib::GError) -> anyhow::Error {
+    if error.is_null() {
+        return anyhow!("unknown error");
+    }
+    let message = CStr::from_ptr((*error).message)
+        .to_string_lossy()
+        .into_owned();
+    glib::g_error_free(error);
+    anyhow!(message)
+}
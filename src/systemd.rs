@@ -0,0 +1,77 @@
+use anyhow::Context as _;
+use std::env;
+use std::os::linux::net::SocketAddrExt as _;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+// Sends the state of the service to systemd through $NOTIFY_SOCKET (see sd_notify(3)).
+#[derive(Debug)]
+pub struct Notifier {
+    socket: UnixDatagram,
+    address: SocketAddr,
+}
+
+impl Notifier {
+    // Returns None if the process is not run as a service of Type=notify. The variable is removed
+    // so that the commands launched by the tray do not notify on behalf of it.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+            return Ok(None);
+        };
+        env::remove_var("NOTIFY_SOCKET");
+
+        let path = path.to_string_lossy();
+        let address = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name),
+            None => SocketAddr::from_pathname(path.as_ref()),
+        }
+        .with_context(|| format!("invalid NOTIFY_SOCKET: {}", path))?;
+        let socket = UnixDatagram::unbound().context("create notify socket")?;
+
+        Ok(Some(Self { socket, address }))
+    }
+
+    pub fn notify(&self, state: &str) -> anyhow::Result<()> {
+        self.socket
+            .send_to_addr(state.as_bytes(), &self.address)
+            .context("send notify message")?;
+        Ok(())
+    }
+}
+
+pub fn status_message(item_count: usize) -> String {
+    match item_count {
+        1 => "STATUS=1 item".to_owned(),
+        count => format!("STATUS={} items", count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_notify() {
+        let path = env::temp_dir().join(format!("geektray-notify-{}.sock", std::process::id()));
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+
+        let notifier = Notifier {
+            socket: UnixDatagram::unbound().unwrap(),
+            address: SocketAddr::from_pathname(&path).unwrap(),
+        };
+        notifier.notify("READY=1").unwrap();
+        notifier.notify(&status_message(1)).unwrap();
+        notifier.notify(&status_message(3)).unwrap();
+
+        let mut buffer = [0; 64];
+        for expected in ["READY=1", "STATUS=1 item", "STATUS=3 items"] {
+            let len = receiver.recv(&mut buffer).unwrap();
+            assert_eq!(&buffer[..len], expected.as_bytes());
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
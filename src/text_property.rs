@@ -0,0 +1,349 @@
+use std::borrow::Cow;
+use x11rb::protocol::xproto;
+
+use crate::atoms::Atoms;
+use crate::config::TitleEllipsis;
+
+const ESC: u8 = 0x1b;
+const CSI: u8 = 0x9b;
+const STX: u8 = 0x02;
+
+// The charsets designated to GL and GR in COMPOUND_TEXT that can be decoded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Charset {
+    Ascii,
+    Latin1,
+    // Decoded as U+FFFD for each character of the width.
+    Unsupported(usize),
+}
+
+/// Decodes a text property (e.g. WM_NAME) by its type, which is UTF8_STRING, STRING (Latin-1) or
+/// COMPOUND_TEXT. Invalid bytes are replaced with U+FFFD instead of discarding the whole text.
+pub fn decode_text_property(atoms: &Atoms, type_: xproto::Atom, value: &[u8]) -> Option<String> {
+    if type_ == atoms.UTF8_STRING {
+        Some(String::from_utf8_lossy(value).into_owned())
+    } else if type_ == u32::from(xproto::AtomEnum::STRING) {
+        Some(value.iter().map(|byte| char::from(*byte)).collect())
+    } else if type_ == atoms.COMPOUND_TEXT {
+        Some(decode_compound_text(value))
+    } else {
+        None
+    }
+}
+
+/// Replaces the line breaks and the tabs with spaces, and removes the other control characters,
+/// which are drawn as boxes or break the layout of the item.
+pub fn sanitize_title(title: &str) -> String {
+    title
+        .chars()
+        .filter_map(|c| match c {
+            '\t' | '\n' | '\r' => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+/// Truncates the title to the number of grapheme clusters with an ellipsis at the position, which
+/// counts as one of them. The title is returned as it is if the limit is 0.
+pub fn truncate_title(title: &str, max_chars: usize, ellipsis: TitleEllipsis) -> Cow<'_, str> {
+    let graphemes = graphemes(title);
+    if max_chars == 0 || graphemes.len() <= max_chars {
+        return Cow::Borrowed(title);
+    }
+    let kept = max_chars - 1;
+    let (head, tail) = match ellipsis {
+        TitleEllipsis::Start => (0, kept),
+        TitleEllipsis::Middle => (kept - kept / 2, kept / 2),
+        TitleEllipsis::End => (kept, 0),
+    };
+    let head_end = graphemes[head];
+    let tail_start = graphemes
+        .get(graphemes.len() - tail)
+        .copied()
+        .unwrap_or(title.len());
+    Cow::Owned(format!(
+        "{}\u{2026}{}",
+        title[..head_end].trim_end(),
+        title[tail_start..].trim_start()
+    ))
+}
+
+// Returns the byte offsets where the grapheme clusters start. This approximates the extended
+// grapheme clusters of UAX #29 by the characters that never start a cluster: combining marks,
+// joiners, variation selectors, emoji modifiers and tags, and the second of regional indicators.
+fn graphemes(text: &str) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut prev = None;
+    let mut regional_indicators = 0;
+    for (i, c) in text.char_indices() {
+        let is_regional_indicator = matches!(c, '\u{1f1e6}'..='\u{1f1ff}');
+        let extends = match prev {
+            None => false,
+            Some('\r') => c == '\n',
+            Some('\u{200d}') => true,
+            Some(_) if is_regional_indicator => regional_indicators % 2 == 1,
+            Some(_) => is_grapheme_extend(c),
+        };
+        if !extends {
+            offsets.push(i);
+        }
+        regional_indicators = if is_regional_indicator {
+            regional_indicators + 1
+        } else {
+            0
+        };
+        prev = Some(c);
+    }
+    offsets
+}
+
+fn is_grapheme_extend(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036f}'
+            | '\u{0483}'..='\u{0489}'
+            | '\u{0591}'..='\u{05bd}'
+            | '\u{0610}'..='\u{061a}'
+            | '\u{064b}'..='\u{065f}'
+            | '\u{0900}'..='\u{0903}'
+            | '\u{093a}'..='\u{094f}'
+            | '\u{0e31}'
+            | '\u{0e34}'..='\u{0e3a}'
+            | '\u{0e47}'..='\u{0e4e}'
+            | '\u{1160}'..='\u{11ff}'
+            | '\u{1ab0}'..='\u{1aff}'
+            | '\u{1dc0}'..='\u{1dff}'
+            | '\u{200c}'..='\u{200d}'
+            | '\u{20d0}'..='\u{20ff}'
+            | '\u{302a}'..='\u{302f}'
+            | '\u{3099}'..='\u{309a}'
+            | '\u{fe00}'..='\u{fe0f}'
+            | '\u{fe20}'..='\u{fe2f}'
+            | '\u{1f3fb}'..='\u{1f3ff}'
+            | '\u{e0020}'..='\u{e007f}'
+            | '\u{e0100}'..='\u{e01ef}'
+    )
+}
+
+// Decodes the subset of COMPOUND_TEXT that Xlib produces for the common locales: ASCII and
+// ISO 8859-1, and UTF-8 in the XFree86 extension (ESC % G) or extended segments. The characters of
+// the other charsets are replaced with U+FFFD.
+fn decode_compound_text(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    let mut gl = Charset::Ascii;
+    let mut gr = Charset::Latin1;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            ESC => {
+                i += 1;
+                match bytes.get(i..) {
+                    // UTF-8 until ESC % @.
+                    Some([b'%', b'G', ..]) => {
+                        i += 2;
+                        let end = find_subslice(&bytes[i..], &[ESC, b'%', b'@'])
+                            .map_or(bytes.len(), |end| i + end);
+                        text.push_str(&String::from_utf8_lossy(&bytes[i..end]));
+                        i = (end + 3).min(bytes.len());
+                    }
+                    // An extended segment: ESC % / F M L, the length of the rest, the encoding
+                    // name terminated by STX and the text.
+                    Some([b'%', b'/', _, m, l, ..]) => {
+                        let length = ((*m as usize & 0x7f) << 7) | (*l as usize & 0x7f);
+                        let start = i + 5;
+                        let end = (start + length).min(bytes.len());
+                        let segment = &bytes[start..end];
+                        if let Some(name_end) = segment.iter().position(|byte| *byte == STX) {
+                            let name = String::from_utf8_lossy(&segment[..name_end]);
+                            let data = &segment[name_end + 1..];
+                            if name.eq_ignore_ascii_case("utf-8") {
+                                text.push_str(&String::from_utf8_lossy(data));
+                            } else if name.eq_ignore_ascii_case("iso8859-1") {
+                                text.extend(data.iter().map(|byte| char::from(*byte)));
+                            } else {
+                                text.push(char::REPLACEMENT_CHARACTER);
+                            }
+                        }
+                        i = end;
+                    }
+                    // 94-character sets designated to GL.
+                    Some([b'(', final_byte, ..]) => {
+                        gl = match final_byte {
+                            b'B' | b'J' => Charset::Ascii,
+                            _ => Charset::Unsupported(1),
+                        };
+                        i += 2;
+                    }
+                    // 96-character sets designated to GR.
+                    Some([b'-', final_byte, ..]) => {
+                        gr = match final_byte {
+                            b'A' => Charset::Latin1,
+                            _ => Charset::Unsupported(1),
+                        };
+                        i += 2;
+                    }
+                    // Multibyte sets, e.g. ESC $ ( B for JIS X 0208.
+                    Some([b'$', b'(', _, ..]) => {
+                        gl = Charset::Unsupported(2);
+                        i += 3;
+                    }
+                    Some([b'$', b')', _, ..]) => {
+                        gr = Charset::Unsupported(2);
+                        i += 3;
+                    }
+                    // Unknown or truncated sequences are skipped by their intermediate bytes and
+                    // final byte.
+                    _ => {
+                        while bytes
+                            .get(i)
+                            .is_some_and(|byte| (0x20..=0x2f).contains(byte))
+                        {
+                            i += 1;
+                        }
+                        if bytes
+                            .get(i)
+                            .is_some_and(|byte| (0x30..=0x7e).contains(byte))
+                        {
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            // Direction changes (CSI 1 ], CSI 2 ] and CSI ]) do not affect the text.
+            CSI => {
+                i = bytes[i..]
+                    .iter()
+                    .position(|byte| *byte == b']')
+                    .map_or(bytes.len(), |end| i + end + 1);
+            }
+            0x20..=0x7f => {
+                i += decode_char(gl, &bytes[i..], &mut text);
+            }
+            0xa0..=0xff => {
+                i += decode_char(gr, &bytes[i..], &mut text);
+            }
+            // Other control characters (e.g. newlines and tabs) are kept for sanitize_title().
+            byte => {
+                text.push(char::from(byte));
+                i += 1;
+            }
+        }
+    }
+
+    text
+}
+
+// Pushes a character in the charset, and returns the number of bytes consumed. The bytes of
+// ASCII in GL and ISO 8859-1 in GR are the code points as they are.
+fn decode_char(charset: Charset, bytes: &[u8], text: &mut String) -> usize {
+    match charset {
+        Charset::Ascii | Charset::Latin1 => {
+            text.push(char::from(bytes[0]));
+            1
+        }
+        Charset::Unsupported(width) => {
+            text.push(char::REPLACEMENT_CHARACTER);
+            width.min(bytes.len())
+        }
+    }
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_compound_text() {
+        assert_eq!(decode_compound_text(b"Hello"), "Hello");
+        // ISO 8859-1 is designated to GR by default.
+        assert_eq!(decode_compound_text(b"caf\xe9"), "café");
+        assert_eq!(decode_compound_text(b"\x1b-Acaf\xe9 \x1b(Bok"), "café ok");
+        assert_eq!(
+            decode_compound_text(b"a\x1b%G\xe6\x97\xa5\xe6\x9c\xac\x1b%@b"),
+            "a日本b"
+        );
+        // The UTF-8 segment may be left open until the end.
+        assert_eq!(decode_compound_text(b"\x1b%G\xc3\xa9"), "é");
+        assert_eq!(
+            decode_compound_text(b"\x1b%/1\x80\x89utf-8\x02\xe2\x9c\x93ok"),
+            "✓ok"
+        );
+        // JIS X 0208 in GL is not supported, one U+FFFD for each character.
+        assert_eq!(
+            decode_compound_text(b"\x1b$(B$\"$$\x1b(Bx"),
+            "\u{fffd}\u{fffd}x"
+        );
+        // ISO 8859-2 in GR is not supported.
+        assert_eq!(decode_compound_text(b"\x1b-B\xb1"), "\u{fffd}");
+        assert_eq!(decode_compound_text(b"\x9b1]abc\x9b]"), "abc");
+        // Truncated sequences do not panic.
+        assert_eq!(decode_compound_text(b"abc\x1b"), "abc");
+        assert_eq!(decode_compound_text(b"abc\x1b%/1"), "abc");
+        assert_eq!(decode_compound_text(b"\x9b1"), "");
+    }
+
+    #[test]
+    fn test_truncate_title() {
+        let title = "Inbox (3) - Mozilla Firefox";
+        assert_eq!(truncate_title(title, 0, TitleEllipsis::End), title);
+        assert_eq!(truncate_title(title, 27, TitleEllipsis::End), title);
+        assert_eq!(
+            truncate_title(title, 16, TitleEllipsis::Start),
+            "\u{2026}Mozilla Firefox"
+        );
+        assert_eq!(
+            truncate_title(title, 16, TitleEllipsis::Middle),
+            "Inbox (3\u{2026}Firefox"
+        );
+        assert_eq!(
+            truncate_title(title, 16, TitleEllipsis::End),
+            "Inbox (3) - Moz\u{2026}"
+        );
+        // The spaces next to the ellipsis are trimmed.
+        assert_eq!(
+            truncate_title(title, 7, TitleEllipsis::End),
+            "Inbox\u{2026}"
+        );
+        assert_eq!(truncate_title(title, 1, TitleEllipsis::Middle), "\u{2026}");
+        // Combining marks, emoji sequences and flags are not split.
+        assert_eq!(
+            truncate_title("e\u{301}e\u{301}e\u{301}", 2, TitleEllipsis::End),
+            "e\u{301}\u{2026}"
+        );
+        assert_eq!(
+            truncate_title(
+                "\u{1f469}\u{200d}\u{1f4bb}\u{1f44d}\u{1f3fd}x",
+                2,
+                TitleEllipsis::Start
+            ),
+            "\u{2026}x"
+        );
+        assert_eq!(
+            truncate_title(
+                "\u{1f1ef}\u{1f1f5}\u{1f1fa}\u{1f1f8}\u{1f1eb}\u{1f1f7}",
+                2,
+                TitleEllipsis::End
+            ),
+            "\u{1f1ef}\u{1f1f5}\u{2026}"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_title() {
+        assert_eq!(sanitize_title("Foo"), "Foo");
+        assert_eq!(sanitize_title("Foo\nBar\tBaz\r\n"), "Foo Bar Baz");
+        assert_eq!(sanitize_title("\x07Foo\x1b[0m\u{7f}\u{85}"), "Foo[0m");
+        assert_eq!(sanitize_title("  \u{fffd} "), "\u{fffd}");
+        assert_eq!(sanitize_title("\x00\x01"), "");
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct TimerQueue<T> {
+    timers: Vec<(Instant, T)>,
+}
+
+impl<T> TimerQueue<T> {
+    pub fn new() -> Self {
+        Self { timers: Vec::new() }
+    }
+
+    pub fn schedule(&mut self, deadline: Instant, token: T) {
+        let index = self
+            .timers
+            .partition_point(|(other_deadline, _)| *other_deadline <= deadline);
+        self.timers.insert(index, (deadline, token));
+    }
+
+    // Returns the duration until the nearest deadline, or `None` if no timers are scheduled.
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        self.timers
+            .first()
+            .map(|(deadline, _)| deadline.saturating_duration_since(now))
+    }
+
+    pub fn cancel(&mut self, predicate: impl Fn(&T) -> bool) {
+        self.timers.retain(|(_, token)| !predicate(token));
+    }
+
+    pub fn take_expired(&mut self, now: Instant) -> Vec<T> {
+        let expired_len = self
+            .timers
+            .partition_point(|(deadline, _)| *deadline <= now);
+        self.timers
+            .drain(..expired_len)
+            .map(|(_, token)| token)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_expired() {
+        let now = Instant::now();
+        let mut timers = TimerQueue::new();
+
+        timers.schedule(now + Duration::from_secs(3), "c");
+        timers.schedule(now + Duration::from_secs(1), "a");
+        timers.schedule(now + Duration::from_secs(2), "b");
+
+        assert_eq!(timers.timeout(now), Some(Duration::from_secs(1)));
+        assert_eq!(timers.take_expired(now), Vec::<&str>::new());
+        assert_eq!(
+            timers.take_expired(now + Duration::from_secs(2)),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            timers.timeout(now + Duration::from_secs(4)),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(timers.take_expired(now + Duration::from_secs(4)), vec!["c"]);
+        assert_eq!(timers.timeout(now), None);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let now = Instant::now();
+        let mut timers = TimerQueue::new();
+
+        timers.schedule(now + Duration::from_secs(1), "a");
+        timers.schedule(now + Duration::from_secs(2), "b");
+        timers.cancel(|token| *token == "a");
+
+        assert_eq!(timers.timeout(now), Some(Duration::from_secs(2)));
+        assert_eq!(timers.take_expired(now + Duration::from_secs(2)), vec!["b"]);
+    }
+}
@@ -1,28 +1,142 @@
 use anyhow::{self, Context as _};
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs;
 use std::ops::ControlFlow;
+use std::os::unix::ffi::OsStrExt as _;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
 use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::sync;
+use x11rb::protocol::sync::ConnectionExt as _;
 use x11rb::protocol::xproto;
 use x11rb::protocol::xproto::ConnectionExt as _;
 use x11rb::wrapper::ConnectionExt;
 use x11rb::{properties, protocol};
 
 use crate::atoms::Atoms;
-use crate::config::{UIConfig, WindowConfig};
-use crate::event::MouseButton;
-use crate::geometrics::{PhysicalPoint, PhysicalSize, Rect, Size};
-use crate::render_context::{HAlign, RenderContext, VAlign};
-use crate::xembed::XEmbedInfo;
+use crate::color::Color;
+use crate::command;
+use crate::config::{
+    ClickConfig, GroupsConfig, ItemStyleConfig, KeyBinding, MessageDestination, Placement,
+    RedrawRateConfig, RemoteIconPolicy, SelectionStyle, SortOrder, UIConfig, WindowConfig,
+    WindowState, WindowType,
+};
+use crate::desktop_entry::DesktopEntryIndex;
+use crate::event::{Modifiers, MouseButton};
+use crate::geometrics::{PhysicalPoint, PhysicalRect, PhysicalSize, Point, Rect, Size};
+use crate::help_overlay::HelpOverlay;
+use crate::icon_theme::IconTheme;
+use crate::message_history::{MessageHistory, ReceivedMessage};
+use crate::rate_limiter::RateLimiter;
+use crate::render_context::{encode_png, load_image, measure_text, HAlign, VAlign};
+use crate::renderer::{Image, Renderer};
+use crate::startup_notification;
+use crate::state_file::ItemState;
+use crate::text_property::truncate_title;
+use crate::tray_manager::parse_wm_class;
+use crate::usage_stats::UsageStats;
+use crate::window_position;
+use crate::xembed::{send_xembed_message, XEmbedInfo, XEmbedMessage, XEMBED_FOCUS_CURRENT};
 
+const MIN_WINDOW_WIDTH: i32 = 64;
+const PARKED_POSITION: i32 = -32000;
+const NET_WM_STATE_ADD: u32 = 1;
+const MWM_HINTS_DECORATIONS: u32 = 1 << 1;
+const SOURCE_INDICATION_APPLICATION: u32 = 1;
+const SOURCE_INDICATION_PAGER: u32 = 2;
+const WHEEL_UP: xproto::Button = 4;
+const WHEEL_DOWN: xproto::Button = 5;
+
+/// The popup window that lists the docked icons with their titles.
 pub struct TrayEmbedder<C: Connection> {
     connection: Rc<C>,
     screen_num: usize,
+    atoms: Rc<Atoms>,
     window: xproto::Window,
+    states: Vec<xproto::Atom>,
+    override_redirect: bool,
+    // An input-only window over the whole screen under the tray window, which catches the clicks
+    // outside it. It is created when the window is shown first.
+    close_on_outside_click: bool,
+    outside_click_window: Option<xproto::Window>,
+    placement: Placement,
+    remember_position: bool,
+    // The position where the window was shown, and the last position moved by the user.
+    shown_position: Option<PhysicalPoint>,
+    saved_position: Option<PhysicalPoint>,
+    is_moved: bool,
     size: PhysicalSize,
+    // The range of the window width that fits the titles, if it is enabled.
+    auto_width: Option<(f64, f64)>,
+    // The rows always reserved, and the rows shown at most before the others are scrolled. 0 is
+    // unlimited for the maximum.
+    min_visible_items: usize,
+    max_visible_items: usize,
+    // The top and the bottom of the area where the items are visible.
+    viewport: (f64, f64),
+    scroll_offset: f64,
+    scroll_step: f64,
+    should_scroll_to_selection: bool,
     is_mapped: bool,
+    // With preload, the hidden window stays mapped out of the screen.
+    preload: bool,
+    is_parked: bool,
+    focus_before_shown: Option<xproto::Window>,
+    shown_at: Option<Instant>,
     tray_items: Vec<TrayItem>,
+    pinned_items: Vec<PinnedItem>,
+    custom_items: Vec<CustomItem>,
+    groups: GroupsConfig,
+    click: ClickConfig,
+    sort_order: SortOrder,
+    // The number of redraws per second by the changes of each icon, and the overrides by class.
+    redraw_rate: f64,
+    redraw_rate_rules: Vec<RedrawRateConfig>,
+    item_styles: Vec<ItemStyle>,
+    // The names and the icons of the applications by class.
+    desktop_entries: Rc<DesktopEntryIndex>,
+    // Looks up the icons drawn for the pinned items.
+    icon_theme: Option<IconTheme>,
+    is_redraw_deferred: bool,
+    deferred_redraw_at: Option<Instant>,
+    // How long the items flash when their titles change, if it is enabled.
+    flash_duration: Option<Duration>,
+    // The pattern that extracts the count of notifications from titles.
+    count_pattern: Option<Regex>,
+    // Whether the newest icons are listed first.
+    reverse: bool,
+    group_headers: Vec<GroupHeader>,
+    separators: Vec<f64>,
+    footer_bounds: Option<Rect>,
+    // The counter of the extended sync protocol, and the value requested by the window manager,
+    // which is set to the counter after the next redraw.
+    sync_counter: Option<sync::Counter>,
+    sync_value: Option<sync::Int64>,
     selected_index: Option<usize>,
+    // The icon that the key events are forwarded to.
+    focused_icon: Option<xproto::Window>,
+    last_clicked_at: Option<Instant>,
+    // The startup sequences of launched commands that have not been completed yet.
+    startup_ids: Vec<String>,
+    // How the items have been clicked, which are saved if remember_usage is true.
+    usage_stats: UsageStats,
+    remember_usage: bool,
+    // The help of the key bindings or the message history, which is shown in place of the items.
+    overlay: Option<Overlay>,
+    // The balloon messages received recently, from the oldest.
+    messages: VecDeque<ReceivedMessage>,
+    message_history_size: usize,
+    // The template of the window title, and the title currently set to the window.
+    title_template: Cow<'static, str>,
+    title: String,
     should_layout: bool,
     should_redraw: bool,
 }
@@ -31,8 +145,10 @@ impl<C: Connection> TrayEmbedder<C> {
     pub fn new(
         connection: Rc<C>,
         screen_num: usize,
-        atoms: &Atoms,
+        atoms: Rc<Atoms>,
         config: &WindowConfig,
+        groups: GroupsConfig,
+        click: ClickConfig,
         size: PhysicalSize,
     ) -> anyhow::Result<Self> {
         let window = connection.generate_id().context("generate window id")?;
@@ -58,11 +174,14 @@ impl<C: Connection> TrayEmbedder<C> {
             | xproto::EventMask::KEY_RELEASE
             | xproto::EventMask::LEAVE_WINDOW
             | xproto::EventMask::PROPERTY_CHANGE
-            | xproto::EventMask::STRUCTURE_NOTIFY;
+            | xproto::EventMask::STRUCTURE_NOTIFY
+            // The tray manager tracks the frames of the icons by this.
+            | xproto::EventMask::SUBSTRUCTURE_NOTIFY;
         let values = xproto::CreateWindowAux::new()
             .event_mask(event_mask)
             .colormap(colormap)
-            .border_pixel(screen.black_pixel);
+            .border_pixel(screen.black_pixel)
+            .override_redirect(u32::from(config.override_redirect));
 
         connection
             .create_window(
@@ -83,42 +202,40 @@ impl<C: Connection> TrayEmbedder<C> {
 
         set_size_hints(&*connection, window, size)?;
 
+        let sync_counter = create_sync_counter(&*connection)?;
+        let mut protocols = vec![
+            atoms._NET_WM_PING,
+            atoms.WM_DELETE_WINDOW,
+            atoms.WM_SAVE_YOURSELF,
+        ];
+
+        if let Some(sync_counter) = sync_counter {
+            protocols.push(atoms._NET_WM_SYNC_REQUEST);
+            connection
+                .change_property32(
+                    xproto::PropMode::REPLACE,
+                    window,
+                    atoms._NET_WM_SYNC_REQUEST_COUNTER,
+                    xproto::AtomEnum::CARDINAL,
+                    &[sync_counter],
+                )?
+                .check()
+                .context("set _NET_WM_SYNC_REQUEST_COUNTER")?;
+        }
+
         connection
             .change_property32(
                 xproto::PropMode::REPLACE,
                 window,
                 atoms.WM_PROTOCOLS,
                 xproto::AtomEnum::ATOM,
-                &[
-                    atoms._NET_WM_PING,
-                    atoms._NET_WM_SYNC_REQUEST,
-                    atoms.WM_DELETE_WINDOW,
-                ],
+                &protocols,
             )?
             .check()
             .context("set WM_PROTOCOLS")?;
 
-        connection
-            .change_property8(
-                xproto::PropMode::REPLACE,
-                window,
-                xproto::AtomEnum::WM_NAME,
-                xproto::AtomEnum::STRING,
-                config.title.as_bytes(),
-            )?
-            .check()
-            .context("set WM_NAME")?;
-
-        connection
-            .change_property8(
-                xproto::PropMode::REPLACE,
-                window,
-                atoms._NET_WM_NAME,
-                atoms.UTF8_STRING,
-                config.title.as_bytes(),
-            )?
-            .check()
-            .context("set _NET_WM_NAME")?;
+        let title = format_title(&config.title, 0, None);
+        set_window_title(&*connection, &atoms, window, &title)?;
 
         {
             let class_string = format!(
@@ -155,22 +272,24 @@ impl<C: Connection> TrayEmbedder<C> {
                 window,
                 atoms._NET_WM_WINDOW_TYPE,
                 xproto::AtomEnum::ATOM,
-                &[atoms._NET_WM_WINDOW_TYPE_NORMAL],
+                &[window_type_atom(&atoms, config.window_type)],
             )?
             .check()
             .context("set _NET_WM_WINDOW_TYPE")?;
 
+        let states: Vec<_> = config
+            .states
+            .iter()
+            .map(|state| window_state_atom(&atoms, *state))
+            .collect();
+
         connection
             .change_property32(
                 xproto::PropMode::REPLACE,
                 window,
                 atoms._NET_WM_STATE,
                 xproto::AtomEnum::ATOM,
-                &[
-                    atoms._NET_WM_STATE_ABOVE,
-                    atoms._NET_WM_STATE_STAYS_ON_TOP,
-                    atoms._NET_WM_STATE_STICKY,
-                ],
+                &states,
             )?
             .check()
             .context("set _NET_WM_STATE")?;
@@ -186,33 +305,289 @@ impl<C: Connection> TrayEmbedder<C> {
             .check()
             .context("set _NET_WM_DESKTOP")?;
 
+        if !config.decorations {
+            // flags, functions, decorations, input_mode and status
+            connection
+                .change_property32(
+                    xproto::PropMode::REPLACE,
+                    window,
+                    atoms._MOTIF_WM_HINTS,
+                    atoms._MOTIF_WM_HINTS,
+                    &[MWM_HINTS_DECORATIONS, 0, 0, 0, 0],
+                )?
+                .check()
+                .context("set _MOTIF_WM_HINTS")?;
+        }
+
         Ok(Self {
             connection,
             screen_num,
+            atoms,
             window,
+            states,
+            override_redirect: config.override_redirect,
+            close_on_outside_click: false,
+            outside_click_window: None,
+            placement: config.placement,
+            remember_position: config.remember_position,
+            auto_width: config
+                .auto_width
+                .then_some((config.min_width, config.max_width)),
+            min_visible_items: config.min_visible_items,
+            max_visible_items: config.max_visible_items,
+            viewport: (0.0, f64::INFINITY),
+            scroll_offset: 0.0,
+            scroll_step: 0.0,
+            should_scroll_to_selection: false,
+            shown_position: None,
+            is_moved: false,
+            saved_position: if config.remember_position {
+                window_position::load(screen_num)
+            } else {
+                None
+            },
             size,
             is_mapped: false,
+            preload: config.preload,
+            is_parked: false,
+            focus_before_shown: None,
+            shown_at: None,
             tray_items: Vec::new(),
+            pinned_items: Vec::new(),
+            custom_items: Vec::new(),
+            groups,
+            click,
+            sort_order: SortOrder::default(),
+            redraw_rate: 0.0,
+            redraw_rate_rules: Vec::new(),
+            item_styles: Vec::new(),
+            desktop_entries: Rc::default(),
+            icon_theme: None,
+            is_redraw_deferred: false,
+            deferred_redraw_at: None,
+            flash_duration: None,
+            count_pattern: None,
+            reverse: false,
+            group_headers: Vec::new(),
+            separators: Vec::new(),
+            footer_bounds: None,
+            sync_counter,
+            sync_value: None,
             selected_index: None,
+            title_template: config.title.clone(),
+            title,
+            focused_icon: None,
+            last_clicked_at: None,
+            startup_ids: Vec::new(),
+            usage_stats: UsageStats::default(),
+            remember_usage: false,
+            overlay: None,
+            messages: VecDeque::new(),
+            message_history_size: 0,
             should_layout: true,
             should_redraw: true,
         })
     }
 
-    pub fn add_icon(&mut self, icon: xproto::Window, title: String, xembed_info: XEmbedInfo) {
-        let tray_item = TrayItem::new(icon, title, xembed_info);
-        self.tray_items.push(tray_item);
+    pub fn add_icon(
+        &mut self,
+        icon: xproto::Window,
+        title: String,
+        class: Option<String>,
+        is_remote: bool,
+        xembed_info: XEmbedInfo,
+    ) {
+        // Icons of pinned applications are kept in front of the others.
+        let pinned_count = self
+            .tray_items
+            .iter()
+            .take_while(|tray_item| self.pinned_rank(tray_item.class.as_deref()).is_some())
+            .count();
+        let index = if let Some(rank) = self.pinned_rank(class.as_deref()) {
+            self.tray_items[..pinned_count]
+                .iter()
+                .position(|tray_item| {
+                    self.pinned_rank(tray_item.class.as_deref())
+                        .is_some_and(|other_rank| other_rank > rank)
+                })
+                .unwrap_or(pinned_count)
+        } else if self.groups.enabled {
+            self.group_insertion_index(class.as_deref(), pinned_count)
+        } else if self.reverse {
+            pinned_count
+        } else {
+            self.tray_items.len()
+        };
+        let redraw_rate = self
+            .redraw_rate_rules
+            .iter()
+            .find(|rule| class.as_ref() == Some(&rule.class))
+            .map_or(self.redraw_rate, |rule| rule.rate);
+        let mut tray_item = TrayItem::new(icon, title, class, is_remote, xembed_info);
+        if let Some(count_pattern) = &self.count_pattern {
+            tray_item.count = extract_count(count_pattern, &tray_item.title);
+        }
+        tray_item.application_name = application_name(&self.desktop_entries, &tray_item.class);
+        if redraw_rate > 0.0 {
+            tray_item.redraw_limiter = Some(RateLimiter::new(redraw_rate, Instant::now()));
+        }
+        self.tray_items.insert(index, tray_item);
+        self.sort_tray_items();
+        self.should_layout = true;
+    }
+
+    // Adds a placeholder that is listed at the top until the icon of the application is docked.
+    // The label defaults to the name of the application, or the class if it has no desktop entry.
+    pub fn add_pinned_item(
+        &mut self,
+        class: String,
+        label: Option<String>,
+        command: Option<String>,
+    ) {
+        let label = label
+            .or_else(|| {
+                self.desktop_entries
+                    .find(&class)
+                    .map(|entry| entry.name.clone())
+            })
+            .unwrap_or_else(|| class.clone());
+        let icon = self
+            .icon_theme
+            .as_mut()
+            .and_then(|icon_theme| icon_theme.find_class_icon(&class, &self.desktop_entries));
+        self.pinned_items
+            .push(PinnedItem::new(class, label, command, icon));
+        self.should_layout = true;
+    }
+
+    // Adds a pseudo item that is listed after the icons, and returns its index in custom items.
+    pub fn add_custom_item(&mut self, action: Option<String>) -> usize {
+        self.custom_items.push(CustomItem::new(action));
         self.should_layout = true;
+        self.custom_items.len() - 1
+    }
+
+    pub fn change_custom_item_title(&mut self, index: usize, title: String) {
+        if let Some(custom_item) = self.custom_items.get_mut(index) {
+            if custom_item.title != title {
+                custom_item.title = title;
+                self.invalidate_labels();
+            }
+        }
     }
 
     pub fn change_title(&mut self, icon: xproto::Window, title: String) {
+        if let Some(index) = self
+            .tray_items
+            .iter()
+            .position(|tray_item| tray_item.icon == icon)
+        {
+            if let Some(count_pattern) = &self.count_pattern {
+                self.tray_items[index].count = extract_count(count_pattern, &title);
+            }
+            self.tray_items[index].title = title;
+            if self.flash_duration.is_some() {
+                self.tray_items[index].flashed_at = Some(Instant::now());
+            }
+            let should_layout = self.sort_order == SortOrder::Title || self.auto_width.is_some();
+            self.request_icon_redraw(index, should_layout);
+            if self.sort_order == SortOrder::Title {
+                self.sort_tray_items();
+            }
+        }
+    }
+
+    pub fn set_count_pattern(&mut self, count_pattern: Option<Regex>) {
+        self.count_pattern = count_pattern;
+    }
+
+    pub fn set_flash_duration(&mut self, flash_duration: Option<Duration>) {
+        self.flash_duration = flash_duration;
+    }
+
+    // Fades the flashes of the items, and returns true while any of them is still visible.
+    pub fn animate(&mut self, now: Instant) -> bool {
+        let Some(flash_duration) = self.flash_duration else {
+            return false;
+        };
+        let mut is_animating = false;
+        for tray_item in &mut self.tray_items {
+            let Some(flashed_at) = tray_item.flashed_at else {
+                continue;
+            };
+            let progress = now.saturating_duration_since(flashed_at).as_secs_f64()
+                / flash_duration.as_secs_f64();
+            if progress < 1.0 {
+                tray_item.flash_level = 1.0 - progress;
+                is_animating = true;
+            } else {
+                tray_item.flashed_at = None;
+                tray_item.flash_level = 0.0;
+            }
+            self.should_redraw = true;
+        }
+        is_animating && self.is_viewable()
+    }
+
+    pub fn change_urgency(&mut self, icon: xproto::Window, is_urgent: bool) {
+        if let Some(index) = self
+            .tray_items
+            .iter()
+            .position(|tray_item| tray_item.icon == icon)
+        {
+            if self.tray_items[index].is_urgent != is_urgent {
+                self.tray_items[index].is_urgent = is_urgent;
+                self.request_icon_redraw(index, false);
+            }
+        }
+    }
+
+    pub fn change_ping_support(&mut self, icon: xproto::Window, supports_ping: bool) {
         if let Some(tray_item) = self
             .tray_items
             .iter_mut()
             .find(|tray_item| tray_item.icon == icon)
         {
-            tray_item.title = title;
-            self.should_redraw = true;
+            tray_item.supports_ping = supports_ping;
+        }
+    }
+
+    // The icons that have not replied to the previous ping are marked as not responding. The
+    // icons that do not support _NET_WM_PING are never pinged.
+    pub fn ping_icons(&mut self) -> anyhow::Result<()> {
+        let mut is_changed = false;
+        for tray_item in &mut self.tray_items {
+            if tray_item.is_ping_pending && !tray_item.is_unresponsive {
+                log::warn!("icon is not responding (icon: {})", tray_item.icon);
+                tray_item.is_unresponsive = true;
+                is_changed = true;
+            }
+            // The icon may have been destroyed in the meantime.
+            if tray_item.supports_ping && !tray_item.is_ping_pending {
+                match send_ping(&*self.connection, &self.atoms, tray_item.icon) {
+                    Ok(()) => tray_item.is_ping_pending = true,
+                    Err(error) => log::warn!("failed to ping icon: {:#}", error),
+                }
+            }
+        }
+        if is_changed {
+            self.invalidate_labels();
+        }
+        Ok(())
+    }
+
+    pub fn handle_ping_reply(&mut self, icon: xproto::Window) {
+        if let Some(tray_item) = self
+            .tray_items
+            .iter_mut()
+            .find(|tray_item| tray_item.icon == icon)
+        {
+            tray_item.is_ping_pending = false;
+            if tray_item.is_unresponsive {
+                log::info!("icon is responding again (icon: {})", icon);
+                tray_item.is_unresponsive = false;
+                self.invalidate_labels();
+            }
         }
     }
 
@@ -228,591 +603,4415 @@ impl<C: Connection> TrayEmbedder<C> {
     }
 
     pub fn clear_icons(&mut self) {
-        self.tray_items.clear();
+        for tray_item in self.tray_items.drain(..) {
+            destroy_frame(&*self.connection, tray_item.frame);
+        }
         self.should_layout = true;
     }
 
-    pub fn click_selected_item(&mut self, button: MouseButton) -> anyhow::Result<()> {
-        if let Some(selected_item) = self
-            .selected_index
-            .and_then(|index| self.tray_items.get(index))
-        {
-            let (button_index, button_mask) = match button {
-                MouseButton::Left => (xproto::ButtonIndex::M1, xproto::ButtonMask::M1),
-                MouseButton::Right => (xproto::ButtonIndex::M3, xproto::ButtonMask::M3),
-                MouseButton::Middle => (xproto::ButtonIndex::M2, xproto::ButtonMask::M2),
-                MouseButton::X1 => (xproto::ButtonIndex::M4, xproto::ButtonMask::M4),
-                MouseButton::X2 => (xproto::ButtonIndex::M5, xproto::ButtonMask::M5),
-            };
-            click_window(
-                &*self.connection,
-                self.screen_num,
-                selected_item.icon,
-                button_index,
-                button_mask,
-            )?;
+    pub fn click_selected_item(
+        &mut self,
+        button: MouseButton,
+        modifiers: Modifiers,
+    ) -> anyhow::Result<()> {
+        let Some(index) = self.selected_index else {
+            return Ok(());
+        };
+        match self.item_refs().get(index) {
+            Some(ItemRef::Tray(index)) => {
+                let selected_item = &mut self.tray_items[*index];
+                if !selected_item.debounce_click(self.click.debounce_ms, Instant::now()) {
+                    return Ok(());
+                }
+                let (button_index, button_mask) = match button {
+                    MouseButton::Left => (xproto::ButtonIndex::M1, xproto::ButtonMask::M1),
+                    MouseButton::Right => (xproto::ButtonIndex::M3, xproto::ButtonMask::M3),
+                    MouseButton::Middle => (xproto::ButtonIndex::M2, xproto::ButtonMask::M2),
+                    MouseButton::X1 => (xproto::ButtonIndex::M4, xproto::ButtonMask::M4),
+                    MouseButton::X2 => (xproto::ButtonIndex::M5, xproto::ButtonMask::M5),
+                };
+                let button_mask = button_mask | xproto::ButtonMask::from(u16::from(modifiers));
+                click_window(
+                    &*self.connection,
+                    self.screen_num,
+                    should_warp_pointer(self.click, selected_item),
+                    selected_item.icon,
+                    button_index,
+                    button_mask,
+                )?;
+                self.last_clicked_at = Some(Instant::now());
+                let usage_key = selected_item.usage_key().to_owned();
+                self.record_usage(&usage_key);
+            }
+            Some(ItemRef::Pinned(index)) => {
+                if let Some(command) = self.pinned_items[*index].command.clone() {
+                    log::info!("launch pinned application: {}", command);
+                    self.launch(&command, None)?;
+                }
+            }
+            Some(ItemRef::Custom(index)) => {
+                if let Some(action) = self.custom_items[*index].action.clone() {
+                    log::info!("run custom item action: {}", action);
+                    self.launch(&action, None)?;
+                }
+            }
+            None => {}
         }
         Ok(())
     }
 
-    pub fn deselect_item(&mut self) {
-        self.selected_index = None;
-        self.should_redraw = true;
-    }
-
-    pub fn draw(
+    // Runs the command with a new startup sequence, so that the window manager can show the
+    // feedback until its window appears.
+    fn launch(
         &mut self,
-        layout_changed: bool,
-        ui_config: &UIConfig,
-        context: &RenderContext,
+        command: &str,
+        timestamp: Option<xproto::Timestamp>,
     ) -> anyhow::Result<()> {
-        log::debug!("draw tray window");
-
-        let size = context.size().unsnap();
-
-        context.draw_rect(
-            Rect {
-                x: 0.0,
-                y: 0.0,
-                width: size.width,
-                height: size.height,
-            },
-            ui_config.window_background,
-        );
-
-        if self.tray_items.len() > 0 {
-            for (index, tray_item) in self.tray_items.iter_mut().enumerate() {
-                let is_selected = self.selected_index.map_or(false, |i| i == index);
-                tray_item.draw(index, is_selected, ui_config, context);
+        let startup_id = startup_notification::generate_startup_id(timestamp);
+        startup_notification::send_new(
+            &*self.connection,
+            &self.atoms,
+            self.screen_num,
+            self.window,
+            &startup_id,
+            command,
+        )?;
+        match command::spawn_detached(command, &[("DESKTOP_STARTUP_ID", &startup_id)]) {
+            Ok(()) => {
+                self.startup_ids.push(startup_id);
+            }
+            Err(error) => {
+                log::error!("failed to launch command: {}", error);
+                self.remove_startup_sequence(&startup_id)?;
             }
-        } else {
-            context.draw_text(
-                "No tray items found",
-                &ui_config.normal_item_font,
-                ui_config.text_size,
-                HAlign::Center,
-                VAlign::Middle,
-                Rect {
-                    x: ui_config.window_padding,
-                    y: 0.0,
-                    width: size.width - (ui_config.window_padding * 2.0),
-                    height: size.height,
-                },
-                ui_config.window_foreground,
-            );
         }
+        Ok(())
+    }
 
-        context.flush()?;
+    pub fn take_startup_ids(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.startup_ids)
+    }
 
-        for tray_item in &self.tray_items {
-            if !tray_item.should_map {
-                continue;
-            }
+    // Completes the startup sequence on behalf of the application that does not support startup
+    // notification.
+    pub fn remove_startup_sequence(&self, startup_id: &str) -> anyhow::Result<()> {
+        startup_notification::send_remove(
+            &*self.connection,
+            &self.atoms,
+            self.screen_num,
+            self.window,
+            startup_id,
+        )
+    }
 
-            if layout_changed {
-                let values = xproto::ConfigureWindowAux::new()
-                    .x((tray_item.bounds.x + ui_config.item_padding) as i32)
-                    .y((tray_item.bounds.y + ui_config.item_padding) as i32)
-                    .width(ui_config.icon_size as u32)
-                    .height(ui_config.icon_size as u32);
-                self.connection
-                    .configure_window(tray_item.icon, &values)?
-                    .check()
-                    .context("move and resize tray icon")?;
-            }
+    // Activates the main window of the application that owns the selected icon through the window
+    // manager. The window is looked up from _NET_CLIENT_LIST by _NET_WM_PID, or by the class of
+    // WM_CLASS if the icon has no PID.
+    pub fn activate_selected_application(&self) -> anyhow::Result<()> {
+        let Some(tray_item) =
+            self.selected_index
+                .and_then(|index| match self.item_refs().get(index) {
+                    Some(ItemRef::Tray(i)) => Some(&self.tray_items[*i]),
+                    _ => None,
+                })
+        else {
+            return Ok(());
+        };
 
-            if tray_item.is_mapped {
+        let root = self.connection.setup().roots[self.screen_num].root;
+        let pid = get_window_pid(&*self.connection, &self.atoms, tray_item.icon)?;
+        let clients: Vec<xproto::Window> = self
+            .connection
+            .get_property(
+                false,
+                root,
+                self.atoms._NET_CLIENT_LIST,
+                xproto::AtomEnum::WINDOW,
+                0,
+                u32::MAX,
+            )?
+            .reply()
+            .context("get _NET_CLIENT_LIST")?
+            .value32()
+            .map(|iter| iter.collect())
+            .unwrap_or_default();
+
+        // The clients may be destroyed after the list is read, so those that fail are skipped.
+        let mut found_client = None;
+        for client in clients {
+            let is_matched = if pid.is_some() {
+                get_window_pid(&*self.connection, &self.atoms, client).unwrap_or(None) == pid
+            } else if let Some(class) = &tray_item.class {
                 self.connection
-                    .clear_area(
-                        true,
-                        tray_item.icon,
+                    .get_property(
+                        false,
+                        client,
+                        xproto::AtomEnum::WM_CLASS,
+                        xproto::AtomEnum::STRING,
                         0,
-                        0,
-                        ui_config.icon_size as u16,
-                        ui_config.icon_size as u16,
+                        256 / 4,
                     )?
-                    .check()
-                    .context("request redraw tray icon")?;
+                    .reply()
+                    .is_ok_and(|reply| parse_wm_class(&reply).as_ref() == Some(class))
             } else {
-                self.connection
-                    .map_window(tray_item.icon)?
-                    .check()
-                    .context("map tray icon")?;
+                false
+            };
+            if is_matched {
+                found_client = Some(client);
+                break;
             }
         }
 
+        let Some(client) = found_client else {
+            log::info!(
+                "no application window found for icon (icon: {}, pid: {:?}, class: {:?})",
+                tray_item.icon,
+                pid,
+                tray_item.class
+            );
+            return Ok(());
+        };
+
+        log::info!(
+            "activate application window (icon: {}, window: {})",
+            tray_item.icon,
+            client
+        );
+
+        let event = xproto::ClientMessageEvent::new(
+            32,
+            client,
+            self.atoms._NET_ACTIVE_WINDOW,
+            [
+                SOURCE_INDICATION_PAGER,
+                x11rb::CURRENT_TIME,
+                self.window,
+                0,
+                0,
+            ],
+        );
+        self.connection
+            .send_event(
+                false,
+                root,
+                xproto::EventMask::SUBSTRUCTURE_NOTIFY | xproto::EventMask::SUBSTRUCTURE_REDIRECT,
+                event,
+            )?
+            .check()
+            .context("send _NET_ACTIVE_WINDOW message")?;
         self.connection
             .flush()
-            .context("flush after draw tray window")?;
-
-        self.should_redraw = false;
+            .context("flush after activate application window")?;
 
         Ok(())
     }
 
-    pub fn handle_x11_event(
-        &mut self,
-        event: &protocol::Event,
-        control_flow: &mut ControlFlow<()>,
-    ) -> anyhow::Result<()> {
-        use protocol::Event::*;
+    // Saves the image of the selected icon as PNG to the directory, and returns the path of it.
+    // The icon must be shown.
+    pub fn screenshot_selected_item(&self, dir: &Path) -> anyhow::Result<Option<PathBuf>> {
+        let Some(tray_item) =
+            self.selected_index
+                .and_then(|index| match self.item_refs().get(index) {
+                    Some(ItemRef::Tray(i)) => Some(&self.tray_items[*i]),
+                    _ => None,
+                })
+        else {
+            return Ok(None);
+        };
 
-        match event {
-            Expose(event) => {
-                if event.window == self.window && event.count == 0 {
-                    self.should_redraw = true;
-                }
-            }
-            ButtonPress(event) if event.event == self.window => {
-                let cursor = PhysicalPoint {
-                    x: event.event_x as _,
-                    y: event.event_y as _,
-                };
-                for tray_item in &mut self.tray_items {
-                    if tray_item.bounds.snap().contains_pos(cursor) {
-                        tray_item.is_pressed = true;
-                    }
-                }
+        // The icon may be unmapped or destroyed in the meantime, which is not fatal.
+        let geometry = match self.connection.get_geometry(tray_item.icon)?.reply() {
+            Ok(geometry) => geometry,
+            Err(error) => {
+                log::warn!(
+                    "failed to get icon geometry (icon: {}): {}",
+                    tray_item.icon,
+                    error
+                );
+                return Ok(None);
             }
-            ButtonRelease(event) if event.event == self.window => {
-                let cursor = PhysicalPoint {
-                    x: event.event_x as _,
-                    y: event.event_y as _,
-                };
-                for tray_item in &mut self.tray_items {
-                    if !tray_item.is_pressed {
-                        continue;
-                    }
-                    if tray_item.bounds.snap().contains_pos(cursor) {
-                        let button = u8::from(event.detail).into();
-                        let button_mask = u16::from(event.state).into();
-                        click_window(
-                            &*self.connection,
-                            self.screen_num,
-                            tray_item.icon,
-                            button,
-                            button_mask,
-                        )?;
-                    }
-                    tray_item.is_pressed = false;
-                }
+        };
+        let image = match self
+            .connection
+            .get_image(
+                xproto::ImageFormat::Z_PIXMAP,
+                tray_item.icon,
+                0,
+                0,
+                geometry.width,
+                geometry.height,
+                u32::MAX,
+            )?
+            .reply()
+        {
+            Ok(image) => image,
+            Err(error) => {
+                log::warn!(
+                    "failed to get icon image (icon: {}): {}",
+                    tray_item.icon,
+                    error
+                );
+                return Ok(None);
             }
-            ConfigureNotify(event)
-                if event.window == event.event && event.window == self.window =>
-            {
-                let new_size = PhysicalSize {
-                    width: event.width as u32,
-                    height: event.height as u32,
-                };
-                if self.size != new_size {
-                    self.size = new_size;
-                    self.should_layout = true;
-                }
-            }
-            DestroyNotify(event) if event.window == event.event && event.window == self.window => {
-                *control_flow = ControlFlow::Break(());
-            }
-            LeaveNotify(event) if event.event == self.window => {
-                for tray_item in &mut self.tray_items {
-                    tray_item.is_pressed = false;
-                }
-            }
-            MapNotify(event) if event.window == event.event && event.window == self.window => {
-                self.is_mapped = true;
-            }
-            MapNotify(event) if event.window == event.event => {
-                for tray_item in &mut self.tray_items {
-                    if tray_item.icon == event.window {
-                        tray_item.is_mapped = true;
-                    }
-                }
-            }
-            UnmapNotify(event) if event.window == event.event && event.window == self.window => {
-                self.is_mapped = false;
-            }
-            UnmapNotify(event) if event.window == event.event => {
-                for tray_item in &mut self.tray_items {
-                    if tray_item.icon == event.window {
-                        tray_item.is_mapped = false;
-                    }
-                }
-            }
-            _ => {}
-        }
+        };
+        let size = PhysicalSize {
+            width: geometry.width as u32,
+            height: geometry.height as u32,
+        };
+        let byte_order = self.connection.setup().image_byte_order;
+        let pixels = image_to_argb(&image.data, image.depth, byte_order, size)
+            .ok_or_else(|| anyhow::anyhow!("unsupported icon image (depth {})", image.depth))?;
+        let png = encode_png(size, &pixels)?;
 
-        Ok(())
-    }
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = dir.join(format!(
+            "geektray-{}-{}.png",
+            screenshot_file_class(tray_item.class.as_deref()),
+            timestamp
+        ));
+        fs::create_dir_all(dir).context("create screenshot dir")?;
+        fs::write(&path, png).context("write icon screenshot")?;
 
-    pub fn hide(&mut self) -> anyhow::Result<()> {
-        self.selected_index = None;
-        self.connection
-            .unmap_window(self.window)?
-            .check()
-            .context("unmap tray window")?;
-        self.connection
-            .flush()
-            .context("flush after unmap tray window")?;
-        Ok(())
+        Ok(Some(path))
     }
 
-    pub fn is_mapped(&self) -> bool {
-        self.is_mapped
+    // Looks up the process that owns the icon, and its name from /proc.
+    pub fn fetch_process(&mut self, icon: xproto::Window) -> anyhow::Result<()> {
+        let Some(tray_item) = self
+            .tray_items
+            .iter_mut()
+            .find(|tray_item| tray_item.icon == icon && !tray_item.is_remote)
+        else {
+            return Ok(());
+        };
+        let pid = get_window_pid(&*self.connection, &self.atoms, icon)?;
+        tray_item.pid = pid;
+        tray_item.process_name = pid.and_then(process_name);
+        self.invalidate_labels();
+        Ok(())
     }
 
-    pub fn layout(&mut self, ui_config: &UIConfig) -> anyhow::Result<PhysicalSize> {
-        log::debug!("layout tray window");
-
-        let window_size = self.size.unsnap();
-        let item_height =
-            ui_config.icon_size.max(ui_config.text_size) + ui_config.item_padding * 2.0;
-        let mut y_offset = ui_config.window_padding;
-        let mut total_height = ui_config.window_padding * 2.0;
-
-        if self.tray_items.len() > 0 {
-            for (i, tray_item) in self.tray_items.iter_mut().enumerate() {
-                let bounds = Rect {
-                    x: ui_config.window_padding,
-                    y: y_offset,
-                    width: window_size.width - ui_config.item_padding * 2.0,
-                    height: item_height,
-                };
-
-                y_offset += bounds.height + ui_config.item_gap;
-                total_height += bounds.height;
-
-                if i > 0 {
-                    total_height += ui_config.item_gap;
-                }
+    // Returns the icon whose process is killed.
+    pub fn kill_selected_item_process(&self) -> anyhow::Result<Option<xproto::Window>> {
+        let Some(tray_item) =
+            self.selected_index
+                .and_then(|index| match self.item_refs().get(index) {
+                    Some(ItemRef::Tray(i)) => Some(&self.tray_items[*i]),
+                    _ => None,
+                })
+        else {
+            return Ok(None);
+        };
 
-                tray_item.bounds = bounds;
-            }
-        } else {
-            total_height += item_height;
+        if tray_item.is_remote {
+            log::warn!(
+                "cannot kill the process of a remote icon (icon: {})",
+                tray_item.icon
+            );
+            return Ok(None);
         }
 
-        let size = Size {
-            width: window_size.width,
-            height: total_height,
+        let Some(pid) = get_window_pid(&*self.connection, &self.atoms, tray_item.icon)? else {
+            log::warn!("icon has no _NET_WM_PID (icon: {})", tray_item.icon);
+            return Ok(None);
+        };
+
+        // Never kill init or this process even if an icon claims them.
+        if pid <= 1 || pid == process::id() {
+            log::warn!(
+                "refuse to kill process (icon: {}, pid: {})",
+                tray_item.icon,
+                pid
+            );
+            return Ok(None);
         }
-        .snap();
 
-        set_size_hints(&*self.connection, self.window, size)?;
-        resize_window(&*self.connection, self.screen_num, self.window, size)?;
+        log::info!(
+            "kill process (icon: {}, pid: {}, name: {:?})",
+            tray_item.icon,
+            pid,
+            process_name(pid)
+        );
 
-        self.should_layout = false;
+        // The process may have exited already (ESRCH) or belong to another user (EPERM).
+        if let Err(error) = signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM) {
+            log::warn!(
+                "failed to kill process (icon: {}, pid: {}): {}",
+                tray_item.icon,
+                pid,
+                error
+            );
+            return Ok(None);
+        }
 
-        Ok(size)
+        Ok(Some(tray_item.icon))
     }
 
-    pub fn remove_icon(&mut self, icon: xproto::Window) {
-        if let Some(i) = self
-            .tray_items
-            .iter()
-            .position(|tray_item| tray_item.icon == icon)
-        {
-            self.tray_items.remove(i);
-            self.should_layout = true;
-        }
-    }
+    // Sends a ClientMessage about the selected icon to the icon itself, or to the root window for
+    // the window manager.
+    pub fn send_client_message(
+        &self,
+        message_type: xproto::Atom,
+        data: [u32; 5],
+        destination: MessageDestination,
+    ) -> anyhow::Result<()> {
+        let root = self.connection.setup().roots[self.screen_num].root;
+        let selected_icon =
+            self.selected_index
+                .and_then(|index| match self.item_refs().get(index) {
+                    Some(ItemRef::Tray(i)) => Some(self.tray_items[*i].icon),
+                    _ => None,
+                });
+        let (window, destination, event_mask) = match (destination, selected_icon) {
+            (MessageDestination::Icon, Some(icon)) => (icon, icon, xproto::EventMask::NO_EVENT),
+            (MessageDestination::Icon, None) => return Ok(()),
+            (MessageDestination::Root, window) => (
+                window.unwrap_or(root),
+                root,
+                xproto::EventMask::SUBSTRUCTURE_NOTIFY | xproto::EventMask::SUBSTRUCTURE_REDIRECT,
+            ),
+        };
 
-    pub fn request_redraw(&mut self) {
-        self.should_redraw = true
-    }
+        log::info!(
+            "send client message (window: {}, destination: {}, type: {}, data: {:?})",
+            window,
+            destination,
+            message_type,
+            data
+        );
 
-    pub fn select_item(&mut self, index: usize) {
-        self.selected_index = Some(index);
-        self.should_redraw = true;
-    }
+        let event = xproto::ClientMessageEvent::new(32, window, message_type, data);
+        self.connection
+            .send_event(false, destination, event_mask, event)?
+            .check()
+            .context("send client message")?;
+        self.connection
+            .flush()
+            .context("flush after send client message")?;
 
-    pub fn select_next_item(&mut self) {
-        self.selected_index = match self.selected_index {
-            Some(index) if index + 1 < self.tray_items.len() => Some(index + 1),
-            Some(_) => None,
-            _ => {
-                if self.tray_items.len() > 0 {
-                    Some(0)
-                } else {
-                    None
-                }
-            }
-        };
-        self.should_redraw = true;
+        Ok(())
     }
 
-    pub fn select_previous_item(&mut self) {
-        self.selected_index = match self.selected_index {
-            Some(index) if index > 0 => Some(index - 1),
-            Some(_) => None,
-            _ => {
-                if self.tray_items.len() > 0 {
-                    Some(self.tray_items.len() - 1)
-                } else {
-                    None
-                }
-            }
+    pub fn focus_selected_icon(&mut self) -> anyhow::Result<()> {
+        let Some(index) = self.selected_index else {
+            return Ok(());
         };
-        self.should_redraw = true;
+        let Some(ItemRef::Tray(index)) = self.item_refs().get(index).copied() else {
+            return Ok(());
+        };
+        let icon = self.tray_items[index].icon;
+        self.unfocus_icon()?;
+        log::info!("focus icon: {}", icon);
+        send_xembed_message(
+            &*self.connection,
+            &self.atoms,
+            icon,
+            XEmbedMessage::WindowActivate,
+            0,
+        )?;
+        send_xembed_message(
+            &*self.connection,
+            &self.atoms,
+            icon,
+            XEmbedMessage::FocusIn,
+            XEMBED_FOCUS_CURRENT,
+        )?;
+        self.connection.flush().context("flush after focus icon")?;
+        self.focused_icon = Some(icon);
+        Ok(())
     }
 
-    pub fn should_layout(&self) -> bool {
-        self.should_layout
+    pub fn unfocus_icon(&mut self) -> anyhow::Result<()> {
+        let Some(icon) = self.focused_icon.take() else {
+            return Ok(());
+        };
+        log::info!("unfocus icon: {}", icon);
+        send_xembed_message(
+            &*self.connection,
+            &self.atoms,
+            icon,
+            XEmbedMessage::FocusOut,
+            0,
+        )?;
+        send_xembed_message(
+            &*self.connection,
+            &self.atoms,
+            icon,
+            XEmbedMessage::WindowDeactivate,
+            0,
+        )?;
+        self.connection
+            .flush()
+            .context("flush after unfocus icon")?;
+        Ok(())
     }
 
-    pub fn should_redraw(&self) -> bool {
-        self.should_redraw
+    pub fn focused_icon(&self) -> Option<xproto::Window> {
+        self.focused_icon
     }
 
-    pub fn show(&self) -> anyhow::Result<()> {
-        {
-            let screen = &self.connection.setup().roots[self.screen_num];
-            let values = xproto::ConfigureWindowAux::new()
-                .x(((screen.width_in_pixels as f64 - self.size.width as f64) / 2.0) as i32)
-                .y(((screen.height_in_pixels as f64 - self.size.height as f64) / 2.0) as i32)
-                .stack_mode(xproto::StackMode::ABOVE);
-            self.connection
-                .configure_window(self.window, &values)?
-                .check()
-                .context("move tray window in screen center")?;
-        }
+    pub fn forward_key_event(&self, event: &xproto::KeyPressEvent) -> anyhow::Result<()> {
+        let Some(icon) = self.focused_icon else {
+            return Ok(());
+        };
+        let event_mask = if event.response_type == xproto::KEY_PRESS_EVENT {
+            xproto::EventMask::KEY_PRESS
+        } else {
+            xproto::EventMask::KEY_RELEASE
+        };
+        let forwarded_event = xproto::KeyPressEvent {
+            event: icon,
+            child: x11rb::NONE,
+            event_x: 0,
+            event_y: 0,
+            ..*event
+        };
         self.connection
-            .map_window(self.window)?
+            .send_event(false, icon, event_mask, forwarded_event)?
             .check()
-            .context("map tray window")?;
+            .context("forward key event")?;
         self.connection
             .flush()
-            .context("flush after map tray window")?;
+            .context("flush after forward key event")?;
         Ok(())
     }
 
-    pub fn window(&self) -> xproto::Window {
-        self.window
+    pub fn toggle_help(
+        &mut self,
+        key_bindings: &[KeyBinding],
+        conflicted_key_bindings: &[usize],
+    ) -> anyhow::Result<()> {
+        if matches!(self.overlay.take(), Some(Overlay::Help(_))) {
+            self.should_layout = true;
+            return Ok(());
+        }
+        self.show_overlay(Overlay::Help(HelpOverlay::new(
+            key_bindings,
+            conflicted_key_bindings,
+        )))
     }
-}
 
-impl<C: Connection> Drop for TrayEmbedder<C> {
-    fn drop(&mut self) {
-        self.connection.destroy_window(self.window).ok();
+    pub fn toggle_message_history(&mut self) -> anyhow::Result<()> {
+        if matches!(self.overlay.take(), Some(Overlay::MessageHistory(_))) {
+            self.should_layout = true;
+            return Ok(());
+        }
+        let message_history = MessageHistory::new(&self.messages, Instant::now());
+        self.show_overlay(Overlay::MessageHistory(message_history))
     }
-}
-
-#[derive(Debug)]
-struct TrayItem {
-    icon: xproto::Window,
-    title: String,
-    should_map: bool,
-    is_mapped: bool,
-    is_pressed: bool,
-    bounds: Rect,
-}
 
-impl TrayItem {
-    fn new(icon: xproto::Window, title: String, xembed_info: XEmbedInfo) -> Self {
-        Self {
-            icon,
-            title,
-            should_map: xembed_info.is_mapped(),
-            is_mapped: false,
-            is_pressed: false,
-            bounds: Rect::ZERO,
+    // Keeps the balloon message for the history with the title of the icon.
+    pub fn record_message(&mut self, icon: xproto::Window, text: &str) {
+        if self.message_history_size == 0 {
+            return;
+        }
+        let source = self
+            .tray_items
+            .iter()
+            .find(|tray_item| tray_item.icon == icon)
+            .map_or_else(
+                || format!("0x{:x}", icon),
+                |tray_item| tray_item.title.clone(),
+            );
+        if self.messages.len() >= self.message_history_size {
+            self.messages.pop_front();
         }
+        self.messages.push_back(ReceivedMessage {
+            source,
+            text: text.to_owned(),
+            received_at: Instant::now(),
+        });
     }
 
-    fn draw(&self, index: usize, is_selected: bool, ui_config: &UIConfig, context: &RenderContext) {
-        let (background, foreground, font) = if is_selected {
-            (
-                ui_config.selected_item_background,
-                ui_config.selected_item_foreground,
-                &ui_config.selected_item_font,
-            )
-        } else {
-            (
-                ui_config.normal_item_background,
-                ui_config.normal_item_foreground,
-                &ui_config.normal_item_font,
-            )
-        };
+    // Only the override-redirect window can be stacked right above the input-only window, since
+    // the window manager reparents the others.
+    pub fn set_close_on_outside_click(&mut self, close_on_outside_click: bool) {
+        self.close_on_outside_click = close_on_outside_click && self.override_redirect;
+    }
 
-        if ui_config.item_corner_radius > 0.0 {
-            context.draw_rounded_rect(
-                self.bounds,
-                background,
-                Size {
-                    width: ui_config.item_corner_radius,
-                    height: ui_config.item_corner_radius,
-                },
-            )
-        } else {
-            context.draw_rect(self.bounds, background);
+    pub fn set_message_history_size(&mut self, message_history_size: usize) {
+        self.message_history_size = message_history_size;
+        while self.messages.len() > message_history_size {
+            self.messages.pop_front();
         }
+    }
 
-        let text_bounds = Rect {
-            x: self.bounds.x + (ui_config.icon_size + ui_config.item_padding * 2.0),
-            y: self.bounds.y,
-            width: self.bounds.width - (ui_config.icon_size + ui_config.item_padding * 3.0),
-            height: self.bounds.height,
-        };
-        let text_content = if ui_config.show_number {
-            format!("{}. {}", index + 1, &self.title)
-        } else {
-            format!("{}", self.title)
-        };
+    fn show_overlay(&mut self, overlay: Overlay) -> anyhow::Result<()> {
+        // The icons would be drawn over the overlay.
+        for tray_item in &mut self.tray_items {
+            if tray_item.is_mapped {
+                self.connection
+                    .unmap_window(tray_item.frame)?
+                    .check()
+                    .context("unmap tray icon frame")?;
+                tray_item.is_mapped = false;
+            }
+        }
+        self.overlay = Some(overlay);
+        self.should_layout = true;
+        Ok(())
+    }
 
-        context.draw_text(
-            &text_content,
-            font,
-            ui_config.text_size,
-            HAlign::Left,
-            VAlign::Middle,
-            text_bounds,
-            foreground,
-        );
+    pub fn deselect_item(&mut self) {
+        self.selected_index = None;
+        self.should_redraw = true;
     }
-}
 
-fn click_window(
-    connection: &impl Connection,
-    screen_num: usize,
-    window: xproto::Window,
-    button: xproto::ButtonIndex,
-    button_mask: xproto::ButtonMask,
-) -> anyhow::Result<()> {
-    let screen = &connection.setup().roots[screen_num];
-    let saved_pointer = connection.query_pointer(screen.root)?.reply()?;
-    let absolute_position = connection
-        .translate_coordinates(window, screen.root, 0, 0)?
-        .reply()?;
+    pub fn draw(
+        &mut self,
+        layout_changed: bool,
+        ui_config: &UIConfig,
+        context: &impl Renderer,
+    ) -> anyhow::Result<()> {
+        log::debug!("draw tray window");
 
-    connection
-        .warp_pointer(
-            x11rb::NONE,                    // src_window
-            screen.root,                    // dst_window
-            0,                              // src_x
-            0,                              // src_y
-            0,                              // src_width
-            0,                              // src_heihgt
-            absolute_position.dst_x as i16, // dst_x
-            absolute_position.dst_y as i16, // dst_y
-        )?
-        .check()
-        .context("move cursor to icon")?;
+        let size = context.size().unsnap();
 
-    send_button_event(
-        connection,
-        screen_num,
-        window,
-        button,
-        button_mask,
-        xproto::BUTTON_PRESS_EVENT,
-        0,
-        0,
-        absolute_position.dst_x,
-        absolute_position.dst_y,
-    )?;
+        draw_window_frame(size, ui_config, context);
 
-    send_button_event(
-        connection,
-        screen_num,
-        window,
-        button,
-        button_mask,
-        xproto::BUTTON_RELEASE_EVENT,
-        0,
-        0,
+        if let Some(overlay) = &self.overlay {
+            overlay.draw(
+                Rect {
+                    x: ui_config.window_padding,
+                    y: ui_config.window_padding,
+                    width: size.width - ui_config.window_padding * 2.0,
+                    height: size.height - ui_config.window_padding * 2.0,
+                },
+                ui_config,
+                context,
+            );
+            context.flush()?;
+            self.update_sync_counter()?;
+            self.should_redraw = false;
+            return Ok(());
+        }
+
+        for group_header in &self.group_headers {
+            if !self.is_visible(group_header.bounds) {
+                continue;
+            }
+            context.draw_text(
+                &group_header.label,
+                &ui_config.normal_item_font,
+                ui_config.text_size,
+                HAlign::Left,
+                VAlign::Middle,
+                group_header.bounds,
+                ui_config.window_foreground,
+            );
+        }
+
+        for separator_y in &self.separators {
+            if *separator_y < self.viewport.0 || *separator_y > self.viewport.1 {
+                continue;
+            }
+            context.draw_rect(
+                Rect {
+                    x: ui_config.window_padding,
+                    y: separator_y.floor(),
+                    width: size.width - ui_config.window_padding * 2.0,
+                    height: 1.0,
+                },
+                self.groups.separator_color,
+            );
+        }
+
+        if self.item_count() > 0 {
+            for (index, item_ref) in self.item_refs().into_iter().enumerate() {
+                if !self.is_visible(self.item_bounds(item_ref)) {
+                    continue;
+                }
+                let is_selected = self.selected_index == Some(index);
+                let is_dimmed = self.selected_index.is_some() && !is_selected;
+                match item_ref {
+                    ItemRef::Tray(i) => {
+                        let style =
+                            find_item_style(&self.item_styles, self.tray_items[i].class.as_deref());
+                        self.tray_items[i].background =
+                            item_background(is_selected, is_dimmed, style, ui_config);
+                        self.tray_items[i].draw(
+                            index,
+                            is_selected,
+                            is_dimmed,
+                            style,
+                            ui_config,
+                            context,
+                        )
+                    }
+                    ItemRef::Pinned(i) => {
+                        let style =
+                            find_item_style(&self.item_styles, Some(&self.pinned_items[i].class));
+                        self.pinned_items[i].draw(
+                            index,
+                            is_selected,
+                            is_dimmed,
+                            style,
+                            ui_config,
+                            context,
+                        )
+                    }
+                    ItemRef::Custom(i) => {
+                        self.custom_items[i].draw(index, is_selected, is_dimmed, ui_config, context)
+                    }
+                }
+            }
+        } else if !ui_config.empty_text.is_empty() {
+            // The footer is under the space for the empty text.
+            let height = self.footer_bounds.map_or(size.height, |bounds| bounds.y);
+            context.draw_text(
+                &ui_config.empty_text,
+                &ui_config.normal_item_font,
+                ui_config.text_size,
+                HAlign::Center,
+                VAlign::Middle,
+                Rect {
+                    x: ui_config.window_padding,
+                    y: 0.0,
+                    width: size.width - (ui_config.window_padding * 2.0),
+                    height,
+                },
+                ui_config.window_foreground,
+            );
+        }
+
+        if let Some(footer_bounds) = self.footer_bounds {
+            draw_footer(
+                &footer_text(self.selected_index, self.item_count()),
+                footer_bounds,
+                ui_config,
+                context,
+            );
+        }
+
+        context.flush()?;
+
+        let (top, bottom) = self.viewport;
+        for tray_item in &mut self.tray_items {
+            if !tray_item.should_map {
+                continue;
+            }
+
+            if tray_item.frame == x11rb::NONE {
+                tray_item.frame =
+                    create_frame(&*self.connection, self.window, tray_item.icon, ui_config)?;
+            }
+
+            // Icons of ParentRelative background are repainted with it by clearing them below.
+            let frame_background = tray_item.background.blend(ui_config.window_background);
+            if tray_item.frame_background != Some(frame_background) {
+                let values = xproto::ChangeWindowAttributesAux::new()
+                    .background_pixel(frame_background.to_rgb());
+                self.connection
+                    .change_window_attributes(tray_item.frame, &values)?
+                    .check()
+                    .context("change tray icon frame background")?;
+                tray_item.frame_background = Some(frame_background);
+            }
+
+            // The icons scrolled out are unmapped, otherwise they are drawn over the padding. The
+            // icons replaced with the image of the style are also unmapped.
+            let bounds = tray_item.bounds;
+            let is_replaced = find_item_style(&self.item_styles, tray_item.class.as_deref())
+                .is_some_and(|style| style.icon.is_some());
+            if is_replaced || bounds.y < top - 0.5 || bounds.y + bounds.height > bottom + 0.5 {
+                if tray_item.is_mapped {
+                    self.connection
+                        .unmap_window(tray_item.frame)?
+                        .check()
+                        .context("unmap tray icon frame")?;
+                    tray_item.is_mapped = false;
+                }
+                continue;
+            }
+
+            if layout_changed {
+                let values = xproto::ConfigureWindowAux::new()
+                    .x((tray_item.bounds.x + ui_config.item_padding) as i32)
+                    .y((tray_item.bounds.y + ui_config.item_padding) as i32)
+                    .width(ui_config.icon_size as u32)
+                    .height(ui_config.icon_size as u32);
+                self.connection
+                    .configure_window(tray_item.frame, &values)?
+                    .check()
+                    .context("move and resize tray icon frame")?;
+                let values = xproto::ConfigureWindowAux::new()
+                    .x(0)
+                    .y(0)
+                    .width(ui_config.icon_size as u32)
+                    .height(ui_config.icon_size as u32);
+                self.connection
+                    .configure_window(tray_item.icon, &values)?
+                    .check()
+                    .context("resize tray icon")?;
+            }
+
+            if tray_item.is_mapped {
+                self.connection
+                    .clear_area(
+                        true,
+                        tray_item.icon,
+                        0,
+                        0,
+                        ui_config.icon_size as u16,
+                        ui_config.icon_size as u16,
+                    )?
+                    .check()
+                    .context("request redraw tray icon")?;
+            } else {
+                self.connection
+                    .map_window(tray_item.frame)?
+                    .check()
+                    .context("map tray icon frame")?;
+            }
+        }
+
+        self.connection
+            .flush()
+            .context("flush after draw tray window")?;
+
+        self.update_sync_counter()?;
+        self.should_redraw = false;
+
+        if let Some(shown_at) = self.shown_at.take() {
+            log::debug!("tray window drawn in {:?} after shown", shown_at.elapsed());
+        }
+
+        Ok(())
+    }
+
+    pub fn handle_x11_event(
+        &mut self,
+        event: &protocol::Event,
+        control_flow: &mut ControlFlow<()>,
+    ) -> anyhow::Result<()> {
+        use protocol::Event::*;
+
+        match event {
+            Expose(event) if event.window == self.window && event.count == 0 => {
+                self.should_redraw = true;
+            }
+            ButtonPress(event) if Some(event.event) == self.outside_click_window => {
+                log::info!("hide window by the click outside it");
+                self.hide()?;
+            }
+            ButtonPress(event) if event.event == self.window && self.overlay.is_some() => {}
+            // Any click closes the overlay.
+            ButtonRelease(event) if event.event == self.window && self.overlay.is_some() => {
+                self.overlay = None;
+                self.should_layout = true;
+            }
+            // The wheel is ignored unless it is scrollable.
+            ButtonPress(event)
+                if event.event == self.window
+                    && (event.detail == WHEEL_UP || event.detail == WHEEL_DOWN)
+                    && self.is_scrollable() =>
+            {
+                if event.detail == WHEEL_UP {
+                    self.scroll_offset -= self.scroll_step;
+                } else {
+                    self.scroll_offset += self.scroll_step;
+                }
+                self.should_layout = true;
+            }
+            ButtonPress(event) if event.event == self.window => {
+                let cursor = PhysicalPoint {
+                    x: event.event_x as _,
+                    y: event.event_y as _,
+                };
+                for tray_item in &mut self.tray_items {
+                    if tray_item.bounds.snap().contains_pos(cursor) {
+                        tray_item.is_pressed = true;
+                    }
+                }
+                for pinned_item in &mut self.pinned_items {
+                    if pinned_item.bounds.snap().contains_pos(cursor) {
+                        pinned_item.is_pressed = true;
+                    }
+                }
+                for custom_item in &mut self.custom_items {
+                    if custom_item.bounds.snap().contains_pos(cursor) {
+                        custom_item.is_pressed = true;
+                    }
+                }
+            }
+            ButtonRelease(event) if event.event == self.window => {
+                let cursor = PhysicalPoint {
+                    x: event.event_x as _,
+                    y: event.event_y as _,
+                };
+                let mut usage_keys = Vec::new();
+                for tray_item in &mut self.tray_items {
+                    if !tray_item.is_pressed {
+                        continue;
+                    }
+                    if tray_item.bounds.snap().contains_pos(cursor)
+                        && tray_item.debounce_click(self.click.debounce_ms, Instant::now())
+                    {
+                        let button = event.detail.into();
+                        let button_mask = u16::from(event.state).into();
+                        click_window(
+                            &*self.connection,
+                            self.screen_num,
+                            should_warp_pointer(self.click, tray_item),
+                            tray_item.icon,
+                            button,
+                            button_mask,
+                        )?;
+                        self.last_clicked_at = Some(Instant::now());
+                        usage_keys.push(tray_item.usage_key().to_owned());
+                    }
+                    tray_item.is_pressed = false;
+                }
+                for usage_key in usage_keys {
+                    self.record_usage(&usage_key);
+                }
+                let mut commands = Vec::new();
+                for pinned_item in &mut self.pinned_items {
+                    if pinned_item.is_pressed && pinned_item.bounds.snap().contains_pos(cursor) {
+                        if let Some(command) = &pinned_item.command {
+                            log::info!("launch pinned application: {}", command);
+                            commands.push(command.clone());
+                        }
+                    }
+                    pinned_item.is_pressed = false;
+                }
+                for custom_item in &mut self.custom_items {
+                    if custom_item.is_pressed && custom_item.bounds.snap().contains_pos(cursor) {
+                        if let Some(action) = &custom_item.action {
+                            log::info!("run custom item action: {}", action);
+                            commands.push(action.clone());
+                        }
+                    }
+                    custom_item.is_pressed = false;
+                }
+                for command in commands {
+                    self.launch(&command, Some(event.time))?;
+                }
+            }
+            ConfigureNotify(event)
+                if event.window == event.event && event.window == self.window =>
+            {
+                let new_size = PhysicalSize {
+                    width: event.width as u32,
+                    height: event.height as u32,
+                };
+                if self.size != new_size {
+                    self.size = new_size;
+                    self.should_layout = true;
+                }
+            }
+            DestroyNotify(event) if event.window == event.event && event.window == self.window => {
+                *control_flow = ControlFlow::Break(());
+            }
+            LeaveNotify(event) if event.event == self.window => {
+                for tray_item in &mut self.tray_items {
+                    tray_item.is_pressed = false;
+                }
+                for pinned_item in &mut self.pinned_items {
+                    pinned_item.is_pressed = false;
+                }
+                for custom_item in &mut self.custom_items {
+                    custom_item.is_pressed = false;
+                }
+            }
+            MapNotify(event) if event.window == event.event && event.window == self.window => {
+                self.is_mapped = true;
+                // No window manager gives the focus to the override-redirect window.
+                if self.override_redirect && !self.is_parked {
+                    self.connection
+                        .set_input_focus(
+                            xproto::InputFocus::PARENT,
+                            self.window,
+                            x11rb::CURRENT_TIME,
+                        )?
+                        .check()
+                        .context("set input focus to tray window")?;
+                }
+            }
+            PropertyNotify(event)
+                if event.window == self.window
+                    && event.atom == self.atoms._NET_WM_STATE
+                    && self.is_mapped =>
+            {
+                self.restore_states()?;
+            }
+            MapNotify(event) if event.window == event.event => {
+                for tray_item in &mut self.tray_items {
+                    if tray_item.frame == event.window {
+                        tray_item.is_mapped = true;
+                    }
+                }
+            }
+            UnmapNotify(event) if event.window == event.event && event.window == self.window => {
+                self.is_mapped = false;
+            }
+            UnmapNotify(event) if event.window == event.event => {
+                for tray_item in &mut self.tray_items {
+                    if tray_item.frame == event.window {
+                        tray_item.is_mapped = false;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn hide(&mut self) -> anyhow::Result<()> {
+        self.selected_index = None;
+        if self.sort_order == SortOrder::Mru {
+            self.sort_tray_items();
+            self.should_layout = true;
+        }
+        if self.overlay.take().is_some() {
+            self.should_layout = true;
+        }
+        self.unfocus_icon()?;
+        if self.remember_position && self.is_mapped() {
+            self.save_position()?;
+        }
+        if let Some(outside_click_window) = self.outside_click_window {
+            self.connection
+                .unmap_window(outside_click_window)?
+                .check()
+                .context("unmap outside click window")?;
+        }
+        if self.preload && self.is_mapped {
+            return self.park();
+        }
+        self.connection
+            .unmap_window(self.window)?
+            .check()
+            .context("unmap tray window")?;
+        self.connection
+            .flush()
+            .context("flush after unmap tray window")?;
+        Ok(())
+    }
+
+    // The window manager owns _NET_WM_STATE while the window is mapped, so the states it has
+    // removed are requested again.
+    fn restore_states(&self) -> anyhow::Result<()> {
+        let reply = self
+            .connection
+            .get_property(
+                false,
+                self.window,
+                self.atoms._NET_WM_STATE,
+                xproto::AtomEnum::ATOM,
+                0,
+                u32::MAX,
+            )?
+            .reply()
+            .context("get _NET_WM_STATE")?;
+        let current_states: Vec<xproto::Atom> = reply
+            .value32()
+            .map(|iter| iter.collect())
+            .unwrap_or_default();
+        let root = self.connection.setup().roots[self.screen_num].root;
+
+        for state in &self.states {
+            if current_states.contains(state) {
+                continue;
+            }
+            log::info!("restore window state: {}", state);
+            let event = xproto::ClientMessageEvent::new(
+                32,
+                self.window,
+                self.atoms._NET_WM_STATE,
+                [
+                    NET_WM_STATE_ADD,
+                    *state,
+                    0,
+                    SOURCE_INDICATION_APPLICATION,
+                    0,
+                ],
+            );
+            self.connection
+                .send_event(
+                    false,
+                    root,
+                    xproto::EventMask::SUBSTRUCTURE_NOTIFY
+                        | xproto::EventMask::SUBSTRUCTURE_REDIRECT,
+                    event,
+                )?
+                .check()
+                .context("send _NET_WM_STATE message")?;
+        }
+
+        self.connection
+            .flush()
+            .context("flush after restore window states")?;
+
+        Ok(())
+    }
+
+    // Whether the window is visible to the user. The parked window is mapped but not visible.
+    pub fn is_mapped(&self) -> bool {
+        self.is_mapped && !self.is_parked
+    }
+
+    // Whether the window can be drawn, including while it is parked.
+    pub fn is_viewable(&self) -> bool {
+        self.is_mapped
+    }
+
+    // Maps the input-only window covering the screen that catches the clicks outside the tray
+    // window. The window is mapped at the top, and the tray window is raised above it afterwards.
+    fn map_outside_click_window(&mut self) -> anyhow::Result<()> {
+        let outside_click_window = match self.outside_click_window {
+            Some(outside_click_window) => outside_click_window,
+            None => {
+                let screen = &self.connection.setup().roots[self.screen_num];
+                let outside_click_window = self.connection.generate_id()?;
+                let values = xproto::CreateWindowAux::new()
+                    .event_mask(xproto::EventMask::BUTTON_PRESS)
+                    .override_redirect(1);
+                self.connection
+                    .create_window(
+                        0,
+                        outside_click_window,
+                        screen.root,
+                        0,
+                        0,
+                        screen.width_in_pixels,
+                        screen.height_in_pixels,
+                        0,
+                        xproto::WindowClass::INPUT_ONLY,
+                        x11rb::COPY_FROM_PARENT,
+                        &values,
+                    )?
+                    .check()
+                    .context("create outside click window")?;
+                self.outside_click_window = Some(outside_click_window);
+                outside_click_window
+            }
+        };
+        self.connection
+            .map_window(outside_click_window)?
+            .check()
+            .context("map outside click window")?;
+        Ok(())
+    }
+
+    // Maps the window out of the screen, so that it is laid out and drawn in advance and shown
+    // just by moving it.
+    pub fn park(&mut self) -> anyhow::Result<()> {
+        log::debug!("park tray window");
+        let values = xproto::ConfigureWindowAux::new()
+            .x(PARKED_POSITION)
+            .y(PARKED_POSITION);
+        self.connection
+            .configure_window(self.window, &values)?
+            .check()
+            .context("park tray window")?;
+        if self.is_mapped {
+            if let Some(focus) = self.focus_before_shown.take() {
+                self.connection
+                    .set_input_focus(xproto::InputFocus::POINTER_ROOT, focus, x11rb::CURRENT_TIME)?
+                    .check()
+                    .context("restore input focus")?;
+            }
+        } else {
+            self.connection
+                .map_window(self.window)?
+                .check()
+                .context("map tray window")?;
+        }
+        self.connection
+            .flush()
+            .context("flush after park tray window")?;
+        self.is_parked = true;
+        Ok(())
+    }
+
+    pub fn layout(&mut self, ui_config: &UIConfig) -> anyhow::Result<PhysicalSize> {
+        log::debug!("layout tray window");
+
+        let mut window_size = self.size.unsnap();
+        if let Some((min_width, max_width)) = self.auto_width {
+            window_size.width = self.fit_width(ui_config).clamp(min_width, max_width);
+        }
+        let item_height =
+            ui_config.icon_size.max(ui_config.text_size) + ui_config.item_padding * 2.0;
+        let header_height = ui_config.text_size + ui_config.item_padding;
+        let row_width = window_size.width - ui_config.item_padding * 2.0;
+        let mut rows = RowCursor::new(ui_config.window_padding, ui_config.item_gap);
+
+        self.group_headers.clear();
+        self.separators.clear();
+
+        let item_refs = if self.overlay.is_some() {
+            Vec::new()
+        } else {
+            self.item_refs()
+        };
+
+        for (i, item_ref) in item_refs.iter().enumerate() {
+            // Custom items belong to a group of their own.
+            let group = self.item_class(*item_ref);
+            let is_new_group = i == 0 || self.item_class(item_refs[i - 1]) != group;
+            let label = group
+                .and_then(|class| self.group_label(class))
+                .map(str::to_owned);
+
+            if self.groups.enabled && is_new_group {
+                if i > 0 && self.groups.show_separators {
+                    self.separators.push(rows.separator_y());
+                }
+                if let Some(label) = label {
+                    let bounds = Rect {
+                        x: ui_config.window_padding,
+                        y: rows.next(header_height),
+                        width: row_width,
+                        height: header_height,
+                    };
+                    self.group_headers.push(GroupHeader { label, bounds });
+                }
+            }
+
+            let bounds = Rect {
+                x: ui_config.window_padding,
+                y: rows.next(item_height),
+                width: row_width,
+                height: item_height,
+            };
+            match *item_ref {
+                ItemRef::Tray(i) => self.tray_items[i].bounds = bounds,
+                ItemRef::Pinned(i) => self.pinned_items[i].bounds = bounds,
+                ItemRef::Custom(i) => self.custom_items[i].bounds = bounds,
+            }
+        }
+
+        if let Some(overlay) = &self.overlay {
+            rows.next(overlay.height(ui_config));
+        } else if item_refs.is_empty() {
+            rows.next(item_height);
+        }
+
+        let rows_height = |count: usize| {
+            count as f64 * item_height + count.saturating_sub(1) as f64 * ui_config.item_gap
+        };
+        let content_bottom = rows
+            .bottom()
+            .max(ui_config.window_padding + rows_height(self.min_visible_items));
+        let viewport_bottom =
+            if self.max_visible_items > 0 && item_refs.len() > self.max_visible_items {
+                content_bottom.min(ui_config.window_padding + rows_height(self.max_visible_items))
+            } else {
+                content_bottom
+            };
+        self.viewport = (ui_config.window_padding, viewport_bottom);
+        self.scroll_step = item_height + ui_config.item_gap;
+        self.scroll(content_bottom - viewport_bottom);
+        rows.set_bottom(viewport_bottom);
+
+        self.footer_bounds = if ui_config.show_footer && self.overlay.is_none() {
+            let footer_height = ui_config.text_size + ui_config.item_padding * 2.0;
+            Some(Rect {
+                x: ui_config.window_padding,
+                y: rows.next(footer_height),
+                width: row_width,
+                height: footer_height,
+            })
+        } else {
+            None
+        };
+
+        let total_height = rows.bottom() + ui_config.window_padding;
+
+        let size = Size {
+            width: window_size.width,
+            height: total_height,
+        }
+        .snap();
+
+        set_size_hints(&*self.connection, self.window, size)?;
+        // Only the centered window is moved as its size changes.
+        let position = match (self.saved_position, self.placement) {
+            (None, Placement::Center) if !self.is_moved && !self.is_parked => {
+                Some(self.center_position(size))
+            }
+            _ => None,
+        };
+        resize_window(&*self.connection, self.window, size, position)?;
+
+        self.should_layout = false;
+
+        Ok(size)
+    }
+
+    // Returns the class and the process of the icon.
+    pub fn icon_process(&self, icon: xproto::Window) -> Option<(Option<&str>, Option<u32>)> {
+        self.tray_items
+            .iter()
+            .find(|tray_item| tray_item.icon == icon)
+            .map(|tray_item| (tray_item.class.as_deref(), tray_item.pid))
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.tray_items
+            .iter()
+            .any(|tray_item| tray_item.class.as_deref() == Some(class))
+    }
+
+    pub fn remove_icon(&mut self, icon: xproto::Window) {
+        if let Some(i) = self
+            .tray_items
+            .iter()
+            .position(|tray_item| tray_item.icon == icon)
+        {
+            let tray_item = self.tray_items.remove(i);
+            destroy_frame(&*self.connection, tray_item.frame);
+            self.should_layout = true;
+        }
+        if self.focused_icon == Some(icon) {
+            self.focused_icon = None;
+        }
+    }
+
+    pub fn request_layout(&mut self) {
+        self.should_layout = true
+    }
+
+    pub fn request_redraw(&mut self) {
+        self.should_redraw = true
+    }
+
+    // Redraws the window and tells the window manager that it has been done with the value.
+    pub fn request_sync(&mut self, value: sync::Int64) {
+        if self.sync_counter.is_some() {
+            self.sync_value = Some(value);
+        }
+        self.should_redraw = true
+    }
+
+    pub fn select_item(&mut self, index: usize) {
+        self.selected_index = Some(index);
+        self.invalidate_selection();
+    }
+
+    pub fn select_next_item(&mut self) {
+        if let Some(Overlay::MessageHistory(message_history)) = &mut self.overlay {
+            message_history.select_next();
+            self.should_redraw = true;
+            return;
+        }
+        self.selected_index = match self.selected_index {
+            Some(index) if index + 1 < self.item_count() => Some(index + 1),
+            Some(_) => None,
+            _ => {
+                if self.item_count() > 0 {
+                    Some(0)
+                } else {
+                    None
+                }
+            }
+        };
+        self.invalidate_selection();
+    }
+
+    // Selects the next item whose icon demands attention, wrapping around at the end.
+    pub fn select_next_urgent_item(&mut self) {
+        let item_refs = self.item_refs();
+        let start = self.selected_index.map_or(0, |index| index + 1);
+        if let Some(index) = (0..item_refs.len())
+            .map(|i| (start + i) % item_refs.len())
+            .find(|index| {
+                matches!(item_refs[*index], ItemRef::Tray(i) if self.tray_items[i].is_urgent)
+            })
+        {
+            self.selected_index = Some(index);
+            self.invalidate_selection();
+        }
+    }
+
+    // Selects the next item whose class is equal to and whose title contains the given ones,
+    // wrapping around at the end. The selection is cleared if no item matches, so that the
+    // following actions do not affect another item.
+    pub fn select_matching_item(&mut self, class: Option<&str>, title: Option<&str>) {
+        let item_refs = self.item_refs();
+        let title = title.map(str::to_lowercase);
+        let start = self.selected_index.map_or(0, |index| index + 1);
+        self.selected_index = (0..item_refs.len())
+            .map(|i| (start + i) % item_refs.len())
+            .find(|index| {
+                let item_ref = item_refs[*index];
+                let item_title = match item_ref {
+                    ItemRef::Tray(i) => self.tray_items[i].title.as_str(),
+                    ItemRef::Pinned(i) => self.pinned_items[i].label.as_str(),
+                    ItemRef::Custom(i) => self.custom_items[i].title.as_str(),
+                };
+                class.is_none_or(|class| self.item_class(item_ref).flatten() == Some(class))
+                    && title
+                        .as_ref()
+                        .is_none_or(|title| item_title.to_lowercase().contains(title.as_str()))
+            });
+        self.invalidate_selection();
+    }
+
+    pub fn select_last_used_item(&mut self) {
+        let item_refs = self.item_refs();
+        let last_used = item_refs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item_ref)| match item_ref {
+                ItemRef::Tray(i) => self
+                    .usage_stats
+                    .get(self.tray_items[*i].usage_key())
+                    .map(|usage| (index, usage.last_used)),
+                _ => None,
+            })
+            .max_by_key(|(index, last_used)| (*last_used, std::cmp::Reverse(*index)));
+        if let Some((index, _)) = last_used {
+            self.selected_index = Some(index);
+            self.invalidate_selection();
+        }
+    }
+
+    // Loads the usage stats saved last time, and saves them whenever an item is clicked.
+    pub fn load_usage_stats(&mut self) {
+        self.usage_stats = UsageStats::load(self.screen_num);
+        self.remember_usage = true;
+        self.sort_tray_items();
+    }
+
+    pub fn select_previous_item(&mut self) {
+        if let Some(Overlay::MessageHistory(message_history)) = &mut self.overlay {
+            message_history.select_previous();
+            self.should_redraw = true;
+            return;
+        }
+        self.selected_index = match self.selected_index {
+            Some(index) if index > 0 => Some(index - 1),
+            Some(_) => None,
+            _ => {
+                if self.item_count() > 0 {
+                    Some(self.item_count() - 1)
+                } else {
+                    None
+                }
+            }
+        };
+        self.invalidate_selection();
+    }
+
+    // Only the icons docked after this are affected.
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+    }
+
+    pub fn set_sort_order(&mut self, sort_order: SortOrder) {
+        self.sort_order = sort_order;
+        self.sort_tray_items();
+        self.should_layout = true;
+    }
+
+    // Sets the background of the window that shows through the icons of ParentRelative
+    // background before the window is drawn.
+    pub fn set_background(&self, background: Color) -> anyhow::Result<()> {
+        let values = xproto::ChangeWindowAttributesAux::new().background_pixel(background.to_rgb());
+        self.connection
+            .change_window_attributes(self.window, &values)?
+            .check()
+            .context("set tray window background")?;
+        Ok(())
+    }
+
+    pub fn set_opacity(&self, opacity: f64) -> anyhow::Result<()> {
+        if opacity < 1.0 {
+            let opacity = (opacity.max(0.0) * u32::MAX as f64).round() as u32;
+            self.connection
+                .change_property32(
+                    xproto::PropMode::REPLACE,
+                    self.window,
+                    self.atoms._NET_WM_WINDOW_OPACITY,
+                    xproto::AtomEnum::CARDINAL,
+                    &[opacity],
+                )?
+                .check()
+                .context("set _NET_WM_WINDOW_OPACITY")?;
+        } else {
+            self.connection
+                .delete_property(self.window, self.atoms._NET_WM_WINDOW_OPACITY)?
+                .check()
+                .context("delete _NET_WM_WINDOW_OPACITY")?;
+        }
+        Ok(())
+    }
+
+    // Sets WM_COMMAND, which session managers run to restore the application. This is also the
+    // reply to WM_SAVE_YOURSELF.
+    pub fn set_command(&self, command: &[OsString]) -> anyhow::Result<()> {
+        let mut command_string = Vec::new();
+        for arg in command {
+            command_string.extend_from_slice(arg.as_bytes());
+            command_string.push(0);
+        }
+        self.connection
+            .change_property8(
+                xproto::PropMode::REPLACE,
+                self.window,
+                xproto::AtomEnum::WM_COMMAND,
+                xproto::AtomEnum::STRING,
+                &command_string,
+            )?
+            .check()
+            .context("set WM_COMMAND")?;
+        Ok(())
+    }
+
+    pub fn set_client_machine(&self, hostname: &str) -> anyhow::Result<()> {
+        self.connection
+            .change_property8(
+                xproto::PropMode::REPLACE,
+                self.window,
+                xproto::AtomEnum::WM_CLIENT_MACHINE,
+                xproto::AtomEnum::STRING,
+                hostname.as_bytes(),
+            )?
+            .check()
+            .context("set WM_CLIENT_MACHINE")?;
+        Ok(())
+    }
+
+    pub fn should_layout(&self) -> bool {
+        self.should_layout
+    }
+
+    // Returns when a click was last forwarded to an icon.
+    pub fn last_clicked_at(&self) -> Option<Instant> {
+        self.last_clicked_at
+    }
+
+    pub fn should_redraw(&self) -> bool {
+        self.should_redraw
+    }
+
+    pub fn show(&mut self) -> anyhow::Result<()> {
+        // Some window managers remove _NET_WM_STATE when the window is withdrawn.
+        self.connection
+            .change_property32(
+                xproto::PropMode::REPLACE,
+                self.window,
+                self.atoms._NET_WM_STATE,
+                xproto::AtomEnum::ATOM,
+                &self.states,
+            )?
+            .check()
+            .context("set _NET_WM_STATE")?;
+        if self.close_on_outside_click {
+            self.map_outside_click_window()?;
+        }
+        {
+            let position = match (self.saved_position, self.placement) {
+                (Some(position), _) => position,
+                (None, Placement::Center) => self.center_position(self.size),
+                (None, Placement::Pointer) => self.position_near_pointer()?,
+            };
+            self.shown_position = Some(position);
+            self.shown_at = Some(Instant::now());
+            self.is_moved = false;
+            let values = xproto::ConfigureWindowAux::new()
+                .x(position.x)
+                .y(position.y)
+                .stack_mode(xproto::StackMode::ABOVE);
+            self.connection
+                .configure_window(self.window, &values)?
+                .check()
+                .context("move tray window")?;
+        }
+        if self.is_parked && self.is_mapped {
+            // Moving the window does not give it the focus unlike mapping.
+            self.focus_before_shown = Some(
+                self.connection
+                    .get_input_focus()?
+                    .reply()
+                    .context("get input focus")?
+                    .focus,
+            );
+            self.connection
+                .set_input_focus(xproto::InputFocus::PARENT, self.window, x11rb::CURRENT_TIME)?
+                .check()
+                .context("set input focus to tray window")?;
+        } else {
+            self.connection
+                .map_window(self.window)?
+                .check()
+                .context("map tray window")?;
+        }
+        self.is_parked = false;
+        self.connection
+            .flush()
+            .context("flush after map tray window")?;
+        Ok(())
+    }
+
+    pub fn move_window_by(&mut self, dx: i32, dy: i32) -> anyhow::Result<()> {
+        if !self.is_mapped() {
+            return Ok(());
+        }
+        let position = self.current_position()?;
+        let values = xproto::ConfigureWindowAux::new()
+            .x(position.x + dx)
+            .y(position.y + dy);
+        self.connection
+            .configure_window(self.window, &values)?
+            .check()
+            .context("move tray window")?;
+        self.connection
+            .flush()
+            .context("flush after move tray window")?;
+        self.is_moved = true;
+        Ok(())
+    }
+
+    // The height is determined by the layout.
+    pub fn resize_window_by(&mut self, dw: i32) {
+        let width = (self.size.width as i32 + dw).max(MIN_WINDOW_WIDTH);
+        self.size.width = width as u32;
+        self.should_layout = true;
+    }
+
+    fn current_position(&self) -> anyhow::Result<PhysicalPoint> {
+        let screen = &self.connection.setup().roots[self.screen_num];
+        let reply = self
+            .connection
+            .translate_coordinates(self.window, screen.root, 0, 0)?
+            .reply()
+            .context("get tray window position")?;
+        Ok(PhysicalPoint {
+            x: reply.dst_x as i32,
+            y: reply.dst_y as i32,
+        })
+    }
+
+    fn update_sync_counter(&mut self) -> anyhow::Result<()> {
+        if let (Some(sync_counter), Some(sync_value)) = (self.sync_counter, self.sync_value.take())
+        {
+            self.connection
+                .sync_set_counter(sync_counter, sync_value)?
+                .check()
+                .context("set sync counter")?;
+        }
+        Ok(())
+    }
+
+    // Saves the current position if the window has been moved since it was shown.
+    fn save_position(&mut self) -> anyhow::Result<()> {
+        let position = self.current_position()?;
+        if self.shown_position != Some(position) {
+            log::info!("save window position: ({}, {})", position.x, position.y);
+            window_position::save(self.screen_num, position)?;
+            self.saved_position = Some(position);
+        }
+        Ok(())
+    }
+
+    fn center_position(&self, size: PhysicalSize) -> PhysicalPoint {
+        let screen = &self.connection.setup().roots[self.screen_num];
+        PhysicalPoint {
+            x: ((screen.width_in_pixels as f64 - size.width as f64) / 2.0) as i32,
+            y: ((screen.height_in_pixels as f64 - size.height as f64) / 2.0) as i32,
+        }
+    }
+
+    fn position_near_pointer(&self) -> anyhow::Result<PhysicalPoint> {
+        let screen = &self.connection.setup().roots[self.screen_num];
+        let pointer = self
+            .connection
+            .query_pointer(screen.root)?
+            .reply()
+            .context("query pointer")?;
+        let pointer = PhysicalPoint {
+            x: pointer.root_x as i32,
+            y: pointer.root_y as i32,
+        };
+        let monitor = monitor_at(&*self.connection, screen, pointer);
+        Ok(place_near_pointer(pointer, self.size, monitor))
+    }
+
+    pub fn window(&self) -> xproto::Window {
+        self.window
+    }
+
+    // Items are kept in the display order: the groups listed in the rules come first, and the
+    // other groups follow in the order they appear.
+    fn group_insertion_index(&self, class: Option<&str>, pinned_count: usize) -> usize {
+        let tray_items = &self.tray_items[pinned_count..];
+        let group_index = if self.reverse {
+            tray_items
+                .iter()
+                .position(|tray_item| tray_item.class.as_deref() == class)
+        } else {
+            tray_items
+                .iter()
+                .rposition(|tray_item| tray_item.class.as_deref() == class)
+                .map(|last_index| last_index + 1)
+        };
+        if let Some(index) = group_index {
+            return pinned_count + index;
+        }
+        let rank = self.group_rank(class);
+        // With reverse, new groups that have no rule come before the others of them.
+        if rank.is_none() && !self.reverse {
+            return self.tray_items.len();
+        }
+        pinned_count
+            + tray_items
+                .iter()
+                .position(|tray_item| {
+                    let other_rank = self.group_rank(tray_item.class.as_deref());
+                    match rank {
+                        Some(rank) => other_rank.is_none_or(|other_rank| other_rank > rank),
+                        None => other_rank.is_none(),
+                    }
+                })
+                .unwrap_or(tray_items.len())
+    }
+
+    // The items are sorted by the usage when the window is hidden, so that they do not move while
+    // the user is choosing.
+    fn record_usage(&mut self, usage_key: &str) {
+        self.usage_stats.record(usage_key, SystemTime::now());
+        if self.remember_usage {
+            if let Err(error) = self.usage_stats.save(self.screen_num) {
+                log::error!("failed to save usage stats: {}", error);
+            }
+        }
+    }
+
+    // Sorts the icons within each run of the same pinned application or group, so that the order
+    // of them is kept. The sort is stable, so equal icons stay in the dock order.
+    fn sort_tray_items(&mut self) {
+        if self.sort_order == SortOrder::DockOrder {
+            return;
+        }
+        let segments: Vec<_> = self
+            .tray_items
+            .iter()
+            .map(|tray_item| {
+                let class = tray_item.class.as_deref();
+                (
+                    self.pinned_rank(class),
+                    self.groups.enabled.then(|| class.map(str::to_owned)),
+                )
+            })
+            .collect();
+        let mut start = 0;
+        while start < self.tray_items.len() {
+            let end = start
+                + segments[start..]
+                    .iter()
+                    .take_while(|segment| **segment == segments[start])
+                    .count();
+            let usage_stats = &self.usage_stats;
+            let run = &mut self.tray_items[start..end];
+            match self.sort_order {
+                SortOrder::DockOrder => {}
+                SortOrder::Title => {
+                    run.sort_by_cached_key(|tray_item| tray_item.title.to_lowercase())
+                }
+                SortOrder::Class => run.sort_by_cached_key(|tray_item| {
+                    // Icons without the class come last.
+                    (
+                        tray_item.class.is_none(),
+                        tray_item.class.as_deref().map(str::to_lowercase),
+                    )
+                }),
+                // Icons never used come last in the dock order.
+                SortOrder::Mru => run.sort_by_cached_key(|tray_item| {
+                    std::cmp::Reverse(
+                        usage_stats
+                            .get(tray_item.usage_key())
+                            .map(|usage| usage.last_used),
+                    )
+                }),
+            }
+            start = end;
+        }
+    }
+
+    // Scrolls the items laid out from the top so that the selected item is visible if it has been
+    // changed.
+    fn scroll(&mut self, max_offset: f64) {
+        let (top, bottom) = self.viewport;
+        let mut offset = self.scroll_offset;
+        if self.should_scroll_to_selection {
+            let selected_bounds = self
+                .selected_index
+                .and_then(|index| self.item_refs().get(index).copied())
+                .map(|item_ref| self.item_bounds(item_ref));
+            if let Some(bounds) = selected_bounds {
+                offset = offset
+                    .min(bounds.y - top)
+                    .max(bounds.y + bounds.height - bottom);
+            }
+            self.should_scroll_to_selection = false;
+        }
+        self.scroll_offset = offset.clamp(0.0, max_offset.max(0.0));
+
+        let offset = self.scroll_offset;
+        for bounds in self
+            .tray_items
+            .iter_mut()
+            .map(|tray_item| &mut tray_item.bounds)
+            .chain(
+                self.pinned_items
+                    .iter_mut()
+                    .map(|pinned_item| &mut pinned_item.bounds),
+            )
+            .chain(
+                self.custom_items
+                    .iter_mut()
+                    .map(|custom_item| &mut custom_item.bounds),
+            )
+            .chain(
+                self.group_headers
+                    .iter_mut()
+                    .map(|group_header| &mut group_header.bounds),
+            )
+        {
+            bounds.y -= offset;
+        }
+        for separator_y in &mut self.separators {
+            *separator_y -= offset;
+        }
+    }
+
+    fn is_visible(&self, bounds: Rect) -> bool {
+        let (top, bottom) = self.viewport;
+        // Allows the errors of floating point numbers.
+        bounds.y >= top - 0.5 && bounds.y + bounds.height <= bottom + 0.5
+    }
+
+    fn is_scrollable(&self) -> bool {
+        self.max_visible_items > 0 && self.item_count() > self.max_visible_items
+    }
+
+    fn item_bounds(&self, item_ref: ItemRef) -> Rect {
+        match item_ref {
+            ItemRef::Tray(i) => self.tray_items[i].bounds,
+            ItemRef::Pinned(i) => self.pinned_items[i].bounds,
+            ItemRef::Custom(i) => self.custom_items[i].bounds,
+        }
+    }
+
+    fn invalidate_selection(&mut self) {
+        self.should_redraw = true;
+        if self.is_scrollable() {
+            self.should_scroll_to_selection = true;
+            self.should_layout = true;
+        }
+    }
+
+    // Redraws the window for the change of the icon, or defers it if the icon changes too often.
+    fn request_icon_redraw(&mut self, index: usize, should_layout: bool) {
+        let tray_item = &mut self.tray_items[index];
+        let result = tray_item
+            .redraw_limiter
+            .as_mut()
+            .map_or(Ok(()), |redraw_limiter| {
+                redraw_limiter.acquire(Instant::now())
+            });
+        match result {
+            Ok(()) => {
+                self.should_redraw = true;
+                self.should_layout |= should_layout;
+            }
+            // The redraw already deferred draws this change too.
+            Err(_) if self.is_redraw_deferred => {}
+            Err(available_at) => {
+                log::debug!("defer redraw (icon: {})", tray_item.icon);
+                self.is_redraw_deferred = true;
+                self.deferred_redraw_at = Some(available_at);
+            }
+        }
+    }
+
+    // Returns the time to redraw the deferred changes once after they are deferred.
+    pub fn take_deferred_redraw(&mut self) -> Option<Instant> {
+        self.deferred_redraw_at.take()
+    }
+
+    pub fn redraw_deferred(&mut self) {
+        self.is_redraw_deferred = false;
+        self.should_redraw = true;
+        self.should_layout = true;
+    }
+
+    pub fn set_redraw_rate(&mut self, rate: f64, rules: Vec<RedrawRateConfig>) {
+        self.redraw_rate = rate;
+        self.redraw_rate_rules = rules;
+    }
+
+    // The styles whose icon fails to load are used without the icon. SVG icons are rendered at the
+    // icon size.
+    pub fn set_item_styles(&mut self, styles: &[ItemStyleConfig], icon_size: f64) {
+        let icon_size = PhysicalSize {
+            width: icon_size as u32,
+            height: icon_size as u32,
+        };
+        self.item_styles = styles
+            .iter()
+            .map(|style| ItemStyle {
+                class: style.class.clone(),
+                background: style.background,
+                foreground: style.foreground,
+                icon: style.icon.as_ref().and_then(|path| {
+                    load_image(Path::new(path), icon_size)
+                        .map_err(|error| {
+                            log::error!("failed to load item icon {}: {:#}", path, error)
+                        })
+                        .ok()
+                }),
+            })
+            .collect();
+        self.should_redraw = true;
+    }
+
+    // The icons of the pinned items added already are looked up again.
+    pub fn set_icon_theme(&mut self, icon_theme: Option<IconTheme>) {
+        self.icon_theme = icon_theme;
+        self.update_pinned_icons();
+        self.should_redraw = true;
+    }
+
+    // Replaces the names of the applications of the tray items and the icons of the pinned items.
+    // The labels of the pinned items are kept.
+    pub fn set_desktop_entries(&mut self, desktop_entries: Rc<DesktopEntryIndex>) {
+        self.desktop_entries = desktop_entries;
+        for tray_item in &mut self.tray_items {
+            tray_item.application_name = application_name(&self.desktop_entries, &tray_item.class);
+        }
+        self.update_pinned_icons();
+        self.invalidate_labels();
+    }
+
+    fn update_pinned_icons(&mut self) {
+        for pinned_item in &mut self.pinned_items {
+            pinned_item.icon = self.icon_theme.as_mut().and_then(|icon_theme| {
+                icon_theme.find_class_icon(&pinned_item.class, &self.desktop_entries)
+            });
+        }
+    }
+
+    // The window width may depend on the labels of the items.
+    fn invalidate_labels(&mut self) {
+        self.should_redraw = true;
+        if self.auto_width.is_some() {
+            self.should_layout = true;
+        }
+    }
+
+    // Returns the window width where the longest label of the items is not ellipsized.
+    fn fit_width(&self, ui_config: &UIConfig) -> f64 {
+        let item_refs = self.item_refs();
+        if item_refs.is_empty() {
+            let text_size = measure_text(
+                &ui_config.empty_text,
+                &ui_config.normal_item_font,
+                ui_config.text_size,
+            );
+            return (text_size.width + ui_config.window_padding * 2.0).ceil();
+        }
+        let text_width = item_refs
+            .into_iter()
+            .enumerate()
+            .map(|(index, item_ref)| {
+                let label = match item_ref {
+                    ItemRef::Tray(i) => self.tray_items[i].label(ui_config),
+                    ItemRef::Pinned(i) => Cow::Owned(self.pinned_items[i].display_label()),
+                    ItemRef::Custom(i) => Cow::Borrowed(self.custom_items[i].title.as_str()),
+                };
+                // The selected item is drawn in its font, which may be wider.
+                measure_text(
+                    &item_text(&label, index, ui_config),
+                    &ui_config.selected_item_font,
+                    ui_config.text_size,
+                )
+                .width
+            })
+            .fold(0.0, f64::max);
+        // The inverse of the text bounds in draw_item().
+        (text_width + ui_config.icon_size + ui_config.item_padding * 5.0).ceil()
+    }
+
+    fn group_rank(&self, class: Option<&str>) -> Option<usize> {
+        let class = class?;
+        self.groups
+            .rules
+            .iter()
+            .position(|rule| rule.class == class)
+    }
+
+    fn group_label(&self, class: Option<&str>) -> Option<&str> {
+        let rank = self.group_rank(class)?;
+        self.groups.rules[rank].label.as_deref()
+    }
+
+    fn item_class(&self, item_ref: ItemRef) -> Option<Option<&str>> {
+        match item_ref {
+            ItemRef::Tray(i) => Some(self.tray_items[i].class.as_deref()),
+            ItemRef::Pinned(i) => Some(Some(self.pinned_items[i].class.as_str())),
+            ItemRef::Custom(_) => None,
+        }
+    }
+
+    fn pinned_rank(&self, class: Option<&str>) -> Option<usize> {
+        let class = class?;
+        self.pinned_items
+            .iter()
+            .position(|pinned_item| pinned_item.class == class)
+    }
+
+    // Returns the items in the order they are listed. Each pinned item is replaced by the icons
+    // of the application while it is running.
+    fn item_refs(&self) -> Vec<ItemRef> {
+        let mut item_refs = Vec::with_capacity(
+            self.tray_items.len() + self.pinned_items.len() + self.custom_items.len(),
+        );
+        let mut tray_index = 0;
+
+        for (i, pinned_item) in self.pinned_items.iter().enumerate() {
+            let start = tray_index;
+            while self
+                .tray_items
+                .get(tray_index)
+                .is_some_and(|tray_item| tray_item.class.as_ref() == Some(&pinned_item.class))
+            {
+                item_refs.push(ItemRef::Tray(tray_index));
+                tray_index += 1;
+            }
+            if tray_index == start {
+                item_refs.push(ItemRef::Pinned(i));
+            }
+        }
+
+        item_refs.extend((tray_index..self.tray_items.len()).map(ItemRef::Tray));
+        item_refs.extend((0..self.custom_items.len()).map(ItemRef::Custom));
+        item_refs
+    }
+
+    // Sets the title formatted with the current items to the window if it has been changed.
+    pub fn update_title(&mut self) -> anyhow::Result<()> {
+        let item_refs = self.item_refs();
+        let selected = self
+            .selected_index
+            .and_then(|index| item_refs.get(index))
+            .map(|item_ref| match item_ref {
+                ItemRef::Tray(i) => self.tray_items[*i].title.as_str(),
+                ItemRef::Pinned(i) => self.pinned_items[*i].label.as_str(),
+                ItemRef::Custom(i) => self.custom_items[*i].title.as_str(),
+            });
+        let title = format_title(&self.title_template, item_refs.len(), selected);
+        if title != self.title {
+            set_window_title(&*self.connection, &self.atoms, self.window, &title)?;
+            self.title = title;
+        }
+        Ok(())
+    }
+
+    pub fn item_states(&self) -> impl Iterator<Item = ItemState<'_>> {
+        self.tray_items.iter().map(|tray_item| ItemState {
+            screen_num: self.screen_num,
+            icon: tray_item.icon,
+            title: &tray_item.title,
+            class: tray_item.class.as_deref(),
+            is_urgent: tray_item.is_urgent,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.item_count() == 0
+    }
+
+    fn item_count(&self) -> usize {
+        self.item_refs().len()
+    }
+}
+
+impl<C: Connection> Drop for TrayEmbedder<C> {
+    fn drop(&mut self) {
+        if self.remember_position && self.is_mapped() {
+            if let Err(error) = self.save_position() {
+                log::error!("failed to save window position: {}", error);
+            }
+        }
+        if let Some(sync_counter) = self.sync_counter {
+            self.connection.sync_destroy_counter(sync_counter).ok();
+        }
+        if let Some(outside_click_window) = self.outside_click_window {
+            self.connection.destroy_window(outside_click_window).ok();
+        }
+        self.connection.destroy_window(self.window).ok();
+    }
+}
+
+#[derive(Debug)]
+enum Overlay {
+    Help(HelpOverlay),
+    MessageHistory(MessageHistory),
+}
+
+impl Overlay {
+    fn height(&self, ui_config: &UIConfig) -> f64 {
+        match self {
+            Self::Help(help_overlay) => help_overlay.height(ui_config),
+            Self::MessageHistory(message_history) => message_history.height(ui_config),
+        }
+    }
+
+    fn draw(&self, bounds: Rect, ui_config: &UIConfig, context: &impl Renderer) {
+        match self {
+            Self::Help(help_overlay) => help_overlay.draw(bounds, ui_config, context),
+            Self::MessageHistory(message_history) => {
+                message_history.draw(bounds, ui_config, context)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TrayItem {
+    icon: xproto::Window,
+    // The window that the icon is reparented into, which clips the icon and fills the background
+    // behind it. It is created when the icon is drawn first.
+    frame: xproto::Window,
+    // The background of the item when it was drawn last, and the one set to the frame.
+    background: Color,
+    frame_background: Option<Color>,
+    title: String,
+    class: Option<String>,
+    // The name of the application from its desktop entry.
+    application_name: Option<String>,
+    // Whether the application runs on another machine.
+    is_remote: bool,
+    pid: Option<u32>,
+    process_name: Option<String>,
+    redraw_limiter: Option<RateLimiter>,
+    last_clicked_at: Option<Instant>,
+    // When the title changed, and the opacity of the flash for it.
+    flashed_at: Option<Instant>,
+    flash_level: f64,
+    // The count of notifications extracted from the title.
+    count: Option<u32>,
+    should_map: bool,
+    is_mapped: bool,
+    is_pressed: bool,
+    is_urgent: bool,
+    // Whether WM_PROTOCOLS of the icon has _NET_WM_PING, which the tray manager reads when it
+    // is docked.
+    supports_ping: bool,
+    is_ping_pending: bool,
+    is_unresponsive: bool,
+    bounds: Rect,
+}
+
+impl TrayItem {
+    fn new(
+        icon: xproto::Window,
+        title: String,
+        class: Option<String>,
+        is_remote: bool,
+        xembed_info: XEmbedInfo,
+    ) -> Self {
+        Self {
+            icon,
+            frame: x11rb::NONE,
+            background: Color::BLACK,
+            frame_background: None,
+            title,
+            class,
+            application_name: None,
+            is_remote,
+            pid: None,
+            process_name: None,
+            redraw_limiter: None,
+            last_clicked_at: None,
+            flashed_at: None,
+            flash_level: 0.0,
+            count: None,
+            should_map: xembed_info.is_mapped(),
+            is_mapped: false,
+            is_pressed: false,
+            is_urgent: false,
+            supports_ping: false,
+            is_ping_pending: false,
+            is_unresponsive: false,
+            bounds: Rect::ZERO,
+        }
+    }
+
+    // Records the click, or returns false if it is within the debounce interval of the last one.
+    fn usage_key(&self) -> &str {
+        self.class.as_deref().unwrap_or(&self.title)
+    }
+
+    fn debounce_click(&mut self, debounce_ms: u64, now: Instant) -> bool {
+        if debounce_ms > 0
+            && self.last_clicked_at.is_some_and(|last_clicked_at| {
+                now.saturating_duration_since(last_clicked_at) < Duration::from_millis(debounce_ms)
+            })
+        {
+            log::debug!("ignore the click within debounce (icon: {})", self.icon);
+            return false;
+        }
+        self.last_clicked_at = Some(now);
+        true
+    }
+
+    fn label(&self, ui_config: &UIConfig) -> Cow<'_, str> {
+        let title = match &self.application_name {
+            Some(application_name) if ui_config.show_application_name => application_name,
+            _ => &self.title,
+        };
+        let title = truncate_title(title, ui_config.max_title_chars, ui_config.title_ellipsis);
+        let label = match &self.process_name {
+            Some(process_name) if ui_config.show_process_name => {
+                Cow::Owned(format!("{} ({})", title, process_name))
+            }
+            _ => title,
+        };
+        if self.is_unresponsive {
+            Cow::Owned(format!("{} (not responding)", label))
+        } else {
+            label
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        &self,
+        index: usize,
+        is_selected: bool,
+        is_dimmed: bool,
+        style: Option<&ItemStyle>,
+        ui_config: &UIConfig,
+        context: &impl Renderer,
+    ) {
+        draw_item(
+            &self.label(ui_config),
+            self.bounds,
+            index,
+            is_selected,
+            is_dimmed,
+            self.is_unresponsive,
+            style,
+            style.and_then(|style| style.icon.as_ref()),
+            ui_config,
+            context,
+        );
+        if self.flash_level > 0.0 {
+            let color = ui_config.flash_color.scale_alpha(self.flash_level);
+            if ui_config.item_corner_radius > 0.0 {
+                let corner_radius = Size {
+                    width: ui_config.item_corner_radius,
+                    height: ui_config.item_corner_radius,
+                };
+                context.draw_rounded_rect(self.bounds, color, corner_radius);
+            } else {
+                context.draw_rect(self.bounds, color);
+            }
+        }
+        if let Some(count) = self.count {
+            draw_count_badge(count, self.bounds, self.is_urgent, ui_config, context);
+        }
+        if self.is_urgent {
+            draw_attention_badge(self.bounds, ui_config, context);
+        }
+    }
+}
+
+// The colors and the icon image overridden for the items of the application.
+#[derive(Debug)]
+struct ItemStyle {
+    class: String,
+    background: Option<Color>,
+    foreground: Option<Color>,
+    icon: Option<Image>,
+}
+
+fn application_name(desktop_entries: &DesktopEntryIndex, class: &Option<String>) -> Option<String> {
+    let entry = desktop_entries.find(class.as_deref()?)?;
+    Some(entry.name.clone())
+}
+
+fn find_item_style<'a>(styles: &'a [ItemStyle], class: Option<&str>) -> Option<&'a ItemStyle> {
+    let class = class?;
+    styles.iter().find(|style| style.class == class)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ItemRef {
+    Tray(usize),
+    Pinned(usize),
+    Custom(usize),
+}
+
+#[derive(Debug)]
+struct PinnedItem {
+    class: String,
+    label: String,
+    command: Option<String>,
+    // The icon of the application from the icon theme, drawn unless the style has an icon.
+    icon: Option<Rc<Image>>,
+    is_pressed: bool,
+    bounds: Rect,
+}
+
+impl PinnedItem {
+    fn new(class: String, label: String, command: Option<String>, icon: Option<Rc<Image>>) -> Self {
+        Self {
+            class,
+            label,
+            command,
+            icon,
+            is_pressed: false,
+            bounds: Rect::ZERO,
+        }
+    }
+
+    fn display_label(&self) -> String {
+        format!("{} (not running)", self.label)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        &self,
+        index: usize,
+        is_selected: bool,
+        is_dimmed: bool,
+        style: Option<&ItemStyle>,
+        ui_config: &UIConfig,
+        context: &impl Renderer,
+    ) {
+        draw_item(
+            &self.display_label(),
+            self.bounds,
+            index,
+            is_selected,
+            is_dimmed,
+            true,
+            style,
+            style
+                .and_then(|style| style.icon.as_ref())
+                .or(self.icon.as_deref()),
+            ui_config,
+            context,
+        );
+    }
+}
+
+#[derive(Debug)]
+struct GroupHeader {
+    label: String,
+    bounds: Rect,
+}
+
+// Stacks rows vertically with gaps between them.
+#[derive(Debug)]
+struct RowCursor {
+    y: f64,
+    gap: f64,
+    has_rows: bool,
+}
+
+impl RowCursor {
+    fn new(y: f64, gap: f64) -> Self {
+        Self {
+            y,
+            gap,
+            has_rows: false,
+        }
+    }
+
+    // Returns the top of the next row.
+    fn next(&mut self, height: f64) -> f64 {
+        if self.has_rows {
+            self.y += self.gap;
+        }
+        self.has_rows = true;
+        let top = self.y;
+        self.y += height;
+        top
+    }
+
+    // Returns the middle of the gap before the next row.
+    fn separator_y(&self) -> f64 {
+        self.y + self.gap / 2.0
+    }
+
+    fn bottom(&self) -> f64 {
+        self.y
+    }
+
+    fn set_bottom(&mut self, bottom: f64) {
+        self.y = bottom;
+    }
+}
+
+#[derive(Debug)]
+struct CustomItem {
+    title: String,
+    action: Option<String>,
+    is_pressed: bool,
+    bounds: Rect,
+}
+
+impl CustomItem {
+    fn new(action: Option<String>) -> Self {
+        Self {
+            title: String::new(),
+            action,
+            is_pressed: false,
+            bounds: Rect::ZERO,
+        }
+    }
+
+    fn draw(
+        &self,
+        index: usize,
+        is_selected: bool,
+        is_dimmed: bool,
+        ui_config: &UIConfig,
+        context: &impl Renderer,
+    ) {
+        draw_item(
+            &self.title,
+            self.bounds,
+            index,
+            is_selected,
+            is_dimmed,
+            false,
+            None,
+            None,
+            ui_config,
+            context,
+        );
+    }
+}
+
+// Replaces "{count}" in the template with the number of items and "{selected}" with the title of
+// the selected item.
+fn format_title(template: &str, count: usize, selected: Option<&str>) -> String {
+    template
+        .replace("{count}", &count.to_string())
+        .replace("{selected}", selected.unwrap_or(""))
+}
+
+fn set_window_title(
+    connection: &impl Connection,
+    atoms: &Atoms,
+    window: xproto::Window,
+    title: &str,
+) -> anyhow::Result<()> {
+    connection
+        .change_property8(
+            xproto::PropMode::REPLACE,
+            window,
+            xproto::AtomEnum::WM_NAME,
+            xproto::AtomEnum::STRING,
+            title.as_bytes(),
+        )?
+        .check()
+        .context("set WM_NAME")?;
+
+    connection
+        .change_property8(
+            xproto::PropMode::REPLACE,
+            window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            title.as_bytes(),
+        )?
+        .check()
+        .context("set _NET_WM_NAME")?;
+
+    Ok(())
+}
+
+fn draw_window_frame(size: Size, ui_config: &UIConfig, context: &impl Renderer) {
+    let bounds = Rect {
+        x: 0.0,
+        y: 0.0,
+        width: size.width,
+        height: size.height,
+    };
+
+    context.draw_rect(bounds, ui_config.window_background);
+
+    if ui_config.window_border_size > 0.0 {
+        context.stroke_rect(
+            bounds,
+            ui_config.window_border_color,
+            ui_config.window_border_size,
+        );
+    }
+}
+
+fn draw_footer(text: &str, bounds: Rect, ui_config: &UIConfig, context: &impl Renderer) {
+    if ui_config.item_corner_radius > 0.0 {
+        let corner_radius = Size {
+            width: ui_config.item_corner_radius,
+            height: ui_config.item_corner_radius,
+        };
+        context.draw_rounded_rect(bounds, ui_config.footer_background, corner_radius)
+    } else {
+        context.draw_rect(bounds, ui_config.footer_background);
+    }
+    context.draw_text(
+        text,
+        &ui_config.footer_font,
+        ui_config.text_size,
+        HAlign::Center,
+        VAlign::Middle,
+        bounds,
+        ui_config.footer_foreground,
+    );
+}
+
+// Returns the 1-based position of the selected item and the number of items, e.g. "3/7".
+fn footer_text(selected_index: Option<usize>, item_count: usize) -> String {
+    match selected_index {
+        Some(index) => format!("{}/{}", index + 1, item_count),
+        None => format!("-/{}", item_count),
+    }
+}
+
+fn draw_selection_indicator(
+    bounds: Rect,
+    corner_radius: Size,
+    ui_config: &UIConfig,
+    context: &impl Renderer,
+) {
+    const INDICATOR_SIZE: f64 = 3.0;
+
+    let color = ui_config.selected_item_background;
+
+    match ui_config.selection_style {
+        SelectionStyle::Background => {}
+        SelectionStyle::LeftBar => {
+            context.draw_rect(
+                Rect {
+                    width: INDICATOR_SIZE,
+                    ..bounds
+                },
+                color,
+            );
+        }
+        SelectionStyle::Underline => {
+            context.draw_rect(
+                Rect {
+                    y: bounds.y + bounds.height - INDICATOR_SIZE,
+                    height: INDICATOR_SIZE,
+                    ..bounds
+                },
+                color,
+            );
+        }
+        SelectionStyle::Outline => {
+            if ui_config.item_corner_radius > 0.0 {
+                context.stroke_rounded_rect(bounds, color, corner_radius, INDICATOR_SIZE);
+            } else {
+                context.stroke_rect(bounds, color, INDICATOR_SIZE);
+            }
+        }
+        SelectionStyle::Arrow => {
+            // The arrow is placed in the padding before the icon.
+            let size = ui_config.item_padding.min(bounds.height / 2.0);
+            let x = bounds.x + (ui_config.item_padding - size / 2.0) / 2.0;
+            let y = bounds.y + bounds.height / 2.0;
+            context.draw_polygon(
+                &[
+                    Point {
+                        x,
+                        y: y - size / 2.0,
+                    },
+                    Point {
+                        x: x + size / 2.0,
+                        y,
+                    },
+                    Point {
+                        x,
+                        y: y + size / 2.0,
+                    },
+                ],
+                color,
+            );
+        }
+    }
+}
+
+// The badge is placed at the end of the item, because the icon window covers the icon area.
+const ATTENTION_BADGE_SIZE: f64 = 8.0;
+
+// The count is drawn left to the attention badge if the item has both.
+fn draw_count_badge(
+    count: u32,
+    bounds: Rect,
+    is_urgent: bool,
+    ui_config: &UIConfig,
+    context: &impl Renderer,
+) {
+    let text = if count > 99 {
+        "99+".to_owned()
+    } else {
+        count.to_string()
+    };
+    let font_size = ui_config.text_size * 0.8;
+    let height = (font_size * 1.6).round();
+    let width = (font_size * 0.6 * text.len() as f64 + height * 0.5).max(height);
+    let mut right = bounds.x + bounds.width - ui_config.item_padding;
+    if is_urgent {
+        right -= ATTENTION_BADGE_SIZE + ui_config.item_padding;
+    }
+    let badge_bounds = Rect {
+        x: right - width,
+        y: bounds.y + (bounds.height - height) / 2.0,
+        width,
+        height,
+    };
+    context.draw_rounded_rect(
+        badge_bounds,
+        ui_config.count_badge_background,
+        Size {
+            width: height / 2.0,
+            height: height / 2.0,
+        },
+    );
+    context.draw_text(
+        &text,
+        &ui_config.normal_item_font,
+        font_size,
+        HAlign::Center,
+        VAlign::Middle,
+        badge_bounds,
+        ui_config.count_badge_foreground,
+    );
+}
+
+fn extract_count(count_pattern: &Regex, title: &str) -> Option<u32> {
+    let captures = count_pattern.captures(title)?;
+    let count = captures.get(1).or_else(|| captures.get(0))?;
+    count.as_str().trim().parse().ok()
+}
+
+fn draw_attention_badge(bounds: Rect, ui_config: &UIConfig, context: &impl Renderer) {
+    context.draw_rounded_rect(
+        Rect {
+            x: bounds.x + bounds.width - ui_config.item_padding - ATTENTION_BADGE_SIZE,
+            y: bounds.y + (bounds.height - ATTENTION_BADGE_SIZE) / 2.0,
+            width: ATTENTION_BADGE_SIZE,
+            height: ATTENTION_BADGE_SIZE,
+        },
+        ui_config.attention_badge_color,
+        Size {
+            width: ATTENTION_BADGE_SIZE / 2.0,
+            height: ATTENTION_BADGE_SIZE / 2.0,
+        },
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_item(
+    title: &str,
+    bounds: Rect,
+    index: usize,
+    is_selected: bool,
+    is_dimmed: bool,
+    is_inactive: bool,
+    style: Option<&ItemStyle>,
+    icon: Option<&Image>,
+    ui_config: &UIConfig,
+    context: &impl Renderer,
+) {
+    let background = item_background(is_selected, is_dimmed, style, ui_config);
+    let (mut foreground, font) = if is_selected {
+        (
+            ui_config.selected_item_foreground,
+            &ui_config.selected_item_font,
+        )
+    } else {
+        (
+            style
+                .and_then(|style| style.foreground)
+                .unwrap_or(ui_config.normal_item_foreground),
+            &ui_config.normal_item_font,
+        )
+    };
+
+    if is_dimmed {
+        foreground = foreground.scale_alpha(1.0 - ui_config.dim_unselected);
+    }
+
+    if is_inactive {
+        foreground = foreground.scale_alpha(0.5);
+    }
+
+    let corner_radius = Size {
+        width: ui_config.item_corner_radius,
+        height: ui_config.item_corner_radius,
+    };
+
+    if ui_config.item_corner_radius > 0.0 {
+        context.draw_rounded_rect(bounds, background, corner_radius)
+    } else {
+        context.draw_rect(bounds, background);
+    }
+
+    if is_selected {
+        draw_selection_indicator(bounds, corner_radius, ui_config, context);
+    }
+
+    if let Some(icon) = icon {
+        context.draw_image(
+            icon,
+            Rect {
+                x: bounds.x + ui_config.item_padding,
+                y: bounds.y + ui_config.item_padding,
+                width: ui_config.icon_size,
+                height: ui_config.icon_size,
+            },
+        );
+    }
+
+    let text_bounds = Rect {
+        x: bounds.x + (ui_config.icon_size + ui_config.item_padding * 2.0),
+        y: bounds.y,
+        width: bounds.width - (ui_config.icon_size + ui_config.item_padding * 3.0),
+        height: bounds.height,
+    };
+    context.draw_text(
+        &item_text(title, index, ui_config),
+        font,
+        ui_config.text_size,
+        HAlign::Left,
+        VAlign::Middle,
+        text_bounds,
+        foreground,
+    );
+}
+
+fn item_background(
+    is_selected: bool,
+    is_dimmed: bool,
+    style: Option<&ItemStyle>,
+    ui_config: &UIConfig,
+) -> Color {
+    let background = if is_selected && ui_config.selection_style == SelectionStyle::Background {
+        ui_config.selected_item_background
+    } else {
+        style
+            .and_then(|style| style.background)
+            .unwrap_or(ui_config.normal_item_background)
+    };
+    if is_dimmed {
+        background.scale_alpha(1.0 - ui_config.dim_unselected)
+    } else {
+        background
+    }
+}
+
+fn item_text(title: &str, index: usize, ui_config: &UIConfig) -> String {
+    if ui_config.show_number {
+        format!("{}. {}", index + 1, title)
+    } else {
+        title.to_string()
+    }
+}
+
+// Returns the bounds of the monitor containing the point, or the whole screen if RandR is not
+// available.
+fn monitor_at(
+    connection: &impl Connection,
+    screen: &xproto::Screen,
+    point: PhysicalPoint,
+) -> PhysicalRect {
+    let monitors = connection
+        .randr_get_monitors(screen.root, true)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .map(|reply| reply.monitors)
+        .unwrap_or_default();
+    monitors
+        .iter()
+        .map(|monitor| PhysicalRect {
+            x: monitor.x as i32,
+            y: monitor.y as i32,
+            width: monitor.width as u32,
+            height: monitor.height as u32,
+        })
+        .find(|bounds| bounds.contains_pos(point))
+        .unwrap_or(PhysicalRect {
+            x: 0,
+            y: 0,
+            width: screen.width_in_pixels as u32,
+            height: screen.height_in_pixels as u32,
+        })
+}
+
+// Places the window at the bottom right of the pointer like a context menu. It is flipped to the
+// other side of the pointer if it would overflow the monitor, and then clamped within it.
+fn place_near_pointer(
+    pointer: PhysicalPoint,
+    size: PhysicalSize,
+    monitor: PhysicalRect,
+) -> PhysicalPoint {
+    fn place(pointer: i32, size: i32, start: i32, end: i32) -> i32 {
+        let position = if pointer + size > end {
+            pointer - size
+        } else {
+            pointer
+        };
+        position.min(end - size).max(start)
+    }
+
+    PhysicalPoint {
+        x: place(
+            pointer.x,
+            size.width as i32,
+            monitor.x,
+            monitor.x + monitor.width as i32,
+        ),
+        y: place(
+            pointer.y,
+            size.height as i32,
+            monitor.y,
+            monitor.y + monitor.height as i32,
+        ),
+    }
+}
+
+fn window_type_atom(atoms: &Atoms, window_type: WindowType) -> xproto::Atom {
+    match window_type {
+        WindowType::Normal => atoms._NET_WM_WINDOW_TYPE_NORMAL,
+        WindowType::Dialog => atoms._NET_WM_WINDOW_TYPE_DIALOG,
+        WindowType::Utility => atoms._NET_WM_WINDOW_TYPE_UTILITY,
+        WindowType::Dock => atoms._NET_WM_WINDOW_TYPE_DOCK,
+        WindowType::Notification => atoms._NET_WM_WINDOW_TYPE_NOTIFICATION,
+    }
+}
+
+fn window_state_atom(atoms: &Atoms, state: WindowState) -> xproto::Atom {
+    match state {
+        WindowState::Above => atoms._NET_WM_STATE_ABOVE,
+        WindowState::StaysOnTop => atoms._NET_WM_STATE_STAYS_ON_TOP,
+        WindowState::Sticky => atoms._NET_WM_STATE_STICKY,
+    }
+}
+
+fn get_window_pid(
+    connection: &impl Connection,
+    atoms: &Atoms,
+    window: xproto::Window,
+) -> anyhow::Result<Option<u32>> {
+    let reply = connection
+        .get_property(
+            false,
+            window,
+            atoms._NET_WM_PID,
+            xproto::AtomEnum::CARDINAL,
+            0,
+            1,
+        )?
+        .reply()
+        .context("get _NET_WM_PID")?;
+    Ok(reply.value32().and_then(|mut iter| iter.next()))
+}
+
+// The class is given by the client, so only the characters safe in a file name are kept.
+fn screenshot_file_class(class: Option<&str>) -> String {
+    let class: String = class
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .collect();
+    if class.is_empty() {
+        "icon".to_owned()
+    } else {
+        class
+    }
+}
+
+// The reply is sent to the root window, with the icon in the third data.
+fn send_ping(
+    connection: &impl Connection,
+    atoms: &Atoms,
+    window: xproto::Window,
+) -> anyhow::Result<()> {
+    let event = xproto::ClientMessageEvent::new(
+        32,
+        window,
+        atoms.WM_PROTOCOLS,
+        [atoms._NET_WM_PING, x11rb::CURRENT_TIME, window, 0, 0],
+    );
+    connection
+        .send_event(false, window, xproto::EventMask::NO_EVENT, event)?
+        .check()
+        .context("send _NET_WM_PING")?;
+    Ok(())
+}
+
+fn process_name(pid: u32) -> Option<String> {
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(comm.trim_end().to_owned())
+}
+
+fn should_warp_pointer(click: ClickConfig, tray_item: &TrayItem) -> bool {
+    click.warp_pointer
+        && !(tray_item.is_remote && click.remote_icons == RemoteIconPolicy::SendEvent)
+}
+
+fn click_window(
+    connection: &impl Connection,
+    screen_num: usize,
+    warp_pointer: bool,
+    window: xproto::Window,
+    button: xproto::ButtonIndex,
+    button_mask: xproto::ButtonMask,
+) -> anyhow::Result<()> {
+    let screen = &connection.setup().roots[screen_num];
+    let absolute_position = connection
+        .translate_coordinates(window, screen.root, 0, 0)?
+        .reply()?;
+    let saved_pointer = if warp_pointer {
+        let saved_pointer = connection.query_pointer(screen.root)?.reply()?;
+        connection
+            .warp_pointer(
+                x11rb::NONE,             // src_window
+                screen.root,             // dst_window
+                0,                       // src_x
+                0,                       // src_y
+                0,                       // src_width
+                0,                       // src_heihgt
+                absolute_position.dst_x, // dst_x
+                absolute_position.dst_y, // dst_y
+            )?
+            .check()
+            .context("move cursor to icon")?;
+        Some(saved_pointer)
+    } else {
+        None
+    };
+
+    send_button_event(
+        connection,
+        screen_num,
+        window,
+        button,
+        button_mask,
+        xproto::BUTTON_PRESS_EVENT,
+        0,
+        0,
+        absolute_position.dst_x,
+        absolute_position.dst_y,
+    )?;
+
+    send_button_event(
+        connection,
+        screen_num,
+        window,
+        button,
+        button_mask,
+        xproto::BUTTON_RELEASE_EVENT,
+        0,
+        0,
         absolute_position.dst_x,
         absolute_position.dst_y,
     )?;
 
-    connection
-        .warp_pointer(
-            x11rb::NONE,                 // src_window
-            screen.root,                 // dst_window
-            0,                           // src_x
-            0,                           // src_y
-            0,                           // src_width
-            0,                           // src_heihgt
-            saved_pointer.root_x as i16, // dst_x
-            saved_pointer.root_y as i16, // dst_y
-        )?
-        .check()
-        .context("restore cursor position")?;
+    if let Some(saved_pointer) = saved_pointer {
+        connection
+            .warp_pointer(
+                x11rb::NONE,          // src_window
+                screen.root,          // dst_window
+                0,                    // src_x
+                0,                    // src_y
+                0,                    // src_width
+                0,                    // src_heihgt
+                saved_pointer.root_x, // dst_x
+                saved_pointer.root_y, // dst_y
+            )?
+            .check()
+            .context("restore cursor position")?;
+    }
+
+    connection.flush().context("flush after click icon")?;
+
+    Ok(())
+}
+
+fn create_frame(
+    connection: &impl Connection,
+    parent: xproto::Window,
+    icon: xproto::Window,
+    ui_config: &UIConfig,
+) -> anyhow::Result<xproto::Window> {
+    let frame = connection.generate_id().context("generate frame id")?;
+    // The tray manager tracks the windows that the clients create in the frame by
+    // SUBSTRUCTURE_NOTIFY.
+    let values = xproto::CreateWindowAux::new()
+        .event_mask(xproto::EventMask::STRUCTURE_NOTIFY | xproto::EventMask::SUBSTRUCTURE_NOTIFY);
+
+    connection
+        .create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            frame,
+            parent,
+            0,
+            0,
+            ui_config.icon_size as u16,
+            ui_config.icon_size as u16,
+            0, // border_width
+            xproto::WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &values,
+        )?
+        .check()
+        .context("create tray icon frame")?;
+
+    connection
+        .reparent_window(icon, frame, 0, 0)?
+        .check()
+        .context("reparent tray icon into frame")?;
+    connection
+        .map_window(icon)?
+        .check()
+        .context("map tray icon")?;
+
+    Ok(frame)
+}
+
+// The icon must have left the frame, otherwise it is destroyed together.
+fn destroy_frame(connection: &impl Connection, frame: xproto::Window) {
+    if frame != x11rb::NONE {
+        connection.destroy_window(frame).ok();
+    }
+}
+
+fn resize_window(
+    connection: &impl Connection,
+    window: xproto::Window,
+    size: PhysicalSize,
+    position: Option<PhysicalPoint>,
+) -> anyhow::Result<()> {
+    let values = xproto::ConfigureWindowAux::new()
+        .x(position.map(|position| position.x))
+        .y(position.map(|position| position.y))
+        .height(size.height)
+        .width(size.width)
+        .stack_mode(xproto::StackMode::ABOVE);
+
+    connection
+        .configure_window(window, &values)?
+        .check()
+        .context("resize tray window")?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_button_event(
+    connection: &impl Connection,
+    screen_num: usize,
+    window: xproto::Window,
+    button: xproto::ButtonIndex,
+    button_mask: xproto::ButtonMask,
+    event_type: u8,
+    x: i16,
+    y: i16,
+    root_x: i16,
+    root_y: i16,
+) -> anyhow::Result<()> {
+    let screen = &connection.setup().roots[screen_num];
+
+    let event = xproto::ButtonPressEvent {
+        response_type: event_type,
+        detail: button.into(),
+        sequence: 0,
+        time: x11rb::CURRENT_TIME,
+        root: screen.root,
+        event: window,
+        child: x11rb::NONE,
+        event_x: x,
+        event_y: y,
+        root_x,
+        root_y,
+        state: u16::from(button_mask).into(),
+        same_screen: true,
+    };
+
+    connection
+        .send_event(true, window, xproto::EventMask::NO_EVENT, event)?
+        .check()
+        .context("send ButtonPressEvent")?;
+
+    Ok(())
+}
+
+// Converts the Z-pixmap image of 32 bits per pixel into ARGB words. Images of depth 24 have no
+// alpha channel, so they are opaque.
+fn image_to_argb(
+    data: &[u8],
+    depth: u8,
+    byte_order: xproto::ImageOrder,
+    size: PhysicalSize,
+) -> Option<Vec<u32>> {
+    let alpha = match depth {
+        24 => 0xff000000,
+        32 => 0,
+        _ => return None,
+    };
+    let pixel_count = (size.width * size.height) as usize;
+    if data.len() < pixel_count * 4 {
+        return None;
+    }
+    let pixels = data
+        .chunks_exact(4)
+        .take(pixel_count)
+        .map(|bytes| {
+            let bytes = bytes.try_into().unwrap();
+            let pixel = if byte_order == xproto::ImageOrder::MSB_FIRST {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            };
+            pixel | alpha
+        })
+        .collect();
+    Some(pixels)
+}
+
+// The sync extension is optional, and the window manager does not wait for the redraws without it.
+fn create_sync_counter(connection: &impl Connection) -> anyhow::Result<Option<sync::Counter>> {
+    if connection
+        .extension_information(sync::X11_EXTENSION_NAME)?
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    connection
+        .sync_initialize(3, 1)?
+        .reply()
+        .context("init sync extension")?;
+
+    let sync_counter = connection
+        .generate_id()
+        .context("generate sync counter id")?;
+    connection
+        .sync_create_counter(sync_counter, sync::Int64 { hi: 0, lo: 0 })?
+        .check()
+        .context("create sync counter")?;
+
+    Ok(Some(sync_counter))
+}
+
+fn set_size_hints(
+    connection: &impl Connection,
+    window: xproto::Window,
+    size: PhysicalSize,
+) -> anyhow::Result<()> {
+    let mut size_hints = properties::WmSizeHints::new();
+    size_hints.min_size = Some((size.width as i32, size.height as i32));
+    size_hints.max_size = Some((size.width as i32, size.height as i32));
+    // The position is given for the window itself rather than its frame, so that a remembered
+    // position is restored exactly.
+    size_hints.win_gravity = Some(xproto::Gravity::STATIC);
+    size_hints
+        .set_normal_hints(connection, window)?
+        .check()
+        .context("set tray window size hints")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use x11rb::x11_utils::TryParse as _;
+
+    use super::*;
+    use crate::color::Color;
+    use crate::config::{GroupRule, KeyBindingProfile};
+    use crate::fake_connection::{FakeConnection, ROOT_WINDOW};
+    use crate::render_context::{assert_snapshot, run_with_bundled_font, RenderContext};
+
+    // Titles are left empty so that the snapshots do not depend on the installed fonts, except in
+    // the tests run with the bundled font.
+    fn draw_items(ui_config: &UIConfig, selected_index: Option<usize>) -> RenderContext {
+        draw_items_with_urgency(ui_config, selected_index, None)
+    }
+
+    fn draw_items_with_urgency(
+        ui_config: &UIConfig,
+        selected_index: Option<usize>,
+        urgent_index: Option<usize>,
+    ) -> RenderContext {
+        draw_items_with_titles(ui_config, selected_index, urgent_index, ["", ""])
+    }
+
+    fn draw_items_with_titles(
+        ui_config: &UIConfig,
+        selected_index: Option<usize>,
+        urgent_index: Option<usize>,
+        titles: [&str; 2],
+    ) -> RenderContext {
+        let item_height = ui_config.icon_size + ui_config.item_padding * 2.0;
+        let size = Size {
+            width: 160.0,
+            height: ui_config.window_padding * 2.0 + item_height * 2.0 + ui_config.item_gap,
+        };
+        let context = RenderContext::new_image(size.snap()).unwrap();
+        draw_window_frame(size, ui_config, &context);
+
+        for (index, title) in titles.into_iter().enumerate() {
+            let mut tray_item = TrayItem::new(
+                index as xproto::Window,
+                title.to_owned(),
+                None,
+                false,
+                XEmbedInfo::default(),
+            );
+            tray_item.bounds = Rect {
+                x: ui_config.window_padding,
+                y: ui_config.window_padding + (item_height + ui_config.item_gap) * index as f64,
+                width: size.width - ui_config.window_padding * 2.0,
+                height: item_height,
+            };
+            tray_item.is_urgent = urgent_index == Some(index);
+            let is_selected = selected_index == Some(index);
+            let is_dimmed = selected_index.is_some() && !is_selected;
+            tray_item.draw(index, is_selected, is_dimmed, None, ui_config, &context);
+        }
+
+        context
+    }
+
+    fn create_tray_embedder(groups: GroupsConfig) -> TrayEmbedder<FakeConnection> {
+        let connection = Rc::new(FakeConnection::new());
+        let atoms = Rc::new(Atoms::new(&*connection).unwrap().reply().unwrap());
+        TrayEmbedder::new(
+            connection,
+            0,
+            atoms,
+            &WindowConfig::default(),
+            groups,
+            ClickConfig::default(),
+            PhysicalSize {
+                width: 160,
+                height: 100,
+            },
+        )
+        .unwrap()
+    }
+
+    fn ui_config() -> UIConfig {
+        UIConfig {
+            show_number: false,
+            ..UIConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_draw_items() {
+        assert_snapshot(&draw_items(&ui_config(), None), "items");
+    }
+
+    #[test]
+    fn test_draw_selected_item() {
+        assert_snapshot(&draw_items(&ui_config(), Some(1)), "items_selected");
+    }
+
+    #[test]
+    fn test_draw_items_without_corner_radius() {
+        let ui_config = UIConfig {
+            item_corner_radius: 0.0,
+            ..ui_config()
+        };
+        assert_snapshot(&draw_items(&ui_config, Some(0)), "items_square");
+    }
+
+    #[test]
+    fn test_draw_item_with_style() {
+        let ui_config = UIConfig {
+            item_corner_radius: 0.0,
+            ..ui_config()
+        };
+        let style = ItemStyle {
+            class: "Foo".to_owned(),
+            background: Some(Color::from_rgb(0xff0000)),
+            foreground: None,
+            icon: Some(Image {
+                size: PhysicalSize {
+                    width: 4,
+                    height: 4,
+                },
+                pixels: vec![0xff0000ff; 16],
+            }),
+        };
+        let size = PhysicalSize {
+            width: 80,
+            height: (ui_config.icon_size + ui_config.item_padding * 2.0) as u32,
+        };
+        let context = RenderContext::new_image(size).unwrap();
+        let mut tray_item = TrayItem::new(
+            1,
+            String::new(),
+            Some("Foo".to_owned()),
+            false,
+            XEmbedInfo::default(),
+        );
+        tray_item.bounds = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: size.width as f64,
+            height: size.height as f64,
+        };
+        tray_item.draw(0, false, false, Some(&style), &ui_config, &context);
+
+        let pixels = context.image_data().unwrap();
+        let pixel = |x: f64, y: f64| pixels[y as usize * size.width as usize + x as usize];
+        let icon_center = ui_config.item_padding + ui_config.icon_size / 2.0;
+        assert_eq!(pixel(icon_center, icon_center), 0xff0000ff);
+        assert_eq!(pixel(size.width as f64 - 1.0, 0.0), 0xffff0000);
+    }
+
+    #[test]
+    fn test_draw_items_with_custom_colors() {
+        let ui_config = UIConfig {
+            item_corner_radius: 12.0,
+            window_background: Color::from_rgb(0xffffff),
+            normal_item_background: Color::from_rgba(0xff000080),
+            selected_item_background: Color::from_rgba(0x0000ffc0),
+            ..ui_config()
+        };
+        assert_snapshot(&draw_items(&ui_config, Some(0)), "items_custom_colors");
+    }
+
+    #[test]
+    fn test_draw_items_with_border() {
+        let ui_config = UIConfig {
+            window_border_size: 2.0,
+            window_border_color: Color::from_rgb(0xff8000),
+            ..ui_config()
+        };
+        assert_snapshot(&draw_items(&ui_config, None), "items_border");
+    }
+
+    #[test]
+    fn test_draw_selection_styles() {
+        for (selection_style, name) in [
+            (SelectionStyle::LeftBar, "items_left_bar"),
+            (SelectionStyle::Underline, "items_underline"),
+            (SelectionStyle::Outline, "items_outline"),
+            (SelectionStyle::Arrow, "items_arrow"),
+        ] {
+            let ui_config = UIConfig {
+                selection_style,
+                ..ui_config()
+            };
+            assert_snapshot(&draw_items(&ui_config, Some(0)), name);
+        }
+    }
+
+    #[test]
+    fn test_draw_items_dimmed() {
+        let ui_config = UIConfig {
+            dim_unselected: 0.5,
+            ..ui_config()
+        };
+        assert_snapshot(&draw_items(&ui_config, Some(1)), "items_dimmed");
+    }
+
+    #[test]
+    fn test_draw_urgent_item() {
+        assert_snapshot(
+            &draw_items_with_urgency(&ui_config(), Some(0), Some(1)),
+            "items_urgent",
+        );
+    }
+
+    #[test]
+    fn test_draw_items_with_titles() {
+        if !run_with_bundled_font("tray_embedder::tests::test_draw_items_with_titles") {
+            return;
+        }
+        assert_snapshot(
+            &draw_items_with_titles(
+                &ui_config(),
+                Some(0),
+                None,
+                ["Foo", "A long title to ellipsize"],
+            ),
+            "items_titles",
+        );
+    }
+
+    #[test]
+    fn test_group_items_by_class() {
+        let groups = GroupsConfig {
+            enabled: true,
+            rules: vec![
+                GroupRule {
+                    class: "Foo".to_owned(),
+                    label: Some("Foo".to_owned()),
+                },
+                GroupRule {
+                    class: "Bar".to_owned(),
+                    label: None,
+                },
+            ],
+            ..GroupsConfig::default()
+        };
+        let mut tray_embedder = create_tray_embedder(groups);
+
+        for (icon, class) in [
+            (1, None),
+            (2, Some("Bar")),
+            (3, Some("Foo")),
+            (4, Some("Bar")),
+        ] {
+            tray_embedder.add_icon(
+                icon,
+                String::new(),
+                class.map(str::to_owned),
+                false,
+                XEmbedInfo::default(),
+            );
+        }
+
+        let icons: Vec<_> = tray_embedder
+            .tray_items
+            .iter()
+            .map(|tray_item| tray_item.icon)
+            .collect();
+        assert_eq!(icons, [3, 2, 4, 1]);
+
+        let ui_config = UIConfig::default();
+        tray_embedder.layout(&ui_config).unwrap();
+
+        let labels: Vec<_> = tray_embedder
+            .group_headers
+            .iter()
+            .map(|group_header| group_header.label.as_str())
+            .collect();
+        assert_eq!(labels, ["Foo"]);
+        assert_eq!(tray_embedder.separators.len(), 2);
+        assert!(tray_embedder.group_headers[0].bounds.y < tray_embedder.tray_items[0].bounds.y);
+    }
+
+    #[test]
+    fn test_sort_tray_items() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        tray_embedder.add_pinned_item("Pinned".to_owned(), Some("Pinned".to_owned()), None);
+        for (icon, title, class) in [
+            (1, "charlie", Some("Bar")),
+            (2, "Alpha", None),
+            (3, "zulu", Some("Pinned")),
+            (4, "bravo", Some("Foo")),
+        ] {
+            tray_embedder.add_icon(
+                icon,
+                title.to_owned(),
+                class.map(str::to_owned),
+                false,
+                XEmbedInfo::default(),
+            );
+        }
+        let icons = |tray_embedder: &TrayEmbedder<FakeConnection>| -> Vec<_> {
+            tray_embedder
+                .tray_items
+                .iter()
+                .map(|tray_item| tray_item.icon)
+                .collect()
+        };
+        assert_eq!(icons(&tray_embedder), [3, 1, 2, 4]);
+
+        tray_embedder.set_sort_order(SortOrder::Title);
+        assert_eq!(icons(&tray_embedder), [3, 2, 4, 1]);
+
+        tray_embedder.change_title(2, "delta".to_owned());
+        assert_eq!(icons(&tray_embedder), [3, 4, 1, 2]);
+
+        tray_embedder.set_sort_order(SortOrder::Class);
+        assert_eq!(icons(&tray_embedder), [3, 1, 4, 2]);
+    }
+
+    #[test]
+    fn test_mru_sort_order() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        for (icon, title) in [(1, "Foo"), (2, "Bar"), (3, "Baz")] {
+            tray_embedder.add_icon(icon, title.to_owned(), None, false, XEmbedInfo::default());
+        }
+        tray_embedder.set_sort_order(SortOrder::Mru);
+
+        tray_embedder.select_last_used_item();
+        assert_eq!(tray_embedder.selected_index, None);
+
+        tray_embedder.select_item(2);
+        tray_embedder
+            .click_selected_item(MouseButton::Left, Modifiers::NONE)
+            .unwrap();
+        tray_embedder.select_item(1);
+        tray_embedder
+            .click_selected_item(MouseButton::Left, Modifiers::NONE)
+            .unwrap();
+        let icons = |tray_embedder: &TrayEmbedder<FakeConnection>| -> Vec<_> {
+            tray_embedder
+                .tray_items
+                .iter()
+                .map(|tray_item| tray_item.icon)
+                .collect()
+        };
+        assert_eq!(icons(&tray_embedder), [1, 2, 3]);
+
+        tray_embedder.hide().unwrap();
+        assert_eq!(icons(&tray_embedder), [2, 3, 1]);
+
+        tray_embedder.select_last_used_item();
+        assert_eq!(tray_embedder.selected_index, Some(0));
+    }
+
+    #[test]
+    fn test_reverse() {
+        for (groups_enabled, expected_icons) in [(false, [5, 4, 3, 2, 1]), (true, [4, 2, 3, 5, 1])]
+        {
+            let groups = GroupsConfig {
+                enabled: groups_enabled,
+                rules: vec![GroupRule {
+                    class: "Foo".to_owned(),
+                    label: None,
+                }],
+                ..GroupsConfig::default()
+            };
+            let mut tray_embedder = create_tray_embedder(groups);
+            tray_embedder.set_reverse(true);
+            for (icon, class) in [
+                (1, None),
+                (2, Some("Foo")),
+                (3, Some("Bar")),
+                (4, Some("Foo")),
+                (5, None),
+            ] {
+                tray_embedder.add_icon(
+                    icon,
+                    String::new(),
+                    class.map(str::to_owned),
+                    false,
+                    XEmbedInfo::default(),
+                );
+            }
+            let icons: Vec<_> = tray_embedder
+                .tray_items
+                .iter()
+                .map(|tray_item| tray_item.icon)
+                .collect();
+            assert_eq!(icons, expected_icons);
+        }
+    }
+
+    #[test]
+    fn test_pinned_items() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        for class in ["Foo", "Bar"] {
+            tray_embedder.add_pinned_item(class.to_owned(), None, None);
+        }
+        tray_embedder.add_custom_item(None);
 
-    connection.flush().context("flush after click icon")?;
+        assert_eq!(
+            tray_embedder.item_refs(),
+            [ItemRef::Pinned(0), ItemRef::Pinned(1), ItemRef::Custom(0)]
+        );
 
-    Ok(())
-}
+        for (icon, class) in [(1, None), (2, Some("Bar")), (3, Some("Foo"))] {
+            tray_embedder.add_icon(
+                icon,
+                String::new(),
+                class.map(str::to_owned),
+                false,
+                XEmbedInfo::default(),
+            );
+        }
 
-fn resize_window(
-    connection: &impl Connection,
-    screen_num: usize,
-    window: xproto::Window,
-    size: PhysicalSize,
-) -> anyhow::Result<()> {
-    let screen = &connection.setup().roots[screen_num];
-    let values = xproto::ConfigureWindowAux::new()
-        .x(((screen.width_in_pixels as u32 - size.width) / 2) as i32)
-        .y(((screen.height_in_pixels as u32 - size.height) / 2) as i32)
-        .height(size.height)
-        .width(size.width)
-        .stack_mode(xproto::StackMode::ABOVE);
+        assert_eq!(
+            tray_embedder.item_refs(),
+            [
+                ItemRef::Tray(0),
+                ItemRef::Tray(1),
+                ItemRef::Tray(2),
+                ItemRef::Custom(0)
+            ]
+        );
+        let icons: Vec<_> = tray_embedder
+            .tray_items
+            .iter()
+            .map(|tray_item| tray_item.icon)
+            .collect();
+        assert_eq!(icons, [3, 2, 1]);
 
-    connection
-        .configure_window(window, &values)?
-        .check()
-        .context("resize tray window")?;
+        tray_embedder.remove_icon(3);
 
-    Ok(())
-}
+        assert_eq!(
+            tray_embedder.item_refs(),
+            [
+                ItemRef::Pinned(0),
+                ItemRef::Tray(0),
+                ItemRef::Tray(1),
+                ItemRef::Custom(0)
+            ]
+        );
+    }
 
-fn send_button_event(
-    connection: &impl Connection,
-    screen_num: usize,
-    window: xproto::Window,
-    button: xproto::ButtonIndex,
-    button_mask: xproto::ButtonMask,
-    event_type: u8,
-    x: i16,
-    y: i16,
-    root_x: i16,
-    root_y: i16,
-) -> anyhow::Result<()> {
-    let screen = &connection.setup().roots[screen_num];
+    #[test]
+    fn test_select_next_urgent_item() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        for icon in 1..=4 {
+            tray_embedder.add_icon(icon, String::new(), None, false, XEmbedInfo::default());
+        }
 
-    let event = xproto::ButtonPressEvent {
-        response_type: event_type,
-        detail: button.into(),
-        sequence: 0,
-        time: x11rb::CURRENT_TIME,
-        root: screen.root,
-        event: window,
-        child: x11rb::NONE,
-        event_x: x,
-        event_y: y,
-        root_x,
-        root_y,
-        state: u16::from(button_mask).into(),
-        same_screen: true,
-    };
+        tray_embedder.select_next_urgent_item();
+        assert_eq!(tray_embedder.selected_index, None);
 
-    connection
-        .send_event(true, window, xproto::EventMask::NO_EVENT, event)?
-        .check()
-        .context("send ButtonPressEvent")?;
+        tray_embedder.change_urgency(2, true);
+        tray_embedder.change_urgency(4, true);
 
-    Ok(())
-}
+        tray_embedder.select_next_urgent_item();
+        assert_eq!(tray_embedder.selected_index, Some(1));
+        tray_embedder.select_next_urgent_item();
+        assert_eq!(tray_embedder.selected_index, Some(3));
+        tray_embedder.select_next_urgent_item();
+        assert_eq!(tray_embedder.selected_index, Some(1));
+    }
 
-fn set_size_hints(
-    connection: &impl Connection,
-    window: xproto::Window,
-    size: PhysicalSize,
-) -> anyhow::Result<()> {
-    let mut size_hints = properties::WmSizeHints::new();
-    size_hints.min_size = Some((size.width as i32, size.height as i32));
-    size_hints.max_size = Some((size.width as i32, size.height as i32));
-    size_hints
-        .set_normal_hints(connection, window)?
-        .check()
-        .context("set tray window size hints")?;
+    #[test]
+    fn test_select_matching_item() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        tray_embedder.add_icon(
+            1,
+            "Telegram (2)".to_owned(),
+            None,
+            false,
+            XEmbedInfo::default(),
+        );
+        tray_embedder.add_icon(
+            2,
+            "Network".to_owned(),
+            Some("Nm-applet".to_owned()),
+            false,
+            XEmbedInfo::default(),
+        );
+        tray_embedder.add_icon(3, "telegram".to_owned(), None, false, XEmbedInfo::default());
 
-    Ok(())
+        tray_embedder.select_matching_item(None, Some("Telegram"));
+        assert_eq!(tray_embedder.selected_index, Some(0));
+        tray_embedder.select_matching_item(None, Some("Telegram"));
+        assert_eq!(tray_embedder.selected_index, Some(2));
+        tray_embedder.select_matching_item(None, Some("Telegram"));
+        assert_eq!(tray_embedder.selected_index, Some(0));
+
+        tray_embedder.select_matching_item(Some("Nm-applet"), None);
+        assert_eq!(tray_embedder.selected_index, Some(1));
+        tray_embedder.select_matching_item(Some("Nm-applet"), Some("Telegram"));
+        assert_eq!(tray_embedder.selected_index, None);
+    }
+
+    #[test]
+    fn test_format_title() {
+        assert_eq!(format_title("GeekTray", 5, None), "GeekTray");
+        assert_eq!(format_title("GeekTray ({count})", 5, None), "GeekTray (5)");
+        assert_eq!(
+            format_title("{selected} - GeekTray ({count})", 2, Some("foo")),
+            "foo - GeekTray (2)"
+        );
+        assert_eq!(format_title("{selected}", 0, None), "");
+    }
+
+    #[test]
+    fn test_place_near_pointer() {
+        let size = PhysicalSize {
+            width: 200,
+            height: 100,
+        };
+        let monitor = PhysicalRect {
+            x: 1920,
+            y: 0,
+            width: 1280,
+            height: 720,
+        };
+        assert_eq!(
+            place_near_pointer(PhysicalPoint { x: 2000, y: 100 }, size, monitor),
+            PhysicalPoint { x: 2000, y: 100 }
+        );
+        assert_eq!(
+            place_near_pointer(PhysicalPoint { x: 3150, y: 700 }, size, monitor),
+            PhysicalPoint { x: 2950, y: 600 }
+        );
+        assert_eq!(
+            place_near_pointer(PhysicalPoint { x: 1950, y: 50 }, size, monitor),
+            PhysicalPoint { x: 1950, y: 50 }
+        );
+        assert_eq!(
+            place_near_pointer(
+                PhysicalPoint { x: 2000, y: 50 },
+                PhysicalSize {
+                    width: 200,
+                    height: 1000,
+                },
+                monitor
+            ),
+            PhysicalPoint { x: 2000, y: 0 }
+        );
+    }
+
+    #[test]
+    fn test_resize_window_by() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        tray_embedder.should_layout = false;
+
+        tray_embedder.resize_window_by(40);
+        assert_eq!(tray_embedder.size.width, 200);
+        assert!(tray_embedder.should_layout);
+
+        tray_embedder.resize_window_by(-1000);
+        assert_eq!(tray_embedder.size.width, MIN_WINDOW_WIDTH as u32);
+    }
+
+    #[test]
+    fn test_focus_selected_icon() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let connection = tray_embedder.connection.clone();
+        tray_embedder.add_icon(20, String::new(), None, false, XEmbedInfo::default());
+        tray_embedder.select_item(0);
+        connection.take_requests();
+
+        let xembed_messages = |requests: Vec<protocol::Request>| -> Vec<[u32; 5]> {
+            requests
+                .into_iter()
+                .filter_map(|request| match request {
+                    protocol::Request::SendEvent(request) if request.destination == 20 => {
+                        xproto::ClientMessageEvent::try_parse(request.event.as_slice())
+                            .ok()
+                            .map(|(event, _)| event.data.as_data32())
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+
+        tray_embedder.focus_selected_icon().unwrap();
+        assert_eq!(tray_embedder.focused_icon(), Some(20));
+        assert_eq!(
+            xembed_messages(connection.take_requests()),
+            [
+                [0, XEmbedMessage::WindowActivate.into(), 0, 0, 0],
+                [0, XEmbedMessage::FocusIn.into(), XEMBED_FOCUS_CURRENT, 0, 0],
+            ]
+        );
+
+        tray_embedder.unfocus_icon().unwrap();
+        assert_eq!(tray_embedder.focused_icon(), None);
+        assert_eq!(
+            xembed_messages(connection.take_requests()),
+            [
+                [0, XEmbedMessage::FocusOut.into(), 0, 0, 0],
+                [0, XEmbedMessage::WindowDeactivate.into(), 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_click_selected_item_with_modifiers() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let connection = tray_embedder.connection.clone();
+        tray_embedder.add_icon(20, String::new(), None, false, XEmbedInfo::default());
+        tray_embedder.select_item(0);
+        connection.take_requests();
+
+        tray_embedder
+            .click_selected_item(MouseButton::Left, Modifiers::CONTROL)
+            .unwrap();
+
+        let states: Vec<_> = connection
+            .take_requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                protocol::Request::SendEvent(request) if request.destination == 20 => {
+                    xproto::ButtonPressEvent::try_parse(request.event.as_slice())
+                        .ok()
+                        .map(|(event, _)| (event.response_type, u16::from(event.state)))
+                }
+                _ => None,
+            })
+            .collect();
+        let state = u16::from(xproto::ButtonMask::M1) | u16::from(xproto::ModMask::CONTROL);
+        assert_eq!(
+            states,
+            [
+                (xproto::BUTTON_PRESS_EVENT, state),
+                (xproto::BUTTON_RELEASE_EVENT, state),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flash_on_update() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        tray_embedder.set_flash_duration(Some(Duration::from_millis(1000)));
+        tray_embedder.add_icon(20, "foo".to_owned(), None, false, XEmbedInfo::default());
+        tray_embedder.add_icon(30, "bar".to_owned(), None, false, XEmbedInfo::default());
+
+        let now = Instant::now();
+        tray_embedder.change_title(20, "baz".to_owned());
+        let flashed_at = tray_embedder.tray_items[0].flashed_at.unwrap();
+        assert!(flashed_at >= now);
+        assert_eq!(tray_embedder.tray_items[1].flashed_at, None);
+
+        tray_embedder.should_redraw = false;
+        tray_embedder.animate(flashed_at + Duration::from_millis(250));
+        assert_eq!(tray_embedder.tray_items[0].flash_level, 0.75);
+        assert_eq!(tray_embedder.tray_items[1].flash_level, 0.0);
+        assert!(tray_embedder.should_redraw);
+
+        tray_embedder.should_redraw = false;
+        tray_embedder.animate(flashed_at + Duration::from_millis(1000));
+        assert_eq!(tray_embedder.tray_items[0].flashed_at, None);
+        assert_eq!(tray_embedder.tray_items[0].flash_level, 0.0);
+        assert!(tray_embedder.should_redraw);
+
+        tray_embedder.should_redraw = false;
+        assert!(!tray_embedder.animate(flashed_at + Duration::from_millis(1500)));
+        assert!(!tray_embedder.should_redraw);
+    }
+
+    #[test]
+    fn test_extract_count() {
+        let count_pattern = Regex::new(r"\((\d+)\)$").unwrap();
+        assert_eq!(extract_count(&count_pattern, "Telegram (3)"), Some(3));
+        assert_eq!(extract_count(&count_pattern, "Telegram"), None);
+        assert_eq!(extract_count(&count_pattern, "Telegram (3) foo"), None);
+
+        let count_pattern = Regex::new(r"\d+").unwrap();
+        assert_eq!(extract_count(&count_pattern, "12 unread"), Some(12));
+        assert_eq!(
+            extract_count(&count_pattern, "99999999999 unread"),
+            None,
+            "overflowed counts are ignored"
+        );
+
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        tray_embedder.set_count_pattern(Some(Regex::new(r"\((\d+)\)$").unwrap()));
+        tray_embedder.add_icon(20, "Foo (1)".to_owned(), None, false, XEmbedInfo::default());
+        assert_eq!(tray_embedder.tray_items[0].count, Some(1));
+        tray_embedder.change_title(20, "Foo (5)".to_owned());
+        assert_eq!(tray_embedder.tray_items[0].count, Some(5));
+        tray_embedder.change_title(20, "Foo".to_owned());
+        assert_eq!(tray_embedder.tray_items[0].count, None);
+    }
+
+    #[test]
+    fn test_click_debounce() {
+        let connection = Rc::new(FakeConnection::new());
+        let atoms = Rc::new(Atoms::new(&*connection).unwrap().reply().unwrap());
+        let mut tray_embedder = TrayEmbedder::new(
+            connection.clone(),
+            0,
+            atoms,
+            &WindowConfig::default(),
+            GroupsConfig::default(),
+            ClickConfig {
+                debounce_ms: 60_000,
+                ..ClickConfig::default()
+            },
+            PhysicalSize {
+                width: 160,
+                height: 100,
+            },
+        )
+        .unwrap();
+        tray_embedder.add_icon(20, String::new(), None, false, XEmbedInfo::default());
+        tray_embedder.add_icon(30, String::new(), None, false, XEmbedInfo::default());
+        connection.take_requests();
+
+        let mut click = |index: usize| {
+            tray_embedder.select_item(index);
+            tray_embedder
+                .click_selected_item(MouseButton::Left, Modifiers::NONE)
+                .unwrap();
+            connection
+                .take_requests()
+                .into_iter()
+                .filter_map(|request| match request {
+                    protocol::Request::SendEvent(request) => Some(request.destination),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(click(0), [20, 20]);
+        assert_eq!(click(0), []);
+        assert_eq!(click(1), [30, 30]);
+    }
+
+    #[test]
+    fn test_activate_selected_application() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let connection = tray_embedder.connection.clone();
+        tray_embedder.add_icon(
+            20,
+            String::new(),
+            Some("Foo".to_owned()),
+            false,
+            XEmbedInfo::default(),
+        );
+        tray_embedder.select_item(0);
+        connection.set_property32(
+            ROOT_WINDOW,
+            connection.atom("_NET_CLIENT_LIST"),
+            xproto::AtomEnum::WINDOW,
+            &[29, 30, 31],
+        );
+        for (window, class) in [(30, b"bar\0Bar\0"), (31, b"foo\0Foo\0")] {
+            connection.set_property8(
+                window,
+                xproto::AtomEnum::WM_CLASS,
+                xproto::AtomEnum::STRING,
+                class,
+            );
+        }
+        // The client destroyed after the list is read is skipped.
+        connection.destroy_window(29);
+        connection.take_requests();
+
+        tray_embedder.activate_selected_application().unwrap();
+
+        let messages: Vec<_> = connection
+            .take_requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                protocol::Request::SendEvent(request) if request.destination == ROOT_WINDOW => {
+                    xproto::ClientMessageEvent::try_parse(request.event.as_slice())
+                        .ok()
+                        .map(|(event, _)| (event.window, event.type_))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(messages, [(31, connection.atom("_NET_ACTIVE_WINDOW"))]);
+    }
+
+    #[test]
+    fn test_decorations() {
+        let connection = Rc::new(FakeConnection::new());
+        let atoms = Rc::new(Atoms::new(&*connection).unwrap().reply().unwrap());
+        let motif_wm_hints = |config: &WindowConfig| {
+            let tray_embedder = TrayEmbedder::new(
+                connection.clone(),
+                0,
+                atoms.clone(),
+                config,
+                GroupsConfig::default(),
+                ClickConfig::default(),
+                PhysicalSize {
+                    width: 160,
+                    height: 100,
+                },
+            )
+            .unwrap();
+            connection
+                .get_property(
+                    false,
+                    tray_embedder.window(),
+                    atoms._MOTIF_WM_HINTS,
+                    atoms._MOTIF_WM_HINTS,
+                    0,
+                    5,
+                )
+                .unwrap()
+                .reply()
+                .unwrap()
+                .value32()
+                .map(|values| values.collect::<Vec<_>>())
+        };
+
+        assert_eq!(motif_wm_hints(&WindowConfig::default()), None);
+        assert_eq!(
+            motif_wm_hints(&WindowConfig {
+                decorations: false,
+                ..WindowConfig::default()
+            }),
+            Some(vec![MWM_HINTS_DECORATIONS, 0, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn test_image_to_argb() {
+        let size = PhysicalSize {
+            width: 2,
+            height: 1,
+        };
+        let data = [0x11, 0x22, 0x33, 0x00, 0x44, 0x55, 0x66, 0x80];
+        assert_eq!(
+            image_to_argb(&data, 24, xproto::ImageOrder::LSB_FIRST, size),
+            Some(vec![0xff332211, 0xff665544])
+        );
+        assert_eq!(
+            image_to_argb(&data, 32, xproto::ImageOrder::LSB_FIRST, size),
+            Some(vec![0x00332211, 0x80665544])
+        );
+        assert_eq!(
+            image_to_argb(&data, 32, xproto::ImageOrder::MSB_FIRST, size),
+            Some(vec![0x11223300, 0x44556680])
+        );
+        assert_eq!(
+            image_to_argb(&data, 16, xproto::ImageOrder::LSB_FIRST, size),
+            None
+        );
+        assert_eq!(
+            image_to_argb(&data[..4], 24, xproto::ImageOrder::LSB_FIRST, size),
+            None
+        );
+    }
+
+    #[test]
+    fn test_screenshot_file_class() {
+        assert_eq!(screenshot_file_class(Some("Foo-Bar_1")), "Foo-Bar_1");
+        assert_eq!(screenshot_file_class(Some("../../.bashrc")), "bashrc");
+        assert_eq!(screenshot_file_class(Some("/..")), "icon");
+        assert_eq!(screenshot_file_class(None), "icon");
+    }
+
+    #[test]
+    fn test_set_command() {
+        let tray_embedder = create_tray_embedder(GroupsConfig::default());
+        tray_embedder
+            .set_command(&["geektray".into(), "-c".into(), "foo bar.toml".into()])
+            .unwrap();
+        let reply = tray_embedder
+            .connection
+            .get_property(
+                false,
+                tray_embedder.window(),
+                xproto::AtomEnum::WM_COMMAND,
+                xproto::AtomEnum::STRING,
+                0,
+                u32::MAX,
+            )
+            .unwrap()
+            .reply()
+            .unwrap();
+        assert_eq!(reply.value, b"geektray\0-c\0foo bar.toml\0");
+    }
+
+    #[test]
+    fn test_auto_width() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let ui_config = UIConfig::default();
+        tray_embedder.auto_width = Some((100.0, 400.0));
+        assert!(tray_embedder.layout(&ui_config).unwrap().width >= 100);
+
+        tray_embedder.add_icon(20, "a".to_owned(), None, false, XEmbedInfo::default());
+        assert_eq!(tray_embedder.layout(&ui_config).unwrap().width, 100);
+
+        tray_embedder.change_title(20, "a".repeat(20));
+        assert!(tray_embedder.should_layout());
+        let width = tray_embedder.layout(&ui_config).unwrap().width;
+        assert!(width > 100 && width < 400);
+
+        tray_embedder.change_title(20, "a".repeat(200));
+        assert_eq!(tray_embedder.layout(&ui_config).unwrap().width, 400);
+    }
+
+    #[test]
+    fn test_visible_items() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let ui_config = UIConfig::default();
+        let item_height = ui_config.item_height();
+        let rows_height = |count: usize| {
+            (ui_config.window_padding * 2.0
+                + item_height * count as f64
+                + ui_config.item_gap * (count - 1) as f64) as u32
+        };
+        tray_embedder.min_visible_items = 2;
+        tray_embedder.max_visible_items = 3;
+        assert_eq!(
+            tray_embedder.layout(&ui_config).unwrap().height,
+            rows_height(2)
+        );
+
+        for icon in 0..5 {
+            tray_embedder.add_icon(icon, String::new(), None, false, XEmbedInfo::default());
+        }
+        assert_eq!(
+            tray_embedder.layout(&ui_config).unwrap().height,
+            rows_height(3)
+        );
+        assert!(tray_embedder.is_visible(tray_embedder.tray_items[0].bounds));
+        assert!(!tray_embedder.is_visible(tray_embedder.tray_items[4].bounds));
+
+        tray_embedder.select_item(4);
+        assert!(tray_embedder.should_layout());
+        tray_embedder.layout(&ui_config).unwrap();
+        assert!(!tray_embedder.is_visible(tray_embedder.tray_items[1].bounds));
+        assert!(tray_embedder.is_visible(tray_embedder.tray_items[2].bounds));
+        assert!(tray_embedder.is_visible(tray_embedder.tray_items[4].bounds));
+    }
+
+    #[test]
+    fn test_redraw_rate() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        tray_embedder.set_redraw_rate(
+            1.0,
+            vec![RedrawRateConfig {
+                class: "Fast".to_owned(),
+                rate: 0.0,
+            }],
+        );
+        tray_embedder.add_icon(20, String::new(), None, false, XEmbedInfo::default());
+        tray_embedder.add_icon(
+            21,
+            String::new(),
+            Some("Fast".to_owned()),
+            false,
+            XEmbedInfo::default(),
+        );
+
+        tray_embedder.should_redraw = false;
+        tray_embedder.change_title(20, "foo".to_owned());
+        assert!(tray_embedder.should_redraw);
+        assert_eq!(tray_embedder.take_deferred_redraw(), None);
+
+        tray_embedder.should_redraw = false;
+        tray_embedder.change_title(20, "bar".to_owned());
+        assert!(!tray_embedder.should_redraw);
+        assert!(tray_embedder.take_deferred_redraw().is_some());
+        tray_embedder.change_urgency(20, true);
+        assert!(!tray_embedder.should_redraw);
+        assert_eq!(tray_embedder.take_deferred_redraw(), None);
+
+        // The icon without the limit is always redrawn.
+        tray_embedder.change_title(21, "baz".to_owned());
+        assert!(tray_embedder.should_redraw);
+
+        tray_embedder.should_redraw = false;
+        tray_embedder.redraw_deferred();
+        assert!(tray_embedder.should_redraw);
+    }
+
+    #[test]
+    fn test_footer_text() {
+        assert_eq!(footer_text(Some(2), 7), "3/7");
+        assert_eq!(footer_text(None, 7), "-/7");
+        assert_eq!(footer_text(None, 0), "-/0");
+    }
+
+    #[test]
+    fn test_layout_footer() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let mut ui_config = UIConfig::default();
+        tray_embedder.add_icon(20, String::new(), None, false, XEmbedInfo::default());
+        let size = tray_embedder.layout(&ui_config).unwrap();
+        assert_eq!(tray_embedder.footer_bounds, None);
+
+        ui_config.show_footer = true;
+        let footer_size = tray_embedder.layout(&ui_config).unwrap();
+        let footer_bounds = tray_embedder.footer_bounds.unwrap();
+        assert!(footer_bounds.y > tray_embedder.tray_items[0].bounds.y);
+        assert_eq!(
+            footer_size.height as f64,
+            size.height as f64 + ui_config.item_gap + footer_bounds.height
+        );
+    }
+
+    #[test]
+    fn test_toggle_help() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let connection = tray_embedder.connection.clone();
+        let ui_config = UIConfig::default();
+        tray_embedder.add_icon(20, String::new(), None, false, XEmbedInfo::default());
+        tray_embedder.tray_items[0].frame = 30;
+        tray_embedder.tray_items[0].is_mapped = true;
+        let items_size = tray_embedder.layout(&ui_config).unwrap();
+        connection.take_requests();
+
+        let key_bindings = KeyBindingProfile::Default.key_bindings();
+        tray_embedder.toggle_help(&key_bindings, &[]).unwrap();
+        assert!(connection
+            .take_requests()
+            .iter()
+            .any(|request| matches!(request, protocol::Request::UnmapWindow(request) if request.window == 30)));
+        assert!(tray_embedder.layout(&ui_config).unwrap().height > items_size.height);
+
+        tray_embedder.hide().unwrap();
+        assert!(tray_embedder.overlay.is_none());
+        assert_eq!(tray_embedder.layout(&ui_config).unwrap(), items_size);
+    }
+
+    #[test]
+    fn test_message_history() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        tray_embedder.add_icon(20, "Foo".to_owned(), None, false, XEmbedInfo::default());
+        tray_embedder.set_message_history_size(2);
+        tray_embedder.record_message(20, "first");
+        tray_embedder.record_message(20, "second");
+        tray_embedder.record_message(30, "third");
+        assert_eq!(
+            tray_embedder
+                .messages
+                .iter()
+                .map(|message| (message.source.as_str(), message.text.as_str()))
+                .collect::<Vec<_>>(),
+            [("Foo", "second"), ("0x1e", "third")]
+        );
+
+        tray_embedder.toggle_message_history().unwrap();
+        assert!(matches!(
+            tray_embedder.overlay,
+            Some(Overlay::MessageHistory(_))
+        ));
+        tray_embedder.select_next_item();
+        assert_eq!(tray_embedder.selected_index, None);
+
+        tray_embedder.toggle_message_history().unwrap();
+        assert!(tray_embedder.overlay.is_none());
+    }
+
+    #[test]
+    fn test_create_frame() {
+        let connection = FakeConnection::new();
+        let ui_config = UIConfig::default();
+        let frame = create_frame(&connection, 10, 20, &ui_config).unwrap();
+
+        let requests = connection.take_requests();
+        assert!(matches!(
+            &requests[0],
+            protocol::Request::CreateWindow(request)
+                if request.wid == frame
+                    && request.parent == 10
+                    && request.width == ui_config.icon_size as u16
+        ));
+        assert!(matches!(
+            &requests[1],
+            protocol::Request::ReparentWindow(request)
+                if request.window == 20 && request.parent == frame
+        ));
+        assert!(matches!(
+            &requests[2],
+            protocol::Request::MapWindow(request) if request.window == 20
+        ));
+    }
+
+    #[test]
+    fn test_process_name() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let connection = tray_embedder.connection.clone();
+        tray_embedder.add_icon(20, String::new(), None, false, XEmbedInfo::default());
+        tray_embedder.select_item(0);
+        connection.set_property32(
+            20,
+            connection.atom("_NET_WM_PID"),
+            xproto::AtomEnum::CARDINAL,
+            &[process::id()],
+        );
+
+        tray_embedder.fetch_process(20).unwrap();
+        assert_eq!(
+            tray_embedder.tray_items[0].process_name,
+            process_name(process::id())
+        );
+        assert!(tray_embedder.tray_items[0].process_name.is_some());
+        assert_eq!(
+            tray_embedder.icon_process(20),
+            Some((None, Some(process::id())))
+        );
+
+        // This process is never killed.
+        assert_eq!(tray_embedder.kill_selected_item_process().unwrap(), None);
+    }
+
+    #[test]
+    fn test_send_client_message() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let connection = tray_embedder.connection.clone();
+        tray_embedder.add_icon(20, String::new(), None, false, XEmbedInfo::default());
+        connection.take_requests();
+
+        tray_embedder
+            .send_client_message(100, [1, 2, 0, 0, 0], MessageDestination::Icon)
+            .unwrap();
+        assert!(connection.take_requests().is_empty());
+
+        tray_embedder.select_item(0);
+        for destination in [MessageDestination::Icon, MessageDestination::Root] {
+            tray_embedder
+                .send_client_message(100, [1, 2, 0, 0, 0], destination)
+                .unwrap();
+        }
+
+        let messages: Vec<_> = connection
+            .take_requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                protocol::Request::SendEvent(request) => {
+                    xproto::ClientMessageEvent::try_parse(request.event.as_slice())
+                        .ok()
+                        .map(|(event, _)| (request.destination, event.window, event.type_))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(messages, [(20, 20, 100), (ROOT_WINDOW, 20, 100)]);
+    }
+
+    #[test]
+    fn test_ping_icons() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let connection = tray_embedder.connection.clone();
+        for icon in [100, 101] {
+            tray_embedder.add_icon(icon, "Foo".to_owned(), None, false, XEmbedInfo::default());
+        }
+        tray_embedder.change_ping_support(100, true);
+        connection.take_requests();
+
+        let pinged_windows = |requests: Vec<protocol::Request>| -> Vec<_> {
+            requests
+                .into_iter()
+                .filter_map(|request| match request {
+                    protocol::Request::SendEvent(request) => Some(request.destination),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // Only the icon supporting _NET_WM_PING is pinged.
+        tray_embedder.ping_icons().unwrap();
+        assert_eq!(pinged_windows(connection.take_requests()), [100]);
+
+        // It is not pinged again until it replies.
+        tray_embedder.ping_icons().unwrap();
+        assert!(pinged_windows(connection.take_requests()).is_empty());
+        assert!(tray_embedder.tray_items[0].is_unresponsive);
+        assert_eq!(
+            tray_embedder.tray_items[0].label(&ui_config()),
+            "Foo (not responding)"
+        );
+        assert!(!tray_embedder.tray_items[1].is_unresponsive);
+
+        tray_embedder.handle_ping_reply(100);
+        assert!(!tray_embedder.tray_items[0].is_unresponsive);
+        tray_embedder.ping_icons().unwrap();
+        assert_eq!(pinged_windows(connection.take_requests()), [100]);
+    }
+
+    #[test]
+    fn test_close_on_outside_click() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let connection = tray_embedder.connection.clone();
+        let window = tray_embedder.window;
+        let mut control_flow = ControlFlow::Continue(());
+
+        // It is ignored unless the window is override-redirect.
+        tray_embedder.set_close_on_outside_click(true);
+        tray_embedder.show().unwrap();
+        assert_eq!(tray_embedder.outside_click_window, None);
+        connection.take_requests();
+
+        tray_embedder.override_redirect = true;
+        tray_embedder.set_close_on_outside_click(true);
+        tray_embedder.show().unwrap();
+        let outside_click_window = tray_embedder.outside_click_window.unwrap();
+        let requests = connection.take_requests();
+        let map_index = requests
+            .iter()
+            .position(|request| matches!(request, protocol::Request::MapWindow(request) if request.window == outside_click_window))
+            .unwrap();
+        let raise_index = requests
+            .iter()
+            .position(|request| matches!(request, protocol::Request::ConfigureWindow(request) if request.window == window))
+            .unwrap();
+        assert!(map_index < raise_index);
+
+        tray_embedder
+            .handle_x11_event(
+                &protocol::Event::ButtonPress(xproto::ButtonPressEvent {
+                    event: outside_click_window,
+                    ..Default::default()
+                }),
+                &mut control_flow,
+            )
+            .unwrap();
+        let unmapped_windows: Vec<_> = connection
+            .take_requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                protocol::Request::UnmapWindow(request) => Some(request.window),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(unmapped_windows, [outside_click_window, window]);
+    }
+
+    #[test]
+    fn test_restore_states() {
+        let mut tray_embedder = create_tray_embedder(GroupsConfig::default());
+        let connection = tray_embedder.connection.clone();
+        let atoms = tray_embedder.atoms.clone();
+        let window = tray_embedder.window;
+        let mut control_flow = ControlFlow::Continue(());
+
+        tray_embedder
+            .handle_x11_event(
+                &protocol::Event::MapNotify(xproto::MapNotifyEvent {
+                    event: window,
+                    window,
+                    ..Default::default()
+                }),
+                &mut control_flow,
+            )
+            .unwrap();
+
+        connection.set_property32(
+            window,
+            atoms._NET_WM_STATE,
+            xproto::AtomEnum::ATOM,
+            &[atoms._NET_WM_STATE_ABOVE],
+        );
+        connection.take_requests();
+
+        tray_embedder
+            .handle_x11_event(
+                &protocol::Event::PropertyNotify(xproto::PropertyNotifyEvent {
+                    window,
+                    atom: atoms._NET_WM_STATE,
+                    ..Default::default()
+                }),
+                &mut control_flow,
+            )
+            .unwrap();
+
+        let requested_states: Vec<_> = connection
+            .take_requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                protocol::Request::SendEvent(request) => {
+                    xproto::ClientMessageEvent::try_parse(request.event.as_slice())
+                        .ok()
+                        .map(|(event, _)| event.data.as_data32())
+                }
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            requested_states,
+            [
+                [NET_WM_STATE_ADD, atoms._NET_WM_STATE_STAYS_ON_TOP, 0, 1, 0],
+                [NET_WM_STATE_ADD, atoms._NET_WM_STATE_STICKY, 0, 1, 0],
+            ]
+        );
+    }
 }
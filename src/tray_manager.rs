@@ -1,19 +1,33 @@
 use anyhow::Context as _;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
 use std::rc::Rc;
 use std::str;
+use std::time::{Duration, Instant};
 use x11rb::connection::Connection;
+use x11rb::cookie::Cookie;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::properties::WmHints;
 use x11rb::protocol;
 use x11rb::protocol::xproto::{self, ConnectionExt as _};
 use x11rb::wrapper::ConnectionExt as _;
 
 use crate::atoms::Atoms;
 use crate::color::Color;
-use crate::xembed::{get_xembed_info, XEmbedInfo, XEmbedMessage};
+use crate::config::MessageOverflow;
+use crate::pending_reply::PendingReply;
+use crate::protocol_trace;
+use crate::text_property::{decode_text_property, sanitize_title};
+use crate::xembed::{parse_xembed_info, request_xembed_info, XEmbedInfo, XEmbedMessage};
 
-const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
-const SYSTEM_TRAY_BEGIN_MESSAGE: u32 = 1;
-const SYSTEM_TRAY_CANCEL_MESSAGE: u32 = 2;
+pub const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+pub const SYSTEM_TRAY_BEGIN_MESSAGE: u32 = 1;
+pub const SYSTEM_TRAY_CANCEL_MESSAGE: u32 = 2;
 
+const DEFAULT_MESSAGE_CAPACITY: usize = 16;
+
+/// The system tray protocol: owns the `_NET_SYSTEM_TRAY_Sn` selection and embeds the icons
+/// that request docking via XEmbed.
 #[derive(Debug)]
 pub struct TrayManager<C: Connection> {
     connection: Rc<C>,
@@ -22,7 +36,25 @@ pub struct TrayManager<C: Connection> {
     atoms: Rc<Atoms>,
     selection_status: SelectionStatus,
     icons: Vec<xproto::Window>,
+    // The icons that IconAdded has been emitted for, which are not added again when they come
+    // back to the embedder after the client has reparented them elsewhere in it.
+    added_icons: Vec<xproto::Window>,
+    // The windows inside the embedder that the icons may be reparented into: the frames of the
+    // embedder, and the windows that clients (e.g. Wine) create in them to wrap the icons again.
+    // They are learned from the substructure events of the embedder and the frames, so that no
+    // round trip is needed to tell whether an icon has left the embedder.
+    embedder_windows: HashSet<xproto::Window>,
+    // The messages being received, and the received ones with their deadlines in the order of
+    // arrival.
     balloon_messages: Vec<BalloonMessage>,
+    received_messages: VecDeque<(BalloonMessage, Option<Instant>)>,
+    message_capacity: usize,
+    message_overflow: MessageOverflow,
+    pending_requests: VecDeque<PendingRequest>,
+    // The events that happened outside of the event that is being translated.
+    pending_events: Vec<TrayEvent>,
+    redock_nudge: bool,
+    orphan_icon_classes: HashSet<String>,
 }
 
 impl<C: Connection> TrayManager<C> {
@@ -37,10 +69,75 @@ impl<C: Connection> TrayManager<C> {
             system_tray_selection_atom,
             selection_status: SelectionStatus::Unmanaged,
             icons: Vec::new(),
+            added_icons: Vec::new(),
+            embedder_windows: HashSet::new(),
             balloon_messages: Vec::new(),
+            received_messages: VecDeque::new(),
+            message_capacity: DEFAULT_MESSAGE_CAPACITY,
+            message_overflow: MessageOverflow::default(),
+            pending_requests: VecDeque::new(),
+            pending_events: Vec::new(),
+            redock_nudge: false,
+            orphan_icon_classes: HashSet::new(),
         })
     }
 
+    /// Maps and unmaps each icon again before it is returned to the root window, so that the
+    /// applications notice the undocking and dock again when a new tray appears. It is done while
+    /// the icon is still in the embedder, since the window manager would frame the icon mapped on
+    /// the root window.
+    pub fn set_redock_nudge(&mut self, redock_nudge: bool) {
+        self.redock_nudge = redock_nudge;
+    }
+
+    /// Sets the classes of WM_CLASS that icons are known to have, e.g. from the dock history. The
+    /// unmapped windows of these classes left on the root window with `_XEMBED_INFO` are docked
+    /// when the tray selection is acquired, since some applications never dock again after a tray
+    /// crashes.
+    pub fn set_orphan_icon_classes(&mut self, classes: HashSet<String>) {
+        self.orphan_icon_classes = classes;
+    }
+
+    /// Sets the number of the received balloon messages that are kept until they expire, and
+    /// which message is dropped when a message is received beyond it.
+    pub fn set_message_queue(&mut self, capacity: usize, overflow: MessageOverflow) {
+        self.message_capacity = capacity;
+        self.message_overflow = overflow;
+    }
+
+    /// Returns the balloon messages that have been received and neither expired nor cancelled.
+    pub fn messages(&self) -> impl Iterator<Item = &BalloonMessage> {
+        self.received_messages.iter().map(|(message, _)| message)
+    }
+
+    /// Removes the balloon messages whose timeout has elapsed, and returns
+    /// [`TrayEvent::MessageExpired`] for each of them. This should be called after the timeout
+    /// of each message received.
+    pub fn expire_messages(&mut self, now: Instant) -> Vec<TrayEvent> {
+        let mut events = Vec::new();
+        self.received_messages.retain(|(message, deadline)| {
+            if deadline.is_some_and(|deadline| deadline <= now) {
+                log::info!(
+                    "expire balloon message (icon: {}, id: {})",
+                    message.icon(),
+                    message.id()
+                );
+                events.push(TrayEvent::MessageExpired(message.icon(), message.id()));
+                false
+            } else {
+                true
+            }
+        });
+        events
+    }
+
+    /// Returns whether this manager owns the tray selection.
+    pub fn is_managed(&self) -> bool {
+        matches!(self.selection_status, SelectionStatus::Managed { .. })
+    }
+
+    /// Creates a manager window and takes over the tray selection. Icons are reparented into
+    /// `embedder` once they are docked.
     pub fn acquire_tray_selection(
         &mut self,
         embedder: xproto::Window,
@@ -61,28 +158,77 @@ impl<C: Connection> TrayManager<C> {
         self.update_selection_status(new_manager, embedder)
     }
 
-    pub fn release_tray_selection(&mut self) -> anyhow::Result<()> {
-        match self.selection_status {
-            SelectionStatus::Managed { manager, .. } => {
-                log::info!("release tray selection (manager: {})", manager);
-
+    /// Returns another tray that owns the tray selection, or that this manager is waiting for to
+    /// exit after taking it over.
+    pub fn current_owner(&self) -> anyhow::Result<Option<SelectionOwner>> {
+        let owner = match self.selection_status {
+            SelectionStatus::Pending { old_manager, .. } => old_manager,
+            SelectionStatus::Managed { .. } => return Ok(None),
+            SelectionStatus::Unmanaged => {
                 self.connection
-                    .set_selection_owner(
-                        x11rb::NONE,
-                        self.system_tray_selection_atom,
-                        x11rb::CURRENT_TIME,
-                    )?
-                    .check()
-                    .context("reset tray selection")?;
+                    .get_selection_owner(self.system_tray_selection_atom)?
+                    .reply()
+                    .context("get selection owner")?
+                    .owner
+            }
+        };
+        if owner == x11rb::NONE {
+            return Ok(None);
+        }
 
-                self.connection
-                    .destroy_window(manager)?
-                    .check()
-                    .context("destory manager window")?;
+        // The error is ignored because the owner may have gone away in the meantime.
+        let class = self
+            .connection
+            .get_property(
+                false,
+                owner,
+                xproto::AtomEnum::WM_CLASS,
+                xproto::AtomEnum::STRING,
+                0,
+                256 / 4,
+            )?
+            .reply()
+            .ok()
+            .and_then(|reply| parse_wm_class(&reply));
 
-                self.clear_embeddings()?;
-            }
-            _ => {}
+        Ok(Some(SelectionOwner {
+            window: owner,
+            class,
+        }))
+    }
+
+    /// Returns the icon to the root window without the redock nudge, and forgets it as if it had
+    /// never been docked.
+    pub fn release_icon(&mut self, icon: xproto::Window) -> anyhow::Result<()> {
+        if self.quit_dock(icon) {
+            quit_embedding(&*self.connection, self.screen_num, icon, false)?;
+            self.connection
+                .flush()
+                .context("flush after release icon")?;
+        }
+        Ok(())
+    }
+
+    /// Gives up the tray selection and returns the embedded icons to the root window.
+    pub fn release_tray_selection(&mut self) -> anyhow::Result<()> {
+        if let SelectionStatus::Managed { manager, .. } = self.selection_status {
+            log::info!("release tray selection (manager: {})", manager);
+
+            self.connection
+                .set_selection_owner(
+                    x11rb::NONE,
+                    self.system_tray_selection_atom,
+                    x11rb::CURRENT_TIME,
+                )?
+                .check()
+                .context("reset tray selection")?;
+
+            self.connection
+                .destroy_window(manager)?
+                .check()
+                .context("destory manager window")?;
+
+            self.clear_embeddings()?;
         }
 
         self.selection_status = SelectionStatus::Unmanaged;
@@ -90,12 +236,40 @@ impl<C: Connection> TrayManager<C> {
         Ok(())
     }
 
+    /// Updates _NET_SYSTEM_TRAY_ORIENTATION on the manager window. The icons watching it are
+    /// notified by PropertyNotify.
+    pub fn set_orientation(&self, orientation: SystemTrayOrientation) -> anyhow::Result<()> {
+        let manager = match self.selection_status {
+            SelectionStatus::Managed { manager, .. } => manager,
+            SelectionStatus::Pending { new_manager, .. } => new_manager,
+            SelectionStatus::Unmanaged => return Ok(()),
+        };
+
+        log::info!(
+            "change tray orientation (manager: {}, orientation: {:?})",
+            manager,
+            orientation
+        );
+
+        set_orientation_property(&*self.connection, &self.atoms, manager, orientation)?;
+        self.connection
+            .flush()
+            .context("flush after change tray orientation")?;
+
+        Ok(())
+    }
+
+    /// Handles an X11 event. Every event from the connection should be passed here.
     pub fn translate_event(
         &mut self,
         event: &protocol::Event,
     ) -> anyhow::Result<Option<TrayEvent>> {
         use x11rb::protocol::Event::*;
 
+        if let SelectionStatus::Managed { embedder, .. } = self.selection_status {
+            self.track_embedder_windows(event, embedder);
+        }
+
         let event = match (event, self.selection_status) {
             (ClientMessage(event), SelectionStatus::Managed { embedder, .. })
                 if event.type_ == self.atoms._NET_SYSTEM_TRAY_OPCODE =>
@@ -113,7 +287,7 @@ impl<C: Connection> TrayManager<C> {
                     self.begin_message(balloon_message);
                 } else if opcode == SYSTEM_TRAY_CANCEL_MESSAGE {
                     let [_, _, id, ..] = event.data.as_data32();
-                    self.cancel_message(id);
+                    return Ok(self.cancel_message(event.window, id));
                 }
                 None
             }
@@ -127,7 +301,8 @@ impl<C: Connection> TrayManager<C> {
                         balloon_message.timeout_millis(),
                         balloon_message.as_str()
                     );
-                    Some(TrayEvent::MessageReceived(balloon_message))
+                    self.enqueue_message(balloon_message, Instant::now())
+                        .map(TrayEvent::MessageReceived)
                 } else {
                     None
                 }
@@ -142,33 +317,111 @@ impl<C: Connection> TrayManager<C> {
                 if event.atom == self.atoms._XEMBED_INFO && self.icons.contains(&event.window) =>
             {
                 log::info!("change xembed info (icon: {})", event.window);
-                get_xembed_info(&*self.connection, &self.atoms, event.window)?
-                    .map(|xembed_info| TrayEvent::XEmbedInfoChanged(event.window, xembed_info))
+                let xembed_info = PendingReply::new(request_xembed_info(
+                    &*self.connection,
+                    &self.atoms,
+                    event.window,
+                )?);
+                self.pending_requests
+                    .push_back(PendingRequest::XEmbedInfoChanged {
+                        icon: event.window,
+                        xembed_info,
+                    });
+                None
             }
             (PropertyNotify(event), SelectionStatus::Managed { .. })
-                if event.atom == self.atoms._NET_WM_NAME && self.icons.contains(&event.window) =>
+                if (event.atom == self.atoms._NET_WM_NAME
+                    || event.atom == u32::from(xproto::AtomEnum::WM_NAME))
+                    && self.icons.contains(&event.window) =>
             {
                 log::info!("change window title (icon: {})", event.window);
-                let title = get_window_title(&*self.connection, &self.atoms, event.window)?
-                    .unwrap_or_default();
-                Some(TrayEvent::TitleChanged(event.window, title))
+                let title = TitleRequest::new(&*self.connection, &self.atoms, event.window)?;
+                self.pending_requests
+                    .push_back(PendingRequest::TitleChanged {
+                        icon: event.window,
+                        title,
+                    });
+                None
+            }
+            (PropertyNotify(event), SelectionStatus::Managed { .. })
+                if (event.atom == u32::from(xproto::AtomEnum::WM_HINTS)
+                    || event.atom == self.atoms._NET_WM_STATE)
+                    && self.icons.contains(&event.window) =>
+            {
+                log::info!("change urgency (icon: {})", event.window);
+                let urgency = UrgencyRequest::new(&*self.connection, &self.atoms, event.window)?;
+                self.pending_requests
+                    .push_back(PendingRequest::UrgencyChanged {
+                        icon: event.window,
+                        urgency,
+                    });
+                None
+            }
+            (PropertyNotify(event), SelectionStatus::Managed { .. })
+                if event.atom == self.atoms.WM_PROTOCOLS && self.icons.contains(&event.window) =>
+            {
+                let protocols = request_protocols(&*self.connection, &self.atoms, event.window)?;
+                self.pending_requests
+                    .push_back(PendingRequest::PingSupportChanged {
+                        icon: event.window,
+                        protocols,
+                    });
+                None
             }
             (ReparentNotify(event), SelectionStatus::Managed { embedder, .. })
                 if event.event == event.window =>
             {
                 if event.parent == embedder {
-                    if self.icons.contains(&event.window) {
-                        let title = get_window_title(&*self.connection, &self.atoms, event.window)?
-                            .unwrap_or_default();
-                        get_xembed_info(&*self.connection, &self.atoms, event.window)?.map(
-                            |xembed_info| TrayEvent::IconAdded(event.window, title, xembed_info),
-                        )
-                    } else {
-                        None
+                    if self.icons.contains(&event.window)
+                        && !self.added_icons.contains(&event.window)
+                    {
+                        let title =
+                            TitleRequest::new(&*self.connection, &self.atoms, event.window)?;
+                        let class =
+                            PendingReply::new(request_wm_class(&*self.connection, event.window)?);
+                        let client_machine = PendingReply::new(self.connection.get_property(
+                            false,
+                            event.window,
+                            xproto::AtomEnum::WM_CLIENT_MACHINE,
+                            xproto::AtomEnum::ANY,
+                            0,
+                            256 / 4,
+                        )?);
+                        let xembed_info = PendingReply::new(request_xembed_info(
+                            &*self.connection,
+                            &self.atoms,
+                            event.window,
+                        )?);
+                        let urgency =
+                            UrgencyRequest::new(&*self.connection, &self.atoms, event.window)?;
+                        self.pending_requests.push_back(PendingRequest::IconAdded {
+                            icon: event.window,
+                            title,
+                            class,
+                            client_machine,
+                            xembed_info,
+                        });
+                        self.pending_requests
+                            .push_back(PendingRequest::UrgencyChanged {
+                                icon: event.window,
+                                urgency,
+                            });
+                        let protocols =
+                            request_protocols(&*self.connection, &self.atoms, event.window)?;
+                        self.pending_requests
+                            .push_back(PendingRequest::PingSupportChanged {
+                                icon: event.window,
+                                protocols,
+                            });
                     }
+                    None
+                } else if self.embedder_windows.contains(&event.parent) {
+                    // The icon has been wrapped in a frame window by the embedder, or in other
+                    // windows inside it by the client.
+                    None
                 } else {
                     self.quit_dock(event.window)
-                        .then(|| TrayEvent::IconRemoved(event.window))
+                        .then_some(TrayEvent::IconRemoved(event.window))
                 }
             }
             (
@@ -189,18 +442,37 @@ impl<C: Connection> TrayManager<C> {
             }
             (DestroyNotify(event), SelectionStatus::Managed { .. }) => self
                 .quit_dock(event.window)
-                .then(|| TrayEvent::IconRemoved(event.window)),
+                .then_some(TrayEvent::IconRemoved(event.window)),
             _ => None,
         };
 
         Ok(event)
     }
 
+    /// Resolves the replies of the requests sent by `translate_event()`. This should be called
+    /// after each batch of events.
+    pub fn process_pending_requests(&mut self) -> anyhow::Result<Vec<TrayEvent>> {
+        let mut events = std::mem::take(&mut self.pending_events);
+
+        while let Some(request) = self.pending_requests.pop_front() {
+            match self.resolve_request(request) {
+                Ok(Some(event)) => events.push(event),
+                Ok(None) => {}
+                Err(ReplyError::X11Error(error)) => {
+                    log::warn!("the icon window has gone away: {:?}", error);
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        Ok(events)
+    }
+
     fn begin_message(&mut self, balloon_message: BalloonMessage) {
         log::info!(
             "begin balloon message (icon: {}, id: {})",
             balloon_message.icon(),
-            balloon_message.icon()
+            balloon_message.id()
         );
         self.balloon_messages.push(balloon_message);
     }
@@ -211,13 +483,13 @@ impl<C: Connection> TrayManager<C> {
         if self.icons.contains(&icon) {
             log::warn!("duplicated icon (icon: {})", icon);
         } else {
-            if let Some(xembed_info) = get_xembed_info(&*self.connection, &self.atoms, icon)? {
-                begin_embedding(&*self.connection, &self.atoms, icon, embedder, xembed_info)?;
-                self.connection
-                    .flush()
-                    .context("flush after begin embedding")?;
-                self.icons.push(icon);
-            }
+            let xembed_info =
+                PendingReply::new(request_xembed_info(&*self.connection, &self.atoms, icon)?);
+            self.pending_requests.push_back(PendingRequest::Dock {
+                icon,
+                embedder,
+                xembed_info,
+            });
         }
 
         Ok(())
@@ -255,19 +527,90 @@ impl<C: Connection> TrayManager<C> {
         Ok(())
     }
 
-    fn cancel_message(&mut self, id: u32) {
-        log::info!("cancel balloon message (id: {})", id);
+    // Docks the icons left on the root window, e.g. by a tray that crashed, since some
+    // applications never dock again by themselves. Only the children of the root window whose
+    // class is known to be of icons are docked, so that the XEmbed clients of other embedders
+    // are left alone. They are found when the replies are resolved.
+    fn dock_orphan_icons(&mut self, embedder: xproto::Window) -> anyhow::Result<()> {
+        if self.orphan_icon_classes.is_empty() {
+            return Ok(());
+        }
+        let root = self.connection.setup().roots[self.screen_num].root;
+        let tree = PendingReply::new(self.connection.query_tree(root)?);
+        self.pending_requests
+            .push_back(PendingRequest::OrphanIcons { embedder, tree });
+        Ok(())
+    }
+
+    // The message may be cancelled while it is being received. Only the received one is notified.
+    fn cancel_message(&mut self, icon: xproto::Window, id: u32) -> Option<TrayEvent> {
+        log::info!("cancel balloon message (icon: {}, id: {})", icon, id);
         self.balloon_messages
-            .retain(|balloon_message| balloon_message.id() != id);
+            .retain(|balloon_message| balloon_message.icon() != icon || balloon_message.id() != id);
+        let i = self
+            .received_messages
+            .iter()
+            .position(|(message, _)| message.icon() == icon && message.id() == id)?;
+        self.received_messages.remove(i);
+        Some(TrayEvent::MessageCancelled(icon, id))
+    }
+
+    // Returns the message unless it is dropped by the overflow of the queue. The dropped older
+    // message is notified as expired.
+    fn enqueue_message(
+        &mut self,
+        balloon_message: BalloonMessage,
+        now: Instant,
+    ) -> Option<BalloonMessage> {
+        if self.message_capacity == 0 {
+            return None;
+        }
+        if self.received_messages.len() >= self.message_capacity {
+            match self.message_overflow {
+                MessageOverflow::DropOldest => {
+                    if let Some((message, _)) = self.received_messages.pop_front() {
+                        log::warn!(
+                            "drop the oldest balloon message (icon: {}, id: {})",
+                            message.icon(),
+                            message.id()
+                        );
+                        self.pending_events
+                            .push(TrayEvent::MessageExpired(message.icon(), message.id()));
+                    }
+                }
+                MessageOverflow::DropNewest => {
+                    log::warn!(
+                        "drop the balloon message since the queue is full (icon: {}, id: {})",
+                        balloon_message.icon(),
+                        balloon_message.id()
+                    );
+                    return None;
+                }
+            }
+        }
+        // The timeout of 0 means that the message never expires.
+        let deadline = (balloon_message.timeout_millis() > 0)
+            .then(|| now + Duration::from_millis(balloon_message.timeout_millis() as u64));
+        self.received_messages
+            .push_back((balloon_message.clone(), deadline));
+        Some(balloon_message)
     }
 
     fn clear_embeddings(&mut self) -> anyhow::Result<()> {
         log::info!("clear embeddings");
 
         self.balloon_messages.clear();
+        self.received_messages.clear();
+        self.pending_events.clear();
+
+        for request in self.pending_requests.drain(..) {
+            request.discard(&*self.connection);
+        }
 
+        self.added_icons.clear();
+        self.embedder_windows.clear();
         for icon in self.icons.drain(..) {
-            quit_embedding(&*self.connection, self.screen_num, icon)?;
+            quit_embedding(&*self.connection, self.screen_num, icon, self.redock_nudge)?;
         }
 
         self.connection
@@ -321,15 +664,7 @@ impl<C: Connection> TrayManager<C> {
             )?
             .check()?;
 
-        self.connection
-            .change_property32(
-                xproto::PropMode::REPLACE,
-                window,
-                self.atoms._NET_SYSTEM_TRAY_ORIENTATION,
-                xproto::AtomEnum::CARDINAL,
-                &[orientation.0],
-            )?
-            .check()?;
+        set_orientation_property(&*self.connection, &self.atoms, window, orientation)?;
 
         self.connection
             .change_property(
@@ -349,7 +684,10 @@ impl<C: Connection> TrayManager<C> {
     fn quit_dock(&mut self, icon: xproto::Window) -> bool {
         self.balloon_messages
             .retain(|balloon_message| balloon_message.icon() != icon);
+        self.received_messages
+            .retain(|(message, _)| message.icon() != icon);
 
+        self.added_icons.retain(|added_icon| *added_icon != icon);
         if let Some(i) = self.icons.iter().position(|i| *i == icon) {
             self.icons.remove(i);
             true
@@ -381,6 +719,166 @@ impl<C: Connection> TrayManager<C> {
         }
     }
 
+    fn resolve_request(
+        &mut self,
+        request: PendingRequest,
+    ) -> Result<Option<TrayEvent>, ReplyError> {
+        let event = match request {
+            PendingRequest::Dock {
+                icon,
+                embedder,
+                xembed_info,
+            } => {
+                let xembed_info = xembed_info.reply(&*self.connection)?;
+                if self.icons.contains(&icon) {
+                    log::warn!("duplicated icon (icon: {})", icon);
+                } else if let Some(xembed_info) = parse_xembed_info(&xembed_info) {
+                    self.embed_icon(icon, embedder, xembed_info)?;
+                }
+                None
+            }
+            PendingRequest::OrphanIcons { embedder, tree } => {
+                for window in tree.reply(&*self.connection)?.children {
+                    if self.icons.contains(&window) {
+                        continue;
+                    }
+                    let attributes =
+                        PendingReply::new(self.connection.get_window_attributes(window)?);
+                    let class = PendingReply::new(request_wm_class(&*self.connection, window)?);
+                    let xembed_info = PendingReply::new(request_xembed_info(
+                        &*self.connection,
+                        &self.atoms,
+                        window,
+                    )?);
+                    self.pending_requests.push_back(PendingRequest::OrphanIcon {
+                        icon: window,
+                        embedder,
+                        attributes,
+                        class,
+                        xembed_info,
+                    });
+                }
+                None
+            }
+            PendingRequest::OrphanIcon {
+                icon,
+                embedder,
+                attributes,
+                class,
+                xembed_info,
+            } => {
+                let attributes = attributes.reply(&*self.connection);
+                let class = class.reply(&*self.connection);
+                let xembed_info = xembed_info.reply(&*self.connection);
+                let (attributes, class, xembed_info) = (attributes?, class?, xembed_info?);
+                let is_orphan = attributes.map_state == xproto::MapState::UNMAPPED
+                    && !attributes.override_redirect
+                    && !self.icons.contains(&icon)
+                    && parse_wm_class(&class)
+                        .is_some_and(|class| self.orphan_icon_classes.contains(&class));
+                if let Some(xembed_info) = parse_xembed_info(&xembed_info).filter(|_| is_orphan) {
+                    log::info!("dock orphan icon (icon: {})", icon);
+                    self.embed_icon(icon, embedder, xembed_info)?;
+                }
+                None
+            }
+            PendingRequest::IconAdded {
+                icon,
+                title,
+                class,
+                client_machine,
+                xembed_info,
+            } => {
+                // Every reply is read before any error is returned, so that the rest are not left
+                // in the connection.
+                let title = title.reply(&*self.connection, &self.atoms);
+                let class = class.reply(&*self.connection);
+                let client_machine = client_machine.reply(&*self.connection);
+                let xembed_info = xembed_info.reply(&*self.connection);
+                let title = title?.unwrap_or_default();
+                let class = parse_wm_class(&class?);
+                let client_machine = parse_client_machine(&client_machine?);
+                let xembed_info = parse_xembed_info(&xembed_info?)
+                    .filter(|_| self.icons.contains(&icon) && !self.added_icons.contains(&icon));
+                xembed_info.map(|xembed_info| {
+                    self.added_icons.push(icon);
+                    TrayEvent::IconAdded(icon, title, class, client_machine, xembed_info)
+                })
+            }
+            PendingRequest::TitleChanged { icon, title } => {
+                let title = title
+                    .reply(&*self.connection, &self.atoms)?
+                    .unwrap_or_default();
+                self.icons
+                    .contains(&icon)
+                    .then_some(TrayEvent::TitleChanged(icon, title))
+            }
+            PendingRequest::XEmbedInfoChanged { icon, xembed_info } => {
+                let xembed_info = xembed_info.reply(&*self.connection)?;
+                parse_xembed_info(&xembed_info)
+                    .filter(|_| self.icons.contains(&icon))
+                    .map(|xembed_info| TrayEvent::XEmbedInfoChanged(icon, xembed_info))
+            }
+            PendingRequest::UrgencyChanged { icon, urgency } => {
+                let is_urgent = urgency.reply(&*self.connection, &self.atoms)?;
+                self.icons
+                    .contains(&icon)
+                    .then_some(TrayEvent::UrgencyChanged(icon, is_urgent))
+            }
+            PendingRequest::PingSupportChanged { icon, protocols } => {
+                let supports_ping =
+                    protocols
+                        .reply(&*self.connection)?
+                        .value32()
+                        .is_some_and(|mut protocols| {
+                            protocols.any(|protocol| protocol == self.atoms._NET_WM_PING)
+                        });
+                self.icons
+                    .contains(&icon)
+                    .then_some(TrayEvent::PingSupportChanged(icon, supports_ping))
+            }
+        };
+        Ok(event)
+    }
+
+    // The windows nested deeper than the children of the frames are not tracked, since their
+    // substructure events are not selected.
+    fn track_embedder_windows(&mut self, event: &protocol::Event, embedder: xproto::Window) {
+        use x11rb::protocol::Event::*;
+
+        let is_inside = |embedder_windows: &HashSet<xproto::Window>, window| {
+            window == embedder || embedder_windows.contains(&window)
+        };
+        match event {
+            CreateNotify(event) if is_inside(&self.embedder_windows, event.parent) => {
+                self.embedder_windows.insert(event.window);
+            }
+            ReparentNotify(event) if event.event != event.window => {
+                if is_inside(&self.embedder_windows, event.parent) {
+                    self.embedder_windows.insert(event.window);
+                } else {
+                    self.embedder_windows.remove(&event.window);
+                }
+            }
+            DestroyNotify(event) => {
+                self.embedder_windows.remove(&event.window);
+            }
+            _ => {}
+        }
+    }
+
+    fn embed_icon(
+        &mut self,
+        icon: xproto::Window,
+        embedder: xproto::Window,
+        xembed_info: XEmbedInfo,
+    ) -> Result<(), ReplyError> {
+        begin_embedding(&*self.connection, &self.atoms, icon, embedder, xembed_info)?;
+        self.connection.flush()?;
+        self.icons.push(icon);
+        Ok(())
+    }
+
     fn update_selection_status(
         &mut self,
         new_manager: xproto::Window,
@@ -397,6 +895,7 @@ impl<C: Connection> TrayManager<C> {
 
         if current_manager == new_manager {
             self.broadcast_manager_message(new_manager)?;
+            self.dock_orphan_icons(embedder)?;
             self.selection_status = SelectionStatus::Managed {
                 manager: new_manager,
                 embedder,
@@ -422,7 +921,7 @@ impl<C: Connection> TrayManager<C> {
         log::info!("wait until the current selection selection_owner will be destroyed");
 
         let values = xproto::ChangeWindowAttributesAux::new()
-            .event_mask(Some(xproto::EventMask::STRUCTURE_NOTIFY.into()));
+            .event_mask(Some(xproto::EventMask::STRUCTURE_NOTIFY));
 
         self.connection
             .change_window_attributes(current_manager, &values)?
@@ -432,16 +931,51 @@ impl<C: Connection> TrayManager<C> {
     }
 }
 
+/// What happened to the tray icons, as the result of handling X11 events.
 #[derive(Debug)]
 pub enum TrayEvent {
-    IconAdded(xproto::Window, String, XEmbedInfo),
+    /// An icon is docked with its title, the class of WM_CLASS and WM_CLIENT_MACHINE.
+    IconAdded(
+        xproto::Window,
+        String,
+        Option<String>,
+        Option<String>,
+        XEmbedInfo,
+    ),
     IconRemoved(xproto::Window),
     TitleChanged(xproto::Window, String),
     XEmbedInfoChanged(xproto::Window, XEmbedInfo),
+    UrgencyChanged(xproto::Window, bool),
+    /// Whether the icon supports `_NET_WM_PING` by WM_PROTOCOLS.
+    PingSupportChanged(xproto::Window, bool),
+    /// A balloon message has been received entirely.
     MessageReceived(BalloonMessage),
+    /// A received balloon message has been cancelled by the icon, with the icon and the id.
+    MessageCancelled(xproto::Window, u32),
+    /// A received balloon message has timed out or been dropped from the full queue, with the
+    /// icon and the id.
+    MessageExpired(xproto::Window, u32),
     SelectionCleared,
 }
 
+/// Another tray that owns the tray selection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectionOwner {
+    pub window: xproto::Window,
+    pub class: Option<String>,
+}
+
+impl fmt::Display for SelectionOwner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "window 0x{:x}, class \"{}\"",
+            self.window,
+            self.class.as_deref().unwrap_or("unknown")
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BalloonMessage {
     icon: xproto::Window,
@@ -473,7 +1007,7 @@ impl BalloonMessage {
     }
 
     pub fn as_str(&self) -> &str {
-        str::from_utf8(&self.buffer.as_slice())
+        str::from_utf8(self.buffer.as_slice())
             .ok()
             .unwrap_or_default()
     }
@@ -493,7 +1027,6 @@ impl BalloonMessage {
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct SystemTrayOrientation(u32);
 
-#[allow(unused)]
 impl SystemTrayOrientation {
     pub const HORZONTAL: Self = Self(0);
     pub const VERTICAL: Self = Self(1);
@@ -566,34 +1099,282 @@ enum SelectionStatus {
     },
 }
 
+#[cfg(test)]
+impl SelectionStatus {
+    fn manager(&self) -> Option<xproto::Window> {
+        match self {
+            Self::Managed { manager, .. } => Some(*manager),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PendingRequest {
+    Dock {
+        icon: xproto::Window,
+        embedder: xproto::Window,
+        xembed_info: PendingReply<xproto::GetPropertyReply>,
+    },
+    IconAdded {
+        icon: xproto::Window,
+        title: TitleRequest,
+        class: PendingReply<xproto::GetPropertyReply>,
+        client_machine: PendingReply<xproto::GetPropertyReply>,
+        xembed_info: PendingReply<xproto::GetPropertyReply>,
+    },
+    TitleChanged {
+        icon: xproto::Window,
+        title: TitleRequest,
+    },
+    XEmbedInfoChanged {
+        icon: xproto::Window,
+        xembed_info: PendingReply<xproto::GetPropertyReply>,
+    },
+    UrgencyChanged {
+        icon: xproto::Window,
+        urgency: UrgencyRequest,
+    },
+    PingSupportChanged {
+        icon: xproto::Window,
+        protocols: PendingReply<xproto::GetPropertyReply>,
+    },
+    OrphanIcons {
+        embedder: xproto::Window,
+        tree: PendingReply<xproto::QueryTreeReply>,
+    },
+    OrphanIcon {
+        icon: xproto::Window,
+        embedder: xproto::Window,
+        attributes: PendingReply<xproto::GetWindowAttributesReply>,
+        class: PendingReply<xproto::GetPropertyReply>,
+        xembed_info: PendingReply<xproto::GetPropertyReply>,
+    },
+}
+
+impl PendingRequest {
+    fn discard(self, connection: &impl Connection) {
+        match self {
+            Self::Dock { xembed_info, .. } | Self::XEmbedInfoChanged { xembed_info, .. } => {
+                xembed_info.discard(connection);
+            }
+            Self::IconAdded {
+                title,
+                class,
+                client_machine,
+                xembed_info,
+                ..
+            } => {
+                title.discard(connection);
+                class.discard(connection);
+                client_machine.discard(connection);
+                xembed_info.discard(connection);
+            }
+            Self::TitleChanged { title, .. } => {
+                title.discard(connection);
+            }
+            Self::UrgencyChanged { urgency, .. } => {
+                urgency.discard(connection);
+            }
+            Self::PingSupportChanged { protocols, .. } => {
+                protocols.discard(connection);
+            }
+            Self::OrphanIcons { tree, .. } => {
+                tree.discard(connection);
+            }
+            Self::OrphanIcon {
+                attributes,
+                class,
+                xembed_info,
+                ..
+            } => {
+                attributes.discard(connection);
+                class.discard(connection);
+                xembed_info.discard(connection);
+            }
+        }
+    }
+}
+
+// Both title properties are requested at once, and _NET_WM_NAME takes precedence over WM_NAME.
+#[derive(Debug)]
+struct TitleRequest {
+    net_wm_name: PendingReply<xproto::GetPropertyReply>,
+    wm_name: PendingReply<xproto::GetPropertyReply>,
+}
+
+impl TitleRequest {
+    fn new(
+        connection: &impl Connection,
+        atoms: &Atoms,
+        window: xproto::Window,
+    ) -> anyhow::Result<Self> {
+        let net_wm_name = connection.get_property(
+            false,
+            window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            0,
+            256 / 4,
+        )?;
+        // WM_NAME may be either STRING or COMPOUND_TEXT.
+        let wm_name = connection.get_property(
+            false,
+            window,
+            xproto::AtomEnum::WM_NAME,
+            xproto::AtomEnum::ANY,
+            0,
+            256 / 4,
+        )?;
+        Ok(Self {
+            net_wm_name: PendingReply::new(net_wm_name),
+            wm_name: PendingReply::new(wm_name),
+        })
+    }
+
+    fn reply(
+        self,
+        connection: &impl Connection,
+        atoms: &Atoms,
+    ) -> Result<Option<String>, ReplyError> {
+        let net_wm_name = self.net_wm_name.reply(connection);
+        let wm_name = self.wm_name.reply(connection);
+        Ok([net_wm_name?, wm_name?].into_iter().find_map(|reply| {
+            let bytes: Vec<u8> = reply.value8()?.collect();
+            decode_text_property(atoms, reply.type_, &bytes)
+                .map(|title| sanitize_title(&title))
+                .filter(|title| !title.is_empty())
+        }))
+    }
+
+    fn discard(self, connection: &impl Connection) {
+        self.net_wm_name.discard(connection);
+        self.wm_name.discard(connection);
+    }
+}
+
+// An icon demands attention by either the urgency flag of WM_HINTS or
+// _NET_WM_STATE_DEMANDS_ATTENTION.
+#[derive(Debug)]
+struct UrgencyRequest {
+    wm_hints: PendingReply<xproto::GetPropertyReply>,
+    net_wm_state: PendingReply<xproto::GetPropertyReply>,
+}
+
+impl UrgencyRequest {
+    fn new(
+        connection: &impl Connection,
+        atoms: &Atoms,
+        window: xproto::Window,
+    ) -> anyhow::Result<Self> {
+        let wm_hints = connection.get_property(
+            false,
+            window,
+            xproto::AtomEnum::WM_HINTS,
+            xproto::AtomEnum::WM_HINTS,
+            0,
+            9,
+        )?;
+        let net_wm_state = connection.get_property(
+            false,
+            window,
+            atoms._NET_WM_STATE,
+            xproto::AtomEnum::ATOM,
+            0,
+            256 / 4,
+        )?;
+        Ok(Self {
+            wm_hints: PendingReply::new(wm_hints),
+            net_wm_state: PendingReply::new(net_wm_state),
+        })
+    }
+
+    fn reply(self, connection: &impl Connection, atoms: &Atoms) -> Result<bool, ReplyError> {
+        let wm_hints = self.wm_hints.reply(connection);
+        let net_wm_state = self.net_wm_state.reply(connection);
+        let (wm_hints, net_wm_state) = (wm_hints?, net_wm_state?);
+        let has_urgency_hint = WmHints::from_reply(&wm_hints).is_ok_and(|wm_hints| wm_hints.urgent);
+        let demands_attention = net_wm_state.value32().is_some_and(|mut states| {
+            states.any(|state| state == atoms._NET_WM_STATE_DEMANDS_ATTENTION)
+        });
+        Ok(has_urgency_hint || demands_attention)
+    }
+
+    fn discard(self, connection: &impl Connection) {
+        self.wm_hints.discard(connection);
+        self.net_wm_state.discard(connection);
+    }
+}
+
+// Returns the class part of WM_CLASS, which consists of the instance name and the class name.
+pub fn parse_wm_class(reply: &xproto::GetPropertyReply) -> Option<String> {
+    let bytes = reply.value8()?.collect::<Vec<_>>();
+    bytes
+        .split(|byte| *byte == 0)
+        .nth(1)
+        .filter(|class| !class.is_empty())
+        .map(|class| String::from_utf8_lossy(class).into_owned())
+}
+
+fn parse_client_machine(reply: &xproto::GetPropertyReply) -> Option<String> {
+    let bytes = reply.value8()?.collect::<Vec<_>>();
+    bytes
+        .split(|byte| *byte == 0)
+        .next()
+        .filter(|machine| !machine.is_empty())
+        .map(|machine| String::from_utf8_lossy(machine).into_owned())
+}
+
+fn set_orientation_property(
+    connection: &impl Connection,
+    atoms: &Atoms,
+    manager: xproto::Window,
+    orientation: SystemTrayOrientation,
+) -> anyhow::Result<()> {
+    connection
+        .change_property32(
+            xproto::PropMode::REPLACE,
+            manager,
+            atoms._NET_SYSTEM_TRAY_ORIENTATION,
+            xproto::AtomEnum::CARDINAL,
+            &[orientation.0],
+        )?
+        .check()
+        .context("set _NET_SYSTEM_TRAY_ORIENTATION")?;
+    Ok(())
+}
+
 fn begin_embedding(
     connection: &impl Connection,
     atoms: &Atoms,
     icon: xproto::Window,
     embedder: xproto::Window,
     xembed_info: XEmbedInfo,
-) -> anyhow::Result<()> {
+) -> Result<(), ReplyError> {
     log::info!("begin embedding for icon (icon: {})", icon);
 
     let values = xproto::ChangeWindowAttributesAux::new().event_mask(Some(
-        (xproto::EventMask::PROPERTY_CHANGE | xproto::EventMask::STRUCTURE_NOTIFY).into(),
+        xproto::EventMask::PROPERTY_CHANGE | xproto::EventMask::STRUCTURE_NOTIFY,
     ));
 
     connection
         .change_window_attributes(icon, &values)?
-        .check()
-        .context("set icon event mask")?;
+        .check()?;
 
     connection
         .change_save_set(xproto::SetMode::INSERT, icon)?
-        .check()
-        .context("change icon save set")?;
+        .check()?;
 
-    connection
-        .reparent_window(icon, embedder, 0, 0)?
-        .check()
-        .context("reparent icon window")?;
+    connection.reparent_window(icon, embedder, 0, 0)?.check()?;
 
+    protocol_trace::trace_xembed_message(
+        "->",
+        icon,
+        x11rb::CURRENT_TIME,
+        XEmbedMessage::EmbeddedNotify.into(),
+        0,
+        [embedder, xembed_info.version()],
+    );
     let event = xproto::ClientMessageEvent::new(
         32,
         icon,
@@ -609,56 +1390,38 @@ fn begin_embedding(
 
     connection
         .send_event(false, icon, xproto::EventMask::STRUCTURE_NOTIFY, event)?
-        .check()
-        .context("send _XEMBED message")?;
+        .check()?;
 
     Ok(())
 }
 
-fn get_window_title(
+fn request_wm_class<'c, C: Connection>(
+    connection: &'c C,
+    window: xproto::Window,
+) -> Result<Cookie<'c, C, xproto::GetPropertyReply>, ConnectionError> {
+    connection.get_property(
+        false,
+        window,
+        xproto::AtomEnum::WM_CLASS,
+        xproto::AtomEnum::STRING,
+        0,
+        256 / 4,
+    )
+}
+
+fn request_protocols(
     connection: &impl Connection,
     atoms: &Atoms,
     window: xproto::Window,
-) -> anyhow::Result<Option<String>> {
-    let reply = connection
-        .get_property(
-            false,
-            window,
-            atoms._NET_WM_NAME,
-            atoms.UTF8_STRING,
-            0,
-            256 / 4,
-        )?
-        .reply()
-        .context("get _NET_WM_NAME property")?;
-    if let Some(title) = reply
-        .value8()
-        .and_then(|bytes| String::from_utf8(bytes.collect()).ok())
-        .filter(|title| !title.is_empty())
-    {
-        return Ok(Some(title));
-    }
-
-    let reply = connection
-        .get_property(
-            false,
-            window,
-            xproto::AtomEnum::WM_NAME,
-            xproto::AtomEnum::STRING,
-            0,
-            256 / 4,
-        )?
-        .reply()
-        .context("get WM_NAME property")?;
-    if let Some(title) = reply
-        .value8()
-        .and_then(|bytes| String::from_utf8(bytes.collect()).ok())
-        .filter(|title| !title.is_empty())
-    {
-        return Ok(Some(title));
-    }
-
-    Ok(None)
+) -> anyhow::Result<PendingReply<xproto::GetPropertyReply>> {
+    Ok(PendingReply::new(connection.get_property(
+        false,
+        window,
+        atoms.WM_PROTOCOLS,
+        xproto::AtomEnum::ATOM,
+        0,
+        32,
+    )?))
 }
 
 fn intern_system_tray_selection_atom(
@@ -668,7 +1431,7 @@ fn intern_system_tray_selection_atom(
     let atom = connection
         .intern_atom(
             false,
-            &format!("_NET_SYSTEM_TRAY_S{}", screen_num).as_bytes(),
+            format!("_NET_SYSTEM_TRAY_S{}", screen_num).as_bytes(),
         )?
         .reply()
         .context("intern _NET_SYSTEM_TRAY_S{N}")?
@@ -680,20 +1443,25 @@ fn quit_embedding(
     connection: &impl Connection,
     screen_num: usize,
     icon: xproto::Window,
+    redock_nudge: bool,
 ) -> anyhow::Result<()> {
     log::info!("release embedding for icon (icon: {})", icon);
 
     let screen = &connection.setup().roots[screen_num];
 
     {
-        let values = xproto::ChangeWindowAttributesAux::new()
-            .event_mask(Some(xproto::EventMask::NO_EVENT.into()));
+        let values =
+            xproto::ChangeWindowAttributesAux::new().event_mask(Some(xproto::EventMask::NO_EVENT));
         connection
             .change_window_attributes(icon, &values)?
             .check()
             .context("restore icon event mask")?;
     }
 
+    if redock_nudge {
+        connection.map_window(icon)?.check()?;
+    }
+
     connection.unmap_window(icon)?.check()?;
 
     connection
@@ -703,3 +1471,763 @@ fn quit_embedding(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use x11rb::protocol::{Event, Request};
+    use x11rb::x11_utils::TryParse as _;
+
+    use super::*;
+    use crate::fake_connection::{FakeConnection, ROOT_WINDOW};
+
+    const EMBEDDER: xproto::Window = 10;
+    const ICON: xproto::Window = 20;
+
+    fn create_tray_manager() -> (Rc<FakeConnection>, TrayManager<FakeConnection>) {
+        let connection = Rc::new(FakeConnection::new());
+        let atoms = Rc::new(Atoms::new(&*connection).unwrap().reply().unwrap());
+        let mut tray_manager = TrayManager::new(connection.clone(), 0, atoms).unwrap();
+        tray_manager
+            .acquire_tray_selection(
+                EMBEDDER,
+                SystemTrayOrientation::HORZONTAL,
+                SystemTrayColors::single(Color::WHITE),
+            )
+            .unwrap();
+        (connection, tray_manager)
+    }
+
+    fn create_icon(connection: &FakeConnection, title: &str) -> xproto::Window {
+        connection.set_property8(
+            ICON,
+            xproto::AtomEnum::WM_CLASS,
+            xproto::AtomEnum::STRING,
+            b"foo\0Foo\0",
+        );
+        connection.set_property8(
+            ICON,
+            xproto::AtomEnum::WM_CLIENT_MACHINE,
+            xproto::AtomEnum::STRING,
+            b"localhost",
+        );
+        connection.set_property32(
+            ICON,
+            connection.atom("_XEMBED_INFO"),
+            connection.atom("_XEMBED_INFO"),
+            &[0, 1],
+        );
+        connection.set_property8(
+            ICON,
+            connection.atom("_NET_WM_NAME"),
+            connection.atom("UTF8_STRING"),
+            title.as_bytes(),
+        );
+        ICON
+    }
+
+    fn dock_icon(
+        connection: &FakeConnection,
+        tray_manager: &mut TrayManager<FakeConnection>,
+        icon: xproto::Window,
+    ) -> Vec<TrayEvent> {
+        let request_dock = xproto::ClientMessageEvent::new(
+            32,
+            tray_manager.selection_status.manager().unwrap(),
+            connection.atom("_NET_SYSTEM_TRAY_OPCODE"),
+            [x11rb::CURRENT_TIME, SYSTEM_TRAY_REQUEST_DOCK, icon, 0, 0],
+        );
+        let reparent_notify = xproto::ReparentNotifyEvent {
+            response_type: xproto::REPARENT_NOTIFY_EVENT,
+            event: icon,
+            window: icon,
+            parent: EMBEDDER,
+            ..Default::default()
+        };
+        let mut events = Vec::new();
+        for event in [
+            Event::ClientMessage(request_dock),
+            Event::ReparentNotify(reparent_notify),
+        ] {
+            events.extend(tray_manager.translate_event(&event).unwrap());
+            events.extend(tray_manager.process_pending_requests().unwrap());
+        }
+        events
+    }
+
+    fn sent_client_messages(requests: &[Request]) -> Vec<xproto::ClientMessageEvent> {
+        requests
+            .iter()
+            .filter_map(|request| match request {
+                Request::SendEvent(request) => {
+                    xproto::ClientMessageEvent::try_parse(request.event.as_slice())
+                        .ok()
+                        .map(|(event, _)| event)
+                        .filter(|event| event.response_type == xproto::CLIENT_MESSAGE_EVENT)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_acquire_tray_selection() {
+        let (connection, tray_manager) = create_tray_manager();
+        let manager = tray_manager.selection_status.manager().unwrap();
+        let messages = sent_client_messages(&connection.take_requests());
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].window, ROOT_WINDOW);
+        assert_eq!(messages[0].type_, connection.atom("MANAGER"));
+        assert_eq!(
+            messages[0].data.as_data32()[1..3],
+            [connection.atom("_NET_SYSTEM_TRAY_S0"), manager]
+        );
+    }
+
+    #[test]
+    fn test_dock_orphan_icons() {
+        let connection = Rc::new(FakeConnection::new());
+        let atoms = Rc::new(Atoms::new(&*connection).unwrap().reply().unwrap());
+        let mut tray_manager = TrayManager::new(connection.clone(), 0, atoms).unwrap();
+        tray_manager.set_orphan_icon_classes(HashSet::from(["Foo".to_owned()]));
+        let icon = create_icon(&connection, "foo");
+        connection.add_root_child(icon);
+        connection.add_root_child(30);
+        // The XEmbed client of another embedder.
+        let plug = 31;
+        connection.set_property8(
+            plug,
+            xproto::AtomEnum::WM_CLASS,
+            xproto::AtomEnum::STRING,
+            b"bar\0Bar\0",
+        );
+        connection.set_property32(
+            plug,
+            connection.atom("_XEMBED_INFO"),
+            connection.atom("_XEMBED_INFO"),
+            &[0, 1],
+        );
+        connection.add_root_child(plug);
+
+        tray_manager
+            .acquire_tray_selection(
+                EMBEDDER,
+                SystemTrayOrientation::HORZONTAL,
+                SystemTrayColors::single(Color::WHITE),
+            )
+            .unwrap();
+        tray_manager.process_pending_requests().unwrap();
+
+        assert_eq!(tray_manager.icons, [icon]);
+        let requests = connection.take_requests();
+        assert!(requests.iter().any(|request| matches!(
+            request,
+            Request::ReparentWindow(request) if request.window == icon && request.parent == EMBEDDER
+        )));
+        assert!(!requests.iter().any(|request| matches!(
+            request,
+            Request::ReparentWindow(request) if request.window == plug
+        )));
+    }
+
+    #[test]
+    fn test_dock_orphan_icons_without_classes() {
+        let connection = Rc::new(FakeConnection::new());
+        let atoms = Rc::new(Atoms::new(&*connection).unwrap().reply().unwrap());
+        let mut tray_manager = TrayManager::new(connection.clone(), 0, atoms).unwrap();
+        let icon = create_icon(&connection, "foo");
+        connection.add_root_child(icon);
+
+        tray_manager
+            .acquire_tray_selection(
+                EMBEDDER,
+                SystemTrayOrientation::HORZONTAL,
+                SystemTrayColors::single(Color::WHITE),
+            )
+            .unwrap();
+        tray_manager.process_pending_requests().unwrap();
+
+        assert!(tray_manager.icons.is_empty());
+        assert!(!connection
+            .take_requests()
+            .iter()
+            .any(|request| matches!(request, Request::QueryTree(_))));
+    }
+
+    #[test]
+    fn test_current_owner() {
+        let connection = Rc::new(FakeConnection::new());
+        let atoms = Rc::new(Atoms::new(&*connection).unwrap().reply().unwrap());
+        let tray_manager = TrayManager::new(connection.clone(), 0, atoms).unwrap();
+
+        assert_eq!(tray_manager.current_owner().unwrap(), None);
+
+        connection.set_selection_owner(connection.atom("_NET_SYSTEM_TRAY_S0"), ICON);
+        connection.set_property8(
+            ICON,
+            xproto::AtomEnum::WM_CLASS,
+            xproto::AtomEnum::STRING,
+            b"stalonetray\0stalonetray\0",
+        );
+
+        assert_eq!(
+            tray_manager.current_owner().unwrap(),
+            Some(SelectionOwner {
+                window: ICON,
+                class: Some("stalonetray".to_owned()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_set_orientation() {
+        let (connection, tray_manager) = create_tray_manager();
+        let manager = tray_manager.selection_status.manager().unwrap();
+
+        tray_manager
+            .set_orientation(SystemTrayOrientation::VERTICAL)
+            .unwrap();
+
+        let reply = connection
+            .get_property(
+                false,
+                manager,
+                connection.atom("_NET_SYSTEM_TRAY_ORIENTATION"),
+                xproto::AtomEnum::CARDINAL,
+                0,
+                1,
+            )
+            .unwrap()
+            .reply()
+            .unwrap();
+        assert_eq!(reply.value32().unwrap().collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn test_dock_icon() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        connection.take_requests();
+
+        let events = dock_icon(&connection, &mut tray_manager, icon);
+
+        assert_eq!(tray_manager.icons, [icon]);
+        assert!(matches!(
+            events[..],
+            [
+                TrayEvent::IconAdded(window, ref title, ref class, ref client_machine, xembed_info),
+                TrayEvent::UrgencyChanged(urgent_window, false),
+                TrayEvent::PingSupportChanged(ping_window, false),
+            ] if window == icon
+                && ping_window == icon
+                && urgent_window == icon
+                && title == "foo"
+                && class.as_deref() == Some("Foo")
+                && client_machine.as_deref() == Some("localhost")
+                && xembed_info.is_mapped()
+        ));
+
+        let requests = connection.take_requests();
+        assert!(requests.iter().any(|request| matches!(
+            request,
+            Request::ReparentWindow(request) if request.window == icon && request.parent == EMBEDDER
+        )));
+        let messages = sent_client_messages(&requests);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].type_, connection.atom("_XEMBED"));
+        assert_eq!(
+            messages[0].data.as_data32()[1],
+            u32::from(XEmbedMessage::EmbeddedNotify)
+        );
+    }
+
+    #[test]
+    fn test_dock_icon_with_compound_text_title() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "");
+        connection.set_property8(
+            icon,
+            xproto::AtomEnum::WM_NAME,
+            connection.atom("COMPOUND_TEXT"),
+            b"caf\xe9\n\x1b%G\xe2\x9c\x93\x1b%@",
+        );
+
+        let events = dock_icon(&connection, &mut tray_manager, icon);
+
+        assert!(matches!(
+            events[..],
+            [TrayEvent::IconAdded(_, ref title, ..), ..] if title == "café ✓"
+        ));
+    }
+
+    #[test]
+    fn test_dock_icon_without_xembed_info() {
+        let (connection, mut tray_manager) = create_tray_manager();
+
+        let events = dock_icon(&connection, &mut tray_manager, ICON);
+
+        assert!(events.is_empty());
+        assert!(tray_manager.icons.is_empty());
+    }
+
+    #[test]
+    fn test_undock_icon() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+
+        let property_notify = xproto::PropertyNotifyEvent {
+            response_type: xproto::PROPERTY_NOTIFY_EVENT,
+            window: icon,
+            atom: connection.atom("_NET_WM_NAME"),
+            ..Default::default()
+        };
+        let destroy_notify = xproto::DestroyNotifyEvent {
+            response_type: xproto::DESTROY_NOTIFY_EVENT,
+            event: icon,
+            window: icon,
+            ..Default::default()
+        };
+
+        assert!(tray_manager
+            .translate_event(&Event::PropertyNotify(property_notify))
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            tray_manager.translate_event(&Event::DestroyNotify(destroy_notify)),
+            Ok(Some(TrayEvent::IconRemoved(window))) if window == icon
+        ));
+        assert!(tray_manager.process_pending_requests().unwrap().is_empty());
+        assert!(tray_manager.icons.is_empty());
+    }
+
+    #[test]
+    fn test_reparent_icon_inside_embedder() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+
+        let frame = 0x300;
+        let wrapper = 0x301;
+        let outside = 0x302;
+        let create_notify = |parent, window| {
+            Event::CreateNotify(xproto::CreateNotifyEvent {
+                response_type: xproto::CREATE_NOTIFY_EVENT,
+                parent,
+                window,
+                ..Default::default()
+            })
+        };
+        // The embedder wraps the icon in a frame, and the client creates its own window in it.
+        for (parent, window) in [(EMBEDDER, frame), (frame, wrapper), (ROOT_WINDOW, outside)] {
+            assert!(tray_manager
+                .translate_event(&create_notify(parent, window))
+                .unwrap()
+                .is_none());
+        }
+        let reparent_notify = |parent| {
+            Event::ReparentNotify(xproto::ReparentNotifyEvent {
+                response_type: xproto::REPARENT_NOTIFY_EVENT,
+                event: icon,
+                window: icon,
+                parent,
+                ..Default::default()
+            })
+        };
+
+        assert!(tray_manager
+            .translate_event(&reparent_notify(frame))
+            .unwrap()
+            .is_none());
+        // The client wraps the icon in its own window inside the frame.
+        assert!(tray_manager
+            .translate_event(&reparent_notify(wrapper))
+            .unwrap()
+            .is_none());
+        assert_eq!(tray_manager.icons, [icon]);
+
+        // It is not added again when it comes back to the embedder.
+        assert!(tray_manager
+            .translate_event(&reparent_notify(EMBEDDER))
+            .unwrap()
+            .is_none());
+        assert!(tray_manager.process_pending_requests().unwrap().is_empty());
+
+        assert!(matches!(
+            tray_manager.translate_event(&reparent_notify(outside)),
+            Ok(Some(TrayEvent::IconRemoved(window))) if window == icon
+        ));
+        assert!(tray_manager.icons.is_empty());
+    }
+
+    #[test]
+    fn test_icon_demands_attention() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+
+        let demands_attention = connection.atom("_NET_WM_STATE_DEMANDS_ATTENTION");
+        connection.set_property32(
+            icon,
+            connection.atom("_NET_WM_STATE"),
+            xproto::AtomEnum::ATOM,
+            &[demands_attention],
+        );
+        let property_notify = xproto::PropertyNotifyEvent {
+            response_type: xproto::PROPERTY_NOTIFY_EVENT,
+            window: icon,
+            atom: connection.atom("_NET_WM_STATE"),
+            ..Default::default()
+        };
+
+        assert!(tray_manager
+            .translate_event(&Event::PropertyNotify(property_notify))
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            tray_manager.process_pending_requests().unwrap()[..],
+            [TrayEvent::UrgencyChanged(window, true)] if window == icon
+        ));
+    }
+
+    #[test]
+    fn test_icon_gone_away() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+        connection.destroy_window(icon);
+
+        let property_notify = xproto::PropertyNotifyEvent {
+            response_type: xproto::PROPERTY_NOTIFY_EVENT,
+            window: icon,
+            atom: connection.atom("_XEMBED_INFO"),
+            ..Default::default()
+        };
+
+        assert!(tray_manager
+            .translate_event(&Event::PropertyNotify(property_notify))
+            .unwrap()
+            .is_none());
+        assert!(tray_manager.process_pending_requests().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_icon_destroyed_before_replies() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+
+        let request_dock = xproto::ClientMessageEvent::new(
+            32,
+            tray_manager.selection_status.manager().unwrap(),
+            connection.atom("_NET_SYSTEM_TRAY_OPCODE"),
+            [x11rb::CURRENT_TIME, SYSTEM_TRAY_REQUEST_DOCK, icon, 0, 0],
+        );
+        tray_manager
+            .translate_event(&Event::ClientMessage(request_dock))
+            .unwrap();
+        tray_manager.process_pending_requests().unwrap();
+
+        let reparent_notify = xproto::ReparentNotifyEvent {
+            response_type: xproto::REPARENT_NOTIFY_EVENT,
+            event: icon,
+            window: icon,
+            parent: EMBEDDER,
+            ..Default::default()
+        };
+        connection.destroy_window(icon);
+        tray_manager
+            .translate_event(&Event::ReparentNotify(reparent_notify))
+            .unwrap();
+
+        assert!(tray_manager.process_pending_requests().unwrap().is_empty());
+        assert_eq!(connection.pending_responses(), 0);
+    }
+
+    #[test]
+    fn test_selection_cleared() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+        connection.take_requests();
+
+        let selection_clear = xproto::SelectionClearEvent {
+            response_type: xproto::SELECTION_CLEAR_EVENT,
+            owner: tray_manager.selection_status.manager().unwrap(),
+            selection: connection.atom("_NET_SYSTEM_TRAY_S0"),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            tray_manager.translate_event(&Event::SelectionClear(selection_clear)),
+            Ok(Some(TrayEvent::SelectionCleared))
+        ));
+        assert!(tray_manager.icons.is_empty());
+        assert_eq!(tray_manager.selection_status, SelectionStatus::Unmanaged);
+        assert!(connection.take_requests().iter().any(|request| matches!(
+            request,
+            Request::ReparentWindow(request) if request.window == icon && request.parent == ROOT_WINDOW
+        )));
+    }
+
+    #[test]
+    fn test_release_tray_selection_with_redock_nudge() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+        connection.take_requests();
+
+        tray_manager.set_redock_nudge(true);
+        tray_manager.release_tray_selection().unwrap();
+
+        assert!(!tray_manager.is_managed());
+        let requests: Vec<_> = connection
+            .take_requests()
+            .into_iter()
+            .filter(|request| {
+                matches!(
+                    request,
+                    Request::ReparentWindow(xproto::ReparentWindowRequest { window, .. })
+                        | Request::MapWindow(xproto::MapWindowRequest { window })
+                        | Request::UnmapWindow(xproto::UnmapWindowRequest { window })
+                        if *window == icon
+                )
+            })
+            .collect();
+        assert!(matches!(
+            requests[..],
+            [
+                Request::MapWindow(_),
+                Request::UnmapWindow(_),
+                Request::ReparentWindow(_),
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_release_icon() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+        connection.take_requests();
+
+        tray_manager.set_redock_nudge(true);
+        tray_manager.release_icon(icon).unwrap();
+
+        assert!(tray_manager.is_managed());
+        assert!(tray_manager.icons.is_empty());
+        let requests = connection.take_requests();
+        assert!(requests.iter().any(|request| matches!(
+            request,
+            Request::ReparentWindow(request) if request.window == icon && request.parent == ROOT_WINDOW
+        )));
+        assert!(!requests
+            .iter()
+            .any(|request| matches!(request, Request::MapWindow(_))));
+
+        // The icon that is no longer docked is not released again.
+        tray_manager.release_icon(icon).unwrap();
+        assert!(connection.take_requests().is_empty());
+    }
+
+    #[test]
+    fn test_receive_balloon_message() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+
+        let message = "Hello, this is a balloon message!";
+        let begin_message = xproto::ClientMessageEvent::new(
+            32,
+            icon,
+            connection.atom("_NET_SYSTEM_TRAY_OPCODE"),
+            [
+                x11rb::CURRENT_TIME,
+                SYSTEM_TRAY_BEGIN_MESSAGE,
+                1000,
+                message.len() as u32,
+                123,
+            ],
+        );
+        assert!(tray_manager
+            .translate_event(&Event::ClientMessage(begin_message))
+            .unwrap()
+            .is_none());
+
+        let mut events = Vec::new();
+        for chunk in message.as_bytes().chunks(20) {
+            let mut data = [0; 20];
+            data[..chunk.len()].copy_from_slice(chunk);
+            let message_data = xproto::ClientMessageEvent::new(
+                8,
+                icon,
+                connection.atom("_NET_SYSTEM_TRAY_MESSAGE_DATA"),
+                data,
+            );
+            events.extend(
+                tray_manager
+                    .translate_event(&Event::ClientMessage(message_data))
+                    .unwrap(),
+            );
+        }
+
+        assert!(matches!(
+            &events[..],
+            [TrayEvent::MessageReceived(balloon_message)]
+                if balloon_message.icon() == icon
+                    && balloon_message.id() == 123
+                    && balloon_message.timeout_millis() == 1000
+                    && balloon_message.as_str() == message
+        ));
+        assert!(tray_manager.balloon_messages.is_empty());
+    }
+
+    #[test]
+    fn test_cancel_balloon_message() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+
+        let begin_message = xproto::ClientMessageEvent::new(
+            32,
+            icon,
+            connection.atom("_NET_SYSTEM_TRAY_OPCODE"),
+            [x11rb::CURRENT_TIME, SYSTEM_TRAY_BEGIN_MESSAGE, 0, 40, 123],
+        );
+        let cancel_message = xproto::ClientMessageEvent::new(
+            32,
+            icon,
+            connection.atom("_NET_SYSTEM_TRAY_OPCODE"),
+            [x11rb::CURRENT_TIME, SYSTEM_TRAY_CANCEL_MESSAGE, 123, 0, 0],
+        );
+
+        tray_manager
+            .translate_event(&Event::ClientMessage(begin_message))
+            .unwrap();
+        assert_eq!(tray_manager.balloon_messages.len(), 1);
+
+        tray_manager
+            .translate_event(&Event::ClientMessage(cancel_message))
+            .unwrap();
+        assert!(tray_manager.balloon_messages.is_empty());
+
+        send_message(&connection, &mut tray_manager, icon, 456, 0, "foo");
+        assert_eq!(tray_manager.messages().count(), 1);
+
+        let cancel_message = xproto::ClientMessageEvent::new(
+            32,
+            icon,
+            connection.atom("_NET_SYSTEM_TRAY_OPCODE"),
+            [x11rb::CURRENT_TIME, SYSTEM_TRAY_CANCEL_MESSAGE, 456, 0, 0],
+        );
+        assert!(matches!(
+            tray_manager.translate_event(&Event::ClientMessage(cancel_message)),
+            Ok(Some(TrayEvent::MessageCancelled(cancelled_icon, 456))) if cancelled_icon == icon
+        ));
+        assert_eq!(tray_manager.messages().count(), 0);
+    }
+
+    #[test]
+    fn test_expire_balloon_messages() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+
+        let now = Instant::now();
+        send_message(&connection, &mut tray_manager, icon, 1, 1000, "foo");
+        send_message(&connection, &mut tray_manager, icon, 2, 0, "bar");
+        assert_eq!(tray_manager.messages().count(), 2);
+
+        assert!(tray_manager.expire_messages(now).is_empty());
+        assert!(matches!(
+            &tray_manager.expire_messages(now + Duration::from_secs(2))[..],
+            [TrayEvent::MessageExpired(expired_icon, 1)] if *expired_icon == icon
+        ));
+        assert_eq!(
+            tray_manager
+                .messages()
+                .map(|message| message.id())
+                .collect::<Vec<_>>(),
+            [2]
+        );
+    }
+
+    #[test]
+    fn test_message_overflow() {
+        let (connection, mut tray_manager) = create_tray_manager();
+        let icon = create_icon(&connection, "foo");
+        dock_icon(&connection, &mut tray_manager, icon);
+
+        tray_manager.set_message_queue(2, MessageOverflow::DropOldest);
+        for id in 1..=3 {
+            let events = send_message(&connection, &mut tray_manager, icon, id, 0, "foo");
+            assert!(matches!(
+                &events[..],
+                [TrayEvent::MessageReceived(message)] if message.id() == id
+            ));
+        }
+        assert!(matches!(
+            &tray_manager.process_pending_requests().unwrap()[..],
+            [TrayEvent::MessageExpired(expired_icon, 1)] if *expired_icon == icon
+        ));
+        assert_eq!(
+            tray_manager
+                .messages()
+                .map(|message| message.id())
+                .collect::<Vec<_>>(),
+            [2, 3]
+        );
+
+        tray_manager.set_message_queue(2, MessageOverflow::DropNewest);
+        assert!(send_message(&connection, &mut tray_manager, icon, 4, 0, "foo").is_empty());
+        assert_eq!(
+            tray_manager
+                .messages()
+                .map(|message| message.id())
+                .collect::<Vec<_>>(),
+            [2, 3]
+        );
+    }
+
+    fn send_message(
+        connection: &FakeConnection,
+        tray_manager: &mut TrayManager<FakeConnection>,
+        icon: xproto::Window,
+        id: u32,
+        timeout: u32,
+        message: &str,
+    ) -> Vec<TrayEvent> {
+        let begin_message = xproto::ClientMessageEvent::new(
+            32,
+            icon,
+            connection.atom("_NET_SYSTEM_TRAY_OPCODE"),
+            [
+                x11rb::CURRENT_TIME,
+                SYSTEM_TRAY_BEGIN_MESSAGE,
+                timeout,
+                message.len() as u32,
+                id,
+            ],
+        );
+        tray_manager
+            .translate_event(&Event::ClientMessage(begin_message))
+            .unwrap();
+
+        let mut events = Vec::new();
+        for chunk in message.as_bytes().chunks(20) {
+            let mut data = [0; 20];
+            data[..chunk.len()].copy_from_slice(chunk);
+            let message_data = xproto::ClientMessageEvent::new(
+                8,
+                icon,
+                connection.atom("_NET_SYSTEM_TRAY_MESSAGE_DATA"),
+                data,
+            );
+            events.extend(
+                tray_manager
+                    .translate_event(&Event::ClientMessage(message_data))
+                    .unwrap(),
+            );
+        }
+        events
+    }
+}
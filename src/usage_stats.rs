@@ -0,0 +1,128 @@
+use anyhow::Context as _;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::window_position;
+
+// How many times and when the items of each application have been clicked last. The applications
+// are identified by the class of WM_CLASS, or the title if the icon has no class.
+#[derive(Debug, Default, PartialEq)]
+pub struct UsageStats {
+    entries: HashMap<String, Usage>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Usage {
+    pub count: u64,
+    // Milliseconds since the Unix epoch.
+    pub last_used: u64,
+}
+
+impl UsageStats {
+    // The stats are saved in $XDG_STATE_HOME/geektray for each screen.
+    pub fn load(screen_num: usize) -> Self {
+        state_path(screen_num)
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|content| parse(&content))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, screen_num: usize) -> anyhow::Result<()> {
+        let Some(path) = state_path(screen_num) else {
+            return Ok(());
+        };
+        if let Some(state_dir) = path.parent() {
+            fs::create_dir_all(state_dir).context("create state dir")?;
+        }
+        fs::write(path, self.serialize()).context("write usage stats")?;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<Usage> {
+        self.entries.get(key).copied()
+    }
+
+    pub fn record(&mut self, key: &str, now: SystemTime) {
+        let last_used = now
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u64);
+        let usage = self.entries.entry(key.to_owned()).or_insert(Usage {
+            count: 0,
+            last_used,
+        });
+        usage.count += 1;
+        usage.last_used = last_used;
+    }
+
+    // Each line has the time used last, the count and the key, from the most recently used.
+    fn serialize(&self) -> String {
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.last_used));
+        let mut content = String::new();
+        for (key, usage) in entries {
+            writeln!(content, "{} {} {}", usage.last_used, usage.count, key).unwrap();
+        }
+        content
+    }
+}
+
+fn parse(content: &str) -> UsageStats {
+    let entries = content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ' ');
+            let last_used = fields.next()?.parse().ok()?;
+            let count = fields.next()?.parse().ok()?;
+            let key = fields.next().filter(|key| !key.is_empty())?;
+            Some((key.to_owned(), Usage { count, last_used }))
+        })
+        .collect();
+    UsageStats { entries }
+}
+
+fn state_path(screen_num: usize) -> Option<PathBuf> {
+    window_position::state_dir().map(|state_dir| state_dir.join(format!("usage-{}", screen_num)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_record() {
+        let mut usage_stats = UsageStats::default();
+        usage_stats.record("Foo", UNIX_EPOCH + Duration::from_millis(100));
+        usage_stats.record("Bar", UNIX_EPOCH + Duration::from_millis(200));
+        usage_stats.record("Foo", UNIX_EPOCH + Duration::from_millis(300));
+        assert_eq!(
+            usage_stats.get("Foo"),
+            Some(Usage {
+                count: 2,
+                last_used: 300
+            })
+        );
+        assert_eq!(
+            usage_stats.get("Bar"),
+            Some(Usage {
+                count: 1,
+                last_used: 200
+            })
+        );
+        assert_eq!(usage_stats.get("Baz"), None);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut usage_stats = UsageStats::default();
+        usage_stats.record("Foo Bar", UNIX_EPOCH + Duration::from_millis(100));
+        usage_stats.record("Baz", UNIX_EPOCH + Duration::from_millis(200));
+        let content = usage_stats.serialize();
+        assert_eq!(content, "200 1 Baz\n100 1 Foo Bar\n");
+        assert_eq!(parse(&content), usage_stats);
+        assert_eq!(parse("foo\n1 2\n1 2 \n"), UsageStats::default());
+    }
+}
@@ -0,0 +1,56 @@
+use anyhow::Context as _;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::geometrics::PhysicalPoint;
+
+// Loads the last position of the window on the screen, which is saved in
+// $XDG_STATE_HOME/geektray.
+pub fn load(screen_num: usize) -> Option<PhysicalPoint> {
+    let path = state_path(screen_num)?;
+    let content = fs::read_to_string(path).ok()?;
+    parse(&content)
+}
+
+pub fn save(screen_num: usize, position: PhysicalPoint) -> anyhow::Result<()> {
+    let Some(path) = state_path(screen_num) else {
+        return Ok(());
+    };
+    if let Some(state_dir) = path.parent() {
+        fs::create_dir_all(state_dir).context("create state dir")?;
+    }
+    fs::write(path, format!("{} {}\n", position.x, position.y)).context("write window position")?;
+    Ok(())
+}
+
+fn parse(content: &str) -> Option<PhysicalPoint> {
+    let mut values = content.split_whitespace();
+    let x = values.next()?.parse().ok()?;
+    let y = values.next()?.parse().ok()?;
+    Some(PhysicalPoint { x, y })
+}
+
+fn state_path(screen_num: usize) -> Option<PathBuf> {
+    state_dir().map(|state_dir| state_dir.join(format!("position-{}", screen_num)))
+}
+
+pub fn state_dir() -> Option<PathBuf> {
+    env::var("XDG_STATE_HOME")
+        .map(|state_dir| Path::new(&state_dir).to_path_buf())
+        .or_else(|_| env::var("HOME").map(|home_dir| Path::new(&home_dir).join(".local/state")))
+        .map(|state_dir| state_dir.join("geektray"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("12 -34\n"), Some(PhysicalPoint { x: 12, y: -34 }));
+        assert_eq!(parse("12"), None);
+        assert_eq!(parse("foo bar"), None);
+    }
+}
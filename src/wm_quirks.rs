@@ -0,0 +1,159 @@
+use anyhow::{anyhow, Context as _};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto;
+use x11rb::protocol::xproto::ConnectionExt as _;
+
+use crate::atoms::Atoms;
+use crate::config::{WindowConfig, WindowState};
+
+// The workarounds for the window managers that do not handle the window as requested.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Quirks {
+    // The window manager keeps the window above all others including fullscreen windows.
+    pub skip_stays_on_top: bool,
+    // The window manager tiles the window regardless of its type.
+    pub force_override_redirect: bool,
+}
+
+// The profiles are looked up by the name of the window manager case-insensitively.
+const PROFILES: &[(&str, Quirks)] = &[
+    (
+        "i3",
+        Quirks {
+            skip_stays_on_top: true,
+            force_override_redirect: false,
+        },
+    ),
+    (
+        "dwm",
+        Quirks {
+            skip_stays_on_top: false,
+            force_override_redirect: true,
+        },
+    ),
+];
+
+impl Quirks {
+    // The value is either "auto" to detect the window manager, "none" to disable the workarounds,
+    // or the name of a profile.
+    pub fn resolve(
+        connection: &impl Connection,
+        screen_num: usize,
+        atoms: &Atoms,
+        value: &str,
+    ) -> anyhow::Result<Self> {
+        match value {
+            "none" => Ok(Self::default()),
+            "auto" => {
+                let Some(name) = detect_window_manager(connection, screen_num, atoms)? else {
+                    return Ok(Self::default());
+                };
+                let quirks = Self::for_window_manager(&name).unwrap_or_default();
+                log::info!("detected window manager {:?}: {:?}", name, quirks);
+                Ok(quirks)
+            }
+            name => Self::for_window_manager(name)
+                .ok_or_else(|| anyhow!("unknown window.wm_quirks profile: {:?}", name)),
+        }
+    }
+
+    pub fn for_window_manager(name: &str) -> Option<Self> {
+        PROFILES
+            .iter()
+            .find(|(profile, _)| profile.eq_ignore_ascii_case(name))
+            .map(|(_, quirks)| *quirks)
+    }
+
+    pub fn apply(&self, config: &mut WindowConfig) {
+        if self.skip_stays_on_top {
+            config
+                .states
+                .retain(|state| *state != WindowState::StaysOnTop);
+        }
+        if self.force_override_redirect {
+            config.override_redirect = true;
+        }
+    }
+}
+
+// The window manager is found by the name of the window that _NET_SUPPORTING_WM_CHECK of the root
+// window points to.
+fn detect_window_manager(
+    connection: &impl Connection,
+    screen_num: usize,
+    atoms: &Atoms,
+) -> anyhow::Result<Option<String>> {
+    let screen = &connection.setup().roots[screen_num];
+    let reply = connection
+        .get_property(
+            false,
+            screen.root,
+            atoms._NET_SUPPORTING_WM_CHECK,
+            xproto::AtomEnum::WINDOW,
+            0,
+            1,
+        )?
+        .reply()
+        .context("get _NET_SUPPORTING_WM_CHECK")?;
+    let Some(check_window) = reply.value32().and_then(|mut iter| iter.next()) else {
+        return Ok(None);
+    };
+
+    // The window may be stale if the window manager has exited.
+    let Ok(reply) = connection
+        .get_property(
+            false,
+            check_window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            0,
+            256 / 4,
+        )?
+        .reply()
+    else {
+        return Ok(None);
+    };
+    Ok(reply
+        .value8()
+        .and_then(|bytes| String::from_utf8(bytes.collect()).ok())
+        .filter(|name| !name.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_window_manager() {
+        assert_eq!(
+            Quirks::for_window_manager("i3"),
+            Some(Quirks {
+                skip_stays_on_top: true,
+                force_override_redirect: false,
+            })
+        );
+        assert_eq!(
+            Quirks::for_window_manager("DWM"),
+            Some(Quirks {
+                skip_stays_on_top: false,
+                force_override_redirect: true,
+            })
+        );
+        assert_eq!(Quirks::for_window_manager("Openbox"), None);
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut config = WindowConfig::default();
+        Quirks::for_window_manager("i3").unwrap().apply(&mut config);
+        assert_eq!(config.states, vec![WindowState::Above, WindowState::Sticky]);
+        assert!(!config.override_redirect);
+
+        let mut config = WindowConfig::default();
+        Quirks::for_window_manager("dwm")
+            .unwrap()
+            .apply(&mut config);
+        assert_eq!(config.states, WindowConfig::default().states);
+        assert!(config.override_redirect);
+    }
+}
@@ -1,13 +1,18 @@
 use anyhow::Context as _;
 use x11rb::connection::Connection;
+use x11rb::cookie::Cookie;
+use x11rb::errors::ConnectionError;
 use x11rb::protocol::xproto::{self, ConnectionExt};
 
 use crate::atoms::Atoms;
+use crate::protocol_trace;
 
 const XEMBED_MAPPED: u32 = 1 << 0;
 
+pub const XEMBED_FOCUS_CURRENT: u32 = 0;
+
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct XEmbedInfo {
     version: u32,
     flags: u32,
@@ -49,32 +54,56 @@ impl From<XEmbedMessage> for u32 {
     }
 }
 
-pub fn get_xembed_info(
+pub fn send_xembed_message(
     connection: &impl Connection,
     atoms: &Atoms,
     window: xproto::Window,
-) -> anyhow::Result<Option<XEmbedInfo>> {
-    let reply = connection
-        .get_property(
-            false,
-            window,
-            atoms._XEMBED_INFO,
-            xproto::AtomEnum::ANY,
-            0,
-            2,
-        )?
-        .reply()
-        .context("get _XEMBED_INFO property")?;
-    if let Some(data) = reply
+    message: XEmbedMessage,
+    detail: u32,
+) -> anyhow::Result<()> {
+    protocol_trace::trace_xembed_message(
+        "->",
+        window,
+        x11rb::CURRENT_TIME,
+        message.into(),
+        detail,
+        [0, 0],
+    );
+    let event = xproto::ClientMessageEvent::new(
+        32,
+        window,
+        atoms._XEMBED,
+        [x11rb::CURRENT_TIME, message.into(), detail, 0, 0],
+    );
+    connection
+        .send_event(false, window, xproto::EventMask::NO_EVENT, event)?
+        .check()
+        .context("send XEmbed message")?;
+    Ok(())
+}
+
+pub fn request_xembed_info<'c, C: Connection>(
+    connection: &'c C,
+    atoms: &Atoms,
+    window: xproto::Window,
+) -> Result<Cookie<'c, C, xproto::GetPropertyReply>, ConnectionError> {
+    connection.get_property(
+        false,
+        window,
+        atoms._XEMBED_INFO,
+        xproto::AtomEnum::ANY,
+        0,
+        2,
+    )
+}
+
+pub fn parse_xembed_info(reply: &xproto::GetPropertyReply) -> Option<XEmbedInfo> {
+    reply
         .value32()
         .map(|iter| iter.collect::<Vec<_>>())
         .filter(|data| data.len() == 2)
-    {
-        Ok(Some(XEmbedInfo {
+        .map(|data| XEmbedInfo {
             version: data[0],
             flags: data[1],
-        }))
-    } else {
-        Ok(None)
-    }
+        })
 }
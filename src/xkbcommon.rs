@@ -27,13 +27,9 @@ impl State {
     }
 
     pub fn lookup_keycode(&self, keysym: Keysym) -> Option<u32> {
-        for keycode in self.keymap.all_keycodes() {
-            if self.get_keysym(keycode) == keysym {
-                return Some(keycode);
-            }
-        }
-
-        None
+        self.keymap
+            .all_keycodes()
+            .find(|&keycode| self.get_keysym(keycode) == keysym)
     }
 
     pub fn get_keysym(&self, keycode: u32) -> Keysym {
@@ -95,7 +91,7 @@ impl Clone for State {
     fn clone(&self) -> Self {
         Self {
             keymap: self.keymap.clone(),
-            mod_indices: self.mod_indices.clone(),
+            mod_indices: self.mod_indices,
             state: unsafe { ffi::xkb_state_ref(self.state) },
         }
     }
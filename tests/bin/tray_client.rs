@@ -0,0 +1,205 @@
+// A fake XEmbed client driven by the integration tests. It docks a window titled by the first
+// argument into the system tray, and reports what happens to the window on stdout:
+//
+//   window <window>      The icon window has been created.
+//   reparent <parent>    The icon window has been reparented.
+//   embedded <embedder>  XEMBED_EMBEDDED_NOTIFY has been received.
+//   manager <manager>    A tray has acquired the selection, and the icon is docked into it.
+//   press <button>       A button has been pressed on the icon window.
+//   release <button>     A button has been released on the icon window.
+//
+// Every line read from stdin becomes the new title, and the client exits when stdin is closed.
+
+use anyhow::Context as _;
+use std::env;
+use std::io::{self, BufRead as _};
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use x11rb::connection::Connection;
+use x11rb::protocol::{xproto, xproto::ConnectionExt as _, Event};
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::xcb_ffi::XCBConnection;
+
+const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+const XEMBED_MAPPED: u32 = 1 << 0;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        MANAGER,
+        UTF8_STRING,
+        _NET_SYSTEM_TRAY_OPCODE,
+        _NET_WM_NAME,
+        _XEMBED,
+        _XEMBED_INFO,
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let title = env::args().nth(1).unwrap_or_default();
+    let (connection, screen_num) = XCBConnection::connect(None).context("connect to X server")?;
+    let connection = Arc::new(connection);
+    let atoms = Atoms::new(&*connection)?.reply().context("intern atoms")?;
+    let selection = connection
+        .intern_atom(
+            false,
+            format!("_NET_SYSTEM_TRAY_S{}", screen_num).as_bytes(),
+        )?
+        .reply()
+        .context("intern _NET_SYSTEM_TRAY_S{N}")?
+        .atom;
+    let screen = &connection.setup().roots[screen_num];
+
+    // MANAGER messages are sent to the root window.
+    connection
+        .change_window_attributes(
+            screen.root,
+            &xproto::ChangeWindowAttributesAux::new()
+                .event_mask(xproto::EventMask::STRUCTURE_NOTIFY),
+        )?
+        .check()
+        .context("select root window events")?;
+
+    let window = create_icon_window(&*connection, screen, &atoms, &title)?;
+    println!("window {}", window);
+
+    let manager = connection
+        .get_selection_owner(selection)?
+        .reply()
+        .context("get tray selection owner")?
+        .owner;
+    if manager != x11rb::NONE {
+        request_dock(&*connection, &atoms, manager, window)?;
+    }
+
+    {
+        let connection = connection.clone();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(title) = line else {
+                    break;
+                };
+                connection
+                    .change_property8(
+                        xproto::PropMode::REPLACE,
+                        window,
+                        atoms._NET_WM_NAME,
+                        atoms.UTF8_STRING,
+                        title.as_bytes(),
+                    )
+                    .and_then(|_| connection.flush())
+                    .expect("change title");
+            }
+            process::exit(0);
+        });
+    }
+
+    loop {
+        match connection.wait_for_event()? {
+            Event::ReparentNotify(event) if event.window == window => {
+                println!("reparent {}", event.parent);
+            }
+            Event::ClientMessage(event) if event.type_ == atoms._XEMBED && event.format == 32 => {
+                let [_, message, _, embedder, _] = event.data.as_data32();
+                if message == XEMBED_EMBEDDED_NOTIFY {
+                    println!("embedded {}", embedder);
+                }
+            }
+            Event::ClientMessage(event) if event.type_ == atoms.MANAGER && event.format == 32 => {
+                let [_, selection_atom, manager, ..] = event.data.as_data32();
+                if selection_atom == selection {
+                    println!("manager {}", manager);
+                    request_dock(&*connection, &atoms, manager, window)?;
+                }
+            }
+            Event::ButtonPress(event) if event.event == window => {
+                println!("press {}", event.detail);
+            }
+            Event::ButtonRelease(event) if event.event == window => {
+                println!("release {}", event.detail);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn create_icon_window(
+    connection: &impl Connection,
+    screen: &xproto::Screen,
+    atoms: &Atoms,
+    title: &str,
+) -> anyhow::Result<xproto::Window> {
+    let window = connection.generate_id()?;
+    let values = xproto::CreateWindowAux::new().event_mask(
+        xproto::EventMask::BUTTON_PRESS
+            | xproto::EventMask::BUTTON_RELEASE
+            | xproto::EventMask::STRUCTURE_NOTIFY,
+    );
+    connection
+        .create_window(
+            screen.root_depth,
+            window,
+            screen.root,
+            0,
+            0,
+            24,
+            24,
+            0,
+            xproto::WindowClass::INPUT_OUTPUT,
+            screen.root_visual,
+            &values,
+        )?
+        .check()
+        .context("create icon window")?;
+    connection
+        .change_property8(
+            xproto::PropMode::REPLACE,
+            window,
+            xproto::AtomEnum::WM_CLASS,
+            xproto::AtomEnum::STRING,
+            b"tray-client\0TrayClient\0",
+        )?
+        .check()
+        .context("set WM_CLASS")?;
+    connection
+        .change_property8(
+            xproto::PropMode::REPLACE,
+            window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            title.as_bytes(),
+        )?
+        .check()
+        .context("set _NET_WM_NAME")?;
+    connection
+        .change_property32(
+            xproto::PropMode::REPLACE,
+            window,
+            atoms._XEMBED_INFO,
+            atoms._XEMBED_INFO,
+            &[0, XEMBED_MAPPED],
+        )?
+        .check()
+        .context("set _XEMBED_INFO")?;
+    Ok(window)
+}
+
+fn request_dock(
+    connection: &impl Connection,
+    atoms: &Atoms,
+    manager: xproto::Window,
+    window: xproto::Window,
+) -> anyhow::Result<()> {
+    let event = xproto::ClientMessageEvent::new(
+        32,
+        manager,
+        atoms._NET_SYSTEM_TRAY_OPCODE,
+        [x11rb::CURRENT_TIME, SYSTEM_TRAY_REQUEST_DOCK, window, 0, 0],
+    );
+    connection
+        .send_event(false, manager, xproto::EventMask::NO_EVENT, event)?
+        .check()
+        .context("send SYSTEM_TRAY_REQUEST_DOCK")?;
+    Ok(())
+}
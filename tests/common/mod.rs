@@ -0,0 +1,255 @@
+use nix::fcntl::{self, FcntlArg, FdFlag, OFlag};
+use nix::sys::signal::{self, Signal};
+use nix::unistd::{self, Pid};
+use std::collections::VecDeque;
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, BufRead as _, BufReader, Write as _};
+use std::os::fd::FromRawFd as _;
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection as _;
+use x11rb::protocol::{xproto, xproto::ConnectionExt as _};
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::xcb_ffi::XCBConnection;
+
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+x11rb::atom_manager! {
+    pub Atoms: AtomsCookie {
+        MANAGER,
+        _NET_SYSTEM_TRAY_S0,
+    }
+}
+
+// An Xvfb server on a display chosen by the server itself. It is killed on drop.
+pub struct Xvfb {
+    process: Child,
+    display: String,
+}
+
+impl Xvfb {
+    // Returns `None` when Xvfb is not installed, so that the tests can be skipped, except on CI
+    // where it must be installed.
+    pub fn spawn() -> Option<Self> {
+        // Only the write end is inherited by Xvfb.
+        let (read_fd, write_fd) = unistd::pipe2(OFlag::O_CLOEXEC).expect("create pipe");
+        fcntl::fcntl(write_fd, FcntlArg::F_SETFD(FdFlag::empty())).expect("clear FD_CLOEXEC");
+        let result = Command::new("Xvfb")
+            .arg("-displayfd")
+            .arg(write_fd.to_string())
+            .args(["-screen", "0", "1280x720x24", "-nolisten", "tcp"])
+            .spawn();
+        unistd::close(write_fd).expect("close write end of pipe");
+
+        let mut reader = BufReader::new(unsafe { fs::File::from_raw_fd(read_fd) });
+        let process = match result {
+            Ok(process) => process,
+            Err(error)
+                if error.kind() == io::ErrorKind::NotFound && env::var_os("CI").is_none() =>
+            {
+                eprintln!("Xvfb is not found, skipping");
+                return None;
+            }
+            Err(error) => panic!("spawn Xvfb: {}", error),
+        };
+
+        let mut display_num = String::new();
+        reader
+            .read_line(&mut display_num)
+            .expect("read display number");
+
+        Some(Self {
+            process,
+            display: format!(":{}", display_num.trim()),
+        })
+    }
+
+    pub fn connect(&self) -> XCBConnection {
+        let (connection, _) =
+            XCBConnection::connect(Some(&CString::new(self.display.as_str()).unwrap()))
+                .expect("connect to Xvfb");
+        connection
+    }
+
+    pub fn spawn_geektray(&self, config: &str) -> Geektray {
+        static INSTANCE: AtomicUsize = AtomicUsize::new(0);
+
+        let config_home = env::temp_dir().join(format!(
+            "geektray-test-{}-{}",
+            std::process::id(),
+            INSTANCE.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(config_home.join("geektray")).expect("create config dir");
+        fs::write(config_home.join("geektray").join("config.toml"), config)
+            .expect("write config file");
+
+        let process = Command::new(env!("CARGO_BIN_EXE_geektray"))
+            .env("DISPLAY", &self.display)
+            .env("XDG_CONFIG_HOME", &config_home)
+            .spawn()
+            .expect("spawn geektray");
+
+        Geektray {
+            process,
+            config_home,
+        }
+    }
+}
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        terminate(&mut self.process);
+    }
+}
+
+pub struct Geektray {
+    process: Child,
+    config_home: PathBuf,
+}
+
+impl Drop for Geektray {
+    fn drop(&mut self) {
+        terminate(&mut self.process);
+        fs::remove_dir_all(&self.config_home).ok();
+    }
+}
+
+// The fake XEmbed client in tests/bin/tray_client.rs, which runs as a separate process like
+// real tray icons. It is killed on drop, and so its window is destroyed.
+pub struct TrayClient {
+    process: Child,
+    stdin: ChildStdin,
+    lines: mpsc::Receiver<String>,
+    // Lines that have been read but not waited for yet.
+    pending_lines: VecDeque<String>,
+    pub window: xproto::Window,
+}
+
+impl TrayClient {
+    pub fn spawn(xvfb: &Xvfb, title: &str) -> Self {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_geektray-tray-client"))
+            .arg(title)
+            .env("DISPLAY", &xvfb.display)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("spawn tray client");
+        let stdin = process.stdin.take().unwrap();
+        let stdout = process.stdout.take().unwrap();
+
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = Self {
+            process,
+            stdin,
+            lines,
+            pending_lines: VecDeque::new(),
+            window: x11rb::NONE,
+        };
+        client.window = client.wait_for("window");
+        client
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        writeln!(self.stdin, "{}", title).expect("write title");
+    }
+
+    // Waits for the client to report the message, and returns the window or the button in it.
+    pub fn wait_for(&mut self, message: &str) -> u32 {
+        self.poll_for_until(message, Instant::now() + TIMEOUT)
+            .unwrap_or_else(|| panic!("timed out waiting for {:?}", message))
+    }
+
+    pub fn poll_for_until(&mut self, message: &str, deadline: Instant) -> Option<u32> {
+        let parse = |line: &str| {
+            line.strip_prefix(message)?
+                .strip_prefix(' ')?
+                .parse::<u32>()
+                .ok()
+        };
+        if let Some(index) = self
+            .pending_lines
+            .iter()
+            .position(|line| parse(line).is_some())
+        {
+            return self.pending_lines.remove(index).as_deref().and_then(parse);
+        }
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let line = self.lines.recv_timeout(timeout).ok()?;
+            match parse(&line) {
+                Some(value) => return Some(value),
+                None => self.pending_lines.push_back(line),
+            }
+        }
+    }
+}
+
+impl Drop for TrayClient {
+    fn drop(&mut self) {
+        terminate(&mut self.process);
+    }
+}
+
+// Sends a key press and release to the window, as if the user typed the key into it.
+pub fn type_key(connection: &XCBConnection, window: xproto::Window, keysym: xproto::Keysym) {
+    let setup = connection.setup();
+    let mapping = connection
+        .get_keyboard_mapping(setup.min_keycode, setup.max_keycode - setup.min_keycode + 1)
+        .unwrap()
+        .reply()
+        .unwrap();
+    let per_keycode = mapping.keysyms_per_keycode as usize;
+    let index = mapping
+        .keysyms
+        .iter()
+        .position(|candidate| *candidate == keysym)
+        .expect("keysym is mapped");
+    let keycode = setup.min_keycode + (index / per_keycode) as u8;
+    let root = setup.roots[0].root;
+
+    for response_type in [xproto::KEY_PRESS_EVENT, xproto::KEY_RELEASE_EVENT] {
+        let event = xproto::KeyPressEvent {
+            response_type,
+            detail: keycode,
+            sequence: 0,
+            time: x11rb::CURRENT_TIME,
+            root,
+            event: window,
+            child: x11rb::NONE,
+            root_x: 0,
+            root_y: 0,
+            event_x: 0,
+            event_y: 0,
+            state: 0u16.into(),
+            same_screen: true,
+        };
+        connection
+            .send_event(true, window, xproto::EventMask::NO_EVENT, event)
+            .unwrap()
+            .check()
+            .unwrap();
+    }
+    connection.sync().unwrap();
+}
+
+fn terminate(process: &mut Child) {
+    signal::kill(Pid::from_raw(process.id() as i32), Signal::SIGTERM).ok();
+    process.wait().ok();
+}
@@ -0,0 +1,231 @@
+mod common;
+
+use geektray::{Atoms, Color, SystemTrayColors, SystemTrayOrientation, TrayEvent, TrayManager};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection as _;
+use x11rb::protocol::xproto::{self, ConnectionExt as _};
+use x11rb::xcb_ffi::XCBConnection;
+
+use common::{type_key, TrayClient, Xvfb, TIMEOUT};
+
+const XK_1: xproto::Keysym = 0x0031;
+const XK_L: xproto::Keysym = 0x006c;
+
+const CONFIG: &str = r#"
+[window]
+auto_hide = false
+
+[[key_bindings]]
+keysym = "1"
+actions = [{ type = "SelectItem", index = 0 }]
+
+[[key_bindings]]
+keysym = "l"
+actions = [{ type = "ClickSelectedItem", button = "Left" }]
+"#;
+
+#[test]
+fn test_dock_icon() {
+    let Some(xvfb) = Xvfb::spawn() else {
+        return;
+    };
+    let _geektray = xvfb.spawn_geektray(CONFIG);
+    let connection = xvfb.connect();
+    let mut client = TrayClient::spawn(&xvfb, "foo");
+
+    let embedder = client.wait_for("reparent");
+    assert_ne!(embedder, connection.setup().roots[0].root);
+    assert_eq!(client.wait_for("embedded"), embedder);
+}
+
+#[test]
+fn test_click_icon() {
+    let Some(xvfb) = Xvfb::spawn() else {
+        return;
+    };
+    let _geektray = xvfb.spawn_geektray(CONFIG);
+    let connection = xvfb.connect();
+    let mut client = TrayClient::spawn(&xvfb, "foo");
+
+    let embedder = client.wait_for("reparent");
+
+    // The icon may not be added to the tray yet, so keep clicking until it is.
+    let deadline = Instant::now() + TIMEOUT;
+    let button = loop {
+        for keysym in [XK_1, XK_L] {
+            type_key(&connection, embedder, keysym);
+        }
+        if let Some(button) =
+            client.poll_for_until("press", Instant::now() + Duration::from_millis(100))
+        {
+            break button;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for click");
+    };
+    assert_eq!(button, u8::from(xproto::ButtonIndex::M1) as u32);
+    assert_eq!(
+        client.wait_for("release"),
+        u8::from(xproto::ButtonIndex::M1) as u32
+    );
+}
+
+#[test]
+fn test_selection_handover() {
+    let Some(xvfb) = Xvfb::spawn() else {
+        return;
+    };
+    let _geektray = xvfb.spawn_geektray(CONFIG);
+    let connection = xvfb.connect();
+    let atoms = common::Atoms::new(&connection).unwrap().reply().unwrap();
+    let mut client = TrayClient::spawn(&xvfb, "foo");
+
+    client.wait_for("reparent");
+
+    // Another tray takes over the selection, and announces it as the specification requires.
+    let screen = &connection.setup().roots[0];
+    let new_manager = create_window(&connection, 1, 1);
+    connection
+        .set_selection_owner(new_manager, atoms._NET_SYSTEM_TRAY_S0, x11rb::CURRENT_TIME)
+        .unwrap()
+        .check()
+        .unwrap();
+    let event = xproto::ClientMessageEvent::new(
+        32,
+        screen.root,
+        atoms.MANAGER,
+        [
+            x11rb::CURRENT_TIME,
+            atoms._NET_SYSTEM_TRAY_S0,
+            new_manager,
+            0,
+            0,
+        ],
+    );
+    connection
+        .send_event(
+            false,
+            screen.root,
+            xproto::EventMask::STRUCTURE_NOTIFY,
+            event,
+        )
+        .unwrap()
+        .check()
+        .unwrap();
+
+    assert_eq!(client.wait_for("reparent"), screen.root);
+    assert_eq!(client.wait_for("manager"), new_manager);
+}
+
+#[test]
+fn test_tray_events() {
+    let Some(xvfb) = Xvfb::spawn() else {
+        return;
+    };
+    let connection = Rc::new(xvfb.connect());
+    let atoms = Rc::new(Atoms::new(&*connection).unwrap().reply().unwrap());
+    let embedder = create_window(&connection, 240, 24);
+    let mut tray_manager = TrayManager::new(connection.clone(), 0, atoms).unwrap();
+    tray_manager
+        .acquire_tray_selection(
+            embedder,
+            SystemTrayOrientation::HORZONTAL,
+            SystemTrayColors::single(Color::WHITE),
+        )
+        .unwrap();
+
+    let mut client = TrayClient::spawn(&xvfb, "foo");
+    let window = client.window;
+
+    let tray_events = wait_for_tray_events(&connection, &mut tray_manager, |tray_event| {
+        matches!(tray_event, TrayEvent::IconAdded(..))
+    });
+    assert!(
+        matches!(
+            tray_events.as_slice(),
+            [
+                TrayEvent::IconAdded(icon, title, Some(class), None, _),
+                TrayEvent::UrgencyChanged(_, false),
+                TrayEvent::PingSupportChanged(_, false),
+            ] if *icon == window && title == "foo" && class == "TrayClient"
+        ),
+        "{:?}",
+        tray_events
+    );
+
+    client.set_title("bar");
+    let tray_events = wait_for_tray_events(&connection, &mut tray_manager, |tray_event| {
+        matches!(tray_event, TrayEvent::TitleChanged(..))
+    });
+    assert!(
+        matches!(
+            tray_events.as_slice(),
+            [TrayEvent::TitleChanged(icon, title)] if *icon == window && title == "bar"
+        ),
+        "{:?}",
+        tray_events
+    );
+
+    drop(client);
+    let tray_events = wait_for_tray_events(&connection, &mut tray_manager, |tray_event| {
+        matches!(tray_event, TrayEvent::IconRemoved(..))
+    });
+    assert!(
+        matches!(
+            tray_events.as_slice(),
+            [TrayEvent::IconRemoved(icon)] if *icon == window
+        ),
+        "{:?}",
+        tray_events
+    );
+}
+
+fn create_window(connection: &XCBConnection, width: u16, height: u16) -> xproto::Window {
+    let screen = &connection.setup().roots[0];
+    let window = connection.generate_id().unwrap();
+    connection
+        .create_window(
+            screen.root_depth,
+            window,
+            screen.root,
+            0,
+            0,
+            width,
+            height,
+            0,
+            xproto::WindowClass::INPUT_OUTPUT,
+            screen.root_visual,
+            &Default::default(),
+        )
+        .unwrap()
+        .check()
+        .unwrap();
+    window
+}
+
+// Runs the event loop of the tray manager until it emits the last event, and returns the events
+// emitted so far.
+fn wait_for_tray_events(
+    connection: &XCBConnection,
+    tray_manager: &mut TrayManager<XCBConnection>,
+    mut is_last: impl FnMut(&TrayEvent) -> bool,
+) -> Vec<TrayEvent> {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut tray_events = Vec::new();
+    loop {
+        while let Some(event) = connection.poll_for_event().unwrap() {
+            tray_events.extend(tray_manager.translate_event(&event).unwrap());
+        }
+        tray_events.extend(tray_manager.process_pending_requests().unwrap());
+        if tray_events.iter().any(&mut is_last) {
+            return tray_events;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "timed out waiting for tray events: {:?}",
+            tray_events
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+}